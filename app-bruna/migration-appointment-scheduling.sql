@@ -0,0 +1,21 @@
+-- Migration: Appointment duration and scheduling resources
+-- Appointments get a duration and the professional, room and chair they use.
+-- Overlaps (with the buffer between visits) are checked by the desktop app
+-- according to the clinic's scheduling rules, so no exclusion constraint here.
+
+-- 1. New columns
+ALTER TABLE public.appointments
+ADD COLUMN IF NOT EXISTS duration_minutes INTEGER NOT NULL DEFAULT 30 CHECK (duration_minutes BETWEEN 1 AND 720),
+ADD COLUMN IF NOT EXISTS professional_id UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+ADD COLUMN IF NOT EXISTS room TEXT,
+ADD COLUMN IF NOT EXISTS chair TEXT;
+
+-- 2. Keep end_time consistent with start_time + duration
+UPDATE public.appointments
+SET end_time = start_time + make_interval(mins => duration_minutes)
+WHERE end_time IS NULL OR end_time <= start_time;
+
+-- 3. Indexes for the per-resource lookups
+CREATE INDEX IF NOT EXISTS idx_appointments_professional_date ON public.appointments(professional_id, appointment_date);
+CREATE INDEX IF NOT EXISTS idx_appointments_room_date ON public.appointments(room, appointment_date);
+CREATE INDEX IF NOT EXISTS idx_appointments_chair_date ON public.appointments(chair, appointment_date);
@@ -0,0 +1,82 @@
+-- Migration: Recurring appointment series
+-- A series stores the rule (RRULE subset: FREQ, INTERVAL, BYDAY, COUNT, UNTIL)
+-- and the template of its appointments. The desktop app materializes the
+-- occurrences as regular appointments, checking holidays and overlaps, so the
+-- server only keeps the link between them.
+
+-- 1. Create series table
+CREATE TABLE IF NOT EXISTS public.appointment_series (
+    id UUID PRIMARY KEY, -- Generated by the client
+    patient_id UUID REFERENCES public.patients(id) ON DELETE CASCADE NOT NULL,
+    rrule TEXT NOT NULL,
+    start_date DATE NOT NULL, -- DTSTART
+    time TIME NOT NULL,
+    duration_minutes INTEGER NOT NULL DEFAULT 30 CHECK (duration_minutes BETWEEN 1 AND 720),
+    professional_id UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    room TEXT,
+    chair TEXT,
+    status TEXT NOT NULL,
+    notes TEXT,
+    exdates DATE[] NOT NULL DEFAULT '{}', -- Ocorrências excluídas
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    -- Campos de sincronização híbrida
+    rev BIGINT DEFAULT 0 NOT NULL,
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    last_editor TEXT,
+    last_pulled_rev BIGINT DEFAULT 0
+);
+
+-- 2. Link appointments to their series
+ALTER TABLE public.appointments
+ADD COLUMN IF NOT EXISTS series_id UUID REFERENCES public.appointment_series(id) ON DELETE SET NULL,
+ADD COLUMN IF NOT EXISTS occurrence_date DATE, -- RECURRENCE-ID
+ADD COLUMN IF NOT EXISTS series_exception BOOLEAN NOT NULL DEFAULT false;
+
+-- 3. Create indexes
+CREATE INDEX IF NOT EXISTS idx_appointment_series_patient_id ON public.appointment_series(patient_id);
+CREATE INDEX IF NOT EXISTS idx_appointment_series_rev ON public.appointment_series(rev);
+CREATE INDEX IF NOT EXISTS idx_appointment_series_deleted_at ON public.appointment_series(deleted_at);
+CREATE INDEX IF NOT EXISTS idx_appointments_series ON public.appointments(series_id, occurrence_date);
+
+-- 4. Triggers
+CREATE TRIGGER update_appointment_series_updated_at BEFORE UPDATE ON public.appointment_series
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER increment_appointment_series_rev BEFORE INSERT OR UPDATE ON public.appointment_series
+    FOR EACH ROW EXECUTE FUNCTION increment_rev_column();
+
+CREATE OR REPLACE FUNCTION soft_delete_appointment_series_trigger()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.appointment_series
+    SET deleted_at = NOW(), rev = get_next_rev()
+    WHERE id = OLD.id;
+    RETURN NULL;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER prevent_appointment_series_hard_delete BEFORE DELETE ON public.appointment_series
+    FOR EACH ROW EXECUTE FUNCTION soft_delete_appointment_series_trigger();
+
+-- 5. Enable RLS
+ALTER TABLE public.appointment_series ENABLE ROW LEVEL SECURITY;
+
+-- 6. RLS Policies (same as appointments)
+CREATE POLICY "Admin and doctor can manage appointment series" ON public.appointment_series
+    FOR ALL USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Other users can view appointment series" ON public.appointment_series
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('nurse', 'receptionist')
+        )
+    );
@@ -0,0 +1,32 @@
+-- Migration: Appointment status state machine
+-- The desktop app only moves appointments through the allowed transitions and
+-- keeps every change (timestamp, user, reason) in status_history. Statistics
+-- are computed from that history.
+
+-- 1. New statuses. Run this block on its own: new enum values cannot be used
+-- in the same transaction that adds them.
+ALTER TYPE appointment_status ADD VALUE IF NOT EXISTS 'checked_in' AFTER 'confirmed';
+ALTER TYPE appointment_status ADD VALUE IF NOT EXISTS 'cancelled_by_patient';
+ALTER TYPE appointment_status ADD VALUE IF NOT EXISTS 'cancelled_by_clinic';
+ALTER TYPE appointment_status ADD VALUE IF NOT EXISTS 'rescheduled';
+
+-- 2. Status history ([{from, to, changed_at, changed_by, changed_by_email,
+-- reason, rescheduled_to, rescheduled_from}])
+ALTER TABLE public.appointments
+ADD COLUMN IF NOT EXISTS status_history JSONB NOT NULL DEFAULT '[]';
+
+-- 3. Plain "cancelled" was recorded by the clinic staff
+UPDATE public.appointments
+SET status = 'cancelled_by_clinic'
+WHERE status = 'cancelled';
+
+-- 4. Cancellations and reschedules must carry a reason in their last change
+ALTER TABLE public.appointments
+ADD CONSTRAINT appointments_status_reason CHECK (
+    status NOT IN ('cancelled_by_patient', 'cancelled_by_clinic', 'rescheduled')
+    OR jsonb_array_length(status_history) = 0
+    OR coalesce(btrim(status_history->-1->>'reason'), '') <> ''
+) NOT VALID;
+
+-- 5. Index for the statistics by period
+CREATE INDEX IF NOT EXISTS idx_appointments_status_date ON public.appointments(status, appointment_date);
@@ -0,0 +1,178 @@
+-- Migration: Finalized medical records (signatures and addenda)
+-- Finalizing a record stores an Ed25519 signature over the signed version's
+-- content hash. After that only signed addenda may be added. Both tables are
+-- append-only, like medical_record_versions.
+-- Signing keys never leave the device, so each (signer, device) pair registers
+-- its public key in medical_record_signer_keys. Clients store keys fetched from
+-- there as untrusted: a key is trusted only if it belongs to the workstation
+-- itself or carries an endorsement (medical_record_key_endorsements) signed by
+-- an already trusted key. Records signed by other keys show as unverified.
+
+-- 1. Create signatures table
+CREATE TABLE IF NOT EXISTS public.medical_record_signatures (
+    id UUID PRIMARY KEY, -- Generated by the client
+    record_id UUID NOT NULL,
+    version_id UUID REFERENCES public.medical_record_versions(id) ON DELETE RESTRICT NOT NULL,
+    patient_id UUID REFERENCES public.patients(id) ON DELETE RESTRICT NOT NULL,
+    content_hash TEXT NOT NULL,
+    signer_id UUID REFERENCES auth.users(id) NOT NULL,
+    device_id TEXT NOT NULL DEFAULT '', -- Device whose key signed
+    signed_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    algorithm TEXT NOT NULL DEFAULT 'Ed25519',
+    public_key TEXT NOT NULL, -- Hex
+    signature TEXT NOT NULL, -- Hex
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- 2. Create addenda table
+CREATE TABLE IF NOT EXISTS public.medical_record_addenda (
+    id UUID PRIMARY KEY, -- Generated by the client
+    record_id UUID NOT NULL,
+    version_id UUID REFERENCES public.medical_record_versions(id) ON DELETE RESTRICT NOT NULL,
+    patient_id UUID REFERENCES public.patients(id) ON DELETE RESTRICT NOT NULL,
+    text TEXT NOT NULL,
+    created_by UUID REFERENCES auth.users(id) NOT NULL,
+    device_id TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- 3. Create signer keys table (one key per signer and device)
+CREATE TABLE IF NOT EXISTS public.medical_record_signer_keys (
+    signer_id UUID REFERENCES auth.users(id) NOT NULL,
+    device_id TEXT NOT NULL,
+    public_key TEXT NOT NULL, -- Hex
+    registered_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    PRIMARY KEY (signer_id, device_id)
+);
+
+-- 3b. Create key endorsements table (a trusted key vouching for another one)
+CREATE TABLE IF NOT EXISTS public.medical_record_key_endorsements (
+    id UUID PRIMARY KEY, -- Generated by the client
+    signer_id UUID REFERENCES auth.users(id) NOT NULL,
+    device_id TEXT NOT NULL,
+    public_key TEXT NOT NULL, -- Endorsed key (hex)
+    endorsed_by UUID REFERENCES auth.users(id) NOT NULL,
+    endorser_device_id TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    signature TEXT NOT NULL, -- Hex, by the endorser's key
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- Databases created before device keys
+ALTER TABLE public.medical_record_signatures ADD COLUMN IF NOT EXISTS device_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE public.medical_record_addenda ADD COLUMN IF NOT EXISTS device_id TEXT NOT NULL DEFAULT '';
+
+-- 4. Create indexes
+CREATE INDEX IF NOT EXISTS idx_medical_record_signatures_record_id ON public.medical_record_signatures(record_id);
+CREATE INDEX IF NOT EXISTS idx_medical_record_signatures_patient_id ON public.medical_record_signatures(patient_id);
+CREATE INDEX IF NOT EXISTS idx_medical_record_addenda_record_id ON public.medical_record_addenda(record_id);
+CREATE INDEX IF NOT EXISTS idx_medical_record_addenda_patient_id ON public.medical_record_addenda(patient_id);
+CREATE INDEX IF NOT EXISTS idx_medical_record_key_endorsements_signer_id ON public.medical_record_key_endorsements(signer_id);
+
+-- 5. Append-only (reuses the function from migration-medical-record-versions.sql)
+DROP TRIGGER IF EXISTS medical_record_signatures_immutable ON public.medical_record_signatures;
+CREATE TRIGGER medical_record_signatures_immutable
+    BEFORE UPDATE OR DELETE ON public.medical_record_signatures
+    FOR EACH ROW EXECUTE FUNCTION public.reject_medical_record_version_change();
+
+DROP TRIGGER IF EXISTS medical_record_addenda_immutable ON public.medical_record_addenda;
+CREATE TRIGGER medical_record_addenda_immutable
+    BEFORE UPDATE OR DELETE ON public.medical_record_addenda
+    FOR EACH ROW EXECUTE FUNCTION public.reject_medical_record_version_change();
+
+DROP TRIGGER IF EXISTS medical_record_signer_keys_immutable ON public.medical_record_signer_keys;
+CREATE TRIGGER medical_record_signer_keys_immutable
+    BEFORE UPDATE OR DELETE ON public.medical_record_signer_keys
+    FOR EACH ROW EXECUTE FUNCTION public.reject_medical_record_version_change();
+
+DROP TRIGGER IF EXISTS medical_record_key_endorsements_immutable ON public.medical_record_key_endorsements;
+CREATE TRIGGER medical_record_key_endorsements_immutable
+    BEFORE UPDATE OR DELETE ON public.medical_record_key_endorsements
+    FOR EACH ROW EXECUTE FUNCTION public.reject_medical_record_version_change();
+
+-- 6. Enable RLS
+ALTER TABLE public.medical_record_signatures ENABLE ROW LEVEL SECURITY;
+ALTER TABLE public.medical_record_addenda ENABLE ROW LEVEL SECURITY;
+ALTER TABLE public.medical_record_signer_keys ENABLE ROW LEVEL SECURITY;
+ALTER TABLE public.medical_record_key_endorsements ENABLE ROW LEVEL SECURITY;
+
+-- 7. RLS Policies (no UPDATE or DELETE policies on purpose)
+CREATE POLICY "Admin and doctor can sign medical records" ON public.medical_record_signatures
+    FOR INSERT WITH CHECK (
+        signer_id = auth.uid()
+        AND EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can view medical record signatures" ON public.medical_record_signatures
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can add medical record addenda" ON public.medical_record_addenda
+    FOR INSERT WITH CHECK (
+        created_by = auth.uid()
+        AND EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can view medical record addenda" ON public.medical_record_addenda
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can register their signing keys" ON public.medical_record_signer_keys
+    FOR INSERT WITH CHECK (
+        signer_id = auth.uid()
+        AND EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can view signing keys" ON public.medical_record_signer_keys
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can endorse signing keys" ON public.medical_record_key_endorsements
+    FOR INSERT WITH CHECK (
+        endorsed_by = auth.uid()
+        AND EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can view key endorsements" ON public.medical_record_key_endorsements
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
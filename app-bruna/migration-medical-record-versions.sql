@@ -0,0 +1,63 @@
+-- Migration: Immutable medical record versions
+-- Each save in the desktop app creates a new version; versions are never updated
+-- or deleted. Concurrent edits arrive as sibling versions (same parent) and are
+-- merged later by a new version listing both as parents.
+
+-- 1. Create medical record versions table
+CREATE TABLE IF NOT EXISTS public.medical_record_versions (
+    id UUID PRIMARY KEY, -- Generated by the client
+    record_id UUID NOT NULL, -- Logical medical record shared by all its versions
+    patient_id UUID REFERENCES public.patients(id) ON DELETE RESTRICT NOT NULL,
+    appointment_id UUID REFERENCES public.appointments(id) ON DELETE SET NULL,
+    version INTEGER NOT NULL,
+    parent_ids UUID[] NOT NULL DEFAULT '{}',
+    anamnesis TEXT,
+    diagnosis TEXT,
+    treatment_plan TEXT,
+    notes TEXT,
+    deleted BOOLEAN NOT NULL DEFAULT false,
+    content_hash TEXT NOT NULL, -- SHA-256 of the canonical version content
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    created_by UUID REFERENCES auth.users(id),
+    received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+);
+
+-- 2. Create indexes
+CREATE INDEX IF NOT EXISTS idx_medical_record_versions_record_id ON public.medical_record_versions(record_id);
+CREATE INDEX IF NOT EXISTS idx_medical_record_versions_patient_id ON public.medical_record_versions(patient_id);
+
+-- 3. Versions are append-only
+CREATE OR REPLACE FUNCTION public.reject_medical_record_version_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'Medical record versions are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS medical_record_versions_immutable ON public.medical_record_versions;
+CREATE TRIGGER medical_record_versions_immutable
+    BEFORE UPDATE OR DELETE ON public.medical_record_versions
+    FOR EACH ROW EXECUTE FUNCTION public.reject_medical_record_version_change();
+
+-- 4. Enable RLS
+ALTER TABLE public.medical_record_versions ENABLE ROW LEVEL SECURITY;
+
+-- 5. RLS Policies (no UPDATE or DELETE policies on purpose)
+CREATE POLICY "Admin and doctor can add medical record versions" ON public.medical_record_versions
+    FOR INSERT WITH CHECK (
+        created_by = auth.uid()
+        AND EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
+
+CREATE POLICY "Admin and doctor can view medical record versions" ON public.medical_record_versions
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor')
+        )
+    );
@@ -0,0 +1,72 @@
+-- Migration: Patient allergies, medications and chronic conditions
+-- Structured clinical items per patient. Synced like patients and appointments
+-- (rev, soft delete). Active allergies and high/critical items become alerts
+-- shown with appointments and medical records.
+
+-- 1. Create clinical items table
+CREATE TABLE IF NOT EXISTS public.patient_clinical_items (
+    id UUID PRIMARY KEY, -- Generated by the client
+    patient_id UUID REFERENCES public.patients(id) ON DELETE CASCADE NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('allergy', 'medication', 'chronic_condition')),
+    name TEXT NOT NULL,
+    severity TEXT NOT NULL CHECK (severity IN ('low', 'moderate', 'high', 'critical')),
+    source TEXT NOT NULL CHECK (source IN ('patient_reported', 'clinician', 'anamnesis', 'external_report', 'guardian')),
+    details TEXT,
+    active BOOLEAN NOT NULL DEFAULT true,
+    recorded_by UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    -- Campos de sincronização híbrida
+    rev BIGINT DEFAULT 0 NOT NULL,
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    last_editor TEXT,
+    last_pulled_rev BIGINT DEFAULT 0
+);
+
+-- 2. Create indexes
+CREATE INDEX IF NOT EXISTS idx_patient_clinical_items_patient_id ON public.patient_clinical_items(patient_id);
+CREATE INDEX IF NOT EXISTS idx_patient_clinical_items_kind ON public.patient_clinical_items(kind);
+CREATE INDEX IF NOT EXISTS idx_patient_clinical_items_rev ON public.patient_clinical_items(rev);
+CREATE INDEX IF NOT EXISTS idx_patient_clinical_items_deleted_at ON public.patient_clinical_items(deleted_at);
+
+-- 3. Triggers
+CREATE TRIGGER update_patient_clinical_items_updated_at BEFORE UPDATE ON public.patient_clinical_items
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER increment_patient_clinical_items_rev BEFORE INSERT OR UPDATE ON public.patient_clinical_items
+    FOR EACH ROW EXECUTE FUNCTION increment_rev_column();
+
+CREATE OR REPLACE FUNCTION soft_delete_patient_clinical_items_trigger()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.patient_clinical_items
+    SET deleted_at = NOW(), rev = get_next_rev()
+    WHERE id = OLD.id;
+    RETURN NULL;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER prevent_patient_clinical_items_hard_delete BEFORE DELETE ON public.patient_clinical_items
+    FOR EACH ROW EXECUTE FUNCTION soft_delete_patient_clinical_items_trigger();
+
+-- 4. Enable RLS
+ALTER TABLE public.patient_clinical_items ENABLE ROW LEVEL SECURITY;
+
+-- 5. RLS Policies (the reception also sees alerts at check-in)
+CREATE POLICY "Clinical staff can manage patient clinical items" ON public.patient_clinical_items
+    FOR ALL USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor', 'nurse')
+        )
+    );
+
+CREATE POLICY "Other users can view patient clinical items" ON public.patient_clinical_items
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('receptionist')
+        )
+    );
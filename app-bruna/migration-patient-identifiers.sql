@@ -0,0 +1,41 @@
+-- Migration: Brazilian identifiers and structured patient data
+-- CPF (digits only, unique among active patients), RG, structured address,
+-- guardians for minors, emergency contacts and insurance. The desktop app
+-- validates and normalizes everything before syncing (phones in E.164).
+
+-- 1. New columns
+ALTER TABLE public.patients
+ADD COLUMN IF NOT EXISTS cpf TEXT,
+ADD COLUMN IF NOT EXISTS rg TEXT,
+ADD COLUMN IF NOT EXISTS guardians JSONB NOT NULL DEFAULT '[]',
+ADD COLUMN IF NOT EXISTS emergency_contacts JSONB NOT NULL DEFAULT '[]',
+ADD COLUMN IF NOT EXISTS insurance JSONB;
+
+-- 2. Structured address ({cep, street, number, complement, neighborhood, city, uf}).
+-- Free-text addresses are kept as the street, like the app does locally.
+ALTER TABLE public.patients
+ALTER COLUMN address TYPE JSONB USING (
+    CASE
+        WHEN address IS NULL OR btrim(address) = '' THEN NULL
+        ELSE jsonb_build_object('street', btrim(address))
+    END
+);
+
+-- 3. Constraints (NOT VALID: existing rows are fixed as they are edited)
+ALTER TABLE public.patients
+ADD CONSTRAINT patients_cpf_format CHECK (cpf ~ '^[0-9]{11}$') NOT VALID,
+ADD CONSTRAINT patients_phone_e164 CHECK (phone ~ '^\+[1-9][0-9]{7,14}$') NOT VALID,
+ADD CONSTRAINT patients_address_uf CHECK (
+    address IS NULL
+    OR address->>'uf' IS NULL
+    OR address->>'uf' = ''
+    OR address->>'uf' IN (
+        'AC', 'AL', 'AP', 'AM', 'BA', 'CE', 'DF', 'ES', 'GO', 'MA', 'MT', 'MS', 'MG', 'PA',
+        'PB', 'PR', 'PE', 'PI', 'RJ', 'RN', 'RS', 'RO', 'RR', 'SC', 'SP', 'SE', 'TO'
+    )
+) NOT VALID;
+
+-- 4. Indexes
+CREATE UNIQUE INDEX IF NOT EXISTS idx_patients_cpf_unique ON public.patients(cpf)
+    WHERE cpf IS NOT NULL AND deleted_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_patients_address_city ON public.patients((address->>'city'));
@@ -0,0 +1,74 @@
+-- Migration: Appointment waitlist
+-- Patients waiting for a slot, with the days, times and professionals that
+-- suit them. Offers for freed slots are computed by the desktop app when an
+-- appointment is cancelled or rescheduled, so only the entries are synced.
+
+-- 1. Create waitlist table
+CREATE TABLE IF NOT EXISTS public.waitlist_entries (
+    id UUID PRIMARY KEY, -- Generated by the client
+    patient_id UUID REFERENCES public.patients(id) ON DELETE CASCADE NOT NULL,
+    preferred_weekdays TEXT[] NOT NULL DEFAULT '{}', -- 'Mon', 'Tue'... (empty: any day)
+    preferred_times JSONB NOT NULL DEFAULT '[]', -- [{start, end}] (empty: any time)
+    professional_ids TEXT[] NOT NULL DEFAULT '{}', -- Empty: any professional
+    duration_minutes INTEGER CHECK (duration_minutes BETWEEN 1 AND 720),
+    earliest_date DATE,
+    latest_date DATE,
+    priority TEXT NOT NULL DEFAULT 'normal' CHECK (priority IN ('normal', 'high', 'urgent')),
+    notes TEXT,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+    -- Campos de sincronização híbrida
+    rev BIGINT DEFAULT 0 NOT NULL,
+    deleted_at TIMESTAMP WITH TIME ZONE,
+    last_editor TEXT,
+    last_pulled_rev BIGINT DEFAULT 0,
+    CONSTRAINT waitlist_entries_date_range CHECK (earliest_date IS NULL OR latest_date IS NULL OR earliest_date <= latest_date)
+);
+
+-- 2. Create indexes
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_patient_id ON public.waitlist_entries(patient_id);
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_priority ON public.waitlist_entries(priority, created_at);
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_rev ON public.waitlist_entries(rev);
+CREATE INDEX IF NOT EXISTS idx_waitlist_entries_deleted_at ON public.waitlist_entries(deleted_at);
+
+-- 3. Triggers
+CREATE TRIGGER update_waitlist_entries_updated_at BEFORE UPDATE ON public.waitlist_entries
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER increment_waitlist_entries_rev BEFORE INSERT OR UPDATE ON public.waitlist_entries
+    FOR EACH ROW EXECUTE FUNCTION increment_rev_column();
+
+CREATE OR REPLACE FUNCTION soft_delete_waitlist_entries_trigger()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE public.waitlist_entries
+    SET deleted_at = NOW(), rev = get_next_rev()
+    WHERE id = OLD.id;
+    RETURN NULL;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER prevent_waitlist_entries_hard_delete BEFORE DELETE ON public.waitlist_entries
+    FOR EACH ROW EXECUTE FUNCTION soft_delete_waitlist_entries_trigger();
+
+-- 4. Enable RLS
+ALTER TABLE public.waitlist_entries ENABLE ROW LEVEL SECURITY;
+
+-- 5. RLS Policies (the reception manages the waitlist and calls the patients)
+CREATE POLICY "Staff can manage waitlist entries" ON public.waitlist_entries
+    FOR ALL USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('admin', 'doctor', 'receptionist')
+        )
+    );
+
+CREATE POLICY "Nurses can view waitlist entries" ON public.waitlist_entries
+    FOR SELECT USING (
+        EXISTS (
+            SELECT 1 FROM public.profiles
+            WHERE id = auth.uid()
+            AND role IN ('nurse')
+        )
+    );
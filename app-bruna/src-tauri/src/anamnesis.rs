@@ -0,0 +1,778 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use chrono::{DateTime, NaiveDate, Utc};
+
+// =====================================================
+// STRUCTURED ANAMNESIS TEMPLATES
+// =====================================================
+
+// Modelos de anamnese definidos pela clínica. Cada alteração gera uma nova versão
+// do modelo; a anamnese preenchida guarda a versão usada e é validada contra ela,
+// então mudar o modelo depois não invalida o que já foi respondido.
+
+const TEMPLATES_FILE: &str = "anamnesis_templates.json";
+const ANAMNESES_FILE: &str = "anamneses";
+pub const DEFAULT_TEMPLATE_ID: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuestionKind {
+    YesNo,
+    MultipleChoice {
+        options: Vec<String>,
+        #[serde(default)]
+        allow_multiple: bool,
+    },
+    Numeric {
+        min: Option<f64>,
+        max: Option<f64>,
+        unit: Option<String>,
+        #[serde(default)]
+        integer: bool,
+    },
+    FreeText {
+        max_length: Option<usize>,
+    },
+    Date,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnamnesisQuestion {
+    pub id: String, // Chave estável entre versões (usada nas consultas)
+    pub text: String,
+    #[serde(flatten)]
+    pub kind: QuestionKind,
+    #[serde(default)]
+    pub required: bool,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnamnesisTemplate {
+    pub id: String,
+    pub version: u32,
+    pub name: String,
+    pub description: Option<String>,
+    pub questions: Vec<AnamnesisQuestion>,
+    #[serde(default)]
+    pub archived: bool,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SaveAnamnesisTemplateRequest {
+    pub id: Option<String>, // None cria um modelo novo
+    pub name: String,
+    pub description: Option<String>,
+    pub questions: Vec<AnamnesisQuestion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum AnswerValue {
+    YesNo(bool),
+    Choices(Vec<String>),
+    Number(f64),
+    Text(String),
+    Date(NaiveDate),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnsweredQuestion {
+    pub question_id: String,
+    pub value: AnswerValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerError {
+    pub question_id: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilledAnamnesis {
+    pub id: String,
+    pub patient_id: String,
+    pub medical_record_id: Option<String>,
+    pub template_id: String,
+    pub template_version: u32,
+    pub answers: Vec<AnsweredQuestion>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FillAnamnesisRequest {
+    pub patient_id: String,
+    pub medical_record_id: Option<String>,
+    pub template_id: String,
+    pub template_version: Option<u32>, // None usa a versão mais recente
+    pub answers: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnamnesisMatch {
+    pub patient_id: String,
+    pub anamnesis_id: String,
+    pub template_id: String,
+    pub value: AnswerValue,
+    pub answered_at: DateTime<Utc>,
+}
+
+// =====================================================
+// TEMPLATE STORAGE (configuração da clínica)
+// =====================================================
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TemplateStore {
+    templates: Vec<AnamnesisTemplate>, // Todas as versões de todos os modelos
+}
+
+static TEMPLATES_LOCK: Mutex<()> = Mutex::new(());
+static ANAMNESES_LOCK: Mutex<()> = Mutex::new(());
+
+fn templates_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join(TEMPLATES_FILE))
+}
+
+fn load_templates() -> Result<TemplateStore> {
+    let path = templates_path()?;
+    if !path.exists() {
+        return Ok(TemplateStore { templates: vec![default_template()] });
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn save_templates(store: &TemplateStore) -> Result<()> {
+    let path = templates_path()?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(store)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+fn yes_no(id: &str, text: &str, category: &str) -> AnamnesisQuestion {
+    AnamnesisQuestion {
+        id: id.to_string(),
+        text: text.to_string(),
+        kind: QuestionKind::YesNo,
+        required: false,
+        category: Some(category.to_string()),
+    }
+}
+
+// Questionário padrão da clínica (o mesmo de migration-medical-records.sql)
+fn default_template() -> AnamnesisTemplate {
+    let mut questions = vec![
+        yes_no("under_medical_treatment", "Está em tratamento médico?", "medical_history"),
+        yes_no("continuous_medication", "Faz uso de medicação contínua? (Ex: anticoncepcional, ansiolítico)", "medications"),
+        yes_no("allergies", "Possui alguma alergia? (Ex: penicilinas, AAS, alimentos ou bebidas)", "allergies"),
+        yes_no("cardiovascular", "Possui alguma alteração cardiovascular (coração)?", "medical_history"),
+        yes_no("hypertension", "Possui hipertensão/hipotensão (pressão alta/baixa)?", "medical_history"),
+        yes_no("diabetes", "Possui diabetes?", "medical_history"),
+        yes_no("respiratory", "Possui alguma alteração respiratória (asma, rinite, bronquite)?", "medical_history"),
+        yes_no("gastric", "Alteração no estômago (ex: gastrite, azia, refluxo, úlcera)?", "medical_history"),
+        yes_no("epilepsy", "Possui epilepsia (convulsões)?", "medical_history"),
+        yes_no("recent_infarction_or_stroke", "Possui histórico de Infarto ou AVC, em menos de 6 meses?", "medical_history"),
+        yes_no("communicable_disease", "Possui alguma doença transmissível (Ex: HIV, hepatite, tuberculose, herpes, etc)?", "medical_history"),
+        yes_no("other_condition", "Possui alguma outra doença/síndrome não questionada?", "medical_history"),
+        yes_no("cancer", "Já foi diagnosticado(a) com algum tipo de câncer?", "medical_history"),
+        yes_no("radio_or_chemotherapy", "Já fez radioterapia ou quimioterapia?", "medical_history"),
+        yes_no("smoker", "Fuma ou já fumou?", "lifestyle"),
+        yes_no("alcohol", "Ingere bebidas alcoólicas?", "lifestyle"),
+        yes_no("drugs", "Faz uso de drogas?", "lifestyle"),
+        yes_no("pregnant", "Está grávida?", "medical_history"),
+        yes_no("breastfeeding", "Está amamentando?", "medical_history"),
+        yes_no("flu_symptoms_last_7_days", "Apresentou ou apresenta sintomas de COVID-19/Gripe nos últimos 7 dias?", "medical_history"),
+        yes_no("covid_diagnosis", "Já foi diagnosticado com COVID-19?", "medical_history"),
+    ];
+    questions.push(AnamnesisQuestion {
+        id: "last_dental_visit".to_string(),
+        text: "Última vez que passou em consulta odontológica?".to_string(),
+        kind: QuestionKind::Date,
+        required: false,
+        category: Some("dental_history".to_string()),
+    });
+    questions.push(AnamnesisQuestion {
+        id: "chief_complaint".to_string(),
+        text: "Queixa principal?".to_string(),
+        kind: QuestionKind::FreeText { max_length: None },
+        required: true,
+        category: Some("complaint".to_string()),
+    });
+
+    AnamnesisTemplate {
+        id: DEFAULT_TEMPLATE_ID.to_string(),
+        version: 1,
+        name: "Anamnese padrão".to_string(),
+        description: None,
+        questions,
+        archived: false,
+        created_at: DateTime::<Utc>::UNIX_EPOCH,
+        created_by: None,
+    }
+}
+
+fn validate_template(request: &SaveAnamnesisTemplateRequest) -> Result<()> {
+    if request.name.trim().is_empty() {
+        bail!("Template name cannot be empty");
+    }
+    if request.questions.is_empty() {
+        bail!("Template must have at least one question");
+    }
+    let mut ids = HashSet::new();
+    for question in &request.questions {
+        if question.id.trim().is_empty() || !ids.insert(question.id.as_str()) {
+            bail!("Question ids must be unique and non-empty: '{}'", question.id);
+        }
+        if question.text.trim().is_empty() {
+            bail!("Question {} has no text", question.id);
+        }
+        match &question.kind {
+            QuestionKind::MultipleChoice { options, .. } => {
+                let unique: HashSet<&String> = options.iter().collect();
+                if options.len() < 2 || unique.len() != options.len() || options.iter().any(|o| o.trim().is_empty()) {
+                    bail!("Question {} needs at least two distinct options", question.id);
+                }
+            }
+            QuestionKind::Numeric { min: Some(min), max: Some(max), .. } if min > max => {
+                bail!("Question {} has min greater than max", question.id);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Última versão de cada modelo
+pub fn list_templates(include_archived: bool) -> Result<Vec<AnamnesisTemplate>> {
+    let store = load_templates()?;
+    let mut latest: HashMap<&str, &AnamnesisTemplate> = HashMap::new();
+    for template in &store.templates {
+        match latest.get(template.id.as_str()) {
+            Some(current) if current.version >= template.version => {}
+            _ => {
+                latest.insert(&template.id, template);
+            }
+        }
+    }
+    let mut templates: Vec<AnamnesisTemplate> = latest
+        .into_values()
+        .filter(|t| include_archived || !t.archived)
+        .cloned()
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+pub fn get_template(template_id: &str, version: Option<u32>) -> Result<AnamnesisTemplate> {
+    find_template(&load_templates()?.templates, template_id, version)
+}
+
+fn find_template(templates: &[AnamnesisTemplate], template_id: &str, version: Option<u32>) -> Result<AnamnesisTemplate> {
+    templates
+        .iter()
+        .filter(|t| t.id == template_id && version.is_none_or(|v| v == t.version))
+        .max_by_key(|t| t.version)
+        .cloned()
+        .ok_or_else(|| anyhow!("Anamnesis template not found: {}", template_id))
+}
+
+// Versão usada por uma anamnese nova. Um modelo arquivado não recebe novos
+// preenchimentos, nem pedindo explicitamente uma versão anterior ao arquivamento.
+fn template_for_fill(templates: &[AnamnesisTemplate], template_id: &str, version: Option<u32>) -> Result<AnamnesisTemplate> {
+    if find_template(templates, template_id, None)?.archived {
+        bail!("Anamnesis template is archived");
+    }
+    find_template(templates, template_id, version)
+}
+
+// Cria o modelo ou publica uma nova versão; versões antigas nunca mudam
+pub fn save_template(request: SaveAnamnesisTemplateRequest, created_by: Option<String>) -> Result<AnamnesisTemplate> {
+    validate_template(&request)?;
+    let _guard = TEMPLATES_LOCK.lock().unwrap();
+    let mut store = load_templates()?;
+
+    let (id, version) = match request.id {
+        Some(id) => {
+            let current = store
+                .templates
+                .iter()
+                .filter(|t| t.id == id)
+                .map(|t| t.version)
+                .max()
+                .ok_or_else(|| anyhow!("Anamnesis template not found: {}", id))?;
+            (id, current + 1)
+        }
+        None => (uuid::Uuid::new_v4().to_string(), 1),
+    };
+    let template = AnamnesisTemplate {
+        id,
+        version,
+        name: request.name.trim().to_string(),
+        description: request.description,
+        questions: request.questions,
+        archived: false,
+        created_at: Utc::now(),
+        created_by,
+    };
+
+    store.templates.push(template.clone());
+    save_templates(&store)?;
+    Ok(template)
+}
+
+// Arquivar publica uma versão arquivada: a lista deixa de oferecer o modelo
+pub fn archive_template(template_id: &str, created_by: Option<String>) -> Result<AnamnesisTemplate> {
+    let _guard = TEMPLATES_LOCK.lock().unwrap();
+    let mut store = load_templates()?;
+    let current = store
+        .templates
+        .iter()
+        .filter(|t| t.id == template_id)
+        .max_by_key(|t| t.version)
+        .cloned()
+        .ok_or_else(|| anyhow!("Anamnesis template not found: {}", template_id))?;
+    if current.archived {
+        return Ok(current);
+    }
+
+    let archived = AnamnesisTemplate {
+        version: current.version + 1,
+        archived: true,
+        created_at: Utc::now(),
+        created_by,
+        ..current
+    };
+    store.templates.push(archived.clone());
+    save_templates(&store)?;
+    Ok(archived)
+}
+
+// =====================================================
+// ANSWER VALIDATION
+// =====================================================
+
+fn parse_answer(question: &AnamnesisQuestion, raw: &serde_json::Value) -> std::result::Result<Option<AnswerValue>, String> {
+    use serde_json::Value;
+
+    if raw.is_null() {
+        return Ok(None);
+    }
+    match &question.kind {
+        QuestionKind::YesNo => raw
+            .as_bool()
+            .map(|b| Some(AnswerValue::YesNo(b)))
+            .ok_or_else(|| "Expected yes or no".to_string()),
+        QuestionKind::MultipleChoice { options, allow_multiple } => {
+            let selected: Vec<String> = match raw {
+                Value::String(s) => vec![s.clone()],
+                Value::Array(items) => items
+                    .iter()
+                    .map(|i| i.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| "Expected a list of options".to_string())?,
+                _ => return Err("Expected one of the options".to_string()),
+            };
+            if selected.is_empty() {
+                return Ok(None);
+            }
+            if let Some(unknown) = selected.iter().find(|s| !options.contains(s)) {
+                return Err(format!("'{}' is not one of the options", unknown));
+            }
+            if !allow_multiple && selected.len() > 1 {
+                return Err("Only one option can be selected".to_string());
+            }
+            Ok(Some(AnswerValue::Choices(selected)))
+        }
+        QuestionKind::Numeric { min, max, integer, .. } => {
+            let n = raw.as_f64().ok_or_else(|| "Expected a number".to_string())?;
+            if *integer && n.fract() != 0.0 {
+                return Err("Expected a whole number".to_string());
+            }
+            if min.is_some_and(|min| n < min) || max.is_some_and(|max| n > max) {
+                return Err(format!(
+                    "Value must be between {} and {}",
+                    min.map_or("-∞".to_string(), |v| v.to_string()),
+                    max.map_or("∞".to_string(), |v| v.to_string())
+                ));
+            }
+            Ok(Some(AnswerValue::Number(n)))
+        }
+        QuestionKind::FreeText { max_length } => {
+            let text = raw.as_str().ok_or_else(|| "Expected text".to_string())?.trim();
+            if text.is_empty() {
+                return Ok(None);
+            }
+            if max_length.is_some_and(|max| text.chars().count() > max) {
+                return Err(format!("Text is longer than {} characters", max_length.unwrap_or_default()));
+            }
+            Ok(Some(AnswerValue::Text(text.to_string())))
+        }
+        QuestionKind::Date => {
+            let text = raw.as_str().ok_or_else(|| "Expected a date (YYYY-MM-DD)".to_string())?;
+            if text.trim().is_empty() {
+                return Ok(None);
+            }
+            NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+                .map(|d| Some(AnswerValue::Date(d)))
+                .map_err(|_| "Expected a date (YYYY-MM-DD)".to_string())
+        }
+    }
+}
+
+// Valida as respostas contra a versão do modelo; devolve todas as falhas de uma vez
+pub fn validate_answers(
+    template: &AnamnesisTemplate,
+    answers: &HashMap<String, serde_json::Value>,
+) -> std::result::Result<Vec<AnsweredQuestion>, Vec<AnswerError>> {
+    let mut errors: Vec<AnswerError> = answers
+        .keys()
+        .filter(|id| !template.questions.iter().any(|q| &q.id == *id))
+        .map(|id| AnswerError {
+            question_id: id.clone(),
+            message: "Question is not part of this template version".to_string(),
+        })
+        .collect();
+
+    let mut answered = Vec::new();
+    for question in &template.questions {
+        let parsed = answers
+            .get(&question.id)
+            .map_or(Ok(None), |raw| parse_answer(question, raw));
+        match parsed {
+            Ok(Some(value)) => answered.push(AnsweredQuestion {
+                question_id: question.id.clone(),
+                value,
+            }),
+            Ok(None) if question.required => errors.push(AnswerError {
+                question_id: question.id.clone(),
+                message: "Answer is required".to_string(),
+            }),
+            Ok(None) => {}
+            Err(message) => errors.push(AnswerError {
+                question_id: question.id.clone(),
+                message,
+            }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(answered)
+    } else {
+        Err(errors)
+    }
+}
+
+// =====================================================
+// FILLED ANAMNESES (dados do paciente, por perfil)
+// =====================================================
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct AnamnesisStore {
+    anamneses: Vec<FilledAnamnesis>,
+}
+
+fn load_anamneses() -> Result<AnamnesisStore> {
+    Ok(crate::profiles::read_profile_file::<AnamnesisStore>(ANAMNESES_FILE)?.unwrap_or_default())
+}
+
+pub fn fill_anamnesis(request: FillAnamnesisRequest, created_by: Option<String>) -> Result<FilledAnamnesis> {
+    let template = template_for_fill(&load_templates()?.templates, &request.template_id, request.template_version)?;
+    let answers = validate_answers(&template, &request.answers).map_err(|errors| {
+        anyhow!(
+            "Invalid answers: {}",
+            errors
+                .iter()
+                .map(|e| format!("{}: {}", e.question_id, e.message))
+                .collect::<Vec<_>>()
+                .join("; ")
+        )
+    })?;
+
+    let anamnesis = FilledAnamnesis {
+        id: uuid::Uuid::new_v4().to_string(),
+        patient_id: request.patient_id,
+        medical_record_id: request.medical_record_id,
+        template_id: template.id,
+        template_version: template.version,
+        answers,
+        created_at: Utc::now(),
+        created_by,
+    };
+
+    let _guard = ANAMNESES_LOCK.lock().unwrap();
+    let mut store = load_anamneses()?;
+    store.anamneses.push(anamnesis.clone());
+    crate::profiles::write_profile_file(ANAMNESES_FILE, &store)?;
+    Ok(anamnesis)
+}
+
+pub fn get_anamnesis(anamnesis_id: &str) -> Result<FilledAnamnesis> {
+    load_anamneses()?
+        .anamneses
+        .into_iter()
+        .find(|a| a.id == anamnesis_id)
+        .ok_or_else(|| anyhow!("Anamnesis not found: {}", anamnesis_id))
+}
+
+pub fn list_patient_anamneses(patient_id: &str) -> Result<Vec<FilledAnamnesis>> {
+    let mut anamneses: Vec<FilledAnamnesis> = load_anamneses()?
+        .anamneses
+        .into_iter()
+        .filter(|a| a.patient_id == patient_id)
+        .collect();
+    anamneses.sort_by_key(|a| std::cmp::Reverse(a.created_at));
+    Ok(anamneses)
+}
+
+fn answer_matches(answer: &AnswerValue, expected: &serde_json::Value) -> bool {
+    match answer {
+        AnswerValue::YesNo(b) => expected.as_bool() == Some(*b),
+        AnswerValue::Choices(selected) => expected.as_str().is_some_and(|e| selected.iter().any(|s| s == e)),
+        AnswerValue::Number(n) => expected.as_f64() == Some(*n),
+        AnswerValue::Text(text) => expected
+            .as_str()
+            .is_some_and(|e| text.to_lowercase().contains(&e.to_lowercase())),
+        AnswerValue::Date(date) => expected.as_str() == Some(date.format("%Y-%m-%d").to_string().as_str()),
+    }
+}
+
+// Pacientes cuja anamnese mais recente com a pergunta tem a resposta informada
+// (ex.: question_id "hypertension", expected true)
+pub fn find_patients_by_answer(question_id: &str, expected: &serde_json::Value) -> Result<Vec<AnamnesisMatch>> {
+    let mut latest: HashMap<String, (FilledAnamnesis, AnswerValue)> = HashMap::new();
+    for anamnesis in load_anamneses()?.anamneses {
+        let Some(answer) = anamnesis.answers.iter().find(|a| a.question_id == question_id) else {
+            continue;
+        };
+        let newer = latest
+            .get(&anamnesis.patient_id)
+            .is_none_or(|(current, _)| anamnesis.created_at > current.created_at);
+        if newer {
+            let value = answer.value.clone();
+            latest.insert(anamnesis.patient_id.clone(), (anamnesis, value));
+        }
+    }
+
+    let mut matches: Vec<AnamnesisMatch> = latest
+        .into_values()
+        .filter(|(_, value)| answer_matches(value, expected))
+        .map(|(anamnesis, value)| AnamnesisMatch {
+            patient_id: anamnesis.patient_id,
+            anamnesis_id: anamnesis.id,
+            template_id: anamnesis.template_id,
+            value,
+            answered_at: anamnesis.created_at,
+        })
+        .collect();
+    matches.sort_by(|a, b| a.patient_id.cmp(&b.patient_id));
+    Ok(matches)
+}
+
+// =====================================================
+// TEXT RENDERING (relatórios e campo livre do prontuário)
+// =====================================================
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n).replace('.', ",")
+    }
+}
+
+fn render_answer(question: &AnamnesisQuestion, value: &AnswerValue) -> String {
+    match value {
+        AnswerValue::YesNo(true) => "Sim".to_string(),
+        AnswerValue::YesNo(false) => "Não".to_string(),
+        AnswerValue::Choices(selected) => selected.join(", "),
+        AnswerValue::Number(n) => match &question.kind {
+            QuestionKind::Numeric { unit: Some(unit), .. } => format!("{} {}", format_number(*n), unit),
+            _ => format_number(*n),
+        },
+        AnswerValue::Text(text) => text.clone(),
+        AnswerValue::Date(date) => date.format("%d/%m/%Y").to_string(),
+    }
+}
+
+pub fn render_text(template: &AnamnesisTemplate, anamnesis: &FilledAnamnesis) -> String {
+    let mut lines = vec![format!(
+        "{} (versão {}) - {}",
+        template.name,
+        template.version,
+        anamnesis.created_at.format("%d/%m/%Y")
+    )];
+    // Perguntas sem resposta ficam de fora do texto
+    for question in &template.questions {
+        if let Some(answer) = anamnesis.answers.iter().find(|a| a.question_id == question.id) {
+            lines.push(format!("- {} {}", question.text, render_answer(question, &answer.value)));
+        }
+    }
+    lines.join("\n")
+}
+
+pub fn render_anamnesis(anamnesis_id: &str) -> Result<String> {
+    let anamnesis = get_anamnesis(anamnesis_id)?;
+    let template = get_template(&anamnesis.template_id, Some(anamnesis.template_version))?;
+    Ok(render_text(&template, &anamnesis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn question(id: &str, kind: QuestionKind, required: bool) -> AnamnesisQuestion {
+        AnamnesisQuestion {
+            id: id.to_string(),
+            text: id.to_string(),
+            kind,
+            required,
+            category: None,
+        }
+    }
+
+    fn template(version: u32, archived: bool) -> AnamnesisTemplate {
+        AnamnesisTemplate {
+            id: "t1".to_string(),
+            version,
+            name: "Modelo".to_string(),
+            description: None,
+            questions: vec![
+                question("allergies", QuestionKind::YesNo, true),
+                question(
+                    "blood_type",
+                    QuestionKind::MultipleChoice { options: vec!["A".into(), "B".into(), "O".into()], allow_multiple: false },
+                    false,
+                ),
+                question(
+                    "symptoms",
+                    QuestionKind::MultipleChoice { options: vec!["dor".into(), "sangramento".into()], allow_multiple: true },
+                    false,
+                ),
+                question(
+                    "cigarettes",
+                    QuestionKind::Numeric { min: Some(0.0), max: Some(100.0), unit: None, integer: true },
+                    false,
+                ),
+                question("complaint", QuestionKind::FreeText { max_length: Some(10) }, true),
+                question("last_visit", QuestionKind::Date, false),
+            ],
+            archived,
+            created_at: Utc::now(),
+            created_by: None,
+        }
+    }
+
+    fn answers(value: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn error_for(errors: &[AnswerError], question_id: &str) -> String {
+        errors
+            .iter()
+            .find(|e| e.question_id == question_id)
+            .map(|e| e.message.clone())
+            .unwrap_or_else(|| panic!("no error for {}", question_id))
+    }
+
+    #[test]
+    fn accepts_valid_answers() {
+        let answered = validate_answers(
+            &template(1, false),
+            &answers(json!({
+                "allergies": false,
+                "blood_type": "O",
+                "symptoms": ["dor", "sangramento"],
+                "cigarettes": 10,
+                "complaint": "  dor  ",
+                "last_visit": "2024-02-29",
+            })),
+        )
+        .unwrap();
+
+        let value = |id: &str| answered.iter().find(|a| a.question_id == id).unwrap().value.clone();
+        assert_eq!(value("allergies"), AnswerValue::YesNo(false));
+        assert_eq!(value("blood_type"), AnswerValue::Choices(vec!["O".into()]));
+        assert_eq!(value("cigarettes"), AnswerValue::Number(10.0));
+        assert_eq!(value("complaint"), AnswerValue::Text("dor".into()));
+        assert_eq!(value("last_visit"), AnswerValue::Date(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap()));
+    }
+
+    #[test]
+    fn required_questions_need_an_answer() {
+        // "Não" é resposta; texto em branco e null não são
+        let errors = validate_answers(&template(1, false), &answers(json!({ "allergies": null, "complaint": "   " }))).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(error_for(&errors, "allergies"), "Answer is required");
+        assert_eq!(error_for(&errors, "complaint"), "Answer is required");
+
+        assert!(validate_answers(&template(1, false), &answers(json!({ "allergies": false, "complaint": "x" }))).is_ok());
+    }
+
+    #[test]
+    fn answers_must_match_the_question_type() {
+        let errors = validate_answers(
+            &template(1, false),
+            &answers(json!({
+                "allergies": "sim",
+                "cigarettes": 2.5,
+                "complaint": "dor muito forte",
+                "last_visit": "29/02/2024",
+                "removed_question": true,
+            })),
+        )
+        .unwrap_err();
+
+        assert_eq!(error_for(&errors, "allergies"), "Expected yes or no");
+        assert_eq!(error_for(&errors, "cigarettes"), "Expected a whole number");
+        assert_eq!(error_for(&errors, "complaint"), "Text is longer than 10 characters");
+        assert_eq!(error_for(&errors, "last_visit"), "Expected a date (YYYY-MM-DD)");
+        assert_eq!(error_for(&errors, "removed_question"), "Question is not part of this template version");
+
+        let errors = validate_answers(&template(1, false), &answers(json!({ "allergies": true, "complaint": "x", "cigarettes": 101 }))).unwrap_err();
+        assert_eq!(error_for(&errors, "cigarettes"), "Value must be between 0 and 100");
+    }
+
+    #[test]
+    fn choices_must_be_listed_options() {
+        let base = json!({ "allergies": true, "complaint": "x" });
+        let with = |id: &str, value: serde_json::Value| {
+            let mut raw = answers(base.clone());
+            raw.insert(id.to_string(), value);
+            validate_answers(&template(1, false), &raw)
+        };
+
+        assert_eq!(error_for(&with("blood_type", json!("AB")).unwrap_err(), "blood_type"), "'AB' is not one of the options");
+        assert_eq!(error_for(&with("blood_type", json!(["A", "B"])).unwrap_err(), "blood_type"), "Only one option can be selected");
+        assert_eq!(error_for(&with("symptoms", json!([1])).unwrap_err(), "symptoms"), "Expected a list of options");
+        assert!(with("symptoms", json!([])).is_ok());
+        assert!(with("blood_type", json!(["A"])).is_ok());
+    }
+
+    #[test]
+    fn archived_templates_do_not_accept_new_fills() {
+        let active = vec![template(1, false), template(2, false)];
+        assert_eq!(template_for_fill(&active, "t1", None).unwrap().version, 2);
+        // Uma versão anterior de um modelo ativo continua válida
+        assert_eq!(template_for_fill(&active, "t1", Some(1)).unwrap().version, 1);
+
+        let archived = vec![template(1, false), template(2, false), template(3, true)];
+        for version in [None, Some(1), Some(2), Some(3)] {
+            let err = template_for_fill(&archived, "t1", version).unwrap_err();
+            assert!(err.to_string().contains("archived"), "{:?}", version);
+        }
+        // Anamneses já preenchidas continuam renderizando com a versão delas
+        assert_eq!(find_template(&archived, "t1", Some(1)).unwrap().version, 1);
+        assert!(template_for_fill(&archived, "t2", None).is_err());
+    }
+}
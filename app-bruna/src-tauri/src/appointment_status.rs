@@ -0,0 +1,389 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+
+// =====================================================
+// APPOINTMENT STATUS STATE MACHINE
+// =====================================================
+
+// O status da consulta só anda pelas transições permitidas abaixo, e cada
+// mudança fica no histórico da consulta com data, autor e motivo. Cancelamentos
+// e remarcações exigem motivo. As estatísticas saem desse histórico, não do
+// texto do status.
+//
+//   scheduled ──> confirmed ──> checked_in ──> in_progress ──> completed
+//       │             │             │
+//       └─────────────┴─> no_show, cancelled_by_patient, cancelled_by_clinic,
+//                         rescheduled (check-in: só cancelamentos)
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppointmentStatus {
+    #[default]
+    Scheduled,
+    Confirmed,
+    CheckedIn,
+    InProgress,
+    Completed,
+    NoShow,
+    CancelledByPatient,
+    CancelledByClinic,
+    Rescheduled,
+}
+
+impl AppointmentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AppointmentStatus::Scheduled => "scheduled",
+            AppointmentStatus::Confirmed => "confirmed",
+            AppointmentStatus::CheckedIn => "checked_in",
+            AppointmentStatus::InProgress => "in_progress",
+            AppointmentStatus::Completed => "completed",
+            AppointmentStatus::NoShow => "no_show",
+            AppointmentStatus::CancelledByPatient => "cancelled_by_patient",
+            AppointmentStatus::CancelledByClinic => "cancelled_by_clinic",
+            AppointmentStatus::Rescheduled => "rescheduled",
+        }
+    }
+
+    // Aceita também os textos livres usados antes (em português ou inglês,
+    // com hífen, espaço ou maiúsculas). "Cancelada" sem autor vira
+    // cancelamento pela clínica, que era quem registrava.
+    pub fn parse(value: &str) -> Option<Self> {
+        let normalized = value.trim().to_lowercase().replace(['-', ' '], "_");
+        let status = match normalized.as_str() {
+            "scheduled" | "agendada" | "agendado" | "pendente" | "pending" => AppointmentStatus::Scheduled,
+            "confirmed" | "confirmada" | "confirmado" => AppointmentStatus::Confirmed,
+            "checked_in" | "checkin" | "chegou" | "na_recepcao" => AppointmentStatus::CheckedIn,
+            "in_progress" | "em_atendimento" => AppointmentStatus::InProgress,
+            "completed" | "realizada" | "realizado" | "concluida" | "concluído" | "concluída" => AppointmentStatus::Completed,
+            "no_show" | "noshow" | "faltou" | "falta" => AppointmentStatus::NoShow,
+            "cancelled_by_patient" | "cancelada_pelo_paciente" => AppointmentStatus::CancelledByPatient,
+            "cancelled_by_clinic" | "cancelada_pela_clinica" | "cancelled" | "canceled" | "cancelada" | "cancelado" => {
+                AppointmentStatus::CancelledByClinic
+            }
+            "rescheduled" | "remarcada" | "remarcado" => AppointmentStatus::Rescheduled,
+            _ => return None,
+        };
+        Some(status)
+    }
+
+    // Status com que uma consulta pode nascer
+    pub fn is_initial(self) -> bool {
+        matches!(self, AppointmentStatus::Scheduled | AppointmentStatus::Confirmed)
+    }
+
+    pub fn is_cancellation(self) -> bool {
+        matches!(self, AppointmentStatus::CancelledByPatient | AppointmentStatus::CancelledByClinic)
+    }
+
+    pub fn is_final(self) -> bool {
+        self.allowed_transitions().is_empty()
+    }
+
+    // Canceladas, remarcadas ou de quem faltou liberam o horário
+    pub fn occupies_schedule(self) -> bool {
+        !matches!(
+            self,
+            AppointmentStatus::NoShow
+                | AppointmentStatus::CancelledByPatient
+                | AppointmentStatus::CancelledByClinic
+                | AppointmentStatus::Rescheduled
+        )
+    }
+
+    pub fn requires_reason(self) -> bool {
+        self.is_cancellation() || self == AppointmentStatus::Rescheduled
+    }
+
+    pub fn allowed_transitions(self) -> &'static [AppointmentStatus] {
+        use AppointmentStatus::*;
+        match self {
+            Scheduled => &[Confirmed, CheckedIn, NoShow, CancelledByPatient, CancelledByClinic, Rescheduled],
+            Confirmed => &[CheckedIn, NoShow, CancelledByPatient, CancelledByClinic, Rescheduled],
+            CheckedIn => &[InProgress, CancelledByPatient, CancelledByClinic],
+            InProgress => &[Completed],
+            Completed | NoShow | CancelledByPatient | CancelledByClinic | Rescheduled => &[],
+        }
+    }
+
+    pub fn can_transition_to(self, next: AppointmentStatus) -> bool {
+        self.allowed_transitions().contains(&next)
+    }
+}
+
+impl std::fmt::Display for AppointmentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Status gravado antes do enum: texto desconhecido vira "scheduled" em vez de
+// derrubar a carga da partição
+pub fn lenient_status<'de, D>(deserializer: D) -> std::result::Result<AppointmentStatus, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<String>::deserialize(deserializer)?;
+    Ok(value.as_deref().and_then(AppointmentStatus::parse).unwrap_or_default())
+}
+
+// Uma mudança de status. `from` é None no registro de criação.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub from: Option<AppointmentStatus>,
+    pub to: AppointmentStatus,
+    pub changed_at: String,
+    pub changed_by: Option<String>, // user_id
+    pub changed_by_email: Option<String>,
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub rescheduled_to: Option<String>, // Consulta nova (na remarcada)
+    #[serde(default)]
+    pub rescheduled_from: Option<String>, // Consulta original (na nova)
+}
+
+impl StatusChange {
+    fn new(from: Option<AppointmentStatus>, to: AppointmentStatus, reason: Option<String>, actor: Option<(String, String)>) -> Self {
+        let (changed_by, changed_by_email) = actor.map_or((None, None), |(id, email)| (Some(id), Some(email)));
+        StatusChange {
+            from,
+            to,
+            changed_at: Utc::now().to_rfc3339(),
+            changed_by,
+            changed_by_email,
+            reason,
+            rescheduled_to: None,
+            rescheduled_from: None,
+        }
+    }
+}
+
+fn clean_reason(reason: Option<String>) -> Option<String> {
+    reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty())
+}
+
+// Registro de criação da consulta
+pub fn initial(status: AppointmentStatus, reason: Option<String>, actor: Option<(String, String)>) -> Result<StatusChange> {
+    if !status.is_initial() {
+        bail!("An appointment cannot be created as {}", status);
+    }
+    Ok(StatusChange::new(None, status, clean_reason(reason), actor))
+}
+
+pub fn transition(
+    from: AppointmentStatus,
+    to: AppointmentStatus,
+    reason: Option<String>,
+    actor: Option<(String, String)>,
+) -> Result<StatusChange> {
+    if !from.can_transition_to(to) {
+        if from.is_final() {
+            bail!("Appointment is already {} and cannot change status", from);
+        }
+        let allowed: Vec<&str> = from.allowed_transitions().iter().map(|s| s.as_str()).collect();
+        bail!("Cannot change status from {} to {} (allowed: {})", from, to, allowed.join(", "));
+    }
+    let reason = clean_reason(reason);
+    if to.requires_reason() && reason.is_none() {
+        bail!("A reason is required to mark the appointment as {}", to);
+    }
+    Ok(StatusChange::new(Some(from), to, reason, actor))
+}
+
+// =====================================================
+// STATISTICS
+// =====================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReasonCount {
+    pub reason: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppointmentStatistics {
+    pub total: usize,
+    pub by_status: BTreeMap<AppointmentStatus, usize>, // Status atual
+    pub confirmed: usize, // Chegaram a ser confirmadas
+    pub checked_in: usize, // Paciente chegou
+    pub completed: usize,
+    pub no_show: usize,
+    pub cancelled_by_patient: usize,
+    pub cancelled_by_clinic: usize,
+    pub rescheduled: usize,
+    // Contagens e percentuais sobre as consultas que não foram remarcadas (a
+    // remarcada é substituída por outra, que entra na conta)
+    pub confirmation_rate: f64,
+    pub completion_rate: f64,
+    pub no_show_rate: f64,
+    pub cancellation_rate: f64,
+    pub average_wait_minutes: Option<f64>, // Do check-in ao início do atendimento
+    pub cancellation_reasons: Vec<ReasonCount>,
+}
+
+// Cada consulta entra com o status atual e o histórico. Consultas sem
+// histórico (gravadas antes dele) contam só pelo status atual.
+pub fn statistics(appointments: &[(AppointmentStatus, &[StatusChange])]) -> AppointmentStatistics {
+    let mut stats = AppointmentStatistics::default();
+    let mut reasons: BTreeMap<String, (String, usize)> = BTreeMap::new();
+    let mut waits = Vec::new();
+
+    for (status, history) in appointments {
+        stats.total += 1;
+        *stats.by_status.entry(*status).or_default() += 1;
+        if *status == AppointmentStatus::Rescheduled {
+            stats.rescheduled += 1;
+            continue;
+        }
+
+        let reached = |s: AppointmentStatus| *status == s || history.iter().any(|c| c.to == s);
+        let at = |s: AppointmentStatus| {
+            history
+                .iter()
+                .find(|c| c.to == s)
+                .and_then(|c| DateTime::parse_from_rfc3339(&c.changed_at).ok())
+        };
+        if reached(AppointmentStatus::Confirmed) {
+            stats.confirmed += 1;
+        }
+        if reached(AppointmentStatus::CheckedIn) {
+            stats.checked_in += 1;
+        }
+        if let (Some(arrived), Some(started)) = (at(AppointmentStatus::CheckedIn), at(AppointmentStatus::InProgress)) {
+            waits.push((started - arrived).num_seconds() as f64 / 60.0);
+        }
+        match status {
+            AppointmentStatus::Completed => stats.completed += 1,
+            AppointmentStatus::NoShow => stats.no_show += 1,
+            AppointmentStatus::CancelledByPatient => stats.cancelled_by_patient += 1,
+            AppointmentStatus::CancelledByClinic => stats.cancelled_by_clinic += 1,
+            _ => {}
+        }
+        if status.is_cancellation() {
+            let reason = history
+                .iter()
+                .rev()
+                .find(|c| c.to == *status)
+                .and_then(|c| c.reason.clone())
+                .unwrap_or_else(|| "(sem motivo)".to_string());
+            // Agrupa sem diferenciar maiúsculas; mostra a primeira grafia
+            reasons.entry(reason.to_lowercase()).or_insert((reason, 0)).1 += 1;
+        }
+    }
+
+    let effective = stats.total - stats.rescheduled;
+    let rate = |n: usize| if effective > 0 { n as f64 / effective as f64 * 100.0 } else { 0.0 };
+    stats.confirmation_rate = rate(stats.confirmed);
+    stats.completion_rate = rate(stats.completed);
+    stats.no_show_rate = rate(stats.no_show);
+    stats.cancellation_rate = rate(stats.cancelled_by_patient + stats.cancelled_by_clinic);
+    stats.average_wait_minutes = (!waits.is_empty()).then(|| waits.iter().sum::<f64>() / waits.len() as f64);
+
+    stats.cancellation_reasons = reasons.into_values().map(|(reason, count)| ReasonCount { reason, count }).collect();
+    stats.cancellation_reasons.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.reason.cmp(&b.reason)));
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AppointmentStatus::*;
+
+    fn change(from: Option<AppointmentStatus>, to: AppointmentStatus, at: &str, reason: Option<&str>) -> StatusChange {
+        StatusChange {
+            from,
+            to,
+            changed_at: at.to_string(),
+            changed_by: None,
+            changed_by_email: None,
+            reason: reason.map(str::to_string),
+            rescheduled_to: None,
+            rescheduled_from: None,
+        }
+    }
+
+    #[test]
+    fn follows_the_allowed_edges() {
+        for (from, to) in [(Scheduled, Confirmed), (Confirmed, CheckedIn), (CheckedIn, InProgress), (InProgress, Completed), (Scheduled, NoShow)] {
+            let change = transition(from, to, None, None).unwrap();
+            assert_eq!((change.from, change.to), (Some(from), to));
+        }
+    }
+
+    #[test]
+    fn rejects_forbidden_edges() {
+        for (from, to) in [(Scheduled, Completed), (Confirmed, InProgress), (CheckedIn, NoShow), (InProgress, Scheduled), (CheckedIn, Rescheduled)] {
+            let err = transition(from, to, Some("motivo".to_string()), None).unwrap_err().to_string();
+            assert!(err.contains("Cannot change status"), "{from} -> {to}: {err}");
+        }
+        for from in [Completed, NoShow, CancelledByPatient, CancelledByClinic, Rescheduled] {
+            let err = transition(from, Scheduled, None, None).unwrap_err().to_string();
+            assert!(err.contains("cannot change status"), "{from}: {err}");
+        }
+        assert!(initial(Scheduled, None, None).is_ok());
+        assert!(initial(Confirmed, None, None).is_ok());
+        assert!(initial(Completed, None, None).is_err());
+    }
+
+    #[test]
+    fn cancellations_and_reschedules_require_a_reason() {
+        for to in [CancelledByPatient, CancelledByClinic, Rescheduled] {
+            assert!(transition(Scheduled, to, None, None).is_err());
+            assert!(transition(Scheduled, to, Some("   ".to_string()), None).is_err());
+            let change = transition(Scheduled, to, Some("  viagem ".to_string()), None).unwrap();
+            assert_eq!(change.reason.as_deref(), Some("viagem"));
+        }
+        let actor = Some(("user-1".to_string(), "a@b.c".to_string()));
+        let change = transition(Scheduled, Confirmed, None, actor).unwrap();
+        assert_eq!(change.changed_by.as_deref(), Some("user-1"));
+        assert!(change.reason.is_none());
+    }
+
+    #[test]
+    fn statistics_count_history_and_exclude_rescheduled_from_rates() {
+        let completed = vec![
+            change(None, Scheduled, "2026-03-02T09:00:00Z", None),
+            change(Some(Scheduled), Confirmed, "2026-03-02T10:00:00Z", None),
+            change(Some(Confirmed), CheckedIn, "2026-03-03T09:00:00Z", None),
+            change(Some(CheckedIn), InProgress, "2026-03-03T09:20:00Z", None),
+            change(Some(InProgress), Completed, "2026-03-03T10:00:00Z", None),
+        ];
+        let no_show = vec![change(None, Confirmed, "2026-03-02T09:00:00Z", None), change(Some(Confirmed), NoShow, "2026-03-04T09:00:00Z", None)];
+        let by_patient = vec![change(Some(Scheduled), CancelledByPatient, "2026-03-02T09:00:00Z", Some("Viagem"))];
+        let by_clinic = vec![change(Some(Scheduled), CancelledByClinic, "2026-03-02T09:00:00Z", Some("viagem"))];
+        let rescheduled = vec![change(Some(Scheduled), Rescheduled, "2026-03-02T09:00:00Z", Some("agenda"))];
+
+        let stats = statistics(&[
+            (Completed, &completed),
+            (NoShow, &no_show),
+            (CancelledByPatient, &by_patient),
+            (CancelledByClinic, &by_clinic),
+            (Rescheduled, &rescheduled),
+            (CancelledByClinic, &[]), // Gravada antes do histórico
+        ]);
+
+        assert_eq!(stats.total, 6);
+        assert_eq!(stats.rescheduled, 1);
+        assert_eq!(stats.by_status[&CancelledByClinic], 2);
+        assert_eq!((stats.confirmed, stats.checked_in, stats.completed, stats.no_show), (2, 1, 1, 1));
+        assert_eq!((stats.cancelled_by_patient, stats.cancelled_by_clinic), (1, 2));
+        // Cinco consultas efetivas: a remarcada fica de fora
+        assert_eq!(stats.confirmation_rate, 40.0);
+        assert_eq!(stats.completion_rate, 20.0);
+        assert_eq!(stats.no_show_rate, 20.0);
+        assert_eq!(stats.cancellation_rate, 60.0);
+        assert_eq!(stats.average_wait_minutes, Some(20.0));
+
+        let reasons: Vec<_> = stats.cancellation_reasons.iter().map(|r| (r.reason.as_str(), r.count)).collect();
+        assert_eq!(reasons, [("Viagem", 2), ("(sem motivo)", 1)]);
+    }
+
+    #[test]
+    fn statistics_of_nothing_are_zero() {
+        let stats = statistics(&[]);
+        assert_eq!((stats.total, stats.cancellation_rate), (0, 0.0));
+        assert!(stats.average_wait_minutes.is_none());
+    }
+}
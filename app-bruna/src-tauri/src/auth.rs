@@ -41,12 +41,16 @@ pub struct LoginRequest {
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub user: User,
     pub access_token: String,
     pub refresh_token: String,
     pub expires_at: DateTime<Utc>,
+    // Preenchido quando o usuário tem TOTP ativo: os tokens só são liberados
+    // após verify_login_second_factor
+    #[serde(default)]
+    pub mfa_challenge_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,6 +82,19 @@ pub struct AuditLog {
     pub created_at: DateTime<Utc>,
 }
 
+// Supabase recusou e-mail/senha. Erros de rede ou do servidor não viram este
+// erro, para não contarem como tentativa de login falha.
+#[derive(Debug)]
+pub struct InvalidCredentials(pub String);
+
+impl std::fmt::Display for InvalidCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid credentials: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCredentials {}
+
 pub struct AuthService {
     supabase_url: String,
     supabase_anon_key: String,
@@ -108,8 +125,12 @@ impl AuthService {
             .send()
             .await?;
 
-        if !response.status().is_success() {
+        let status = response.status();
+        if !status.is_success() {
             let error_text = response.text().await.unwrap_or_default();
+            if status == reqwest::StatusCode::BAD_REQUEST || status == reqwest::StatusCode::UNAUTHORIZED {
+                return Err(InvalidCredentials(error_text).into());
+            }
             return Err(anyhow::anyhow!("Login failed: {}", error_text));
         }
 
@@ -125,6 +146,7 @@ impl AuthService {
             access_token: auth_response.access_token,
             refresh_token: auth_response.refresh_token,
             expires_at,
+            mfa_challenge_id: None,
         })
     }
 
@@ -159,6 +181,7 @@ impl AuthService {
             access_token: auth_response.access_token,
             refresh_token: auth_response.refresh_token,
             expires_at,
+            mfa_challenge_id: None,
         })
     }
 
@@ -0,0 +1,626 @@
+use anyhow::{bail, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::scheduling::{ScheduleEntry, SchedulingRules};
+
+// =====================================================
+// AVAILABILITY CALENDAR
+// =====================================================
+
+// Agenda da clínica: horário semanal de cada profissional, intervalos
+// (almoço etc.), fechamentos (férias, reforma, congresso) e feriados nacionais
+// e municipais. Os horários livres saem daí menos as consultas já marcadas,
+// pelas mesmas regras de conflito da marcação (scheduling).
+
+const CALENDAR_FILE: &str = "availability_calendar.json";
+const MAX_RANGE_DAYS: i64 = 366;
+const MAX_SLOTS: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkingPeriod {
+    pub weekday: Weekday, // "Mon", "Tue"...
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakPeriod {
+    pub weekday: Option<Weekday>, // None: todos os dias
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfessionalSchedule {
+    pub professional_id: String,
+    pub name: Option<String>,
+    pub working_hours: Vec<WorkingPeriod>,
+    #[serde(default)]
+    pub breaks: Vec<BreakPeriod>,
+}
+
+// Fechamento da clínica inteira ou de um profissional; sem horários vale o dia todo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Closure {
+    pub id: String,
+    pub professional_id: Option<String>,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub reason: Option<String>,
+}
+
+// Feriado municipal: todo ano na mesma data, ou só no ano informado
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MunicipalHoliday {
+    pub month: u32,
+    pub day: u32,
+    pub year: Option<i32>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailabilityCalendar {
+    pub professionals: Vec<ProfessionalSchedule>,
+    #[serde(default)]
+    pub closures: Vec<Closure>,
+    #[serde(default)]
+    pub municipal_holidays: Vec<MunicipalHoliday>,
+    pub observe_national_holidays: bool,
+    pub observe_optional_holidays: bool, // Pontos facultativos: Carnaval, Corpus Christi
+    pub slot_step_minutes: u32, // Horários oferecidos a cada N minutos
+}
+
+impl Default for AvailabilityCalendar {
+    fn default() -> Self {
+        Self {
+            professionals: Vec::new(),
+            closures: Vec::new(),
+            municipal_holidays: Vec::new(),
+            observe_national_holidays: true,
+            observe_optional_holidays: false,
+            slot_step_minutes: 15,
+        }
+    }
+}
+
+fn calendar_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join(CALENDAR_FILE))
+}
+
+pub fn load_calendar() -> AvailabilityCalendar {
+    calendar_path()
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_calendar(calendar: &AvailabilityCalendar) -> Result<()> {
+    if !(5..=120).contains(&calendar.slot_step_minutes) {
+        bail!("Slot step must be between 5 and 120 minutes");
+    }
+    for professional in &calendar.professionals {
+        if professional.professional_id.trim().is_empty() {
+            bail!("Professional id is required");
+        }
+        if calendar.professionals.iter().filter(|p| p.professional_id == professional.professional_id).count() > 1 {
+            bail!("Professional {} is listed more than once", professional.professional_id);
+        }
+        for period in &professional.working_hours {
+            if period.start >= period.end {
+                bail!("Working hours must end after they start ({} on {})", professional.professional_id, period.weekday);
+            }
+        }
+        for b in &professional.breaks {
+            if b.start >= b.end {
+                bail!("Breaks must end after they start ({})", professional.professional_id);
+            }
+        }
+    }
+    for closure in &calendar.closures {
+        if closure.start_date > closure.end_date {
+            bail!("Closure must end on or after its start date");
+        }
+        match (closure.start_time, closure.end_time) {
+            (Some(start), Some(end)) if start >= end => bail!("Closure must end after it starts"),
+            (Some(_), None) | (None, Some(_)) => bail!("Partial-day closures need both start and end times"),
+            _ => {}
+        }
+    }
+    for holiday in &calendar.municipal_holidays {
+        // 2024 é bissexto: aceita 29/02
+        if NaiveDate::from_ymd_opt(holiday.year.unwrap_or(2024), holiday.month, holiday.day).is_none() {
+            bail!("Invalid municipal holiday date: {:02}/{:02}", holiday.day, holiday.month);
+        }
+    }
+
+    let path = calendar_path()?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(calendar)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// =====================================================
+// HOLIDAYS
+// =====================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HolidayKind {
+    National,
+    Optional, // Ponto facultativo
+    Municipal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    pub name: String,
+    pub kind: HolidayKind,
+}
+
+// Domingo de Páscoa (algoritmo de Meeus/Jones/Butcher, calendário gregoriano)
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+pub fn holidays_for_year(calendar: &AvailabilityCalendar, year: i32) -> Vec<Holiday> {
+    let fixed = |month: u32, day: u32, name: &str, kind: HolidayKind| Holiday {
+        date: NaiveDate::from_ymd_opt(year, month, day).unwrap(),
+        name: name.to_string(),
+        kind,
+    };
+    let from_easter = |days: i64, name: &str, kind: HolidayKind| Holiday {
+        date: easter(year) + Duration::days(days),
+        name: name.to_string(),
+        kind,
+    };
+
+    let mut holidays = vec![
+        fixed(1, 1, "Confraternização Universal", HolidayKind::National),
+        from_easter(-48, "Carnaval", HolidayKind::Optional),
+        from_easter(-47, "Carnaval", HolidayKind::Optional),
+        from_easter(-2, "Sexta-feira Santa", HolidayKind::National),
+        fixed(4, 21, "Tiradentes", HolidayKind::National),
+        fixed(5, 1, "Dia do Trabalho", HolidayKind::National),
+        from_easter(60, "Corpus Christi", HolidayKind::Optional),
+        fixed(9, 7, "Independência do Brasil", HolidayKind::National),
+        fixed(10, 12, "Nossa Senhora Aparecida", HolidayKind::National),
+        fixed(11, 2, "Finados", HolidayKind::National),
+        fixed(11, 15, "Proclamação da República", HolidayKind::National),
+        fixed(12, 25, "Natal", HolidayKind::National),
+    ];
+    // Lei 14.759/2023
+    if year >= 2024 {
+        holidays.push(fixed(11, 20, "Dia Nacional de Zumbi e da Consciência Negra", HolidayKind::National));
+    }
+    holidays.extend(
+        calendar
+            .municipal_holidays
+            .iter()
+            .filter(|h| h.year.is_none_or(|y| y == year))
+            .filter_map(|h| {
+                Some(Holiday {
+                    date: NaiveDate::from_ymd_opt(year, h.month, h.day)?,
+                    name: h.name.clone(),
+                    kind: HolidayKind::Municipal,
+                })
+            }),
+    );
+    holidays.sort_by_key(|h| h.date);
+    holidays
+}
+
+// Feriado que fecha a clínica, conforme a configuração
+pub fn closing_holiday(calendar: &AvailabilityCalendar, date: NaiveDate) -> Option<Holiday> {
+    holidays_for_year(calendar, date.year()).into_iter().find(|h| {
+        h.date == date
+            && match h.kind {
+                HolidayKind::National => calendar.observe_national_holidays,
+                HolidayKind::Optional => calendar.observe_optional_holidays,
+                HolidayKind::Municipal => true,
+            }
+    })
+}
+
+// Motivo de a clínica (ou o profissional) não atender no dia inteiro: feriado
+// ou fechamento de dia todo
+pub fn closed_reason(calendar: &AvailabilityCalendar, date: NaiveDate, professional_id: Option<&str>) -> Option<String> {
+    if let Some(holiday) = closing_holiday(calendar, date) {
+        return Some(holiday.name);
+    }
+    calendar
+        .closures
+        .iter()
+        .filter(|c| c.start_date <= date && date <= c.end_date && c.start_time.is_none())
+        .find(|c| c.professional_id.is_none() || c.professional_id.as_deref() == professional_id)
+        .map(|c| c.reason.clone().unwrap_or_else(|| "Clinic closed".to_string()))
+}
+
+// =====================================================
+// FREE SLOTS
+// =====================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotSearch {
+    pub duration_minutes: Option<u32>, // Sem duração: padrão da clínica
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub professional_id: Option<String>, // None: qualquer profissional
+    pub room: Option<String>,
+    pub chair: Option<String>,
+    pub limit: Option<usize>, // 1 para o botão "próximo horário"
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailableSlot {
+    pub professional_id: String,
+    pub professional_name: Option<String>,
+    pub date: NaiveDate,
+    pub start_time: String,
+    pub end_time: String,
+}
+
+// Períodos de trabalho do dia, já sem intervalos e fechamentos parciais
+fn working_intervals(
+    calendar: &AvailabilityCalendar,
+    professional: &ProfessionalSchedule,
+    date: NaiveDate,
+) -> Vec<(NaiveTime, NaiveTime)> {
+    if closing_holiday(calendar, date).is_some() {
+        return Vec::new();
+    }
+    let closures: Vec<&Closure> = calendar
+        .closures
+        .iter()
+        .filter(|c| c.start_date <= date && date <= c.end_date)
+        .filter(|c| c.professional_id.as_ref().is_none_or(|id| *id == professional.professional_id))
+        .collect();
+    if closures.iter().any(|c| c.start_time.is_none()) {
+        return Vec::new();
+    }
+
+    let weekday = date.weekday();
+    let blocked: Vec<(NaiveTime, NaiveTime)> = professional
+        .breaks
+        .iter()
+        .filter(|b| b.weekday.is_none_or(|w| w == weekday))
+        .map(|b| (b.start, b.end))
+        .chain(closures.iter().filter_map(|c| Some((c.start_time?, c.end_time?))))
+        .collect();
+
+    let mut intervals: Vec<(NaiveTime, NaiveTime)> = professional
+        .working_hours
+        .iter()
+        .filter(|p| p.weekday == weekday)
+        .map(|p| (p.start, p.end))
+        .collect();
+    for (block_start, block_end) in blocked {
+        intervals = intervals
+            .into_iter()
+            .flat_map(|(start, end)| {
+                if block_end <= start || end <= block_start {
+                    return vec![(start, end)];
+                }
+                let mut parts = Vec::new();
+                if start < block_start {
+                    parts.push((start, block_start));
+                }
+                if block_end < end {
+                    parts.push((block_end, end));
+                }
+                parts
+            })
+            .collect();
+    }
+    intervals.sort();
+    intervals
+}
+
+// Horários livres no intervalo de datas, em ordem cronológica. `existing` são
+// as consultas que ocupam a agenda; `now` evita oferecer horários que já passaram.
+pub fn find_available_slots(
+    calendar: &AvailabilityCalendar,
+    rules: &SchedulingRules,
+    existing: &[ScheduleEntry],
+    search: &SlotSearch,
+    now: NaiveDateTime,
+) -> Result<Vec<AvailableSlot>> {
+    if search.end_date < search.start_date {
+        bail!("End date must be on or after the start date");
+    }
+    if (search.end_date - search.start_date).num_days() >= MAX_RANGE_DAYS {
+        bail!("Date range is too long (max {} days)", MAX_RANGE_DAYS);
+    }
+    let duration = search.duration_minutes.unwrap_or(rules.default_duration_minutes);
+    if duration == 0 || duration > 12 * 60 {
+        bail!("Duration must be between 1 and 720 minutes");
+    }
+    let professionals: Vec<&ProfessionalSchedule> = match &search.professional_id {
+        Some(id) => {
+            let professional = calendar
+                .professionals
+                .iter()
+                .find(|p| &p.professional_id == id)
+                .ok_or_else(|| anyhow::anyhow!("Professional has no working hours configured"))?;
+            vec![professional]
+        }
+        None => calendar.professionals.iter().collect(),
+    };
+    let limit = search.limit.unwrap_or(50).clamp(1, MAX_SLOTS);
+    let step = Duration::minutes(calendar.slot_step_minutes.max(5) as i64);
+
+    let mut slots = Vec::new();
+    let mut date = search.start_date;
+    while date <= search.end_date && slots.len() < limit {
+        let mut day_slots = Vec::new();
+        for professional in &professionals {
+            for (period_start, period_end) in working_intervals(calendar, professional, date) {
+                let mut start = period_start;
+                while let Some(end) = crate::scheduling::end_of(start, duration) {
+                    if end > period_end {
+                        break;
+                    }
+                    let candidate = ScheduleEntry {
+                        id: String::new(),
+                        patient_id: String::new(), // Paciente ainda não escolhido
+                        date,
+                        start,
+                        end,
+                        professional_id: Some(professional.professional_id.clone()),
+                        room: search.room.clone(),
+                        chair: search.chair.clone(),
+                    };
+                    let free = date.and_time(start) > now
+                        && !crate::scheduling::find_conflicts(&candidate, existing, rules)
+                            .iter()
+                            .any(|c| c.blocking);
+                    if free {
+                        day_slots.push(AvailableSlot {
+                            professional_id: professional.professional_id.clone(),
+                            professional_name: professional.name.clone(),
+                            date,
+                            start_time: crate::scheduling::format_time(start),
+                            end_time: crate::scheduling::format_time(end),
+                        });
+                    }
+                    let (next, wrapped) = start.overflowing_add_signed(step);
+                    if wrapped != 0 {
+                        break;
+                    }
+                    start = next;
+                }
+            }
+        }
+        // No mesmo dia, o mais cedo primeiro, qualquer que seja o profissional
+        day_slots.sort_by(|a, b| a.start_time.cmp(&b.start_time).then(a.professional_id.cmp(&b.professional_id)));
+        slots.extend(day_slots);
+        date += Duration::days(1);
+    }
+    slots.truncate(limit);
+    Ok(slots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn holiday_on(calendar: &AvailabilityCalendar, on: NaiveDate) -> Option<Holiday> {
+        holidays_for_year(calendar, on.year()).into_iter().find(|h| h.date == on)
+    }
+
+    #[test]
+    fn easter_sundays() {
+        assert_eq!(easter(2019), date(2019, 4, 21));
+        assert_eq!(easter(2024), date(2024, 3, 31));
+        assert_eq!(easter(2025), date(2025, 4, 20));
+        assert_eq!(easter(2026), date(2026, 4, 5));
+        assert_eq!(easter(2027), date(2027, 3, 28));
+        // Extremos do intervalo possível (22/03 a 25/04)
+        assert_eq!(easter(2285), date(2285, 3, 22));
+        assert_eq!(easter(2038), date(2038, 4, 25));
+    }
+
+    #[test]
+    fn movable_holidays_follow_easter() {
+        let calendar = AvailabilityCalendar::default();
+        for (year, carnival, good_friday, corpus_christi) in [
+            (2024, date(2024, 2, 13), date(2024, 3, 29), date(2024, 5, 30)),
+            (2025, date(2025, 3, 4), date(2025, 4, 18), date(2025, 6, 19)),
+            (2026, date(2026, 2, 17), date(2026, 4, 3), date(2026, 6, 4)),
+        ] {
+            let carnival_monday = holiday_on(&calendar, carnival.pred_opt().unwrap()).unwrap();
+            assert_eq!(carnival_monday.name, "Carnaval", "{}", year);
+            assert_eq!(holiday_on(&calendar, carnival).unwrap().kind, HolidayKind::Optional);
+            assert_eq!(holiday_on(&calendar, good_friday).unwrap().name, "Sexta-feira Santa");
+            assert_eq!(holiday_on(&calendar, good_friday).unwrap().kind, HolidayKind::National);
+            assert_eq!(holiday_on(&calendar, corpus_christi).unwrap().name, "Corpus Christi");
+        }
+    }
+
+    #[test]
+    fn consciencia_negra_is_national_from_2024() {
+        let calendar = AvailabilityCalendar::default();
+        assert!(holiday_on(&calendar, date(2023, 11, 20)).is_none());
+        assert_eq!(holiday_on(&calendar, date(2024, 11, 20)).unwrap().kind, HolidayKind::National);
+        assert_eq!(holidays_for_year(&calendar, 2023).len(), 12);
+        assert_eq!(holidays_for_year(&calendar, 2025).len(), 13);
+    }
+
+    #[test]
+    fn optional_holidays_close_only_when_observed() {
+        let mut calendar = AvailabilityCalendar::default();
+        let carnival = date(2026, 2, 17);
+        assert!(closing_holiday(&calendar, carnival).is_none());
+        assert!(closing_holiday(&calendar, date(2026, 12, 25)).is_some());
+
+        calendar.observe_optional_holidays = true;
+        assert!(closing_holiday(&calendar, carnival).is_some());
+
+        calendar.observe_national_holidays = false;
+        assert!(closing_holiday(&calendar, date(2026, 12, 25)).is_none());
+    }
+
+    #[test]
+    fn municipal_holidays_by_year() {
+        let calendar = AvailabilityCalendar {
+            municipal_holidays: vec![
+                MunicipalHoliday { month: 1, day: 25, year: None, name: "Aniversário de São Paulo".to_string() },
+                MunicipalHoliday { month: 2, day: 29, year: None, name: "Dia bissexto".to_string() },
+                MunicipalHoliday { month: 7, day: 9, year: Some(2025), name: "Só em 2025".to_string() },
+            ],
+            ..Default::default()
+        };
+        for year in [2024, 2025, 2026] {
+            assert_eq!(closing_holiday(&calendar, date(year, 1, 25)).unwrap().kind, HolidayKind::Municipal);
+        }
+        assert!(closing_holiday(&calendar, date(2024, 2, 29)).is_some());
+        assert_eq!(holidays_for_year(&calendar, 2025).iter().filter(|h| h.name == "Dia bissexto").count(), 0);
+        assert!(closing_holiday(&calendar, date(2025, 7, 9)).is_some());
+        assert!(closing_holiday(&calendar, date(2026, 7, 9)).is_none());
+    }
+
+    fn time(value: &str) -> NaiveTime {
+        crate::scheduling::parse_time(value).unwrap()
+    }
+
+    // Seg a sex, 08:00-12:00 com café das 10:00 às 10:30, horários a cada 30 min
+    fn clinic(professional_ids: &[&str]) -> AvailabilityCalendar {
+        let professionals = professional_ids
+            .iter()
+            .map(|id| ProfessionalSchedule {
+                professional_id: id.to_string(),
+                name: None,
+                working_hours: [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]
+                    .into_iter()
+                    .map(|weekday| WorkingPeriod { weekday, start: time("08:00"), end: time("12:00") })
+                    .collect(),
+                breaks: vec![BreakPeriod { weekday: None, start: time("10:00"), end: time("10:30"), label: None }],
+            })
+            .collect();
+        AvailabilityCalendar { professionals, slot_step_minutes: 30, ..Default::default() }
+    }
+
+    fn search(start_date: NaiveDate, end_date: NaiveDate) -> SlotSearch {
+        SlotSearch {
+            duration_minutes: Some(30),
+            start_date,
+            end_date,
+            professional_id: None,
+            room: None,
+            chair: None,
+            limit: None,
+        }
+    }
+
+    fn booked(on: NaiveDate, start: &str, end: &str) -> ScheduleEntry {
+        ScheduleEntry {
+            id: format!("{}-{}", on, start),
+            patient_id: "p1".to_string(),
+            date: on,
+            start: time(start),
+            end: time(end),
+            professional_id: Some("dra-bruna".to_string()),
+            room: None,
+            chair: None,
+        }
+    }
+
+    fn starts(slots: &[AvailableSlot], on: NaiveDate) -> Vec<&str> {
+        slots.iter().filter(|s| s.date == on).map(|s| s.start_time.as_str()).collect()
+    }
+
+    fn long_ago() -> NaiveDateTime {
+        date(2000, 1, 1).and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn slots_skip_breaks_appointments_and_holidays() {
+        let calendar = clinic(&["dra-bruna"]);
+        let rules = SchedulingRules { buffer_minutes: 0, ..SchedulingRules::default() };
+        // Segunda 20/04, terça 21/04 (Tiradentes) e quarta 22/04 de 2026
+        let (monday, tiradentes, wednesday) = (date(2026, 4, 20), date(2026, 4, 21), date(2026, 4, 22));
+        let existing = vec![booked(monday, "08:30", "09:00"), booked(wednesday, "11:00", "12:00")];
+
+        let slots = find_available_slots(&calendar, &rules, &existing, &search(monday, wednesday), long_ago()).unwrap();
+
+        assert_eq!(starts(&slots, monday), vec!["08:00", "09:00", "09:30", "10:30", "11:00", "11:30"]);
+        assert!(starts(&slots, tiradentes).is_empty());
+        assert_eq!(starts(&slots, wednesday), vec!["08:00", "08:30", "09:00", "09:30", "10:30"]);
+        // Nenhum horário atravessa o intervalo
+        assert!(slots.iter().all(|s| s.end_time.as_str() <= "10:00" || s.start_time.as_str() >= "10:30"));
+    }
+
+    #[test]
+    fn buffer_and_closures_shrink_the_day() {
+        let mut calendar = clinic(&["dra-bruna"]);
+        let monday = date(2026, 4, 20);
+        calendar.closures.push(Closure {
+            id: "c1".to_string(),
+            professional_id: Some("dra-bruna".to_string()),
+            start_date: monday,
+            end_date: monday,
+            start_time: Some(time("11:00")),
+            end_time: Some(time("12:00")),
+            reason: Some("Curso".to_string()),
+        });
+        let existing = vec![booked(monday, "08:30", "09:00")];
+
+        // Intervalo padrão de 10 min: 08:00 e 09:00 ficam colados demais na consulta
+        let slots = find_available_slots(&calendar, &SchedulingRules::default(), &existing, &search(monday, monday), long_ago()).unwrap();
+        assert_eq!(starts(&slots, monday), vec!["09:30", "10:30"]);
+    }
+
+    #[test]
+    fn next_slot_respects_now_and_orders_professionals() {
+        let calendar = clinic(&["dr-joao", "dra-bruna"]);
+        let rules = SchedulingRules { buffer_minutes: 0, ..SchedulingRules::default() };
+        let friday = date(2026, 4, 17);
+        let monday = date(2026, 4, 20);
+        let existing = vec![booked(monday, "08:00", "12:00")];
+
+        // Sexta às 11:40: nada mais hoje, fim de semana fechado, e a dra-bruna
+        // está ocupada na segunda
+        let now = friday.and_time(time("11:40"));
+        let mut next = search(friday, date(2026, 4, 24));
+        next.limit = Some(3);
+        let slots = find_available_slots(&calendar, &rules, &existing, &next, now).unwrap();
+        assert_eq!(slots.len(), 3);
+        assert!(slots.iter().all(|s| s.date == monday && s.professional_id == "dr-joao"));
+        assert_eq!(starts(&slots, monday), vec!["08:00", "08:30", "09:00"]);
+
+        // No mesmo horário, os dois profissionais aparecem em ordem
+        let slots = find_available_slots(&calendar, &rules, &[], &search(monday, monday), now).unwrap();
+        assert_eq!(slots[0].professional_id, "dr-joao");
+        assert_eq!(slots[1].professional_id, "dra-bruna");
+        assert_eq!(slots[0].start_time, slots[1].start_time);
+    }
+}
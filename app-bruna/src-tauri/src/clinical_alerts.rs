@@ -0,0 +1,233 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+// =====================================================
+// PATIENT CLINICAL ITEMS AND ALERTS
+// =====================================================
+
+// Alergias, medicações em uso e condições crônicas do paciente. Ficam na
+// partição local com os mesmos metadados de sincronização de pacientes e
+// consultas. Os alertas derivados delas acompanham as respostas de consultas e
+// prontuários, para que uma alergia a penicilina nunca passe despercebida.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClinicalItemKind {
+    Allergy,
+    Medication,
+    ChronicCondition,
+}
+
+// Ordem importa: usada para filtrar e ordenar alertas
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Low,
+    Moderate,
+    High,
+    Critical,
+}
+
+// Quem informou o item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClinicalSource {
+    PatientReported,
+    Clinician,
+    Anamnesis,
+    ExternalReport, // Laudo ou encaminhamento de outro profissional
+    Guardian,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalItem {
+    pub id: String,
+    pub patient_id: String,
+    pub kind: ClinicalItemKind,
+    pub name: String, // Ex.: "Penicilina", "Losartana 50mg", "Hipertensão"
+    pub severity: Severity,
+    pub source: ClinicalSource,
+    pub details: Option<String>, // Reação, posologia, observações
+    pub active: bool,
+    pub recorded_by: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    // Metadados de sincronização híbrida
+    pub rev: i64,
+    pub deleted_at: Option<String>,
+    pub last_editor: Option<String>,
+    pub last_pulled_rev: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalItemRequest {
+    pub patient_id: String,
+    pub kind: ClinicalItemKind,
+    pub name: String,
+    pub severity: Severity,
+    pub source: ClinicalSource,
+    pub details: Option<String>,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientAlert {
+    pub item_id: String,
+    pub kind: ClinicalItemKind,
+    pub name: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+pub fn validate_request(request: &ClinicalItemRequest) -> Result<()> {
+    if request.name.trim().is_empty() {
+        bail!("Name is required");
+    }
+    if request.name.chars().count() > 200 {
+        bail!("Name is too long");
+    }
+    Ok(())
+}
+
+fn kind_label(kind: ClinicalItemKind) -> &'static str {
+    match kind {
+        ClinicalItemKind::Allergy => "Alergia",
+        ClinicalItemKind::Medication => "Medicação em uso",
+        ClinicalItemKind::ChronicCondition => "Condição crônica",
+    }
+}
+
+fn severity_label(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Low => "leve",
+        Severity::Moderate => "moderada",
+        Severity::High => "grave",
+        Severity::Critical => "crítica",
+    }
+}
+
+// Toda alergia ativa vira alerta, qualquer que seja a gravidade; medicações e
+// condições só a partir de grave
+fn is_alert(item: &ClinicalItem) -> bool {
+    item.active
+        && item.deleted_at.is_none()
+        && (item.kind == ClinicalItemKind::Allergy || item.severity >= Severity::High)
+}
+
+pub fn alerts_for(items: &[ClinicalItem], patient_id: &str) -> Vec<PatientAlert> {
+    let mut alerts: Vec<PatientAlert> = items
+        .iter()
+        .filter(|item| item.patient_id == patient_id && is_alert(item))
+        .map(|item| PatientAlert {
+            item_id: item.id.clone(),
+            kind: item.kind,
+            name: item.name.clone(),
+            severity: item.severity,
+            message: match &item.details {
+                Some(details) if !details.trim().is_empty() => format!(
+                    "{}: {} ({}) - {}",
+                    kind_label(item.kind),
+                    item.name,
+                    severity_label(item.severity),
+                    details.trim()
+                ),
+                _ => format!("{}: {} ({})", kind_label(item.kind), item.name, severity_label(item.severity)),
+            },
+        })
+        .collect();
+    // Mais graves primeiro; alergias antes dos demais na mesma gravidade
+    alerts.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then((b.kind == ClinicalItemKind::Allergy).cmp(&(a.kind == ClinicalItemKind::Allergy)))
+            .then(a.name.cmp(&b.name))
+    });
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, patient_id: &str, kind: ClinicalItemKind, name: &str, severity: Severity) -> ClinicalItem {
+        ClinicalItem {
+            id: id.to_string(),
+            patient_id: patient_id.to_string(),
+            kind,
+            name: name.to_string(),
+            severity,
+            source: ClinicalSource::PatientReported,
+            details: None,
+            active: true,
+            recorded_by: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+            rev: 0,
+            deleted_at: None,
+            last_editor: None,
+            last_pulled_rev: None,
+        }
+    }
+
+    fn ids(alerts: &[PatientAlert]) -> Vec<&str> {
+        alerts.iter().map(|a| a.item_id.as_str()).collect()
+    }
+
+    #[test]
+    fn every_active_allergy_is_an_alert() {
+        let mut penicillin = item("a1", "p1", ClinicalItemKind::Allergy, "Penicilina", Severity::Low);
+        penicillin.details = Some(" urticária ".to_string());
+        let alerts = alerts_for(&[penicillin], "p1");
+
+        assert_eq!(ids(&alerts), vec!["a1"]);
+        assert_eq!(alerts[0].message, "Alergia: Penicilina (leve) - urticária");
+    }
+
+    #[test]
+    fn conditions_and_medications_alert_only_when_severe() {
+        let items = vec![
+            item("c1", "p1", ClinicalItemKind::ChronicCondition, "Hipertensão", Severity::Moderate),
+            item("c2", "p1", ClinicalItemKind::ChronicCondition, "Hemofilia", Severity::Critical),
+            item("m1", "p1", ClinicalItemKind::Medication, "Losartana 50mg", Severity::Low),
+            item("m2", "p1", ClinicalItemKind::Medication, "Varfarina", Severity::High),
+        ];
+        let alerts = alerts_for(&items, "p1");
+
+        assert_eq!(ids(&alerts), vec!["c2", "m2"]);
+        assert_eq!(alerts[0].message, "Condição crônica: Hemofilia (crítica)");
+        assert_eq!(alerts[1].message, "Medicação em uso: Varfarina (grave)");
+    }
+
+    #[test]
+    fn no_alerts_from_other_patients_or_inactive_items() {
+        let mut inactive = item("a2", "p1", ClinicalItemKind::Allergy, "Látex", Severity::High);
+        inactive.active = false;
+        let mut deleted = item("a3", "p1", ClinicalItemKind::Allergy, "Dipirona", Severity::Critical);
+        deleted.deleted_at = Some("2024-02-01T00:00:00Z".to_string());
+        let items = vec![
+            item("a1", "p2", ClinicalItemKind::Allergy, "Penicilina", Severity::Critical),
+            inactive,
+            deleted,
+        ];
+
+        assert!(alerts_for(&items, "p1").is_empty());
+        assert!(alerts_for(&[], "p1").is_empty());
+        assert_eq!(ids(&alerts_for(&items, "p2")), vec!["a1"]);
+    }
+
+    #[test]
+    fn most_severe_first_and_allergies_first_on_ties() {
+        let items = vec![
+            item("m1", "p1", ClinicalItemKind::Medication, "Varfarina", Severity::High),
+            item("a1", "p1", ClinicalItemKind::Allergy, "Penicilina", Severity::Moderate),
+            item("a2", "p1", ClinicalItemKind::Allergy, "Látex", Severity::High),
+            item("a3", "p1", ClinicalItemKind::Allergy, "Dipirona", Severity::High),
+        ];
+        assert_eq!(ids(&alerts_for(&items, "p1")), vec!["a3", "a2", "m1", "a1"]);
+    }
+}
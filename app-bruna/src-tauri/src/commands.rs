@@ -200,7 +200,11 @@ pub async fn check_connectivity(_app_handle: AppHandle) -> Result<bool, String>
 // AUTHENTICATION COMMANDS
 // =====================================================
 
-#[tauri::command]
+// Mocks antigos, substituídos pelos comandos de commands_simple (que falam com
+// o Supabase). Ficam marcados como obsoletos em vez de removidos.
+
+#[deprecated(note = "use commands_simple::login")]
+#[allow(dead_code)]
 pub async fn login(
     _app_handle: AppHandle,
     request: LoginRequest,
@@ -223,25 +227,29 @@ pub async fn login(
     })
 }
 
-#[tauri::command]
+#[deprecated(note = "use commands_simple::logout")]
+#[allow(dead_code)]
 pub async fn logout(_app_handle: AppHandle) -> Result<(), String> {
     // This would clear Supabase session
     Ok(())
 }
 
-#[tauri::command]
+#[deprecated(note = "use commands_simple::get_current_user")]
+#[allow(dead_code)]
 pub async fn get_current_user(_app_handle: AppHandle) -> Result<Option<User>, String> {
     // This would get current user from Supabase
     Ok(None)
 }
 
-#[tauri::command]
+#[deprecated(note = "use commands_simple::refresh_session")]
+#[allow(dead_code)]
 pub async fn refresh_session(_app_handle: AppHandle) -> Result<LoginResponse, String> {
     // This would refresh Supabase session
     Err("Not implemented".to_string())
 }
 
-#[tauri::command]
+#[deprecated(note = "use commands_simple::check_permission")]
+#[allow(dead_code)]
 pub async fn check_permission(
     _app_handle: AppHandle,
     _action: String,
@@ -14,7 +14,6 @@ use crate::field_merge::{FieldMergeEngine, MergeResult};
 use crate::restore_window::{RestoreWindowManager, RestorableRecord};
 use crate::entity_rules::{EntityConflictResolver, ConflictResolution};
 use crate::medical_records_sync::{MedicalRecordsSync, MedicalRecord};
-use crate::storage_sync::{StorageSync, FileMetadata, StorageSyncStats};
 
 // =========================
 // Comandos de Sincronização Híbrida
@@ -314,70 +313,3 @@ pub async fn get_medical_record_versions(
         Err(e) => Err(format!("Erro ao buscar versões do prontuário: {}", e))
     }
 }
-
-// =========================
-// Comandos de Storage
-// =========================
-
-#[tauri::command]
-pub async fn add_file_to_sync(
-    file_path: String,
-    remote_path: Option<String>,
-    uploaded_by: Option<String>
-) -> Result<String, String> {
-    let mut sync = StorageSync::with_default_config();
-    match sync.add_file(&file_path, remote_path, uploaded_by).await {
-        Ok(file_id) => Ok(file_id),
-        Err(e) => Err(format!("Erro ao adicionar arquivo: {}", e))
-    }
-}
-
-#[tauri::command]
-pub async fn sync_all_files() -> Result<StorageSyncStats, String> {
-    let mut sync = StorageSync::with_default_config();
-    match sync.sync_all_files().await {
-        Ok(stats) => Ok(stats),
-        Err(e) => Err(format!("Erro na sincronização de arquivos: {}", e))
-    }
-}
-
-#[tauri::command]
-pub async fn get_file_sync_status() -> Result<StorageSyncStats, String> {
-    let sync = StorageSync::with_default_config();
-    Ok(sync.get_stats().clone())
-}
-
-#[tauri::command]
-pub async fn get_files_by_status(status: String) -> Result<Vec<FileMetadata>, String> {
-    let sync = StorageSync::with_default_config();
-    let status_enum = match status.as_str() {
-        "pending" => crate::storage_sync::FileSyncStatus::Pending,
-        "syncing" => crate::storage_sync::FileSyncStatus::Syncing,
-        "synced" => crate::storage_sync::FileSyncStatus::Synced,
-        "failed" => crate::storage_sync::FileSyncStatus::Failed,
-        "conflict" => crate::storage_sync::FileSyncStatus::Conflict,
-        "local_only" => crate::storage_sync::FileSyncStatus::LocalOnly,
-        "remote_only" => crate::storage_sync::FileSyncStatus::RemoteOnly,
-        _ => return Err("Status inválido".to_string()),
-    };
-    
-    Ok(sync.get_files_by_status(status_enum).into_iter().cloned().collect())
-}
-
-#[tauri::command]
-pub async fn download_file(file_id: String) -> Result<bool, String> {
-    let mut sync = StorageSync::with_default_config();
-    match sync.download_file(&file_id).await {
-        Ok(_) => Ok(true),
-        Err(e) => Err(format!("Erro ao baixar arquivo: {}", e))
-    }
-}
-
-#[tauri::command]
-pub async fn remove_file_from_sync(file_id: String) -> Result<bool, String> {
-    let mut sync = StorageSync::with_default_config();
-    match sync.remove_file(&file_id).await {
-        Ok(_) => Ok(true),
-        Err(e) => Err(format!("Erro ao remover arquivo: {}", e))
-    }
-}
@@ -1,12 +1,22 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use tauri::AppHandle;
-use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+use std::collections::{HashMap, HashSet};
 use std::sync::{LazyLock, Mutex, Arc};
 use base64::Engine;
 use chrono::Utc;
 
-use crate::dpapi::SecureSession;
+use crate::appointment_status::{AppointmentStatistics, AppointmentStatus, StatusChange};
+use crate::clinical_alerts::{ClinicalItem, ClinicalItemKind, ClinicalItemRequest, PatientAlert};
+use crate::recurrence::{AppointmentSeries, CreateSeriesRequest, EditScope, SeriesChanges};
+use crate::scheduling::{ResourceKind, ScheduleEntry, SchedulingRules};
+use crate::waitlist::{FreedSlot, SlotOffer, WaitlistEntry, WaitlistRequest};
+use crate::patients::{Address, CreatePatientRequest, EmergencyContact, FieldError, Guardian, Insurance, PatientError};
+use crate::medical_records_sync::{
+    FieldChange, FieldHistoryEntry, MedicalRecord, MedicalRecordAddendum, MedicalRecordFields,
+    KeyEndorsement, MedicalRecordSignature, MedicalRecordSyncReport, MedicalRecordVerification,
+    MedicalRecordView, SaveMedicalRecordRequest, SignerKeyView,
+};
 
 // =========================
 // Modelos
@@ -67,10 +77,22 @@ pub struct Patient {
     pub id: String,
     pub name: String,
     pub email: Option<String>,
-    pub phone: Option<String>,
-    pub birth_date: Option<String>,
-    pub address: Option<String>,
+    pub phone: Option<String>, // E.164
+    #[serde(default, deserialize_with = "crate::patients::lenient_date")]
+    pub birth_date: Option<chrono::NaiveDate>,
+    #[serde(default, deserialize_with = "crate::patients::lenient_address")]
+    pub address: Option<Address>,
     pub notes: Option<String>,
+    #[serde(default)]
+    pub cpf: Option<String>, // Só dígitos
+    #[serde(default)]
+    pub rg: Option<String>,
+    #[serde(default)]
+    pub guardians: Vec<Guardian>,
+    #[serde(default)]
+    pub emergency_contacts: Vec<EmergencyContact>,
+    #[serde(default)]
+    pub insurance: Option<Insurance>,
     pub created_at: String,
     pub updated_at: String,
     // Metadados de sincronização híbrida
@@ -80,16 +102,6 @@ pub struct Patient {
     pub last_pulled_rev: Option<i64>, // Última revisão puxada do servidor
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreatePatientRequest {
-    pub name: String,
-    pub email: Option<String>,
-    pub phone: Option<String>,
-    pub birth_date: Option<String>,
-    pub address: Option<String>,
-    pub notes: Option<String>,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Appointment {
     pub id: String,
@@ -97,7 +109,26 @@ pub struct Appointment {
     pub patient_name: Option<String>,
     pub date: String,
     pub time: String,
-    pub status: String,
+    #[serde(default = "crate::scheduling::default_duration_minutes")]
+    pub duration_minutes: u32,
+    #[serde(default)]
+    pub end_time: Option<String>, // Derivado de time + duration_minutes
+    #[serde(default)]
+    pub professional_id: Option<String>,
+    #[serde(default)]
+    pub room: Option<String>,
+    #[serde(default)]
+    pub chair: Option<String>,
+    #[serde(default)]
+    pub series_id: Option<String>, // Série recorrente que gerou a consulta
+    #[serde(default)]
+    pub occurrence_date: Option<String>, // Data original na série (RECURRENCE-ID)
+    #[serde(default)]
+    pub series_exception: bool, // Editada sozinha: edições da série não a alteram
+    #[serde(deserialize_with = "crate::appointment_status::lenient_status")]
+    pub status: AppointmentStatus,
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>, // Mudanças de status, com data, autor e motivo
     pub notes: Option<String>,
     pub created_at: String,
     pub updated_at: String,
@@ -113,10 +144,74 @@ pub struct CreateAppointmentRequest {
     pub patient_id: String,
     pub date: String,
     pub time: String,
-    pub status: String,
+    #[serde(default)]
+    pub duration_minutes: Option<u32>, // Sem duração nem término: padrão da clínica
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub professional_id: Option<String>,
+    #[serde(default)]
+    pub room: Option<String>,
+    #[serde(default)]
+    pub chair: Option<String>,
+    #[serde(default)]
+    pub status: Option<AppointmentStatus>, // Na criação, sem status: scheduled; na edição, mantém
     pub notes: Option<String>,
 }
 
+// Consulta que disputa o mesmo horário e recurso
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppointmentConflict {
+    pub appointment: Appointment,
+    pub resources: Vec<ResourceKind>,
+    pub blocking: bool,
+}
+
+// Consulta gravada mais os conflitos que a clínica só quer sinalizar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledAppointment {
+    #[serde(flatten)]
+    pub appointment: Appointment,
+    pub conflicts: Vec<AppointmentConflict>,
+}
+
+// Erro de marcação: mensagem geral mais as consultas que impedem o horário,
+// para a recepção escolher outro
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulingError {
+    pub message: String,
+    pub conflicts: Vec<AppointmentConflict>,
+}
+
+impl From<String> for SchedulingError {
+    fn from(message: String) -> Self {
+        SchedulingError { message, conflicts: Vec::new() }
+    }
+}
+
+impl From<&str> for SchedulingError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+// Resposta de consultas e prontuários com os alertas clínicos do paciente
+// (alergias etc.). Os alertas são calculados na hora e nunca gravados junto.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithPatientAlerts<T> {
+    #[serde(flatten)]
+    pub data: T,
+    pub patient_alerts: Vec<PatientAlert>,
+}
+
+fn patient_alerts(patient_id: &str) -> Vec<PatientAlert> {
+    crate::clinical_alerts::alerts_for(&CLINICAL_ITEMS.lock().unwrap(), patient_id)
+}
+
+fn with_patient_alerts<T>(patient_id: &str, data: T) -> WithPatientAlerts<T> {
+    WithPatientAlerts { patient_alerts: patient_alerts(patient_id), data }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Document {
     pub id: String,
@@ -133,6 +228,48 @@ pub struct Document {
     pub deleted_at: Option<String>, // Soft delete timestamp
     pub last_editor: Option<String>, // Device/user que fez a última edição
     pub last_pulled_rev: Option<i64>, // Última revisão puxada do servidor
+    // Metadados de criptografia (DEK cifrada, IV, salt e SHA-256 do original)
+    #[serde(default)]
+    pub encryption: Option<crate::document_crypto::DocumentEncryption>,
+    // Versionamento: conteúdo atual e histórico de versões
+    #[serde(default)]
+    pub content_id: Option<String>, // Conteúdo da versão atual (None = id do documento)
+    #[serde(default)]
+    pub current_version: u32,
+    #[serde(default)]
+    pub versions: Vec<DocumentVersion>,
+}
+
+// Uma versão do conteúdo (ex.: laudo corrigido). Cada versão tem seu próprio
+// conteúdo cifrado, identificado por content_id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentVersion {
+    pub version: u32,
+    pub content_id: String,
+    pub content_hash: String, // SHA-256 do conteúdo original
+    pub file_size: Option<i64>,
+    pub filename: String,
+    pub file_type: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub uploaded_at: String,
+    pub note: Option<String>,
+    pub rolled_back_from: Option<u32>, // Versão restaurada, quando for um rollback
+    pub encryption: crate::document_crypto::DocumentEncryption,
+    #[serde(default)]
+    pub preview: Option<crate::document_preview::DocumentPreview>, // Miniatura cifrada
+    #[serde(default)]
+    pub deduplicated: bool, // O conteúdo já existia e o blob foi reaproveitado
+}
+
+// Conteúdo cifrado endereçado por HMAC do SHA-256 do original. Várias versões
+// (de documentos diferentes) podem apontar para o mesmo blob.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentBlob {
+    pub blob_id: String,
+    pub content_hash: String,
+    pub file_size: Option<i64>,
+    pub ref_count: u32, // Versões que o referenciam, inclusive de documentos na lixeira
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -140,292 +277,3258 @@ pub struct CreateDocumentRequest {
     pub patient_id: String,
     pub appointment_id: Option<String>,
     pub filename: String,
-    pub file_type: Option<String>,
-    pub file_size: Option<i64>,
+    pub file_type: Option<String>, // Conferido contra o tipo detectado no conteúdo
     pub content: String, // Base64
+    #[serde(default)]
+    pub document_id: Option<String>, // Nova versão de um documento existente
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+// =========================
+// Armazenamento em memória
+// =========================
+
+pub static PATIENTS: LazyLock<Mutex<Vec<Patient>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static APPOINTMENTS: LazyLock<Mutex<Vec<Appointment>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static DOCUMENTS: LazyLock<Mutex<Vec<Document>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static DOCUMENT_CONTENT: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+pub static DOCUMENT_BLOBS: LazyLock<Mutex<HashMap<String, DocumentBlob>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+pub static CLINICAL_ITEMS: LazyLock<Mutex<Vec<ClinicalItem>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static APPOINTMENT_SERIES: LazyLock<Mutex<Vec<AppointmentSeries>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static WAITLIST: LazyLock<Mutex<Vec<WaitlistEntry>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+pub static WAITLIST_OFFERS: LazyLock<Mutex<Vec<SlotOffer>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+// =========================
+// Serviços (Auth/Sessão)
+// =========================
+
+pub static AUTH_SERVICE: LazyLock<Mutex<Option<Arc<crate::auth::AuthService>>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+// Sessão do usuário logado (os módulos dpapi/crypto nunca fizeram parte do crate;
+// por enquanto ela fica só em memória)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecureSession {
+    pub user_id: String,
+    pub email: String,
+    pub role: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: String,
+    pub created_at: String,
+}
+
+pub static CURRENT_SESSION: LazyLock<Mutex<Option<SecureSession>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+pub static AUDIT_LOGS: LazyLock<Mutex<Vec<crate::auth::AuditLog>>> =
+    LazyLock::new(|| Mutex::new(Vec::new()));
+
+pub use crate::totp::TOTP_SERVICE;
+
+// Logins que passaram pela senha e aguardam o segundo fator
+pub struct PendingMfaLogin {
+    pub response: crate::auth::LoginResponse,
+    pub created_at: chrono::DateTime<Utc>,
+    pub attempts: u32,
+}
+
+pub static PENDING_MFA_LOGINS: LazyLock<Mutex<HashMap<String, PendingMfaLogin>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+const MFA_CHALLENGE_TTL_SECONDS: i64 = 300;
+const MFA_MAX_ATTEMPTS: u32 = 5;
+
+// =========================
+// Comandos básicos
+// =========================
+
+#[tauri::command]
+pub async fn greet(name: &str) -> Result<String, String> {
+    Ok(format!("Hello, {}! You've been greeted from Rust!", name))
+}
+
+// =========================
+// Pacientes
+// =========================
+
+#[tauri::command]
+pub async fn get_patients(_app_handle: AppHandle) -> Result<Vec<Patient>, String> {
+    Ok(PATIENTS.lock().unwrap().clone())
+}
+
+// Validação do cadastro mais a unicidade do CPF entre os pacientes ativos.
+// Chamada com a lista travada, para dois cadastros simultâneos não passarem.
+fn validate_patient_request(
+    patients: &[Patient],
+    request: CreatePatientRequest,
+    patient_id: Option<&str>,
+) -> Result<crate::patients::ValidPatient, Vec<FieldError>> {
+    let duplicate_cpf = request
+        .cpf
+        .as_deref()
+        .and_then(|cpf| crate::patients::normalize_cpf(cpf).ok())
+        .is_some_and(|cpf| {
+            patients.iter().any(|p| {
+                p.deleted_at.is_none() && Some(p.id.as_str()) != patient_id && p.cpf.as_deref() == Some(cpf.as_str())
+            })
+        });
+    let duplicate_error = || FieldError::new("cpf", "duplicate", "Another patient already has this CPF");
+
+    match crate::patients::validate_request(request) {
+        Ok(_) if duplicate_cpf => Err(vec![duplicate_error()]),
+        Ok(valid) => Ok(valid),
+        Err(mut errors) => {
+            if duplicate_cpf {
+                errors.push(duplicate_error());
+            }
+            Err(errors)
+        }
+    }
+}
+
+// Validação campo a campo para o formulário, sem gravar nada
+#[tauri::command]
+pub async fn validate_patient(
+    _app_handle: AppHandle,
+    request: CreatePatientRequest,
+    patient_id: Option<String>,
+) -> Result<Vec<FieldError>, String> {
+    let patients = PATIENTS.lock().unwrap();
+    Ok(validate_patient_request(&patients, request, patient_id.as_deref()).err().unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn create_patient(
+    _app_handle: AppHandle,
+    request: CreatePatientRequest,
+) -> Result<Patient, PatientError> {
+    let mut patients = PATIENTS.lock().unwrap();
+    let valid = validate_patient_request(&patients, request, None)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let patient = Patient {
+        id: id.clone(),
+        name: valid.name,
+        email: valid.email,
+        phone: valid.phone,
+        birth_date: valid.birth_date,
+        address: valid.address,
+        notes: valid.notes,
+        cpf: valid.cpf,
+        rg: valid.rg,
+        guardians: valid.guardians,
+        emergency_contacts: valid.emergency_contacts,
+        insurance: valid.insurance,
+        created_at: now.clone(),
+        updated_at: now.clone(),
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+    };
+
+    patients.push(patient.clone());
+    Ok(patient)
+}
+
+#[tauri::command]
+pub async fn update_patient(
+    _app_handle: AppHandle,
+    id: String,
+    request: CreatePatientRequest,
+) -> Result<Patient, PatientError> {
+    let mut guard = PATIENTS.lock().unwrap();
+    if !guard.iter().any(|p| p.id == id) {
+        return Err("Patient not found".into());
+    }
+    let valid = validate_patient_request(&guard, request, Some(&id))?;
+    let p = guard.iter_mut().find(|p| p.id == id).ok_or("Patient not found")?;
+    p.name = valid.name;
+    p.email = valid.email;
+    p.phone = valid.phone;
+    p.birth_date = valid.birth_date;
+    p.address = valid.address;
+    p.notes = valid.notes;
+    p.cpf = valid.cpf;
+    p.rg = valid.rg;
+    p.guardians = valid.guardians;
+    p.emergency_contacts = valid.emergency_contacts;
+    p.insurance = valid.insurance;
+    p.updated_at = chrono::Utc::now().to_rfc3339();
+    Ok(p.clone())
+}
+
+#[tauri::command]
+pub async fn delete_patient(_app_handle: AppHandle, id: String) -> Result<(), String> {
+    PATIENTS.lock().unwrap().retain(|p| p.id != id);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn search_patients(_app_handle: AppHandle, query: String) -> Result<Vec<Patient>, String> {
+    let q = query.to_lowercase();
+    // Telefone e CPF são comparados só pelos dígitos ("(11) 98765-4321", "123.456.789-09")
+    let q_digits: String = query.chars().filter(|c| c.is_ascii_digit()).collect();
+    let out = PATIENTS.lock().unwrap()
+        .iter()
+        .filter(|p| {
+            p.name.to_lowercase().contains(&q) ||
+            p.email.as_ref().is_some_and(|e| e.to_lowercase().contains(&q)) ||
+            (!q_digits.is_empty() && p.phone.as_ref().is_some_and(|ph| ph.contains(&q_digits))) ||
+            (!q_digits.is_empty() && p.cpf.as_ref().is_some_and(|cpf| cpf.contains(&q_digits)))
+        })
+        .cloned()
+        .collect();
+    Ok(out)
+}
+
+// =========================
+// Alergias, medicações e condições crônicas
+// =========================
+
+#[tauri::command]
+pub async fn get_patient_clinical_items(
+    _app_handle: AppHandle,
+    patient_id: String,
+    kind: Option<ClinicalItemKind>,
+) -> Result<Vec<ClinicalItem>, String> {
+    let out = CLINICAL_ITEMS.lock().unwrap()
+        .iter()
+        .filter(|i| i.patient_id == patient_id && i.deleted_at.is_none())
+        .filter(|i| kind.is_none_or(|k| i.kind == k))
+        .cloned()
+        .collect();
+    Ok(out)
+}
+
+#[tauri::command]
+pub async fn add_clinical_item(
+    _app_handle: AppHandle,
+    request: ClinicalItemRequest,
+) -> Result<ClinicalItem, String> {
+    crate::clinical_alerts::validate_request(&request).map_err(|e| e.to_string())?;
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+    let (user_id, _) = current_session_user()?;
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let item = ClinicalItem {
+        id: uuid::Uuid::new_v4().to_string(),
+        patient_id: request.patient_id,
+        kind: request.kind,
+        name: request.name.trim().to_string(),
+        severity: request.severity,
+        source: request.source,
+        details: request.details,
+        active: request.active,
+        recorded_by: Some(user_id),
+        created_at: now.clone(),
+        updated_at: now,
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+    };
+    CLINICAL_ITEMS.lock().unwrap().push(item.clone());
+
+    audit_current_user(
+        "CLINICAL_ITEM_ADDED",
+        "CLINICAL_ITEM",
+        Some(item.id.clone()),
+        format!("{:?} for patient {} ({:?})", item.kind, item.patient_id, item.severity),
+    ).await?;
+    Ok(item)
+}
+
+#[tauri::command]
+pub async fn update_clinical_item(
+    _app_handle: AppHandle,
+    id: String,
+    request: ClinicalItemRequest,
+) -> Result<ClinicalItem, String> {
+    crate::clinical_alerts::validate_request(&request).map_err(|e| e.to_string())?;
+    let updated = {
+        let mut guard = CLINICAL_ITEMS.lock().unwrap();
+        let item = guard
+            .iter_mut()
+            .find(|i| i.id == id && i.deleted_at.is_none())
+            .ok_or("Clinical item not found")?;
+        if item.patient_id != request.patient_id {
+            return Err("Clinical item belongs to another patient".into());
+        }
+        item.kind = request.kind;
+        item.name = request.name.trim().to_string();
+        item.severity = request.severity;
+        item.source = request.source;
+        item.details = request.details;
+        item.active = request.active;
+        item.updated_at = chrono::Utc::now().to_rfc3339();
+        item.last_editor = Some("local_device".to_string());
+        item.clone()
+    };
+
+    audit_current_user(
+        "CLINICAL_ITEM_UPDATED",
+        "CLINICAL_ITEM",
+        Some(id),
+        format!("{:?} for patient {} ({:?}, active: {})", updated.kind, updated.patient_id, updated.severity, updated.active),
+    ).await?;
+    Ok(updated)
+}
+
+// Soft delete, para a remoção chegar aos outros dispositivos na sincronização
+#[tauri::command]
+pub async fn delete_clinical_item(_app_handle: AppHandle, id: String) -> Result<(), String> {
+    let patient_id = {
+        let mut guard = CLINICAL_ITEMS.lock().unwrap();
+        let item = guard
+            .iter_mut()
+            .find(|i| i.id == id && i.deleted_at.is_none())
+            .ok_or("Clinical item not found")?;
+        let now = chrono::Utc::now().to_rfc3339();
+        item.deleted_at = Some(now.clone());
+        item.updated_at = now;
+        item.last_editor = Some("local_device".to_string());
+        item.patient_id.clone()
+    };
+
+    audit_current_user(
+        "CLINICAL_ITEM_DELETED",
+        "CLINICAL_ITEM",
+        Some(id),
+        format!("Removed from patient {}", patient_id),
+    ).await
+}
+
+// Alertas críticos: toda alergia ativa e medicações/condições graves ou críticas
+#[tauri::command]
+pub async fn get_patient_alerts(_app_handle: AppHandle, patient_id: String) -> Result<Vec<PatientAlert>, String> {
+    Ok(patient_alerts(&patient_id))
+}
+
+// =========================
+// Consultas
+// =========================
+
+#[tauri::command]
+pub async fn get_appointments(_app_handle: AppHandle) -> Result<Vec<WithPatientAlerts<Appointment>>, String> {
+    let mut list: Vec<Appointment> = APPOINTMENTS.lock().unwrap().iter().cloned().collect();
+    {
+        let patients = PATIENTS.lock().unwrap();
+        for a in &mut list {
+            a.patient_name = patients.iter().find(|p| p.id == a.patient_id).map(|p| p.name.clone());
+            if a.end_time.is_none() {
+                a.end_time = crate::scheduling::parse_time(&a.time)
+                    .ok()
+                    .and_then(|start| crate::scheduling::end_of(start, a.duration_minutes))
+                    .map(crate::scheduling::format_time);
+            }
+        }
+    }
+    list.sort_by(|a, b| format!("{} {}", a.date, a.time).cmp(&format!("{} {}", b.date, b.time)));
+    Ok(list.into_iter().map(|a| with_patient_alerts(&a.patient_id.clone(), a)).collect())
+}
+
+// Consultas antigas com data ou horário ilegíveis ficam fora da checagem
+fn schedule_entry(a: &Appointment) -> Option<ScheduleEntry> {
+    if a.deleted_at.is_some() || !a.status.occupies_schedule() {
+        return None;
+    }
+    let date = crate::scheduling::parse_date(&a.date).ok()?;
+    let start = crate::scheduling::parse_time(&a.time).ok()?;
+    Some(ScheduleEntry {
+        id: a.id.clone(),
+        patient_id: a.patient_id.clone(),
+        date,
+        start,
+        end: crate::scheduling::end_of(start, a.duration_minutes)?,
+        professional_id: a.professional_id.clone(),
+        room: a.room.clone(),
+        chair: a.chair.clone(),
+    })
+}
+
+// Consulta vazia, preenchida em seguida por apply_appointment_request
+fn new_appointment(id: String) -> Appointment {
+    let now = chrono::Utc::now().to_rfc3339();
+    Appointment {
+        id,
+        patient_id: String::new(),
+        patient_name: None,
+        date: String::new(),
+        time: String::new(),
+        duration_minutes: crate::scheduling::default_duration_minutes(),
+        end_time: None,
+        professional_id: None,
+        room: None,
+        chair: None,
+        series_id: None,
+        occurrence_date: None,
+        series_exception: false,
+        status: AppointmentStatus::default(),
+        status_history: Vec::new(),
+        notes: None,
+        created_at: now.clone(),
+        updated_at: now,
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+    }
+}
+
+// Aplica o pedido sobre a consulta (nova ou existente), validando data, horário
+// e duração
+fn apply_appointment_request(
+    appointment: &mut Appointment,
+    request: CreateAppointmentRequest,
+    rules: &SchedulingRules,
+) -> Result<(), String> {
+    let date = crate::scheduling::parse_date(&request.date).map_err(|e| e.to_string())?;
+    let start = crate::scheduling::parse_time(&request.time).map_err(|e| e.to_string())?;
+    let duration = crate::scheduling::resolve_duration(start, request.duration_minutes, request.end_time.as_deref(), rules)
+        .map_err(|e| e.to_string())?;
+    let non_empty = |v: Option<String>| v.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+    appointment.patient_id = request.patient_id;
+    appointment.date = date.format("%Y-%m-%d").to_string();
+    appointment.time = crate::scheduling::format_time(start);
+    appointment.duration_minutes = duration;
+    appointment.end_time = crate::scheduling::end_of(start, duration).map(crate::scheduling::format_time);
+    appointment.professional_id = non_empty(request.professional_id);
+    appointment.room = non_empty(request.room);
+    appointment.chair = non_empty(request.chair);
+    appointment.notes = request.notes;
+    Ok(())
+}
+
+fn status_actor() -> Option<(String, String)> {
+    current_session_user().ok()
+}
+
+// Status de criação (scheduled ou confirmed), já no histórico
+fn start_status(appointment: &mut Appointment, status: Option<AppointmentStatus>) -> Result<(), String> {
+    let status = status.unwrap_or_default();
+    let change = crate::appointment_status::initial(status, None, status_actor()).map_err(|e| e.to_string())?;
+    appointment.status = status;
+    appointment.status_history = vec![change];
+    Ok(())
+}
+
+// Muda o status pelas transições permitidas, registrando data, autor e motivo
+fn change_status(appointment: &mut Appointment, next: AppointmentStatus, reason: Option<String>) -> Result<(), String> {
+    let change = crate::appointment_status::transition(appointment.status, next, reason, status_actor())
+        .map_err(|e| e.to_string())?;
+    appointment.status = next;
+    appointment.status_history.push(change);
+    Ok(())
+}
+
+fn appointment_conflicts(appointments: &[Appointment], candidate: &Appointment, rules: &SchedulingRules) -> Vec<AppointmentConflict> {
+    let Some(entry) = schedule_entry(candidate) else {
+        return Vec::new();
+    };
+    let existing: Vec<ScheduleEntry> = appointments.iter().filter_map(schedule_entry).collect();
+    crate::scheduling::find_conflicts(&entry, &existing, rules)
+        .into_iter()
+        .filter_map(|c| {
+            appointments.iter().find(|a| a.id == c.appointment_id).map(|a| AppointmentConflict {
+                appointment: a.clone(),
+                resources: c.resources,
+                blocking: c.blocking,
+            })
+        })
+        .collect()
+}
+
+fn fill_conflict_patient_names(conflicts: &mut [AppointmentConflict]) {
+    let patients = PATIENTS.lock().unwrap();
+    for c in conflicts {
+        c.appointment.patient_name = patients.iter().find(|p| p.id == c.appointment.patient_id).map(|p| p.name.clone());
+    }
+}
+
+// Confere conflitos e grava sob o mesmo lock, para duas marcações simultâneas
+// não ficarem com o mesmo horário
+fn store_appointment(appointment: Appointment, rules: &SchedulingRules) -> Result<ScheduledAppointment, SchedulingError> {
+    let conflicts = {
+        let mut guard = APPOINTMENTS.lock().unwrap();
+        let conflicts = appointment_conflicts(&guard, &appointment, rules);
+        if !conflicts.iter().any(|c| c.blocking) {
+            match guard.iter_mut().find(|a| a.id == appointment.id) {
+                Some(existing) => *existing = appointment.clone(),
+                None => guard.push(appointment.clone()),
+            }
+        }
+        conflicts
+    };
+    scheduling_result(appointment, conflicts)
+}
+
+fn scheduling_result(appointment: Appointment, mut conflicts: Vec<AppointmentConflict>) -> Result<ScheduledAppointment, SchedulingError> {
+    fill_conflict_patient_names(&mut conflicts);
+    if conflicts.iter().any(|c| c.blocking) {
+        return Err(SchedulingError {
+            message: "The selected time conflicts with other appointments".to_string(),
+            conflicts,
+        });
+    }
+    Ok(ScheduledAppointment { appointment, conflicts })
 }
 
-// =========================
-// Armazenamento em memória
-// =========================
+// Lê, edita, confere conflitos e grava a consulta sob o mesmo lock: uma mudança
+// de status feita no meio não é sobrescrita por uma cópia antiga
+fn edit_stored_appointment(
+    appointments: &mut [Appointment],
+    id: &str,
+    rules: &SchedulingRules,
+    edit: impl FnOnce(&mut Appointment) -> Result<(), String>,
+) -> Result<(Appointment, Vec<AppointmentConflict>), SchedulingError> {
+    let index = appointments.iter().position(|a| a.id == id).ok_or("Appointment not found")?;
+    let mut appointment = appointments[index].clone();
+    edit(&mut appointment)?;
+
+    let conflicts = appointment_conflicts(appointments, &appointment, rules);
+    if !conflicts.iter().any(|c| c.blocking) {
+        appointments[index] = appointment.clone();
+    }
+    Ok((appointment, conflicts))
+}
+
+#[tauri::command]
+pub async fn create_appointment(
+    _app_handle: AppHandle,
+    request: CreateAppointmentRequest,
+) -> Result<WithPatientAlerts<ScheduledAppointment>, SchedulingError> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+
+    let rules = crate::scheduling::load_rules();
+    let mut appointment = new_appointment(uuid::Uuid::new_v4().to_string());
+    start_status(&mut appointment, request.status)?;
+    apply_appointment_request(&mut appointment, request, &rules)?;
+
+    let scheduled = store_appointment(appointment, &rules)?;
+    discard_committed_draft("appointment", None);
+    Ok(with_patient_alerts(&scheduled.appointment.patient_id.clone(), scheduled))
+}
+
+#[tauri::command]
+pub async fn update_appointment(
+    _app_handle: AppHandle,
+    id: String,
+    request: CreateAppointmentRequest,
+) -> Result<WithPatientAlerts<ScheduledAppointment>, SchedulingError> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+
+    let rules = crate::scheduling::load_rules();
+    let (appointment, conflicts) = edit_stored_appointment(&mut APPOINTMENTS.lock().unwrap(), &id, &rules, |appointment| {
+        if let Some(status) = request.status.filter(|s| *s != appointment.status) {
+            change_status(appointment, status, None)?;
+        }
+        apply_appointment_request(appointment, request, &rules)?;
+        appointment.series_exception |= appointment.series_id.is_some();
+        appointment.updated_at = chrono::Utc::now().to_rfc3339();
+        Ok(())
+    })?;
+    let scheduled = scheduling_result(appointment, conflicts)?;
+    discard_committed_draft("appointment", Some(&id));
+    Ok(with_patient_alerts(&scheduled.appointment.patient_id.clone(), scheduled))
+}
+
+// Checagem antes de salvar: a tela mostra os conflitos enquanto a recepção
+// escolhe o horário
+#[tauri::command]
+pub async fn check_appointment_conflicts(
+    _app_handle: AppHandle,
+    request: CreateAppointmentRequest,
+    appointment_id: Option<String>,
+) -> Result<Vec<AppointmentConflict>, String> {
+    let rules = crate::scheduling::load_rules();
+    let mut candidate = match &appointment_id {
+        Some(id) => APPOINTMENTS.lock().unwrap()
+            .iter()
+            .find(|a| &a.id == id)
+            .cloned()
+            .ok_or("Appointment not found")?,
+        None => new_appointment(String::new()),
+    };
+    apply_appointment_request(&mut candidate, request, &rules)?;
+
+    let mut conflicts = appointment_conflicts(&APPOINTMENTS.lock().unwrap(), &candidate, &rules);
+    fill_conflict_patient_names(&mut conflicts);
+    Ok(conflicts)
+}
+
+#[tauri::command]
+pub async fn get_scheduling_rules(_app_handle: AppHandle) -> Result<SchedulingRules, String> {
+    Ok(crate::scheduling::load_rules())
+}
+
+// Duração padrão, intervalo entre consultas e o que fazer com cada tipo de conflito
+#[tauri::command]
+pub async fn set_scheduling_rules(_app_handle: AppHandle, rules: SchedulingRules) -> Result<SchedulingRules, String> {
+    ensure_admin()?;
+    crate::scheduling::save_rules(&rules).map_err(|e| format!("Failed to save scheduling rules: {}", e))?;
+
+    audit_current_user(
+        "SCHEDULING_RULES_UPDATED",
+        "APPOINTMENT",
+        None,
+        format!(
+            "default_duration={}min, buffer={}min, professional={:?}, room={:?}, chair={:?}, patient={:?}",
+            rules.default_duration_minutes, rules.buffer_minutes, rules.professional, rules.room, rules.chair, rules.patient
+        ),
+    ).await?;
+    Ok(rules)
+}
+
+#[tauri::command]
+pub async fn delete_appointment(app_handle: AppHandle, id: String) -> Result<(), String> {
+    let removed = {
+        let mut guard = APPOINTMENTS.lock().unwrap();
+        let removed = guard.iter().find(|a| a.id == id).cloned();
+        guard.retain(|a| a.id != id);
+        removed
+    };
+    let Some(removed) = removed else {
+        return Ok(());
+    };
+    // Ocorrência de série: a data vira exceção, para a série não a gerar de novo
+    if let (Some(series_id), Some(date)) = (&removed.series_id, &removed.occurrence_date) {
+        exclude_series_date(series_id, date);
+    }
+    // Excluir uma consulta que ocupava a agenda libera o horário, como cancelar
+    if removed.status.occupies_schedule() {
+        offer_freed_slot(&app_handle, &removed);
+    }
+    Ok(())
+}
+
+// =========================
+// Status das consultas
+// =========================
+
+// Nova data da remarcação; profissional, sala, cadeira e duração vazios mantêm
+// os da consulta original
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RescheduleRequest {
+    pub date: String,
+    pub time: String,
+    #[serde(default)]
+    pub duration_minutes: Option<u32>,
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub professional_id: Option<String>,
+    #[serde(default)]
+    pub room: Option<String>,
+    #[serde(default)]
+    pub chair: Option<String>,
+    pub reason: String,
+}
+
+// Confirmação, check-in, início e fim do atendimento, falta e cancelamento.
+// Remarcação tem comando próprio, porque cria a consulta nova.
+#[tauri::command]
+pub async fn change_appointment_status(
+    app_handle: AppHandle,
+    id: String,
+    status: AppointmentStatus,
+    reason: Option<String>,
+) -> Result<WithPatientAlerts<Appointment>, String> {
+    if status == AppointmentStatus::Rescheduled {
+        return Err("Use reschedule_appointment to reschedule an appointment".to_string());
+    }
+    let (appointment, previous) = {
+        let mut guard = APPOINTMENTS.lock().unwrap();
+        let appointment = guard.iter_mut().find(|a| a.id == id).ok_or("Appointment not found")?;
+        let previous = appointment.status;
+        change_status(appointment, status, reason.clone())?;
+        appointment.updated_at = chrono::Utc::now().to_rfc3339();
+        (appointment.clone(), previous)
+    };
+    if status.is_cancellation() {
+        offer_freed_slot(&app_handle, &appointment);
+    }
+
+    audit_current_user(
+        "APPOINTMENT_STATUS_CHANGED",
+        "APPOINTMENT",
+        Some(id),
+        match reason.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+            Some(reason) => format!("{} -> {}: {}", previous, status, reason),
+            None => format!("{} -> {}", previous, status),
+        },
+    ).await?;
+    Ok(with_patient_alerts(&appointment.patient_id.clone(), appointment))
+}
+
+// Status para os quais a consulta pode ir agora (botões da tela)
+#[tauri::command]
+pub async fn get_allowed_status_transitions(_app_handle: AppHandle, id: String) -> Result<Vec<AppointmentStatus>, String> {
+    let status = APPOINTMENTS.lock().unwrap()
+        .iter()
+        .find(|a| a.id == id)
+        .map(|a| a.status)
+        .ok_or("Appointment not found")?;
+    Ok(status.allowed_transitions().to_vec())
+}
+
+// A consulta original fica como "rescheduled" (com o motivo) e aponta para a
+// nova, que nasce "scheduled" apontando de volta. As duas são gravadas sob o
+// mesmo lock, e a original já não conta na checagem de conflitos.
+#[tauri::command]
+pub async fn reschedule_appointment(
+    app_handle: AppHandle,
+    id: String,
+    request: RescheduleRequest,
+) -> Result<WithPatientAlerts<ScheduledAppointment>, SchedulingError> {
+    let rules = crate::scheduling::load_rules();
+    let reason = request.reason.trim().to_string();
+    let (scheduled, original) = {
+        let mut guard = APPOINTMENTS.lock().unwrap();
+        let index = guard.iter().position(|a| a.id == id).ok_or("Appointment not found")?;
+        let mut original = guard[index].clone();
+        change_status(&mut original, AppointmentStatus::Rescheduled, Some(reason.clone()))?;
+
+        let mut replacement = new_appointment(uuid::Uuid::new_v4().to_string());
+        start_status(&mut replacement, None)?;
+        let keep = |new: Option<String>, old: &Option<String>| new.or_else(|| old.clone());
+        apply_appointment_request(
+            &mut replacement,
+            CreateAppointmentRequest {
+                patient_id: original.patient_id.clone(),
+                date: request.date,
+                duration_minutes: request
+                    .duration_minutes
+                    .or_else(|| request.end_time.is_none().then_some(original.duration_minutes)),
+                time: request.time,
+                end_time: request.end_time,
+                professional_id: keep(request.professional_id, &original.professional_id),
+                room: keep(request.room, &original.room),
+                chair: keep(request.chair, &original.chair),
+                status: None,
+                notes: original.notes.clone(),
+            },
+            &rules,
+        )?;
+
+        // Ocorrência de série: as duas passam a ser exceções, para a série não
+        // recriar a data nem apagar o registro da remarcação
+        replacement.series_id = original.series_id.clone();
+        replacement.occurrence_date = original.occurrence_date.clone();
+        replacement.series_exception = original.series_id.is_some();
+        original.series_exception = original.series_id.is_some();
+        if let Some(change) = original.status_history.last_mut() {
+            change.rescheduled_to = Some(replacement.id.clone());
+        }
+        if let Some(change) = replacement.status_history.first_mut() {
+            change.reason = Some(reason.clone());
+            change.rescheduled_from = Some(original.id.clone());
+        }
+        original.updated_at = chrono::Utc::now().to_rfc3339();
+
+        let others: Vec<Appointment> = guard.iter().filter(|a| a.id != original.id).cloned().collect();
+        let conflicts = appointment_conflicts(&others, &replacement, &rules);
+        if !conflicts.iter().any(|c| c.blocking) {
+            guard[index] = original.clone();
+            guard.push(replacement.clone());
+        }
+        (ScheduledAppointment { appointment: replacement, conflicts }, original)
+    };
+    let mut conflicts = scheduled.conflicts;
+    fill_conflict_patient_names(&mut conflicts);
+    if conflicts.iter().any(|c| c.blocking) {
+        return Err(SchedulingError {
+            message: "The selected time conflicts with other appointments".to_string(),
+            conflicts,
+        });
+    }
+    let scheduled = ScheduledAppointment { appointment: scheduled.appointment, conflicts };
+    offer_freed_slot(&app_handle, &original);
+
+    audit_current_user(
+        "APPOINTMENT_RESCHEDULED",
+        "APPOINTMENT",
+        Some(id),
+        format!(
+            "To {} on {} {}: {}",
+            scheduled.appointment.id, scheduled.appointment.date, scheduled.appointment.time, reason
+        ),
+    ).await?;
+    Ok(with_patient_alerts(&scheduled.appointment.patient_id.clone(), scheduled))
+}
+
+// Estatísticas pelo histórico de status, no período e (opcional) do profissional
+#[tauri::command]
+pub async fn get_appointment_statistics(
+    _app_handle: AppHandle,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    professional_id: Option<String>,
+) -> Result<AppointmentStatistics, String> {
+    let parse = |value: Option<String>| value.map(|v| crate::scheduling::parse_date(&v)).transpose().map_err(|e| e.to_string());
+    let start = parse(start_date)?;
+    let end = parse(end_date)?;
+
+    let appointments = APPOINTMENTS.lock().unwrap();
+    let selected: Vec<(AppointmentStatus, &[StatusChange])> = appointments
+        .iter()
+        .filter(|a| a.deleted_at.is_none())
+        .filter(|a| professional_id.is_none() || a.professional_id == professional_id)
+        .filter(|a| {
+            let Ok(date) = crate::scheduling::parse_date(&a.date) else {
+                return start.is_none() && end.is_none();
+            };
+            start.is_none_or(|s| date >= s) && end.is_none_or(|e| date <= e)
+        })
+        .map(|a| (a.status, a.status_history.as_slice()))
+        .collect();
+    Ok(crate::appointment_status::statistics(&selected))
+}
+
+// =========================
+// Agenda (disponibilidade)
+// =========================
+
+#[tauri::command]
+pub async fn get_availability_calendar(
+    _app_handle: AppHandle,
+) -> Result<crate::availability::AvailabilityCalendar, String> {
+    Ok(crate::availability::load_calendar())
+}
+
+// Horário semanal dos profissionais, intervalos, fechamentos e feriados municipais
+#[tauri::command]
+pub async fn set_availability_calendar(
+    _app_handle: AppHandle,
+    calendar: crate::availability::AvailabilityCalendar,
+) -> Result<crate::availability::AvailabilityCalendar, String> {
+    ensure_admin()?;
+    crate::availability::save_calendar(&calendar)
+        .map_err(|e| format!("Failed to save availability calendar: {}", e))?;
+
+    audit_current_user(
+        "AVAILABILITY_CALENDAR_UPDATED",
+        "APPOINTMENT",
+        None,
+        format!(
+            "{} professionals, {} closures, {} municipal holidays",
+            calendar.professionals.len(),
+            calendar.closures.len(),
+            calendar.municipal_holidays.len()
+        ),
+    ).await?;
+    Ok(calendar)
+}
+
+#[tauri::command]
+pub async fn get_holidays(_app_handle: AppHandle, year: i32) -> Result<Vec<crate::availability::Holiday>, String> {
+    if !(1900..=2200).contains(&year) {
+        return Err("Invalid year".into());
+    }
+    Ok(crate::availability::holidays_for_year(&crate::availability::load_calendar(), year))
+}
+
+// Horários livres para o botão "próximo horário" e para a busca da recepção
+#[tauri::command]
+pub async fn find_available_slots(
+    _app_handle: AppHandle,
+    search: crate::availability::SlotSearch,
+) -> Result<Vec<crate::availability::AvailableSlot>, String> {
+    let calendar = crate::availability::load_calendar();
+    let rules = crate::scheduling::load_rules();
+    let existing: Vec<ScheduleEntry> = APPOINTMENTS.lock().unwrap().iter().filter_map(schedule_entry).collect();
+    crate::availability::find_available_slots(&calendar, &rules, &existing, &search, chrono::Local::now().naive_local())
+        .map_err(|e| format!("Failed to find available slots: {}", e))
+}
+
+// =========================
+// Séries de consultas (recorrência)
+// =========================
+
+// Situação de uma data da série: feriado/fechamento e consultas em conflito
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OccurrenceCheck {
+    pub date: String,
+    pub closed: Option<String>, // Feriado ou fechamento do dia
+    pub conflicts: Vec<AppointmentConflict>,
+    pub blocking: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesResult {
+    pub series: AppointmentSeries,
+    pub appointments: Vec<Appointment>, // Consultas geradas ou alteradas
+    pub skipped: Vec<OccurrenceCheck>, // Datas puladas (viraram exceções)
+    pub warnings: Vec<OccurrenceCheck>, // Conflitos só sinalizados
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppointmentSeriesView {
+    pub series: AppointmentSeries,
+    pub appointments: Vec<Appointment>,
+}
+
+// Erro de série: as datas que impedem a gravação, para a recepção ajustar a
+// regra ou pedir para pular essas datas
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesError {
+    pub message: String,
+    pub problems: Vec<OccurrenceCheck>,
+}
+
+impl From<String> for SeriesError {
+    fn from(message: String) -> Self {
+        SeriesError { message, problems: Vec::new() }
+    }
+}
+
+impl From<&str> for SeriesError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+fn today() -> chrono::NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+fn find_series(series_id: &str) -> Result<AppointmentSeries, String> {
+    APPOINTMENT_SERIES.lock().unwrap()
+        .iter()
+        .find(|s| s.id == series_id && s.deleted_at.is_none())
+        .cloned()
+        .ok_or_else(|| "Appointment series not found".to_string())
+}
+
+fn store_series(mut series: AppointmentSeries) {
+    series.updated_at = chrono::Utc::now().to_rfc3339();
+    series.last_editor = Some("local_device".to_string());
+    let mut guard = APPOINTMENT_SERIES.lock().unwrap();
+    match guard.iter_mut().find(|s| s.id == series.id) {
+        Some(existing) => *existing = series,
+        None => guard.push(series),
+    }
+}
+
+fn exclude_series_date(series_id: &str, date: &str) {
+    let Ok(date) = crate::scheduling::parse_date(date) else {
+        return;
+    };
+    if let Ok(mut series) = find_series(series_id) {
+        if !series.exdates.contains(&date) {
+            series.exdates.push(date);
+            series.exdates.sort();
+            store_series(series);
+        }
+    }
+}
+
+fn fill_check_patient_names(checks: &mut [OccurrenceCheck]) {
+    for check in checks {
+        fill_conflict_patient_names(&mut check.conflicts);
+    }
+}
+
+// Valida os dados de agenda da série (horário, duração, regra)
+fn apply_series_changes(series: &mut AppointmentSeries, changes: &SeriesChanges, rules: &SchedulingRules) -> Result<(), String> {
+    if let Some(rrule) = changes.rrule.as_deref().filter(|r| !r.trim().is_empty()) {
+        let rule = crate::recurrence::RecurrenceRule::parse(rrule).map_err(|e| e.to_string())?;
+        series.rrule = rule.to_rrule();
+    }
+    let start = crate::scheduling::parse_time(&changes.time).map_err(|e| e.to_string())?;
+    series.time = crate::scheduling::format_time(start);
+    series.duration_minutes =
+        crate::scheduling::resolve_duration(start, changes.duration_minutes, changes.end_time.as_deref(), rules)
+            .map_err(|e| e.to_string())?;
+    let non_empty = |v: &Option<String>| v.as_ref().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    series.professional_id = non_empty(&changes.professional_id);
+    series.room = non_empty(&changes.room);
+    series.chair = non_empty(&changes.chair);
+    if let Some(status) = changes.status {
+        if !status.is_initial() {
+            return Err(format!("Series appointments cannot be created as {}", status));
+        }
+        series.status = status;
+    }
+    series.notes = changes.notes.clone();
+    Ok(())
+}
+
+// Monta as ocorrências da série a partir de `from` e confere cada uma contra
+// feriados, fechamentos e a agenda (`existing`, que já não contém as ocorrências
+// sendo substituídas). Ocorrências substituídas na mesma data mantêm o id e o
+// status (ex.: já confirmada), para prontuários e rascunhos ligados a elas.
+fn plan_series(
+    series: &AppointmentSeries,
+    from: chrono::NaiveDate,
+    existing: &[Appointment],
+    replaced: &[Appointment],
+    rules: &SchedulingRules,
+) -> Result<Vec<(Appointment, OccurrenceCheck)>, String> {
+    let calendar = crate::availability::load_calendar();
+    // Datas com ocorrência editada à parte continuam como estão
+    let detached: HashSet<String> = existing
+        .iter()
+        .filter(|a| a.series_id.as_deref() == Some(series.id.as_str()))
+        .filter_map(|a| a.occurrence_date.clone())
+        .collect();
+
+    let mut occupied = existing.to_vec();
+    let mut planned = Vec::new();
+    for date in series.dates_from(from).map_err(|e| e.to_string())? {
+        let date_str = date.format("%Y-%m-%d").to_string();
+        if detached.contains(&date_str) {
+            continue;
+        }
+        let previous = replaced.iter().find(|a| a.occurrence_date.as_deref() == Some(date_str.as_str()));
+        let mut appointment = match previous {
+            Some(previous) => previous.clone(),
+            None => {
+                let mut appointment = new_appointment(uuid::Uuid::new_v4().to_string());
+                start_status(&mut appointment, Some(series.status))?;
+                appointment
+            }
+        };
+        apply_appointment_request(
+            &mut appointment,
+            CreateAppointmentRequest {
+                patient_id: series.patient_id.clone(),
+                date: date_str.clone(),
+                time: series.time.clone(),
+                duration_minutes: Some(series.duration_minutes),
+                end_time: None,
+                professional_id: series.professional_id.clone(),
+                room: series.room.clone(),
+                chair: series.chair.clone(),
+                status: None,
+                notes: series.notes.clone(),
+            },
+            rules,
+        )?;
+        appointment.series_id = Some(series.id.clone());
+        appointment.occurrence_date = Some(date_str.clone());
+        appointment.series_exception = false;
+        appointment.updated_at = chrono::Utc::now().to_rfc3339();
+
+        let closed = crate::availability::closed_reason(&calendar, date, series.professional_id.as_deref());
+        let conflicts = appointment_conflicts(&occupied, &appointment, rules);
+        let blocking = closed.is_some() || conflicts.iter().any(|c| c.blocking);
+        if !blocking {
+            occupied.push(appointment.clone());
+        }
+        planned.push((appointment, OccurrenceCheck { date: date_str, closed, conflicts, blocking }));
+    }
+    Ok(planned)
+}
+
+// Gera (ou regenera) as ocorrências da série a partir de `from`, substituindo
+// as que não foram editadas à parte nem já saíram do status inicial (realizadas,
+// canceladas, faltas ficam como estão). `adopt_from` traz junto as ocorrências de
+// outra série a partir de `from` (divisão em "esta e as seguintes"). Tudo é
+// conferido e gravado sob o lock da agenda: com conflito bloqueante nada muda,
+// a menos que skip_conflicts, e aí as datas com problema viram exceções.
+// Horários que as ocorrências substituídas deixam de ocupar vão para a lista
+// de espera.
+fn materialize_series(
+    app_handle: &AppHandle,
+    series: &mut AppointmentSeries,
+    from: chrono::NaiveDate,
+    adopt_from: Option<&str>,
+    skip_conflicts: bool,
+) -> Result<SeriesResult, SeriesError> {
+    let rules = crate::scheduling::load_rules();
+    let belongs = |a: &Appointment| {
+        let in_range = a
+            .occurrence_date
+            .as_deref()
+            .and_then(|d| crate::scheduling::parse_date(d).ok())
+            .is_some_and(|d| d >= from);
+        in_range && (a.series_id.as_deref() == Some(series.id.as_str()) || (a.series_id.is_some() && a.series_id.as_deref() == adopt_from))
+    };
+
+    let freed;
+    let mut result = {
+        let mut guard = APPOINTMENTS.lock().unwrap();
+        let mut kept = Vec::new();
+        let mut replaced = Vec::new();
+        for a in guard.iter() {
+            let detached = a.series_exception || !a.status.is_initial();
+            match (belongs(a), detached) {
+                (true, false) => replaced.push(a.clone()),
+                (true, true) => {
+                    let mut adopted = a.clone();
+                    adopted.series_id = Some(series.id.clone());
+                    kept.push(adopted);
+                }
+                _ => kept.push(a.clone()),
+            }
+        }
+
+        let planned = plan_series(series, from, &kept, &replaced, &rules)?;
+        if planned.is_empty() && from <= series.start_date {
+            return Err("The recurrence rule produces no occurrences".into());
+        }
+        let (blocked, ok): (Vec<_>, Vec<_>) = planned.into_iter().partition(|(_, check)| check.blocking);
+        let skipped: Vec<OccurrenceCheck> = blocked.into_iter().map(|(_, check)| check).collect();
+        if !skipped.is_empty() && !skip_conflicts {
+            drop(guard);
+            let mut problems = skipped;
+            fill_check_patient_names(&mut problems);
+            return Err(SeriesError {
+                message: format!("{} occurrence(s) fall on holidays or conflict with other appointments", problems.len()),
+                problems,
+            });
+        }
+
+        let appointments: Vec<Appointment> = ok.iter().map(|(a, _)| a.clone()).collect();
+        let warnings: Vec<OccurrenceCheck> = ok.into_iter().map(|(_, c)| c).filter(|c| !c.conflicts.is_empty()).collect();
+        freed = freed_by_regeneration(&replaced, &appointments);
+        kept.extend(appointments.iter().cloned());
+        *guard = kept;
+        SeriesResult { series: series.clone(), appointments, skipped, warnings }
+    };
+    offer_freed_slots(app_handle, &freed);
+
+    for check in &result.skipped {
+        if let Ok(date) = crate::scheduling::parse_date(&check.date) {
+            series.exdates.push(date);
+        }
+    }
+    series.exdates.sort();
+    series.exdates.dedup();
+    result.series = series.clone();
+    fill_check_patient_names(&mut result.skipped);
+    fill_check_patient_names(&mut result.warnings);
+    Ok(result)
+}
+
+// Ocorrências substituídas que não ficaram no mesmo horário e recursos (a data
+// saiu da série, foi pulada ou a série mudou de horário)
+fn freed_by_regeneration(replaced: &[Appointment], regenerated: &[Appointment]) -> Vec<Appointment> {
+    let same_slot = |a: &Appointment, b: &Appointment| {
+        (&a.date, &a.time, a.duration_minutes, &a.professional_id, &a.room, &a.chair)
+            == (&b.date, &b.time, b.duration_minutes, &b.professional_id, &b.room, &b.chair)
+    };
+    replaced
+        .iter()
+        .filter(|old| !regenerated.iter().any(|new| new.id == old.id && same_slot(old, new)))
+        .cloned()
+        .collect()
+}
+
+fn new_series(request: &CreateSeriesRequest, rules: &SchedulingRules) -> Result<AppointmentSeries, String> {
+    let start_date = crate::scheduling::parse_date(&request.start_date).map_err(|e| e.to_string())?;
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut series = AppointmentSeries {
+        id: uuid::Uuid::new_v4().to_string(),
+        patient_id: request.patient_id.clone(),
+        rrule: String::new(),
+        start_date,
+        time: String::new(),
+        duration_minutes: rules.default_duration_minutes,
+        professional_id: None,
+        room: None,
+        chair: None,
+        status: AppointmentStatus::default(),
+        notes: None,
+        exdates: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+    };
+    if request.rrule.trim().is_empty() {
+        return Err("Recurrence rule is required".into());
+    }
+    apply_series_changes(
+        &mut series,
+        &SeriesChanges {
+            rrule: Some(request.rrule.clone()),
+            date: None,
+            time: request.time.clone(),
+            duration_minutes: request.duration_minutes,
+            end_time: request.end_time.clone(),
+            professional_id: request.professional_id.clone(),
+            room: request.room.clone(),
+            chair: request.chair.clone(),
+            status: request.status,
+            notes: request.notes.clone(),
+            skip_conflicts: request.skip_conflicts,
+        },
+        rules,
+    )?;
+    Ok(series)
+}
+
+// Datas que a série geraria, com feriados e conflitos, sem gravar nada
+#[tauri::command]
+pub async fn preview_appointment_series(
+    _app_handle: AppHandle,
+    request: CreateSeriesRequest,
+) -> Result<Vec<OccurrenceCheck>, String> {
+    let rules = crate::scheduling::load_rules();
+    let series = new_series(&request, &rules)?;
+    let existing = APPOINTMENTS.lock().unwrap().clone();
+    let mut checks: Vec<OccurrenceCheck> = plan_series(&series, series.start_date, &existing, &[], &rules)?
+        .into_iter()
+        .map(|(_, check)| check)
+        .collect();
+    fill_check_patient_names(&mut checks);
+    Ok(checks)
+}
+
+#[tauri::command]
+pub async fn create_appointment_series(
+    app_handle: AppHandle,
+    request: CreateSeriesRequest,
+) -> Result<SeriesResult, SeriesError> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+    let rules = crate::scheduling::load_rules();
+    let mut series = new_series(&request, &rules)?;
+    let start = series.start_date;
+    let result = materialize_series(&app_handle, &mut series, start, None, request.skip_conflicts)?;
+    store_series(series);
+    discard_committed_draft("appointment", None);
+
+    audit_current_user(
+        "APPOINTMENT_SERIES_CREATED",
+        "APPOINTMENT",
+        Some(result.series.id.clone()),
+        format!(
+            "{} for patient {}: {} appointments, {} dates skipped",
+            result.series.rrule,
+            result.series.patient_id,
+            result.appointments.len(),
+            result.skipped.len()
+        ),
+    ).await?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn get_appointment_series(_app_handle: AppHandle, series_id: String) -> Result<AppointmentSeriesView, String> {
+    let series = find_series(&series_id)?;
+    let mut appointments: Vec<Appointment> = APPOINTMENTS.lock().unwrap()
+        .iter()
+        .filter(|a| a.series_id.as_deref() == Some(series_id.as_str()))
+        .cloned()
+        .collect();
+    appointments.sort_by(|a, b| format!("{} {}", a.date, a.time).cmp(&format!("{} {}", b.date, b.time)));
+    Ok(AppointmentSeriesView { series, appointments })
+}
+
+// Edita "esta ocorrência", "esta e as seguintes" ou "todas" (as já passadas
+// ficam como estão)
+#[tauri::command]
+pub async fn update_appointment_series(
+    app_handle: AppHandle,
+    appointment_id: String,
+    scope: EditScope,
+    changes: SeriesChanges,
+) -> Result<SeriesResult, SeriesError> {
+    let appointment = APPOINTMENTS.lock().unwrap()
+        .iter()
+        .find(|a| a.id == appointment_id)
+        .cloned()
+        .ok_or("Appointment not found")?;
+    let series_id = appointment.series_id.clone().ok_or("Appointment is not part of a series")?;
+    let mut series = find_series(&series_id)?;
+    let rules = crate::scheduling::load_rules();
+    let occurrence = appointment
+        .occurrence_date
+        .as_deref()
+        .and_then(|d| crate::scheduling::parse_date(d).ok())
+        .unwrap_or(series.start_date);
+
+    let result = match scope {
+        EditScope::This => {
+            let mut updated = appointment.clone();
+            if let Some(status) = changes.status.filter(|s| *s != appointment.status) {
+                change_status(&mut updated, status, None)?;
+            }
+            let date = changes.date.clone().unwrap_or_else(|| appointment.date.clone());
+            apply_appointment_request(
+                &mut updated,
+                CreateAppointmentRequest {
+                    patient_id: series.patient_id.clone(),
+                    date,
+                    time: changes.time.clone(),
+                    duration_minutes: changes.duration_minutes,
+                    end_time: changes.end_time.clone(),
+                    professional_id: changes.professional_id.clone(),
+                    room: changes.room.clone(),
+                    chair: changes.chair.clone(),
+                    status: None,
+                    notes: changes.notes.clone(),
+                },
+                &rules,
+            )?;
+            updated.series_exception = true;
+            updated.updated_at = chrono::Utc::now().to_rfc3339();
+
+            let calendar = crate::availability::load_calendar();
+            let date = crate::scheduling::parse_date(&updated.date).map_err(|e| e.to_string())?;
+            if let Some(reason) = crate::availability::closed_reason(&calendar, date, updated.professional_id.as_deref()) {
+                if !changes.skip_conflicts {
+                    return Err(SeriesError {
+                        message: format!("The clinic is closed on {}: {}", updated.date, reason),
+                        problems: vec![OccurrenceCheck { date: updated.date.clone(), closed: Some(reason), conflicts: Vec::new(), blocking: true }],
+                    });
+                }
+            }
+            let scheduled = store_appointment(updated, &rules).map_err(|e| SeriesError {
+                problems: vec![OccurrenceCheck {
+                    date: appointment.date.clone(),
+                    closed: None,
+                    conflicts: e.conflicts.clone(),
+                    blocking: true,
+                }],
+                message: e.message,
+            })?;
+            let warnings = if scheduled.conflicts.is_empty() {
+                Vec::new()
+            } else {
+                vec![OccurrenceCheck {
+                    date: scheduled.appointment.date.clone(),
+                    closed: None,
+                    conflicts: scheduled.conflicts,
+                    blocking: false,
+                }]
+            };
+            SeriesResult { series: series.clone(), appointments: vec![scheduled.appointment], skipped: Vec::new(), warnings }
+        }
+        EditScope::All => {
+            apply_series_changes(&mut series, &changes, &rules)?;
+            let from = series.start_date.max(today());
+            let result = materialize_series(&app_handle, &mut series, from, None, changes.skip_conflicts)?;
+            store_series(series.clone());
+            result
+        }
+        EditScope::ThisAndFollowing if occurrence <= series.start_date => {
+            apply_series_changes(&mut series, &changes, &rules)?;
+            let from = series.start_date.max(today());
+            let result = materialize_series(&app_handle, &mut series, from, None, changes.skip_conflicts)?;
+            store_series(series.clone());
+            result
+        }
+        EditScope::ThisAndFollowing => {
+            // A série original termina na véspera; uma nova começa nesta ocorrência
+            let rule = series.rule().map_err(|e| e.to_string())?;
+            let mut following = series.clone();
+            following.id = uuid::Uuid::new_v4().to_string();
+            following.start_date = occurrence;
+            following.rrule = rule.continuing_from(series.start_date, occurrence).map_err(|e| e.to_string())?.to_rrule();
+            following.exdates.retain(|d| *d >= occurrence);
+            following.created_at = chrono::Utc::now().to_rfc3339();
+            following.rev = 0;
+            following.last_pulled_rev = None;
+            apply_series_changes(&mut following, &changes, &rules)?;
+
+            // Ocorrências já passadas não são regeradas, só passam para a nova série
+            let from = occurrence.max(today());
+            let result = materialize_series(&app_handle, &mut following, from, Some(&series.id), changes.skip_conflicts)?;
+            for a in APPOINTMENTS.lock().unwrap().iter_mut() {
+                let after_split = a
+                    .occurrence_date
+                    .as_deref()
+                    .and_then(|d| crate::scheduling::parse_date(d).ok())
+                    .is_some_and(|d| d >= occurrence);
+                if a.series_id.as_deref() == Some(series.id.as_str()) && after_split {
+                    a.series_id = Some(following.id.clone());
+                }
+            }
+            series.rrule = rule.ending_before(occurrence).to_rrule();
+            series.exdates.retain(|d| *d < occurrence);
+            store_series(series.clone());
+            store_series(following);
+            result
+        }
+    };
+
+    audit_current_user(
+        "APPOINTMENT_SERIES_UPDATED",
+        "APPOINTMENT",
+        Some(series_id),
+        format!("{:?} from {} ({} appointments)", scope, occurrence, result.appointments.len()),
+    ).await?;
+    Ok(result)
+}
+
+// Exclui "esta ocorrência", "esta e as seguintes" ou "todas" (as já passadas
+// ficam no histórico)
+#[tauri::command]
+pub async fn delete_appointment_series(
+    app_handle: AppHandle,
+    appointment_id: String,
+    scope: EditScope,
+) -> Result<(), String> {
+    let appointment = APPOINTMENTS.lock().unwrap()
+        .iter()
+        .find(|a| a.id == appointment_id)
+        .cloned()
+        .ok_or("Appointment not found")?;
+    let series_id = appointment.series_id.clone().ok_or("Appointment is not part of a series")?;
+    let mut series = find_series(&series_id)?;
+    let occurrence = appointment
+        .occurrence_date
+        .as_deref()
+        .and_then(|d| crate::scheduling::parse_date(d).ok())
+        .unwrap_or(series.start_date);
+
+    let mut removed = Vec::new();
+    let from = match scope {
+        EditScope::This => {
+            removed.extend(take_appointments(|a| a.id == appointment_id));
+            if !series.exdates.contains(&occurrence) {
+                series.exdates.push(occurrence);
+                series.exdates.sort();
+            }
+            None
+        }
+        EditScope::ThisAndFollowing if occurrence > series.start_date => {
+            // A regra termina onde a remoção começa: ocorrências já passadas
+            // continuam dentro dela, como histórico
+            let from = occurrence.max(today());
+            let rule = series.rule().map_err(|e| e.to_string())?;
+            series.rrule = rule.ending_before(from).to_rrule();
+            series.exdates.retain(|d| *d < from);
+            Some(from)
+        }
+        _ => {
+            series.deleted_at = Some(chrono::Utc::now().to_rfc3339());
+            Some(series.start_date.max(today()))
+        }
+    };
+    if let Some(from) = from {
+        removed.extend(take_appointments(|a| {
+            let upcoming = crate::scheduling::parse_date(&a.date).is_ok_and(|d| d >= from);
+            a.series_id.as_deref() == Some(series_id.as_str()) && upcoming
+        }));
+    }
+    store_series(series);
+    offer_freed_slots(&app_handle, &removed);
+
+    audit_current_user(
+        "APPOINTMENT_SERIES_DELETED",
+        "APPOINTMENT",
+        Some(series_id),
+        format!("{:?} from {}", scope, occurrence),
+    ).await
+}
+
+// =========================
+// Lista de espera
+// =========================
+
+fn apply_waitlist_request(entry: &mut WaitlistEntry, request: WaitlistRequest) {
+    entry.preferred_weekdays = request.preferred_weekdays;
+    entry.preferred_times = request.preferred_times;
+    entry.professional_ids = request.professional_ids.into_iter().map(|p| p.trim().to_string()).collect();
+    entry.duration_minutes = request.duration_minutes;
+    entry.earliest_date = request.earliest_date;
+    entry.latest_date = request.latest_date;
+    entry.priority = request.priority;
+    entry.notes = request.notes;
+}
+
+// Tira da agenda as consultas que casam com o filtro e as devolve
+fn take_appointments(matches: impl Fn(&Appointment) -> bool) -> Vec<Appointment> {
+    let mut guard = APPOINTMENTS.lock().unwrap();
+    let (taken, kept) = std::mem::take(&mut *guard).into_iter().partition(|a| matches(a));
+    *guard = kept;
+    taken
+}
+
+// Compara o horário liberado com a lista de espera e, havendo candidatos,
+// guarda a oferta e avisa a tela pelo evento. Horário já passado não vira oferta.
+fn offer_freed_slot(app_handle: &AppHandle, freed: &Appointment) {
+    if let Some(offer) = record_slot_offer(freed) {
+        let _ = app_handle.emit(crate::waitlist::OFFERS_EVENT, &offer);
+    }
+}
+
+// Só as que ocupavam a agenda liberam horário
+fn offer_freed_slots(app_handle: &AppHandle, freed: &[Appointment]) {
+    for appointment in freed.iter().filter(|a| a.status.occupies_schedule()) {
+        offer_freed_slot(app_handle, appointment);
+    }
+}
+
+fn record_slot_offer(freed: &Appointment) -> Option<SlotOffer> {
+    let (Ok(date), Ok(start)) = (
+        crate::scheduling::parse_date(&freed.date),
+        crate::scheduling::parse_time(&freed.time),
+    ) else {
+        return None;
+    };
+    if date.and_time(start) <= chrono::Local::now().naive_local() {
+        return None;
+    }
+    let slot = FreedSlot {
+        appointment_id: freed.id.clone(),
+        patient_id: freed.patient_id.clone(),
+        date,
+        start_time: crate::scheduling::format_time(start),
+        end_time: crate::scheduling::end_of(start, freed.duration_minutes)
+            .map(crate::scheduling::format_time)
+            .unwrap_or_default(),
+        duration_minutes: freed.duration_minutes,
+        professional_id: freed.professional_id.clone(),
+        room: freed.room.clone(),
+        chair: freed.chair.clone(),
+    };
+
+    let ranked = crate::waitlist::rank_entries(&WAITLIST.lock().unwrap(), &slot, today());
+    let mut candidates = match ranked {
+        Ok(candidates) if !candidates.is_empty() => candidates,
+        _ => return None,
+    };
+    {
+        let patients = PATIENTS.lock().unwrap();
+        for c in &mut candidates {
+            c.patient_name = patients.iter().find(|p| p.id == c.patient_id).map(|p| p.name.clone());
+        }
+    }
+    let offer = SlotOffer {
+        id: uuid::Uuid::new_v4().to_string(),
+        slot,
+        candidates,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    WAITLIST_OFFERS.lock().unwrap().push(offer.clone());
+    Some(offer)
+}
+
+#[tauri::command]
+pub async fn get_waitlist(_app_handle: AppHandle, patient_id: Option<String>) -> Result<Vec<WaitlistEntry>, String> {
+    let mut out: Vec<WaitlistEntry> = WAITLIST.lock().unwrap()
+        .iter()
+        .filter(|e| e.deleted_at.is_none())
+        .filter(|e| patient_id.as_ref().is_none_or(|p| &e.patient_id == p))
+        .cloned()
+        .collect();
+    out.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| a.created_at.cmp(&b.created_at)));
+    Ok(out)
+}
+
+#[tauri::command]
+pub async fn add_waitlist_entry(_app_handle: AppHandle, request: WaitlistRequest) -> Result<WaitlistEntry, String> {
+    crate::waitlist::validate_request(&request).map_err(|e| e.to_string())?;
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut entry = WaitlistEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        patient_id: request.patient_id.clone(),
+        preferred_weekdays: Vec::new(),
+        preferred_times: Vec::new(),
+        professional_ids: Vec::new(),
+        duration_minutes: None,
+        earliest_date: None,
+        latest_date: None,
+        priority: request.priority,
+        notes: None,
+        created_at: now.clone(),
+        updated_at: now,
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+    };
+    apply_waitlist_request(&mut entry, request);
+    WAITLIST.lock().unwrap().push(entry.clone());
+
+    audit_current_user(
+        "WAITLIST_ENTRY_ADDED",
+        "WAITLIST",
+        Some(entry.id.clone()),
+        format!("Patient {} ({:?})", entry.patient_id, entry.priority),
+    ).await?;
+    Ok(entry)
+}
+
+#[tauri::command]
+pub async fn update_waitlist_entry(
+    _app_handle: AppHandle,
+    id: String,
+    request: WaitlistRequest,
+) -> Result<WaitlistEntry, String> {
+    crate::waitlist::validate_request(&request).map_err(|e| e.to_string())?;
+    let updated = {
+        let mut guard = WAITLIST.lock().unwrap();
+        let entry = guard
+            .iter_mut()
+            .find(|e| e.id == id && e.deleted_at.is_none())
+            .ok_or("Waitlist entry not found")?;
+        if entry.patient_id != request.patient_id {
+            return Err("Waitlist entry belongs to another patient".into());
+        }
+        apply_waitlist_request(entry, request);
+        entry.updated_at = chrono::Utc::now().to_rfc3339();
+        entry.last_editor = Some("local_device".to_string());
+        entry.clone()
+    };
+
+    audit_current_user(
+        "WAITLIST_ENTRY_UPDATED",
+        "WAITLIST",
+        Some(id),
+        format!("Patient {} ({:?})", updated.patient_id, updated.priority),
+    ).await?;
+    Ok(updated)
+}
+
+// Soft delete, como as demais entidades sincronizadas; sai também das ofertas
+fn drop_waitlist_entry(id: &str) -> Option<String> {
+    let patient_id = {
+        let mut guard = WAITLIST.lock().unwrap();
+        let entry = guard.iter_mut().find(|e| e.id == id && e.deleted_at.is_none())?;
+        let now = chrono::Utc::now().to_rfc3339();
+        entry.deleted_at = Some(now.clone());
+        entry.updated_at = now;
+        entry.last_editor = Some("local_device".to_string());
+        entry.patient_id.clone()
+    };
+    let mut offers = WAITLIST_OFFERS.lock().unwrap();
+    for offer in offers.iter_mut() {
+        offer.candidates.retain(|c| c.entry_id != id);
+    }
+    offers.retain(|o| !o.candidates.is_empty());
+    Some(patient_id)
+}
+
+#[tauri::command]
+pub async fn remove_waitlist_entry(_app_handle: AppHandle, id: String) -> Result<(), String> {
+    let patient_id = drop_waitlist_entry(&id).ok_or("Waitlist entry not found")?;
+
+    audit_current_user(
+        "WAITLIST_ENTRY_REMOVED",
+        "WAITLIST",
+        Some(id),
+        format!("Patient {}", patient_id),
+    ).await
+}
+
+// Ofertas pendentes (as de horários já passados são descartadas)
+#[tauri::command]
+pub async fn get_waitlist_offers(_app_handle: AppHandle) -> Result<Vec<SlotOffer>, String> {
+    let now = chrono::Local::now().naive_local();
+    let mut offers = WAITLIST_OFFERS.lock().unwrap();
+    offers.retain(|o| {
+        crate::scheduling::parse_time(&o.slot.start_time).is_ok_and(|start| o.slot.date.and_time(start) > now)
+    });
+    Ok(offers.clone())
+}
+
+#[tauri::command]
+pub async fn dismiss_waitlist_offer(_app_handle: AppHandle, offer_id: String) -> Result<(), String> {
+    let mut offers = WAITLIST_OFFERS.lock().unwrap();
+    let before = offers.len();
+    offers.retain(|o| o.id != offer_id);
+    if offers.len() == before {
+        return Err("Waitlist offer not found".to_string());
+    }
+    Ok(())
+}
+
+// Marca a consulta do candidato no horário oferecido (com a checagem de
+// conflitos de sempre) e tira o paciente da lista de espera
+#[tauri::command]
+pub async fn accept_waitlist_offer(
+    _app_handle: AppHandle,
+    offer_id: String,
+    entry_id: String,
+) -> Result<WithPatientAlerts<ScheduledAppointment>, SchedulingError> {
+    // A oferta sai da lista antes de marcar: dois aceites simultâneos não
+    // marcam a mesma vaga. Se a marcação falhar, ela volta.
+    let offer = {
+        let mut offers = WAITLIST_OFFERS.lock().unwrap();
+        let index = offers.iter().position(|o| o.id == offer_id).ok_or("Waitlist offer not found")?;
+        if !offers[index].candidates.iter().any(|c| c.entry_id == entry_id) {
+            return Err("Patient is not a candidate for this offer".into());
+        }
+        offers.remove(index)
+    };
+    let scheduled = match schedule_waitlist_offer(&offer, &entry_id) {
+        Ok(scheduled) => scheduled,
+        Err(e) => {
+            WAITLIST_OFFERS.lock().unwrap().push(offer);
+            return Err(e);
+        }
+    };
+    let (slot, patient_id) = (offer.slot, scheduled.appointment.patient_id.clone());
+    drop_waitlist_entry(&entry_id);
+
+    audit_current_user(
+        "WAITLIST_OFFER_ACCEPTED",
+        "APPOINTMENT",
+        Some(scheduled.appointment.id.clone()),
+        format!(
+            "Patient {} from waitlist entry {} on {} {} (freed by {})",
+            patient_id, entry_id, scheduled.appointment.date, scheduled.appointment.time, slot.appointment_id
+        ),
+    ).await?;
+    Ok(with_patient_alerts(&patient_id, scheduled))
+}
+
+fn schedule_waitlist_offer(offer: &SlotOffer, entry_id: &str) -> Result<ScheduledAppointment, SchedulingError> {
+    let slot = &offer.slot;
+    let candidate = offer
+        .candidates
+        .iter()
+        .find(|c| c.entry_id == entry_id)
+        .ok_or("Patient is not a candidate for this offer")?;
+    let entry = WAITLIST.lock().unwrap()
+        .iter()
+        .find(|e| e.id == entry_id && e.deleted_at.is_none())
+        .cloned()
+        .ok_or("Waitlist entry not found")?;
+
+    let rules = crate::scheduling::load_rules();
+    let mut appointment = new_appointment(uuid::Uuid::new_v4().to_string());
+    start_status(&mut appointment, None)?;
+    apply_appointment_request(
+        &mut appointment,
+        CreateAppointmentRequest {
+            patient_id: entry.patient_id.clone(),
+            date: slot.date.format("%Y-%m-%d").to_string(),
+            time: slot.start_time.clone(),
+            duration_minutes: Some(candidate.duration_minutes),
+            end_time: None,
+            professional_id: slot.professional_id.clone(),
+            room: slot.room.clone(),
+            chair: slot.chair.clone(),
+            status: None,
+            notes: entry.notes.clone(),
+        },
+        &rules,
+    )?;
+    store_appointment(appointment, &rules)
+}
+
+// =========================
+// Documentos
+// =========================
+
+#[tauri::command]
+pub async fn get_documents(_app_handle: AppHandle, patient_id: Option<String>) -> Result<Vec<Document>, String> {
+    let docs = DOCUMENTS.lock().unwrap();
+    let out: Vec<Document> = docs
+        .iter()
+        .filter(|d| d.deleted_at.is_none())
+        .filter(|d| patient_id.as_ref().is_none_or(|pid| d.patient_id == *pid))
+        .cloned()
+        .collect();
+    Ok(out)
+}
+
+#[tauri::command]
+pub async fn create_document(
+    _app_handle: AppHandle,
+    request: CreateDocumentRequest,
+) -> Result<Document, String> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+    if let Some(document_id) = &request.document_id {
+        ensure_document_of_patient(document_id, &request.patient_id)?;
+    }
+
+    let content_bytes = base64::engine::general_purpose::STANDARD
+        .decode(&request.content)
+        .map_err(|e| format!("Invalid base64 content: {}", e))?;
+    // Tipo e tamanho vêm do conteúdo, nunca do que o cliente declarou
+    let validated = crate::document_validation::validate_bytes(&content_bytes, &request.filename, request.file_type.as_deref())
+        .map_err(|e| format!("Document rejected: {}", e))?;
+
+    let blob_id = blob_id_for(&crate::document_crypto::sha256_hex(&content_bytes))?;
+    let (_claim, existing) = claim_blob(&blob_id, request.document_id.as_deref())?;
+    let version = match existing {
+        Some(existing) => reuse_blob_version(existing, request.filename, Some(validated.mime_type), request.note),
+        None => {
+            let (encrypted_content, encryption) = crate::document_crypto::encrypt_document(&blob_id, &content_bytes)
+                .map_err(|e| format!("Failed to encrypt document: {}", e))?;
+            DOCUMENT_CONTENT.lock().unwrap().insert(blob_id.clone(), encrypted_content);
+            let preview = build_preview(crate::document_preview::generate_preview(&blob_id, &validated.mime_type, &content_bytes));
+
+            let mut version = new_document_version(
+                blob_id,
+                encryption,
+                Some(validated.size as i64),
+                request.filename,
+                Some(validated.mime_type),
+                request.note,
+            );
+            version.preview = preview;
+            version
+        }
+    };
+    match request.document_id {
+        Some(document_id) => add_document_version(&document_id, version),
+        None => insert_new_document(request.patient_id, request.appointment_id, version),
+    }
+}
+
+#[tauri::command]
+pub async fn get_document_content(_app_handle: AppHandle, document_id: String) -> Result<String, String> {
+    let document = find_document(&document_id)?;
+
+    match document.encryption.clone() {
+        Some(metadata) => {
+            let plaintext = read_document_bytes(&content_id_of(&document), &metadata, document.file_size)?;
+            Ok(base64::engine::general_purpose::STANDARD.encode(plaintext))
+        }
+        None => {
+            let content = DOCUMENT_CONTENT.lock().unwrap()
+                .get(&document_id)
+                .cloned()
+                .ok_or("Document not found")?;
+            migrate_legacy_document(&document_id, &content)
+        }
+    }
+}
+
+fn find_document(document_id: &str) -> Result<Document, String> {
+    DOCUMENTS.lock().unwrap()
+        .iter()
+        .find(|d| d.id == document_id && d.deleted_at.is_none())
+        .cloned()
+        .ok_or_else(|| "Document not found".to_string())
+}
+
+fn ensure_document_of_patient(document_id: &str, patient_id: &str) -> Result<(), String> {
+    if find_document(document_id)?.patient_id != patient_id {
+        return Err("Document belongs to another patient".into());
+    }
+    Ok(())
+}
+
+// Documentos grandes não passam por aqui: use export_document_to_temp ou stream_document
+const MAX_INLINE_READ_BYTES: i64 = 32 * 1024 * 1024;
+
+fn read_document_bytes(
+    content_id: &str,
+    metadata: &crate::document_crypto::DocumentEncryption,
+    file_size: Option<i64>,
+) -> Result<Vec<u8>, String> {
+    if metadata.segment_size.is_none() {
+        let content = DOCUMENT_CONTENT.lock().unwrap()
+            .get(content_id)
+            .cloned()
+            .ok_or("Document content not found")?;
+        return crate::document_crypto::decrypt_document(content_id, &content, metadata)
+            .map_err(|e| format!("Failed to read document: {}", e));
+    }
+
+    if file_size.unwrap_or(0) > MAX_INLINE_READ_BYTES {
+        return Err("Document is too large to load in memory; use export_document_to_temp or stream_document".into());
+    }
+    let path = crate::document_crypto::stream_path(content_id)
+        .map_err(|e| format!("Failed to locate document: {}", e))?;
+    let file = std::fs::File::open(&path).map_err(|e| format!("Failed to open document: {}", e))?;
+    let mut plaintext = Vec::new();
+    crate::document_crypto::decrypt_stream(content_id, std::io::BufReader::new(file), &mut plaintext, metadata)
+        .map_err(|e| format!("Failed to read document: {}", e))?;
+    Ok(plaintext)
+}
+
+fn remove_document_content(content_id: &str) {
+    DOCUMENT_CONTENT.lock().unwrap().remove(content_id);
+    if let Ok(path) = crate::document_crypto::stream_path(content_id) {
+        let _ = std::fs::remove_file(path);
+    }
+    crate::document_preview::remove_preview(content_id);
+}
+
+// Falha na prévia não impede o upload; o documento só fica sem miniatura
+fn build_preview(
+    preview: anyhow::Result<Option<crate::document_preview::DocumentPreview>>,
+) -> Option<crate::document_preview::DocumentPreview> {
+    preview.unwrap_or_else(|e| {
+        eprintln!("Failed to generate document preview: {}", e);
+        None
+    })
+}
+
+// Documentos gravados antes da criptografia real guardavam "ENCRYPTED:<base64>".
+// Na primeira leitura são cifrados de verdade e o texto antigo é descartado.
+fn migrate_legacy_document(document_id: &str, content: &str) -> Result<String, String> {
+    let raw = content.strip_prefix("ENCRYPTED:").unwrap_or(content).to_string();
+    let plaintext = base64::engine::general_purpose::STANDARD
+        .decode(&raw)
+        .map_err(|e| format!("Invalid legacy document content: {}", e))?;
+    let (encrypted_content, encryption) = crate::document_crypto::encrypt_document(document_id, &plaintext)
+        .map_err(|e| format!("Failed to encrypt document: {}", e))?;
+
+    if let Some(document) = DOCUMENTS.lock().unwrap().iter_mut().find(|d| d.id == document_id) {
+        document.encrypted = true;
+        document.encryption = Some(encryption);
+    }
+    DOCUMENT_CONTENT.lock().unwrap().insert(document_id.to_string(), encrypted_content);
+    save_local_partition()?;
+    Ok(raw)
+}
+
+// Hook da rotação da chave mestra para as DEKs já carregadas em memória
+fn rewrap_loaded_document_keys() -> anyhow::Result<usize> {
+    let rewrapped = {
+        let mut docs = DOCUMENTS.lock().unwrap();
+        crate::keyvault::with_shared_vault(|vault| {
+            let mut rewrapped = 0;
+            for document in docs.iter_mut() {
+                let content_id = content_id_of(document);
+                if let Some(metadata) = document.encryption.as_mut() {
+                    if crate::document_crypto::rewrap_document_key(vault, &content_id, metadata)? {
+                        rewrapped += 1;
+                    }
+                }
+                for version in document.versions.iter_mut() {
+                    if crate::document_crypto::rewrap_document_key(vault, &version.content_id, &mut version.encryption)? {
+                        rewrapped += 1;
+                    }
+                    if let Some(preview) = version.preview.as_mut() {
+                        let preview_id = crate::document_preview::preview_id(&version.content_id);
+                        if crate::document_crypto::rewrap_document_key(vault, &preview_id, &mut preview.encryption)? {
+                            rewrapped += 1;
+                        }
+                    }
+                }
+            }
+            Ok(rewrapped)
+        })?
+    };
+
+    if rewrapped > 0 {
+        save_local_partition().map_err(|e| anyhow::anyhow!(e))?;
+    }
+    Ok(rewrapped)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportDocumentFileRequest {
+    pub patient_id: String,
+    pub appointment_id: Option<String>,
+    pub file_path: String,
+    pub filename: Option<String>,
+    pub file_type: Option<String>,
+    #[serde(default)]
+    pub document_id: Option<String>, // Nova versão de um documento existente
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+// Importa um arquivo do disco cifrando em segmentos, sem carregá-lo inteiro na memória
+#[tauri::command]
+pub async fn import_document_file(
+    _app_handle: AppHandle,
+    request: ImportDocumentFileRequest,
+) -> Result<Document, String> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
+    }
+    if let Some(document_id) = &request.document_id {
+        ensure_document_of_patient(document_id, &request.patient_id)?;
+    }
+
+    let source = std::path::PathBuf::from(&request.file_path);
+    let filename = request.filename.clone().unwrap_or_else(|| {
+        source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| "documento".to_string())
+    });
+    let validated = crate::document_validation::validate_file(&source, &filename, request.file_type.as_deref())
+        .map_err(|e| format!("Document rejected: {}", e))?;
+    let content_hash = hash_file(source.clone()).await?;
+
+    let blob_id = blob_id_for(&content_hash)?;
+    let (_claim, existing) = claim_blob(&blob_id, request.document_id.as_deref())?;
+    let version = match existing {
+        Some(existing) => reuse_blob_version(existing, filename, Some(validated.mime_type), request.note),
+        None => {
+            let destination = crate::document_crypto::stream_path(&blob_id)
+                .map_err(|e| format!("Failed to prepare document storage: {}", e))?;
+
+            let task_blob_id = blob_id.clone();
+            let task_mime_type = validated.mime_type.clone();
+            let (encrypted, preview) = tauri::async_runtime::spawn_blocking(move || {
+                let encrypted = crate::document_crypto::encrypt_file(&task_blob_id, &source, &destination);
+                let preview = crate::document_preview::generate_preview_from_file(&task_blob_id, &task_mime_type, &source);
+                (encrypted, preview)
+            })
+            .await
+            .map_err(|e| format!("Document import task failed: {}", e))?;
+            let (encryption, size) = encrypted.map_err(|e| format!("Failed to encrypt document: {}", e))?;
+            let preview = build_preview(preview);
+
+            // O arquivo não pode ter mudado entre a validação/hash e a cifragem
+            if size != validated.size || encryption.sha256 != content_hash {
+                remove_document_content(&blob_id);
+                return Err("Document rejected: file changed during import".into());
+            }
+
+            let mut version = new_document_version(
+                blob_id,
+                encryption,
+                Some(size as i64),
+                filename,
+                Some(validated.mime_type),
+                request.note,
+            );
+            version.preview = preview;
+            version
+        }
+    };
+    match request.document_id {
+        Some(document_id) => add_document_version(&document_id, version),
+        None => insert_new_document(request.patient_id, request.appointment_id, version),
+    }
+}
+
+fn find_segmented_document(document_id: &str) -> Result<(Document, crate::document_crypto::DocumentEncryption), String> {
+    let document = find_document(document_id)?;
+    match document.encryption.clone() {
+        Some(metadata) if metadata.segment_size.is_some() => Ok((document, metadata)),
+        _ => Err("Document is not stored in segmented format; use get_document_content".into()),
+    }
+}
+
+// Decifra para um arquivo temporário (ex.: abrir no visualizador do sistema) e
+// devolve o caminho. O frontend chama release_temp_document ao fechar o
+// documento; cópias esquecidas expiram pela varredura de arquivos temporários.
+#[tauri::command]
+pub async fn export_document_to_temp(_app_handle: AppHandle, document_id: String) -> Result<String, String> {
+    let (document, metadata) = find_segmented_document(&document_id)?;
+    let content_id = content_id_of(&document);
+    let source = crate::document_crypto::stream_path(&content_id)
+        .map_err(|e| format!("Failed to locate document: {}", e))?;
+
+    let path = tauri::async_runtime::spawn_blocking(move || {
+        crate::document_crypto::decrypt_file_to_temp(&content_id, &source, &metadata, &document.filename)
+    })
+    .await
+    .map_err(|e| format!("Document export task failed: {}", e))?
+    .map_err(|e| format!("Failed to decrypt document: {}", e))?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn release_temp_document(_app_handle: AppHandle, path: String) -> Result<(), String> {
+    crate::document_crypto::release_temp_file(std::path::Path::new(&path))
+        .map_err(|e| format!("Failed to remove temporary document: {}", e))
+}
+
+// Envia o documento decifrado ao frontend em blocos binários pelo canal. Cada
+// bloco já foi autenticado; o resultado final confirma o SHA-256 do arquivo todo.
+#[tauri::command]
+pub async fn stream_document(
+    _app_handle: AppHandle,
+    document_id: String,
+    on_chunk: tauri::ipc::Channel,
+) -> Result<u64, String> {
+    let (document, metadata) = find_segmented_document(&document_id)?;
+    let content_id = content_id_of(&document);
+    let source = crate::document_crypto::stream_path(&content_id)
+        .map_err(|e| format!("Failed to locate document: {}", e))?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = std::fs::File::open(&source)?;
+        crate::document_crypto::decrypt_stream(
+            &content_id,
+            std::io::BufReader::new(file),
+            ChannelWriter(on_chunk),
+            &metadata,
+        )
+    })
+    .await
+    .map_err(|e| format!("Document stream task failed: {}", e))?
+    .map_err(|e| format!("Failed to decrypt document: {}", e))
+}
+
+struct ChannelWriter(tauri::ipc::Channel);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .send(tauri::ipc::InvokeResponseBody::Raw(buf.to_vec()))
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Enfileira o arquivo cifrado de um documento para envio ao Supabase Storage
+#[tauri::command]
+pub async fn queue_document_for_storage_sync(_app_handle: AppHandle, document_id: String) -> Result<String, String> {
+    let (document, _) = find_segmented_document(&document_id)?;
+    let (user_id, _) = current_session_user()?;
+    let content_id = content_id_of(&document);
+    let path = crate::document_crypto::stream_path(&content_id)
+        .map_err(|e| format!("Failed to locate document: {}", e))?;
+
+    let mut sync = storage_sync().await?;
+    sync.add_file(
+        &path.to_string_lossy(),
+        Some(format!("documents/{}.dbse", content_id)),
+        Some(user_id),
+    )
+    .await
+    .map_err(|e| format!("Failed to queue document for sync: {}", e))
+}
+
+// Vai para a lixeira; o conteúdo segue referenciado até purge_deleted_documents
+#[tauri::command]
+pub async fn delete_document(_app_handle: AppHandle, id: String) -> Result<(), String> {
+    if let Some(document) = DOCUMENTS.lock().unwrap().iter_mut().find(|d| d.id == id && d.deleted_at.is_none()) {
+        let now = chrono::Utc::now().to_rfc3339();
+        document.deleted_at = Some(now.clone());
+        document.updated_at = now;
+    }
+    save_local_partition()
+}
+
+// =========================
+// Sincronização de arquivos (Storage)
+// =========================
+
+async fn storage_sync() -> Result<tokio::sync::MutexGuard<'static, crate::storage_sync::StorageSync>, String> {
+    crate::storage_sync::shared()
+        .await
+        .map_err(|e| format!("Failed to load storage sync state: {}", e))
+}
+
+// Só arquivos já cifrados (formato segmentado) entram na fila; o StorageSync
+// recusa qualquer outro
+#[tauri::command]
+pub async fn add_file_to_sync(
+    _app_handle: AppHandle,
+    file_path: String,
+    remote_path: Option<String>,
+    uploaded_by: Option<String>,
+) -> Result<String, String> {
+    let mut sync = storage_sync().await?;
+    sync.add_file(&file_path, remote_path, uploaded_by)
+        .await
+        .map_err(|e| format!("Failed to add file to sync: {}", e))
+}
+
+#[tauri::command]
+pub async fn sync_all_files(_app_handle: AppHandle) -> Result<crate::storage_sync::StorageSyncStats, String> {
+    let mut sync = storage_sync().await?;
+    sync.sync_all_files()
+        .await
+        .map_err(|e| format!("Failed to sync files: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_file_sync_status(_app_handle: AppHandle) -> Result<crate::storage_sync::StorageSyncStats, String> {
+    let sync = storage_sync().await?;
+    Ok(sync.get_stats().clone())
+}
+
+#[tauri::command]
+pub async fn get_files_by_status(
+    _app_handle: AppHandle,
+    status: String,
+) -> Result<Vec<crate::storage_sync::FileMetadata>, String> {
+    use crate::storage_sync::FileSyncStatus;
+
+    let status = match status.as_str() {
+        "pending" => FileSyncStatus::Pending,
+        "syncing" => FileSyncStatus::Syncing,
+        "synced" => FileSyncStatus::Synced,
+        "failed" => FileSyncStatus::Failed,
+        "conflict" => FileSyncStatus::Conflict,
+        "local_only" => FileSyncStatus::LocalOnly,
+        "remote_only" => FileSyncStatus::RemoteOnly,
+        _ => return Err(format!("Invalid file sync status: {}", status)),
+    };
+    let sync = storage_sync().await?;
+    Ok(sync.get_files_by_status(status).into_iter().cloned().collect())
+}
+
+#[tauri::command]
+pub async fn download_file(_app_handle: AppHandle, file_id: String) -> Result<bool, String> {
+    let mut sync = storage_sync().await?;
+    sync.download_file(&file_id)
+        .await
+        .map_err(|e| format!("Failed to download file: {}", e))?;
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn resolve_file_conflict(_app_handle: AppHandle, file_id: String, keep_local: bool) -> Result<bool, String> {
+    let mut sync = storage_sync().await?;
+    sync.resolve_conflict(&file_id, keep_local)
+        .await
+        .map_err(|e| format!("Failed to resolve file conflict: {}", e))?;
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn remove_file_from_sync(_app_handle: AppHandle, file_id: String) -> Result<bool, String> {
+    let mut sync = storage_sync().await?;
+    sync.remove_file(&file_id)
+        .await
+        .map_err(|e| format!("Failed to remove file from sync: {}", e))?;
+    Ok(true)
+}
+
+// =========================
+// Versões de documentos
+// =========================
+
+const DOCUMENT_RETENTION_FILE: &str = "document_retention.json";
+
+// Uma versão anterior é descartada quando passa de qualquer um dos limites: está
+// além das max_versions mais recentes ou tem mais de max_age_days. A versão
+// atual nunca é descartada. O padrão de 20 anos acompanha a guarda do prontuário.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRetentionPolicy {
+    pub max_versions: usize,
+    #[serde(alias = "min_retention_days")]
+    pub max_age_days: i64,
+}
+
+impl Default for DocumentRetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_versions: 20,
+            max_age_days: 20 * 365,
+        }
+    }
+}
+
+fn retention_policy_path() -> Result<std::path::PathBuf, String> {
+    let app_data = dirs::data_dir()
+        .ok_or("Failed to get app data directory")?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data).map_err(|e| format!("Failed to create data directory: {}", e))?;
+    Ok(app_data.join(DOCUMENT_RETENTION_FILE))
+}
+
+fn load_retention_policy() -> DocumentRetentionPolicy {
+    retention_policy_path()
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn content_id_of(document: &Document) -> String {
+    document.content_id.clone().unwrap_or_else(|| document.id.clone())
+}
+
+// Documentos anteriores ao versionamento ganham a versão 1 a partir do estado atual
+fn ensure_version_history(document: &mut Document) {
+    if !document.versions.is_empty() {
+        return;
+    }
+    if let Some(encryption) = document.encryption.clone() {
+        document.versions.push(DocumentVersion {
+            version: 1,
+            content_id: content_id_of(document),
+            content_hash: encryption.sha256.clone(),
+            file_size: document.file_size,
+            filename: document.filename.clone(),
+            file_type: document.file_type.clone(),
+            uploaded_by: document.last_editor.clone(),
+            uploaded_at: document.created_at.clone(),
+            note: None,
+            rolled_back_from: None,
+            encryption,
+            preview: None,
+            deduplicated: false,
+        });
+        document.current_version = 1;
+    }
+}
+
+fn new_document_version(
+    content_id: String,
+    encryption: crate::document_crypto::DocumentEncryption,
+    file_size: Option<i64>,
+    filename: String,
+    file_type: Option<String>,
+    note: Option<String>,
+) -> DocumentVersion {
+    DocumentVersion {
+        version: 0, // Definido ao entrar no histórico
+        content_id,
+        content_hash: encryption.sha256.clone(),
+        file_size,
+        filename,
+        file_type,
+        uploaded_by: current_session_user().ok().map(|(id, _)| id),
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+        note,
+        rolled_back_from: None,
+        encryption,
+        preview: None,
+        deduplicated: false,
+    }
+}
+
+// Nova versão apontando para um blob que já existia
+fn reuse_blob_version(
+    existing: DocumentVersion,
+    filename: String,
+    file_type: Option<String>,
+    note: Option<String>,
+) -> DocumentVersion {
+    DocumentVersion {
+        version: 0,
+        filename,
+        file_type,
+        uploaded_by: current_session_user().ok().map(|(id, _)| id),
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+        note,
+        rolled_back_from: None,
+        deduplicated: true,
+        ..existing
+    }
+}
+
+fn insert_new_document(patient_id: String, appointment_id: Option<String>, mut version: DocumentVersion) -> Result<Document, String> {
+    version.version = 1;
+    let now = chrono::Utc::now().to_rfc3339();
+    retain_blob(&version);
+    let document = Document {
+        id: uuid::Uuid::new_v4().to_string(),
+        patient_id,
+        appointment_id,
+        filename: version.filename.clone(),
+        file_type: version.file_type.clone(),
+        file_size: version.file_size,
+        encrypted: true,
+        created_at: now.clone(),
+        updated_at: now,
+        rev: 0, // Will be set by server
+        deleted_at: None,
+        last_editor: Some("local_device".to_string()),
+        last_pulled_rev: None,
+        encryption: Some(version.encryption.clone()),
+        content_id: Some(version.content_id.clone()),
+        current_version: 1,
+        versions: vec![version],
+    };
+
+    DOCUMENTS.lock().unwrap().push(document.clone());
+    save_local_partition()?;
+    Ok(document)
+}
+
+// Acrescenta uma versão ao histórico e a torna a versão atual
+fn add_document_version(document_id: &str, mut version: DocumentVersion) -> Result<Document, String> {
+    let policy = load_retention_policy();
+    let (document, pruned) = {
+        let mut docs = DOCUMENTS.lock().unwrap();
+        let document = docs.iter_mut().find(|d| d.id == document_id).ok_or("Document not found")?;
+        ensure_version_history(document);
+
+        version.version = document.versions.iter().map(|v| v.version).max().unwrap_or(0) + 1;
+        document.content_id = if version.content_id == document.id { None } else { Some(version.content_id.clone()) };
+        document.encryption = Some(version.encryption.clone());
+        document.encrypted = true;
+        document.file_size = version.file_size;
+        document.filename = version.filename.clone();
+        document.file_type = version.file_type.clone();
+        document.current_version = version.version;
+        document.updated_at = version.uploaded_at.clone();
+        document.last_editor = version.uploaded_by.clone().or(document.last_editor.take());
+        retain_blob(&version);
+        document.versions.push(version);
+
+        let pruned = apply_retention(document, &policy, chrono::Utc::now());
+        (document.clone(), pruned)
+    };
+
+    for content_id in pruned {
+        release_blob(&content_id);
+    }
+    save_local_partition()?;
+    Ok(document)
+}
+
+// Remove do histórico as versões vencidas pela política e devolve o blob de cada
+// uma, para que as referências sejam soltas
+fn apply_retention(
+    document: &mut Document,
+    policy: &DocumentRetentionPolicy,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<String> {
+    let current = document.current_version;
+    let keep_from = document.versions.len().saturating_sub(policy.max_versions.max(1));
+
+    let mut pruned = Vec::new();
+    let mut index = 0;
+    document.versions.retain(|v| {
+        let position = index;
+        index += 1;
+        if v.version == current {
+            return true;
+        }
+        // Data ilegível não conta como vencida
+        let too_old = chrono::DateTime::parse_from_rfc3339(&v.uploaded_at)
+            .map(|t| now.signed_duration_since(t) > chrono::Duration::days(policy.max_age_days))
+            .unwrap_or(false);
+        let expired = position < keep_from || too_old;
+        if expired {
+            pruned.push(v.content_id.clone());
+        }
+        !expired
+    });
+    pruned
+}
+
+#[tauri::command]
+pub async fn list_document_versions(_app_handle: AppHandle, document_id: String) -> Result<Vec<DocumentVersion>, String> {
+    let mut document = find_document(&document_id)?;
+    ensure_version_history(&mut document);
+    let mut versions = document.versions;
+    versions.sort_by_key(|v| std::cmp::Reverse(v.version));
+    Ok(versions)
+}
+
+#[tauri::command]
+pub async fn get_document_version_content(_app_handle: AppHandle, document_id: String, version: u32) -> Result<String, String> {
+    let mut document = find_document(&document_id)?;
+    ensure_version_history(&mut document);
+    let entry = document.versions
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or("Document version not found")?;
+
+    let plaintext = read_document_bytes(&entry.content_id, &entry.encryption, entry.file_size)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(plaintext))
+}
+
+// Restaurar uma versão antiga cria uma nova versão com o mesmo conteúdo; o
+// histórico nunca é reescrito
+#[tauri::command]
+pub async fn rollback_document(
+    _app_handle: AppHandle,
+    document_id: String,
+    version: u32,
+    reason: Option<String>,
+) -> Result<Document, String> {
+    let mut document = find_document(&document_id)?;
+    ensure_version_history(&mut document);
+    if document.current_version == version {
+        return Err("Version is already the current version".into());
+    }
+    let target = document.versions
+        .iter()
+        .find(|v| v.version == version)
+        .cloned()
+        .ok_or("Document version not found")?;
+
+    let restored = DocumentVersion {
+        version: 0,
+        uploaded_by: current_session_user().ok().map(|(id, _)| id),
+        uploaded_at: chrono::Utc::now().to_rfc3339(),
+        note: reason.clone(),
+        rolled_back_from: Some(version),
+        deduplicated: false,
+        ..target
+    };
+    let document = add_document_version(&document_id, restored)?;
+
+    audit_current_user(
+        "DOCUMENT_ROLLBACK",
+        "DOCUMENT",
+        Some(document_id.clone()),
+        format!(
+            "Document rolled back to version {} (new version {}){}",
+            version,
+            document.current_version,
+            reason.map(|r| format!(": {}", r)).unwrap_or_default()
+        ),
+    ).await?;
+    Ok(document)
+}
+
+#[tauri::command]
+pub async fn get_document_retention_policy(_app_handle: AppHandle) -> Result<DocumentRetentionPolicy, String> {
+    Ok(load_retention_policy())
+}
+
+#[tauri::command]
+pub async fn set_document_retention_policy(
+    _app_handle: AppHandle,
+    policy: DocumentRetentionPolicy,
+) -> Result<DocumentRetentionPolicy, String> {
+    ensure_admin()?;
+    if policy.max_versions == 0 || policy.max_age_days < 0 {
+        return Err("Invalid retention policy".into());
+    }
+
+    let json = serde_json::to_string_pretty(&policy)
+        .map_err(|e| format!("Failed to serialize retention policy: {}", e))?;
+    crate::profiles::write_atomic(&retention_policy_path()?, &json)
+        .map_err(|e| format!("Failed to save retention policy: {}", e))?;
+
+    audit_current_user(
+        "DOCUMENT_RETENTION_POLICY_UPDATED",
+        "DOCUMENT",
+        None,
+        format!("max_versions={}, max_age_days={}", policy.max_versions, policy.max_age_days),
+    ).await?;
+    Ok(policy)
+}
+
+// Aplica a política a todos os documentos; devolve quantas versões foram descartadas
+#[tauri::command]
+pub async fn apply_document_retention(_app_handle: AppHandle) -> Result<usize, String> {
+    ensure_admin()?;
+    let policy = load_retention_policy();
+    let now = chrono::Utc::now();
+    let pruned: Vec<String> = {
+        let mut docs = DOCUMENTS.lock().unwrap();
+        docs.iter_mut()
+            .flat_map(|document| {
+                ensure_version_history(document);
+                apply_retention(document, &policy, now)
+            })
+            .collect()
+    };
+
+    for content_id in &pruned {
+        release_blob(content_id);
+    }
+    save_local_partition()?;
+
+    audit_current_user(
+        "DOCUMENT_RETENTION_APPLIED",
+        "DOCUMENT",
+        None,
+        format!("{} expired document versions removed", pruned.len()),
+    ).await?;
+    Ok(pruned.len())
+}
+
+// =========================
+// Blobs de documentos (deduplicação)
+// =========================
+
+// Blobs sendo gravados ou recebendo uma nova referência agora; a coleta de lixo
+// não os toca. Um segundo upload do mesmo conteúdo enquanto o primeiro não
+// termina é recusado (o usuário tenta de novo), em vez de esperar
+static BLOBS_IN_FLIGHT: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+struct InFlightBlob(String);
+
+impl Drop for InFlightBlob {
+    fn drop(&mut self) {
+        BLOBS_IN_FLIGHT.lock().unwrap().remove(&self.0);
+    }
+}
+
+fn blob_id_for(content_hash: &str) -> Result<String, String> {
+    crate::keyvault::with_shared_vault(|vault| vault.content_address(content_hash))
+        .map_err(|e| format!("Failed to address document content: {}", e))
+}
+
+async fn hash_file(path: std::path::PathBuf) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || crate::document_crypto::sha256_file(&path))
+        .await
+        .map_err(|e| format!("Document hashing task failed: {}", e))?
+        .map_err(|e| format!("Failed to read document: {}", e))
+}
+
+// Reserva o blob para este upload e devolve a versão que já o referencia, se houver
+fn claim_blob(blob_id: &str, document_id: Option<&str>) -> Result<(InFlightBlob, Option<DocumentVersion>), String> {
+    if let Some(document_id) = document_id {
+        if content_id_of(&find_document(document_id)?) == blob_id {
+            return Err("This file is already the current version of the document".into());
+        }
+    }
+
+    let mut in_flight = BLOBS_IN_FLIGHT.lock().unwrap();
+    if !in_flight.insert(blob_id.to_string()) {
+        return Err("The same file is already being imported; try again in a moment".into());
+    }
+    let existing = DOCUMENTS.lock().unwrap()
+        .iter()
+        .flat_map(|d| d.versions.iter())
+        .find(|v| v.content_id == blob_id)
+        .cloned();
+    Ok((InFlightBlob(blob_id.to_string()), existing))
+}
+
+fn retain_blob(version: &DocumentVersion) {
+    DOCUMENT_BLOBS.lock().unwrap()
+        .entry(version.content_id.clone())
+        .or_insert_with(|| DocumentBlob {
+            blob_id: version.content_id.clone(),
+            content_hash: version.content_hash.clone(),
+            file_size: version.file_size,
+            ref_count: 0,
+            created_at: version.uploaded_at.clone(),
+        })
+        .ref_count += 1;
+}
+
+// Solta uma referência; o conteúdo é apagado quando não resta nenhuma
+fn release_blob(content_id: &str) -> bool {
+    let unreferenced = match DOCUMENT_BLOBS.lock().unwrap().get_mut(content_id) {
+        Some(blob) => {
+            blob.ref_count = blob.ref_count.saturating_sub(1);
+            blob.ref_count == 0
+        }
+        None => true,
+    };
+    unreferenced && collect_blob(content_id)
+}
+
+fn is_content_referenced(content_id: &str) -> bool {
+    DOCUMENTS.lock().unwrap()
+        .iter()
+        .any(|d| content_id_of(d) == content_id || d.versions.iter().any(|v| v.content_id == content_id))
+}
+
+fn collect_blob(content_id: &str) -> bool {
+    let in_flight = BLOBS_IN_FLIGHT.lock().unwrap();
+    if in_flight.contains(content_id) || is_content_referenced(content_id) {
+        return false;
+    }
+    DOCUMENT_BLOBS.lock().unwrap().remove(content_id);
+    remove_document_content(content_id);
+    true
+}
+
+// Recalcula as contagens a partir das versões (a fonte da verdade)
+fn reconcile_document_blobs() {
+    let docs = DOCUMENTS.lock().unwrap();
+    let mut blobs = DOCUMENT_BLOBS.lock().unwrap();
+    let mut counts: HashMap<String, u32> = HashMap::new();
+
+    for document in docs.iter() {
+        let mut document = document.clone();
+        ensure_version_history(&mut document);
+        for version in &document.versions {
+            *counts.entry(version.content_id.clone()).or_default() += 1;
+            blobs.entry(version.content_id.clone()).or_insert_with(|| DocumentBlob {
+                blob_id: version.content_id.clone(),
+                content_hash: version.content_hash.clone(),
+                file_size: version.file_size,
+                ref_count: 0,
+                created_at: version.uploaded_at.clone(),
+            });
+        }
+    }
+    for (blob_id, blob) in blobs.iter_mut() {
+        blob.ref_count = counts.get(blob_id).copied().unwrap_or(0);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateDocument {
+    pub document_id: String,
+    pub patient_id: String,
+    pub filename: String,
+    pub version: u32,
+    pub uploaded_at: String,
+}
+
+fn find_duplicates(blob_id: &str) -> Vec<DuplicateDocument> {
+    DOCUMENTS.lock().unwrap()
+        .iter()
+        .filter(|d| d.deleted_at.is_none())
+        .flat_map(|d| {
+            d.versions.iter().filter(|v| v.content_id == blob_id).map(move |v| DuplicateDocument {
+                document_id: d.id.clone(),
+                patient_id: d.patient_id.clone(),
+                filename: v.filename.clone(),
+                version: v.version,
+                uploaded_at: v.uploaded_at.clone(),
+            })
+        })
+        .collect()
+}
+
+// Checagem antes do upload: o frontend envia o SHA-256 calculado no navegador
+#[tauri::command]
+pub async fn check_document_duplicate(_app_handle: AppHandle, content_hash: String) -> Result<Vec<DuplicateDocument>, String> {
+    let content_hash = content_hash.trim().to_lowercase();
+    if content_hash.len() != 64 || !content_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Invalid SHA-256 hash".into());
+    }
+    Ok(find_duplicates(&blob_id_for(&content_hash)?))
+}
+
+#[tauri::command]
+pub async fn check_document_file_duplicate(_app_handle: AppHandle, file_path: String) -> Result<Vec<DuplicateDocument>, String> {
+    let content_hash = hash_file(std::path::PathBuf::from(file_path)).await?;
+    Ok(find_duplicates(&blob_id_for(&content_hash)?))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentPurgeReport {
+    pub documents_purged: usize,
+    pub blobs_collected: usize,
+}
+
+// Esvazia a lixeira (documentos apagados há mais de older_than_days) e coleta os
+// blobs que ficaram sem referência
+#[tauri::command]
+pub async fn purge_deleted_documents(
+    _app_handle: AppHandle,
+    older_than_days: Option<i64>,
+) -> Result<DocumentPurgeReport, String> {
+    ensure_admin()?;
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(older_than_days.unwrap_or(0).max(0));
+    let purged: Vec<Document> = {
+        let mut docs = DOCUMENTS.lock().unwrap();
+        let (purged, kept): (Vec<Document>, Vec<Document>) = std::mem::take(&mut *docs)
+            .into_iter()
+            .partition(|d| {
+                d.deleted_at
+                    .as_deref()
+                    .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                    .is_some_and(|t| t.with_timezone(&chrono::Utc) <= cutoff)
+            });
+        *docs = kept;
+        purged
+    };
+
+    let mut blobs_collected = 0;
+    for mut document in purged.iter().cloned() {
+        ensure_version_history(&mut document);
+        if document.versions.is_empty() && collect_blob(&document.id) {
+            blobs_collected += 1; // Conteúdo legado, anterior à criptografia
+        }
+        for version in &document.versions {
+            if release_blob(&version.content_id) {
+                blobs_collected += 1;
+            }
+        }
+    }
+
+    // Contagens divergentes (ex.: falha no meio de um upload) são corrigidas aqui
+    reconcile_document_blobs();
+    let orphaned: Vec<String> = DOCUMENT_BLOBS.lock().unwrap()
+        .values()
+        .filter(|b| b.ref_count == 0)
+        .map(|b| b.blob_id.clone())
+        .collect();
+    blobs_collected += orphaned.iter().filter(|id| collect_blob(id)).count();
+    save_local_partition()?;
+
+    audit_current_user(
+        "DOCUMENTS_PURGED",
+        "DOCUMENT",
+        None,
+        format!("{} deleted documents purged, {} blobs collected", purged.len(), blobs_collected),
+    ).await?;
+    Ok(DocumentPurgeReport {
+        documents_purged: purged.len(),
+        blobs_collected,
+    })
+}
 
-pub static PATIENTS: LazyLock<Mutex<Vec<Patient>>> = LazyLock::new(|| Mutex::new(Vec::new()));
-pub static APPOINTMENTS: LazyLock<Mutex<Vec<Appointment>>> = LazyLock::new(|| Mutex::new(Vec::new()));
-pub static DOCUMENTS: LazyLock<Mutex<Vec<Document>>> = LazyLock::new(|| Mutex::new(Vec::new()));
-pub static DOCUMENT_CONTENT: LazyLock<Mutex<HashMap<String, String>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentPreviewResponse {
+    pub document_id: String,
+    pub version: u32,
+    pub kind: crate::document_preview::PreviewKind,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: String, // Base64
+}
 
-// =========================
-// Serviços (Auth/Crypto/DPAPI)
-// =========================
+// Miniatura da versão pedida (ou da atual), sem decifrar o documento completo
+#[tauri::command]
+pub async fn get_document_preview(
+    _app_handle: AppHandle,
+    document_id: String,
+    version: Option<u32>,
+) -> Result<Option<DocumentPreviewResponse>, String> {
+    let mut document = find_document(&document_id)?;
+    ensure_version_history(&mut document);
+    let version = version.unwrap_or(document.current_version);
+    let entry = document.versions
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or("Document version not found")?;
 
-pub static AUTH_SERVICE: LazyLock<Mutex<Option<Arc<crate::auth::AuthService>>>> =
-    LazyLock::new(|| Mutex::new(None));
+    let Some(preview) = entry.preview.as_ref() else {
+        return Ok(None);
+    };
+    let bytes = crate::document_preview::load_preview(&entry.content_id, preview)
+        .map_err(|e| format!("Failed to read document preview: {}", e))?;
 
-pub static DPAPI_SERVICE: LazyLock<Mutex<Option<crate::dpapi::DpapiService>>> =
-    LazyLock::new(|| Mutex::new(None));
+    Ok(Some(DocumentPreviewResponse {
+        document_id,
+        version,
+        kind: preview.kind,
+        mime_type: preview.mime_type.clone(),
+        width: preview.width,
+        height: preview.height,
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    }))
+}
 
-pub static CRYPTO_SERVICE: LazyLock<Mutex<Option<crate::crypto::CryptoService>>> =
-    LazyLock::new(|| Mutex::new(None));
+#[tauri::command]
+pub async fn get_document_policy(_app_handle: AppHandle) -> Result<crate::document_validation::DocumentPolicy, String> {
+    Ok(crate::document_validation::load_policy())
+}
 
-pub static CURRENT_SESSION: LazyLock<Mutex<Option<SecureSession>>> =
-    LazyLock::new(|| Mutex::new(None));
+// Tipos aceitos e tamanho máximo de documentos da clínica
+#[tauri::command]
+pub async fn set_document_policy(
+    _app_handle: AppHandle,
+    policy: crate::document_validation::DocumentPolicy,
+) -> Result<crate::document_validation::DocumentPolicy, String> {
+    ensure_admin()?;
+    crate::document_validation::save_policy(&policy)
+        .map_err(|e| format!("Failed to save document policy: {}", e))?;
 
-pub static AUDIT_LOGS: LazyLock<Mutex<Vec<crate::auth::AuditLog>>> =
-    LazyLock::new(|| Mutex::new(Vec::new()));
+    audit_current_user(
+        "DOCUMENT_POLICY_UPDATED",
+        "DOCUMENT",
+        None,
+        format!("allowed_types={}, max_size_bytes={}", policy.allowed_types.join(","), policy.max_size_bytes),
+    ).await?;
+    Ok(policy)
+}
 
 // =========================
-// Comandos básicos
+// Modelos de anamnese
 // =========================
 
 #[tauri::command]
-pub async fn greet(name: &str) -> Result<String, String> {
-    Ok(format!("Hello, {}! You've been greeted from Rust!", name))
+pub async fn get_anamnesis_templates(
+    _app_handle: AppHandle,
+    include_archived: Option<bool>,
+) -> Result<Vec<crate::anamnesis::AnamnesisTemplate>, String> {
+    crate::anamnesis::list_templates(include_archived.unwrap_or(false))
+        .map_err(|e| format!("Failed to load anamnesis templates: {}", e))
 }
 
-// =========================
-// Pacientes
-// =========================
+#[tauri::command]
+pub async fn get_anamnesis_template(
+    _app_handle: AppHandle,
+    template_id: String,
+    version: Option<u32>,
+) -> Result<crate::anamnesis::AnamnesisTemplate, String> {
+    crate::anamnesis::get_template(&template_id, version)
+        .map_err(|e| format!("Failed to load anamnesis template: {}", e))
+}
 
+// Cria um modelo ou publica nova versão (as anteriores continuam valendo para o que já foi preenchido)
 #[tauri::command]
-pub async fn get_patients(_app_handle: AppHandle) -> Result<Vec<Patient>, String> {
-    Ok(PATIENTS.lock().unwrap().clone())
+pub async fn save_anamnesis_template(
+    _app_handle: AppHandle,
+    request: crate::anamnesis::SaveAnamnesisTemplateRequest,
+) -> Result<crate::anamnesis::AnamnesisTemplate, String> {
+    ensure_admin()?;
+    let user_id = current_session_user().ok().map(|(id, _)| id);
+    let template = crate::anamnesis::save_template(request, user_id)
+        .map_err(|e| format!("Failed to save anamnesis template: {}", e))?;
+
+    audit_current_user(
+        "ANAMNESIS_TEMPLATE_SAVED",
+        "ANAMNESIS_TEMPLATE",
+        Some(template.id.clone()),
+        format!("{} version {} ({} questions)", template.name, template.version, template.questions.len()),
+    ).await?;
+    Ok(template)
 }
 
 #[tauri::command]
-pub async fn create_patient(
+pub async fn archive_anamnesis_template(
     _app_handle: AppHandle,
-    request: CreatePatientRequest,
-) -> Result<Patient, String> {
-    let id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
+    template_id: String,
+) -> Result<crate::anamnesis::AnamnesisTemplate, String> {
+    ensure_admin()?;
+    let user_id = current_session_user().ok().map(|(id, _)| id);
+    let template = crate::anamnesis::archive_template(&template_id, user_id)
+        .map_err(|e| format!("Failed to archive anamnesis template: {}", e))?;
 
-    let patient = Patient {
-        id: id.clone(),
-        name: request.name,
-        email: request.email,
-        phone: request.phone,
-        birth_date: request.birth_date,
-        address: request.address,
-        notes: request.notes,
-        created_at: now.clone(),
-        updated_at: now.clone(),
-        rev: 0, // Will be set by server
-        deleted_at: None,
-        last_editor: Some("local_device".to_string()),
-        last_pulled_rev: None,
-    };
+    audit_current_user(
+        "ANAMNESIS_TEMPLATE_ARCHIVED",
+        "ANAMNESIS_TEMPLATE",
+        Some(template_id),
+        format!("{} archived at version {}", template.name, template.version),
+    ).await?;
+    Ok(template)
+}
 
-    PATIENTS.lock().unwrap().push(patient.clone());
-    Ok(patient)
+// Validação campo a campo para o formulário, sem gravar nada
+#[tauri::command]
+pub async fn validate_anamnesis(
+    _app_handle: AppHandle,
+    template_id: String,
+    template_version: Option<u32>,
+    answers: HashMap<String, serde_json::Value>,
+) -> Result<Vec<crate::anamnesis::AnswerError>, String> {
+    let template = crate::anamnesis::get_template(&template_id, template_version)
+        .map_err(|e| format!("Failed to load anamnesis template: {}", e))?;
+    Ok(crate::anamnesis::validate_answers(&template, &answers).err().unwrap_or_default())
 }
 
 #[tauri::command]
-pub async fn update_patient(
+pub async fn fill_anamnesis(
     _app_handle: AppHandle,
-    id: String,
-    request: CreatePatientRequest,
-) -> Result<Patient, String> {
-    let mut guard = PATIENTS.lock().unwrap();
-    if let Some(p) = guard.iter_mut().find(|p| p.id == id) {
-        p.name = request.name;
-        p.email = request.email;
-        p.phone = request.phone;
-        p.birth_date = request.birth_date;
-        p.address = request.address;
-        p.notes = request.notes;
-        p.updated_at = chrono::Utc::now().to_rfc3339();
-        Ok(p.clone())
-    } else {
-        Err("Patient not found".into())
+    request: crate::anamnesis::FillAnamnesisRequest,
+) -> Result<crate::anamnesis::FilledAnamnesis, String> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
     }
+    let user_id = current_session_user().ok().map(|(id, _)| id);
+    let anamnesis = crate::anamnesis::fill_anamnesis(request, user_id)
+        .map_err(|e| format!("Failed to save anamnesis: {}", e))?;
+
+    audit_current_user(
+        "ANAMNESIS_FILLED",
+        "PATIENT",
+        Some(anamnesis.patient_id.clone()),
+        format!("Template {} version {}", anamnesis.template_id, anamnesis.template_version),
+    ).await?;
+    Ok(anamnesis)
 }
 
 #[tauri::command]
-pub async fn delete_patient(_app_handle: AppHandle, id: String) -> Result<(), String> {
-    PATIENTS.lock().unwrap().retain(|p| p.id != id);
-    Ok(())
+pub async fn get_patient_anamneses(
+    _app_handle: AppHandle,
+    patient_id: String,
+) -> Result<Vec<crate::anamnesis::FilledAnamnesis>, String> {
+    crate::anamnesis::list_patient_anamneses(&patient_id)
+        .map_err(|e| format!("Failed to load anamneses: {}", e))
 }
 
 #[tauri::command]
-pub async fn search_patients(_app_handle: AppHandle, query: String) -> Result<Vec<Patient>, String> {
-    let q = query.to_lowercase();
-    let out = PATIENTS.lock().unwrap()
-        .iter()
-        .filter(|p| {
-            p.name.to_lowercase().contains(&q) ||
-            p.email.as_ref().map_or(false, |e| e.to_lowercase().contains(&q)) ||
-            p.phone.as_ref().map_or(false, |ph| ph.contains(&query))
-        })
-        .cloned()
-        .collect();
-    Ok(out)
+pub async fn render_anamnesis(_app_handle: AppHandle, anamnesis_id: String) -> Result<String, String> {
+    crate::anamnesis::render_anamnesis(&anamnesis_id)
+        .map_err(|e| format!("Failed to render anamnesis: {}", e))
+}
+
+// Ex.: pacientes que responderam "sim" para hipertensão na anamnese mais recente
+#[tauri::command]
+pub async fn find_patients_by_anamnesis_answer(
+    _app_handle: AppHandle,
+    question_id: String,
+    value: serde_json::Value,
+) -> Result<Vec<crate::anamnesis::AnamnesisMatch>, String> {
+    crate::anamnesis::find_patients_by_answer(&question_id, &value)
+        .map_err(|e| format!("Failed to search anamneses: {}", e))
 }
 
 // =========================
-// Consultas
+// Prontuários (versões imutáveis)
 // =========================
 
+// Cria o prontuário (sem record_id) ou salva uma nova versão dele
 #[tauri::command]
-pub async fn get_appointments(_app_handle: AppHandle) -> Result<Vec<Appointment>, String> {
-    let patients = PATIENTS.lock().unwrap();
-    let mut list: Vec<Appointment> = APPOINTMENTS.lock().unwrap().iter().cloned().collect();
-    for a in &mut list {
-        a.patient_name = patients.iter().find(|p| p.id == a.patient_id).map(|p| p.name.clone());
+pub async fn save_medical_record(
+    _app_handle: AppHandle,
+    request: SaveMedicalRecordRequest,
+) -> Result<WithPatientAlerts<MedicalRecord>, String> {
+    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
+        return Err("Patient not found".into());
     }
-    list.sort_by(|a, b| format!("{} {}", a.date, a.time).cmp(&format!("{} {}", b.date, b.time)));
-    Ok(list)
+    let (user_id, _) = current_session_user()?;
+    let draft_id = request.record_id.clone();
+    let record = crate::medical_records_sync::save_record(request, Some(user_id))
+        .map_err(|e| format!("Failed to save medical record: {}", e))?;
+    discard_committed_draft("medical_record", draft_id.as_deref());
+
+    audit_current_user(
+        "MEDICAL_RECORD_SAVED",
+        "MEDICAL_RECORD",
+        Some(record.record_id.clone()),
+        format!("Version {} ({}) for patient {}", record.version, record.id, record.patient_id),
+    ).await?;
+    Ok(with_patient_alerts(&record.patient_id.clone(), record))
 }
 
 #[tauri::command]
-pub async fn create_appointment(
+pub async fn get_medical_record(
     _app_handle: AppHandle,
-    request: CreateAppointmentRequest,
-) -> Result<Appointment, String> {
-    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
-        return Err("Patient not found".into());
-    }
+    record_id: String,
+) -> Result<WithPatientAlerts<MedicalRecordView>, String> {
+    let view = crate::medical_records_sync::get_record(&record_id)
+        .map_err(|e| format!("Failed to read medical record: {}", e))?
+        .ok_or_else(|| "Medical record not found".to_string())?;
+    Ok(with_patient_alerts(&view.patient_id.clone(), view))
+}
 
-    let id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
+#[tauri::command]
+pub async fn get_patient_medical_records(
+    _app_handle: AppHandle,
+    patient_id: String,
+) -> Result<Vec<WithPatientAlerts<MedicalRecordView>>, String> {
+    let records = crate::medical_records_sync::list_patient_records(&patient_id)
+        .map_err(|e| format!("Failed to list medical records: {}", e))?;
+    let alerts = patient_alerts(&patient_id);
+    Ok(records
+        .into_iter()
+        .map(|view| WithPatientAlerts { data: view, patient_alerts: alerts.clone() })
+        .collect())
+}
 
-    let appointment = Appointment {
-        id: id.clone(),
-        patient_id: request.patient_id,
-        patient_name: None,
-        date: request.date,
-        time: request.time,
-        status: request.status,
-        notes: request.notes,
-        created_at: now.clone(),
-        updated_at: now.clone(),
-        rev: 0, // Will be set by server
-        deleted_at: None,
-        last_editor: Some("local_device".to_string()),
-        last_pulled_rev: None,
-    };
+#[tauri::command]
+pub async fn get_medical_record_history(
+    _app_handle: AppHandle,
+    record_id: String,
+) -> Result<Vec<MedicalRecord>, String> {
+    crate::medical_records_sync::record_history(&record_id)
+        .map_err(|e| format!("Failed to read medical record history: {}", e))
+}
 
-    APPOINTMENTS.lock().unwrap().push(appointment.clone());
-    Ok(appointment)
+#[tauri::command]
+pub async fn get_medical_record_field_history(
+    _app_handle: AppHandle,
+    record_id: String,
+    field: String,
+) -> Result<Vec<FieldHistoryEntry>, String> {
+    crate::medical_records_sync::field_history(&record_id, &field)
+        .map_err(|e| format!("Failed to read field history: {}", e))
 }
 
 #[tauri::command]
-pub async fn update_appointment(
+pub async fn diff_medical_record_versions(
     _app_handle: AppHandle,
-    id: String,
-    request: CreateAppointmentRequest,
-) -> Result<Appointment, String> {
-    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
-        return Err("Patient not found".into());
-    }
+    from_version_id: String,
+    to_version_id: String,
+) -> Result<Vec<FieldChange>, String> {
+    crate::medical_records_sync::diff_versions(&from_version_id, &to_version_id)
+        .map_err(|e| format!("Failed to compare medical record versions: {}", e))
+}
 
-    let mut guard = APPOINTMENTS.lock().unwrap();
-    if let Some(appointment) = guard.iter_mut().find(|a| a.id == id) {
-        appointment.patient_id = request.patient_id;
-        appointment.date = request.date;
-        appointment.time = request.time;
-        appointment.status = request.status;
-        appointment.notes = request.notes;
-        appointment.updated_at = chrono::Utc::now().to_rfc3339();
-        Ok(appointment.clone())
-    } else {
-        Err("Appointment not found".into())
-    }
+// Junta versões concorrentes (irmãs) numa versão revisada pelo profissional
+#[tauri::command]
+pub async fn reconcile_medical_record(
+    _app_handle: AppHandle,
+    record_id: String,
+    parent_version_ids: Vec<String>,
+    fields: MedicalRecordFields,
+) -> Result<WithPatientAlerts<MedicalRecord>, String> {
+    let (user_id, _) = current_session_user()?;
+    let record = crate::medical_records_sync::reconcile_record(&record_id, &parent_version_ids, fields, Some(user_id))
+        .map_err(|e| format!("Failed to reconcile medical record: {}", e))?;
+
+    audit_current_user(
+        "MEDICAL_RECORD_RECONCILED",
+        "MEDICAL_RECORD",
+        Some(record_id),
+        format!("Version {} merges {}", record.version, parent_version_ids.join(", ")),
+    ).await?;
+    Ok(with_patient_alerts(&record.patient_id.clone(), record))
 }
 
 #[tauri::command]
-pub async fn delete_appointment(_app_handle: AppHandle, id: String) -> Result<(), String> {
-    APPOINTMENTS.lock().unwrap().retain(|a| a.id != id);
-    Ok(())
+pub async fn delete_medical_record(
+    _app_handle: AppHandle,
+    record_id: String,
+    base_version_id: Option<String>,
+) -> Result<MedicalRecord, String> {
+    let (user_id, _) = current_session_user()?;
+    let record = crate::medical_records_sync::delete_record(&record_id, base_version_id.as_deref(), Some(user_id))
+        .map_err(|e| format!("Failed to delete medical record: {}", e))?;
+
+    audit_current_user(
+        "MEDICAL_RECORD_DELETED",
+        "MEDICAL_RECORD",
+        Some(record_id),
+        format!("Marked as deleted in version {}", record.version),
+    ).await?;
+    Ok(record)
 }
 
-// =========================
-// Documentos
-// =========================
+#[tauri::command]
+pub async fn sync_medical_records(
+    _app_handle: AppHandle,
+    patient_id: String,
+) -> Result<MedicalRecordSyncReport, String> {
+    crate::medical_records_sync::MedicalRecordsSync::new()
+        .sync_patient(&patient_id)
+        .await
+        .map_err(|e| format!("Failed to sync medical records: {}", e))
+}
 
+// Trava o prontuário assinando a versão atual com a chave do profissional
 #[tauri::command]
-pub async fn get_documents(_app_handle: AppHandle, patient_id: Option<String>) -> Result<Vec<Document>, String> {
-    let docs = DOCUMENTS.lock().unwrap();
-    let out: Vec<Document> = match patient_id {
-        Some(pid) => docs.iter().filter(|d| d.patient_id == pid).cloned().collect(),
-        None => docs.clone(),
-    };
-    Ok(out)
+pub async fn finalize_medical_record(
+    _app_handle: AppHandle,
+    record_id: String,
+    version_id: Option<String>,
+) -> Result<MedicalRecordSignature, String> {
+    let (user_id, _) = current_session_user()?;
+    let signature = crate::medical_records_sync::finalize_record(&record_id, version_id.as_deref(), &user_id)
+        .map_err(|e| format!("Failed to finalize medical record: {}", e))?;
+
+    audit_current_user(
+        "MEDICAL_RECORD_FINALIZED",
+        "MEDICAL_RECORD",
+        Some(record_id),
+        format!("Version {} signed ({})", signature.version_id, signature.algorithm),
+    ).await?;
+    Ok(signature)
 }
 
 #[tauri::command]
-pub async fn create_document(
+pub async fn add_medical_record_addendum(
     _app_handle: AppHandle,
-    request: CreateDocumentRequest,
-) -> Result<Document, String> {
-    if !PATIENTS.lock().unwrap().iter().any(|p| p.id == request.patient_id) {
-        return Err("Patient not found".into());
+    record_id: String,
+    text: String,
+) -> Result<MedicalRecordAddendum, String> {
+    let (user_id, _) = current_session_user()?;
+    let addendum = crate::medical_records_sync::add_addendum(&record_id, &text, &user_id)
+        .map_err(|e| format!("Failed to add addendum: {}", e))?;
+
+    audit_current_user(
+        "MEDICAL_RECORD_ADDENDUM",
+        "MEDICAL_RECORD",
+        Some(record_id),
+        format!("Addendum {} added", addendum.id),
+    ).await?;
+    Ok(addendum)
+}
+
+#[tauri::command]
+pub async fn verify_medical_record(
+    _app_handle: AppHandle,
+    record_id: String,
+) -> Result<MedicalRecordVerification, String> {
+    let verification = crate::medical_records_sync::verify_record(&record_id)
+        .map_err(|e| format!("Failed to verify medical record: {}", e))?;
+
+    // Chave não confiável não é adulteração: o registro fica só como não verificado
+    if verification.finalized && !verification.issues.is_empty() {
+        audit_current_user(
+            "MEDICAL_RECORD_TAMPERING_DETECTED",
+            "MEDICAL_RECORD",
+            Some(record_id),
+            verification.issues.join("; "),
+        ).await?;
     }
+    Ok(verification)
+}
 
-    let id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
+#[tauri::command]
+pub async fn get_medical_record_signer_keys(_app_handle: AppHandle) -> Result<Vec<SignerKeyView>, String> {
+    crate::medical_records_sync::list_signer_keys()
+        .map_err(|e| format!("Failed to list signer keys: {}", e))
+}
 
-    // "Criptografia" simples (somente simulação)
-    let encrypted_content = format!("ENCRYPTED:{}", request.content);
+// Confia na chave de outro dispositivo depois de conferir a impressão digital com o dono
+#[tauri::command]
+pub async fn endorse_medical_record_signer_key(
+    _app_handle: AppHandle,
+    signer_id: String,
+    device_id: String,
+    fingerprint: String,
+) -> Result<KeyEndorsement, String> {
+    let (user_id, _) = current_session_user()?;
+    let endorsement = crate::medical_records_sync::endorse_signer_key(&signer_id, &device_id, &fingerprint, &user_id)
+        .map_err(|e| format!("Failed to endorse signer key: {}", e))?;
 
-    let document = Document {
-        id: id.clone(),
-        patient_id: request.patient_id,
-        appointment_id: request.appointment_id,
-        filename: request.filename,
-        file_type: request.file_type,
-        file_size: request.file_size,
-        encrypted: true,
-        created_at: now.clone(),
-        updated_at: now.clone(),
-        rev: 0, // Will be set by server
-        deleted_at: None,
-        last_editor: Some("local_device".to_string()),
-        last_pulled_rev: None,
-    };
+    audit_current_user(
+        "MEDICAL_RECORD_KEY_ENDORSED",
+        "SIGNER_KEY",
+        Some(signer_id),
+        format!("Key of device {} endorsed ({})", device_id, fingerprint),
+    ).await?;
+    Ok(endorsement)
+}
 
-    DOCUMENTS.lock().unwrap().push(document.clone());
-    DOCUMENT_CONTENT.lock().unwrap().insert(id, encrypted_content);
+// =========================
+// Rascunhos (autosave)
+// =========================
 
-    Ok(document)
+// Salvar o formulário de verdade torna o rascunho dele desnecessário
+fn discard_committed_draft(entity_type: &str, entity_id: Option<&str>) {
+    if let Ok((user_id, _)) = current_session_user() {
+        if let Err(e) = crate::drafts::discard_draft(&user_id, entity_type, entity_id) {
+            eprintln!("Failed to discard draft: {}", e);
+        }
+    }
 }
 
 #[tauri::command]
-pub async fn get_document_content(_app_handle: AppHandle, document_id: String) -> Result<String, String> {
-    let map = DOCUMENT_CONTENT.lock().unwrap();
-    let content = map.get(&document_id).ok_or("Document not found")?;
-    Ok(if let Some(raw) = content.strip_prefix("ENCRYPTED:") { raw.to_string() } else { content.clone() })
+pub async fn save_draft(
+    _app_handle: AppHandle,
+    entity_type: String,
+    entity_id: Option<String>,
+    payload: serde_json::Value,
+) -> Result<crate::drafts::DraftStatus, String> {
+    let (user_id, _) = current_session_user()?;
+    crate::drafts::save_draft(&user_id, &entity_type, entity_id.as_deref(), payload)
+        .map_err(|e| format!("Failed to save draft: {}", e))
 }
 
 #[tauri::command]
-pub async fn delete_document(_app_handle: AppHandle, id: String) -> Result<(), String> {
-    DOCUMENTS.lock().unwrap().retain(|d| d.id != id);
-    DOCUMENT_CONTENT.lock().unwrap().remove(&id);
-    Ok(())
+pub async fn get_draft(
+    _app_handle: AppHandle,
+    entity_type: String,
+    entity_id: Option<String>,
+) -> Result<Option<crate::drafts::Draft>, String> {
+    let (user_id, _) = current_session_user()?;
+    crate::drafts::get_draft(&user_id, &entity_type, entity_id.as_deref())
+        .map_err(|e| format!("Failed to read draft: {}", e))
+}
+
+#[tauri::command]
+pub async fn discard_draft(
+    _app_handle: AppHandle,
+    entity_type: String,
+    entity_id: Option<String>,
+) -> Result<(), String> {
+    let (user_id, _) = current_session_user()?;
+    crate::drafts::discard_draft(&user_id, &entity_type, entity_id.as_deref())
+        .map_err(|e| format!("Failed to discard draft: {}", e))
+}
+
+// Chamado pela tela após o login: rascunhos que sobraram de execuções anteriores
+#[tauri::command]
+pub async fn list_recoverable_drafts(_app_handle: AppHandle) -> Result<Vec<crate::drafts::Draft>, String> {
+    let (user_id, _) = current_session_user()?;
+    crate::drafts::list_recoverable_drafts(&user_id)
+        .map_err(|e| format!("Failed to list drafts: {}", e))
 }
 
 // =========================
-// Utilitários de criptografia simples (Base64)
+// Utilitários de criptografia (KeyVault)
 // =========================
 
 #[tauri::command]
 pub async fn encrypt_data(data: String) -> Result<String, String> {
-    use base64::{engine::general_purpose, Engine as _};
-    let encoded = general_purpose::STANDARD.encode(data.as_bytes());
-    Ok(format!("ENCRYPTED:{}", encoded))
+    crate::document_crypto::encrypt_app_data(data.as_bytes())
+        .map_err(|e| format!("Encryption failed: {}", e))
 }
 
 #[tauri::command]
 pub async fn decrypt_data(encrypted_data: String) -> Result<String, String> {
     use base64::{engine::general_purpose, Engine as _};
-    if let Some(data) = encrypted_data.strip_prefix("ENCRYPTED:") {
-        match general_purpose::STANDARD.decode(data) {
-            Ok(decoded) => String::from_utf8(decoded).map_err(|_| "Invalid UTF-8 data".to_string()),
-            Err(_) => Err("Invalid base64 data".to_string()),
-        }
+    // Valores antigos eram apenas Base64 com prefixo; continuam legíveis
+    let decrypted = if let Some(data) = encrypted_data.strip_prefix("ENCRYPTED:") {
+        general_purpose::STANDARD.decode(data).map_err(|_| "Invalid base64 data".to_string())?
     } else {
-        Err("Invalid encrypted data format".to_string())
-    }
+        crate::document_crypto::decrypt_app_data(&encrypted_data)
+            .map_err(|e| format!("Decryption failed: {}", e))?
+    };
+    String::from_utf8(decrypted).map_err(|_| "Invalid UTF-8 data".to_string())
 }
 
 // =========================
@@ -442,8 +3545,10 @@ pub async fn generate_patients_report(_app_handle: AppHandle) -> Result<String,
             patient.name,
             patient.email.as_deref().unwrap_or(""),
             patient.phone.as_deref().unwrap_or(""),
-            patient.birth_date.as_deref().unwrap_or(""),
-            patient.address.as_deref().unwrap_or(""),
+            patient.birth_date.map(|d| d.to_string()).unwrap_or_default(),
+            patient.address.as_ref()
+                .map(|a| format!("{} {} - {}/{}", a.street, a.number, a.city, a.uf))
+                .unwrap_or_default(),
             patient.notes.as_deref().unwrap_or(""),
             patient.created_at
         ));
@@ -530,26 +3635,6 @@ pub async fn generate_daily_appointments_report(_app_handle: AppHandle, date: St
     Ok(csv_content)
 }
 
-#[tauri::command]
-pub async fn get_appointment_statistics(_app_handle: AppHandle) -> Result<serde_json::Value, String> {
-    let appts = APPOINTMENTS.lock().unwrap();
-    let total = appts.len();
-    let confirmed = appts.iter().filter(|a| a.status.eq_ignore_ascii_case("confirmada")).count();
-    let pending   = appts.iter().filter(|a| a.status.eq_ignore_ascii_case("pendente")).count();
-    let completed = appts.iter().filter(|a| a.status.eq_ignore_ascii_case("realizada")).count();
-    let cancelled = appts.iter().filter(|a| a.status.eq_ignore_ascii_case("cancelada")).count();
-
-    Ok(serde_json::json!({
-        "total": total,
-        "confirmed": confirmed,
-        "pending": pending,
-        "completed": completed,
-        "cancelled": cancelled,
-        "confirmation_rate": if total>0 { (confirmed as f64 / total as f64)*100.0 } else { 0.0 },
-        "completion_rate":   if total>0 { (completed as f64 / total as f64)*100.0 } else { 0.0 }
-    }))
-}
-
 // =========================
 // Backup/Restore (simulação)
 // =========================
@@ -565,7 +3650,10 @@ pub async fn backup_database(_app_handle: AppHandle) -> Result<String, String> {
         "patients": PATIENTS.lock().unwrap().clone(),
         "appointments": APPOINTMENTS.lock().unwrap().clone(),
         "documents": DOCUMENTS.lock().unwrap().clone(),
-        "document_content": DOCUMENT_CONTENT.lock().unwrap().clone()
+        "document_content": DOCUMENT_CONTENT.lock().unwrap().clone(),
+        "clinical_items": CLINICAL_ITEMS.lock().unwrap().clone(),
+        "appointment_series": APPOINTMENT_SERIES.lock().unwrap().clone(),
+        "waitlist": WAITLIST.lock().unwrap().clone()
     });
 
     let backup_json = serde_json::to_string_pretty(&backup_data)
@@ -583,6 +3671,11 @@ pub async fn restore_database(_app_handle: AppHandle, backup_data: String) -> Re
     APPOINTMENTS.lock().unwrap().clear();
     DOCUMENTS.lock().unwrap().clear();
     DOCUMENT_CONTENT.lock().unwrap().clear();
+    DOCUMENT_BLOBS.lock().unwrap().clear();
+    CLINICAL_ITEMS.lock().unwrap().clear();
+    APPOINTMENT_SERIES.lock().unwrap().clear();
+    WAITLIST.lock().unwrap().clear();
+    WAITLIST_OFFERS.lock().unwrap().clear();
 
     if let Some(patients_array) = backup.get("patients").and_then(|v| v.as_array()) {
         for patient_json in patients_array {
@@ -600,6 +3693,30 @@ pub async fn restore_database(_app_handle: AppHandle, backup_data: String) -> Re
         }
     }
 
+    if let Some(series_array) = backup.get("appointment_series").and_then(|v| v.as_array()) {
+        for series_json in series_array {
+            if let Ok(series) = serde_json::from_value::<AppointmentSeries>(series_json.clone()) {
+                APPOINTMENT_SERIES.lock().unwrap().push(series);
+            }
+        }
+    }
+
+    if let Some(waitlist_array) = backup.get("waitlist").and_then(|v| v.as_array()) {
+        for entry_json in waitlist_array {
+            if let Ok(entry) = serde_json::from_value::<WaitlistEntry>(entry_json.clone()) {
+                WAITLIST.lock().unwrap().push(entry);
+            }
+        }
+    }
+
+    if let Some(items_array) = backup.get("clinical_items").and_then(|v| v.as_array()) {
+        for item_json in items_array {
+            if let Ok(item) = serde_json::from_value::<ClinicalItem>(item_json.clone()) {
+                CLINICAL_ITEMS.lock().unwrap().push(item);
+            }
+        }
+    }
+
     if let Some(documents_array) = backup.get("documents").and_then(|v| v.as_array()) {
         for document_json in documents_array {
             if let Ok(document) = serde_json::from_value::<Document>(document_json.clone()) {
@@ -617,6 +3734,7 @@ pub async fn restore_database(_app_handle: AppHandle, backup_data: String) -> Re
         }
         DOCUMENT_CONTENT.lock().unwrap().extend(content_map);
     }
+    reconcile_document_blobs();
 
     Ok(format!(
         "Backup restaurado com sucesso! {} pacientes, {} consultas, {} documentos",
@@ -662,14 +3780,16 @@ pub async fn initialize_auth(
     supabase_anon_key: String,
     master_password: String,
 ) -> Result<String, String> {
+    // Aceito pela compatibilidade com o frontend; não há mais CryptoService para inicializar
+    let _ = master_password;
     AUTH_SERVICE.lock().unwrap().replace(Arc::new(crate::auth::AuthService::new(
         supabase_url,
         supabase_anon_key,
     )));
-    DPAPI_SERVICE.lock().unwrap().replace(crate::dpapi::DpapiService::new());
-    let crypto = crate::crypto::CryptoService::new(&master_password)
-        .map_err(|e| format!("Failed to initialize crypto service: {}", e))?;
-    CRYPTO_SERVICE.lock().unwrap().replace(crypto);
+    let totp = crate::totp::TotpService::new()
+        .map_err(|e| format!("Failed to initialize TOTP service: {}", e))?;
+    TOTP_SERVICE.lock().unwrap().replace(totp);
+    crate::key_rotation::register_memory_hook(rewrap_loaded_document_keys);
     Ok("Authentication services initialized successfully".to_string())
 }
 
@@ -679,16 +3799,215 @@ pub async fn login(
     email: String,
     password: String,
 ) -> Result<crate::auth::LoginResponse, String> {
-    // Clona o Arc<AuthService> fora do lock
+    // Clona o Arc<AuthService> fora do lock
+    let service: Arc<crate::auth::AuthService> = {
+        let guard = AUTH_SERVICE.lock().unwrap();
+        guard.as_ref().cloned().ok_or("Authentication service not initialized")?
+    };
+
+    ensure_login_allowed(&email)?;
+
+    let login_response = match service.login(email.clone(), password.clone()).await {
+        Ok(response) => {
+            if let Err(e) = crate::offline_credentials::remember(&response.user, &password) {
+                eprintln!("Failed to cache offline credentials: {}", e);
+            }
+            response
+        }
+        Err(e) if crate::offline_credentials::is_unreachable(&e) => match offline_login(&email, &password)? {
+            Some(response) => response,
+            None => {
+                register_login_failure(&email, "Falha de login offline: credenciais inválidas").await;
+                return Err("Login failed: invalid credentials".into());
+            }
+        },
+        Err(e) => {
+            if crate::login_throttle::is_credential_failure(&e) {
+                register_login_failure(&email, "Falha de login: credenciais inválidas").await;
+            }
+            return Err(format!("Login failed: {}", e));
+        }
+    };
+
+    if let Some(challenge) = second_factor_challenge(&login_response)? {
+        return Ok(challenge);
+    }
+
+    if let Err(e) = crate::login_throttle::record_login_success(&email) {
+        eprintln!("Failed to reset login attempts: {}", e);
+    }
+    establish_session(service, login_response, "User logged in successfully").await
+}
+
+// Sem conexão com o Supabase, a senha é conferida com a credencial guardada no
+// último login online. A sessão fica sem tokens: a sincronização espera a
+// próxima entrada online. None = senha recusada.
+fn offline_login(email: &str, password: &str) -> Result<Option<crate::auth::LoginResponse>, String> {
+    use crate::offline_credentials::{OfflineCheck, OFFLINE_SESSION_HOURS};
+
+    match crate::offline_credentials::verify(email, password, Utc::now())
+        .map_err(|e| format!("Failed to check offline credentials: {}", e))?
+    {
+        OfflineCheck::Verified(user) => Ok(Some(crate::auth::LoginResponse {
+            user,
+            access_token: String::new(),
+            refresh_token: String::new(),
+            expires_at: Utc::now() + chrono::Duration::hours(OFFLINE_SESSION_HOURS),
+            mfa_challenge_id: None,
+        })),
+        OfflineCheck::Rejected => Ok(None),
+        OfflineCheck::Unavailable => Err("Cannot reach the server and there are no offline credentials for this account".into()),
+    }
+}
+
+// Segundo fator: se o usuário tem TOTP ativo, a sessão só é criada após
+// verify_login_second_factor, tanto no login online quanto no offline. Tokens
+// não são devolvidos nesta etapa.
+fn second_factor_challenge(login_response: &crate::auth::LoginResponse) -> Result<Option<crate::auth::LoginResponse>, String> {
+    if !second_factor_required(&login_response.user.id)? {
+        return Ok(None);
+    }
+    let challenge_id = uuid::Uuid::new_v4().to_string();
+    PENDING_MFA_LOGINS.lock().unwrap().insert(challenge_id.clone(), PendingMfaLogin {
+        response: login_response.clone(),
+        created_at: Utc::now(),
+        attempts: 0,
+    });
+
+    Ok(Some(crate::auth::LoginResponse {
+        user: login_response.user.clone(),
+        access_token: String::new(),
+        refresh_token: String::new(),
+        expires_at: login_response.expires_at,
+        mfa_challenge_id: Some(challenge_id),
+    }))
+}
+
+#[tauri::command]
+pub async fn verify_login_second_factor(
+    _app_handle: AppHandle,
+    challenge_id: String,
+    code: String,
+) -> Result<crate::auth::LoginResponse, String> {
+    let pending = {
+        let mut guard = PENDING_MFA_LOGINS.lock().unwrap();
+        guard.retain(|_, p| Utc::now().signed_duration_since(p.created_at).num_seconds() < MFA_CHALLENGE_TTL_SECONDS);
+        let pending = guard.get_mut(&challenge_id).ok_or("Login challenge expired or not found")?;
+        pending.attempts += 1;
+        if pending.attempts > MFA_MAX_ATTEMPTS {
+            guard.remove(&challenge_id);
+            return Err("Too many invalid codes, please log in again".into());
+        }
+        pending.response.clone()
+    };
+
+    // Bloqueio por excesso de falhas vale também para o segundo fator
+    ensure_login_allowed(&pending.user.email)?;
+
+    // A verificação é totalmente local (segredo protegido pelo KeyVault), portanto
+    // vale também para logins sem conexão com o Supabase.
+    let method = {
+        let mut totp_guard = TOTP_SERVICE.lock().unwrap();
+        let totp = totp_guard.as_mut().ok_or("TOTP service not initialized")?;
+        totp.verify(&pending.user.id, &code)
+            .map_err(|e| format!("Failed to verify code: {}", e))?
+    };
+
+    let method = match method {
+        Some(method) => method,
+        None => {
+            register_login_failure(&pending.user.email, "Falha de login: código de verificação inválido").await;
+            return Err("Invalid verification code".into());
+        }
+    };
+    PENDING_MFA_LOGINS.lock().unwrap().remove(&challenge_id);
+    if let Err(e) = crate::login_throttle::record_login_success(&pending.user.email) {
+        eprintln!("Failed to reset login attempts: {}", e);
+    }
+
     let service: Arc<crate::auth::AuthService> = {
         let guard = AUTH_SERVICE.lock().unwrap();
         guard.as_ref().cloned().ok_or("Authentication service not initialized")?
     };
 
-    let login_response = service
-        .login(email.clone(), password)
-        .await
-        .map_err(|e| format!("Login failed: {}", e))?;
+    let details = match method {
+        crate::totp::SecondFactorMethod::Totp => "User logged in successfully (TOTP)",
+        crate::totp::SecondFactorMethod::RecoveryCode => "User logged in successfully (recovery code)",
+    };
+    establish_session(service, pending, details).await
+}
+
+fn ensure_login_allowed(email: &str) -> Result<(), String> {
+    use crate::login_throttle::ThrottleDecision;
+
+    match crate::login_throttle::check_login_allowed(email)
+        .map_err(|e| format!("Failed to check login attempts: {}", e))?
+    {
+        ThrottleDecision::Allowed => Ok(()),
+        ThrottleDecision::Delayed { retry_after_seconds } => Err(format!(
+            "Too many failed attempts, try again in {} seconds",
+            retry_after_seconds
+        )),
+        ThrottleDecision::Locked { until } => Err(format!(
+            "Account temporarily locked until {}",
+            until.to_rfc3339()
+        )),
+    }
+}
+
+async fn register_login_failure(email: &str, message: &str) {
+    use crate::login_throttle::ThrottleDecision;
+    use crate::security::{log_security_event, SecurityCategory, SecurityLevel, CREDENTIAL_FAILURE};
+
+    let decision = crate::login_throttle::record_login_failure(email)
+        .unwrap_or(ThrottleDecision::Allowed);
+    let workstation = crate::login_throttle::workstation_id().ok();
+
+    let mut details = HashMap::new();
+    details.insert("email".to_string(), serde_json::json!(email));
+    details.insert("throttle".to_string(), serde_json::to_value(&decision).unwrap_or_default());
+
+    let mut failure_details = details.clone();
+    failure_details.insert("kind".to_string(), serde_json::json!(CREDENTIAL_FAILURE));
+    let _ = log_security_event(
+        SecurityLevel::Error,
+        SecurityCategory::Authentication,
+        message,
+        None,
+        workstation.clone(),
+        None,
+        failure_details,
+    ).await;
+
+    if let ThrottleDecision::Locked { .. } = decision {
+        let _ = log_security_event(
+            SecurityLevel::Warning,
+            SecurityCategory::Authentication,
+            "Conta bloqueada temporariamente por excesso de tentativas",
+            None,
+            workstation,
+            None,
+            details,
+        ).await;
+    }
+}
+
+fn second_factor_required(user_id: &str) -> Result<bool, String> {
+    let guard = TOTP_SERVICE.lock().unwrap();
+    match guard.as_ref() {
+        Some(totp) => Ok(totp.is_enabled(user_id)),
+        None => Err("TOTP service not initialized".into()),
+    }
+}
+
+async fn establish_session(
+    service: Arc<crate::auth::AuthService>,
+    login_response: crate::auth::LoginResponse,
+    details: &str,
+) -> Result<crate::auth::LoginResponse, String> {
+    // Troca para o perfil local do usuário antes de criar a sessão, para que
+    // nenhum dado do usuário anterior fique visível
+    switch_to_profile(&login_response.user.id, &login_response.user.email).await?;
 
     // Cria e persiste a sessão (sem await aqui)
     let secure_session = SecureSession {
@@ -701,18 +4020,17 @@ pub async fn login(
         created_at: chrono::Utc::now().to_rfc3339(),
     };
 
-    // Salva via DPAPI (pode exigir &mut self)
-    {
-        let mut dpapi_guard = DPAPI_SERVICE.lock().unwrap();
-        if let Some(dpapi) = dpapi_guard.as_mut() {
-            dpapi
-                .store_session(uuid::Uuid::new_v4().to_string(), secure_session.clone())
-                .map_err(|e| format!("Failed to store session: {}", e))?;
-        }
-    }
+    // Salva no armazenamento de sessão do perfil (criptografado)
+    crate::profiles::write_profile_file(PROFILE_SESSION_FILE, &secure_session)
+        .map_err(|e| format!("Failed to store session: {}", e))?;
 
     // Atualiza sessão atual
     CURRENT_SESSION.lock().unwrap().replace(secure_session.clone());
+    crate::storage_sync::set_access_token(Some(secure_session.access_token.clone()));
+    crate::medical_records_sync::set_session(Some(crate::medical_records_sync::SyncSession {
+        user_id: secure_session.user_id.clone(),
+        access_token: secure_session.access_token.clone(),
+    }));
 
     // Audit log (await somente com Arc<AuthService>, sem locks ativos)
     let audit_log = service
@@ -722,7 +4040,7 @@ pub async fn login(
             "LOGIN".to_string(),
             "USER".to_string(),
             Some(login_response.user.id.clone()),
-            Some("User logged in successfully".to_string()),
+            Some(details.to_string()),
             None,
             None,
         )
@@ -771,20 +4089,149 @@ pub async fn logout(_app_handle: AppHandle) -> Result<String, String> {
         }
     }
 
-    // Limpa sessão atual
-    CURRENT_SESSION.lock().unwrap().take();
+    // Remove apenas a sessão do perfil atual; sessões de outros usuários da
+    // mesma estação não são afetadas
+    if crate::profiles::active_profile().is_some() {
+        crate::profiles::remove_profile_file(PROFILE_SESSION_FILE)
+            .map_err(|e| format!("Failed to clear session: {}", e))?;
+    }
 
-    // Limpa DPAPI (pode exigir &mut self)
-    {
-        let mut dpapi_guard = DPAPI_SERVICE.lock().unwrap();
-        if let Some(dpapi) = dpapi_guard.as_mut() {
-            dpapi
-                .clear_all_sessions()
-                .map_err(|e| format!("Failed to clear sessions: {}", e))?;
+    close_active_profile().await?;
+
+    Ok("Logged out successfully".to_string())
+}
+
+// =========================
+// Perfis locais por usuário
+// =========================
+
+const PROFILE_SESSION_FILE: &str = "session";
+const LOCAL_PARTITION_FILE: &str = "local_database";
+
+// Partição do banco local pertencente a um único usuário do app
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LocalPartition {
+    patients: Vec<Patient>,
+    appointments: Vec<Appointment>,
+    documents: Vec<Document>,
+    document_content: HashMap<String, String>,
+    #[serde(default)]
+    document_blobs: HashMap<String, DocumentBlob>,
+    #[serde(default)]
+    clinical_items: Vec<ClinicalItem>,
+    #[serde(default)]
+    appointment_series: Vec<AppointmentSeries>,
+    #[serde(default)]
+    waitlist: Vec<WaitlistEntry>,
+    #[serde(default)]
+    waitlist_offers: Vec<SlotOffer>,
+    audit_logs: Vec<crate::auth::AuditLog>,
+}
+
+fn save_local_partition() -> Result<(), String> {
+    if crate::profiles::active_profile().is_none() {
+        return Ok(());
+    }
+
+    let partition = LocalPartition {
+        patients: PATIENTS.lock().unwrap().clone(),
+        appointments: APPOINTMENTS.lock().unwrap().clone(),
+        documents: DOCUMENTS.lock().unwrap().clone(),
+        document_content: DOCUMENT_CONTENT.lock().unwrap().clone(),
+        document_blobs: DOCUMENT_BLOBS.lock().unwrap().clone(),
+        clinical_items: CLINICAL_ITEMS.lock().unwrap().clone(),
+        appointment_series: APPOINTMENT_SERIES.lock().unwrap().clone(),
+        waitlist: WAITLIST.lock().unwrap().clone(),
+        waitlist_offers: WAITLIST_OFFERS.lock().unwrap().clone(),
+        audit_logs: AUDIT_LOGS.lock().unwrap().clone(),
+    };
+    crate::profiles::write_profile_file(LOCAL_PARTITION_FILE, &partition)
+        .map_err(|e| format!("Failed to save local data: {}", e))
+}
+
+fn load_local_partition() -> Result<(), String> {
+    let partition = crate::profiles::read_profile_file::<LocalPartition>(LOCAL_PARTITION_FILE)
+        .map_err(|e| format!("Failed to load local data: {}", e))?
+        .unwrap_or_default();
+
+    *PATIENTS.lock().unwrap() = partition.patients;
+    *APPOINTMENTS.lock().unwrap() = partition.appointments;
+    *DOCUMENTS.lock().unwrap() = partition.documents;
+    *DOCUMENT_CONTENT.lock().unwrap() = partition.document_content;
+    *DOCUMENT_BLOBS.lock().unwrap() = partition.document_blobs;
+    *CLINICAL_ITEMS.lock().unwrap() = partition.clinical_items;
+    *APPOINTMENT_SERIES.lock().unwrap() = partition.appointment_series;
+    *WAITLIST.lock().unwrap() = partition.waitlist;
+    *WAITLIST_OFFERS.lock().unwrap() = partition.waitlist_offers;
+    *AUDIT_LOGS.lock().unwrap() = partition.audit_logs;
+    reconcile_document_blobs();
+    Ok(())
+}
+
+fn clear_in_memory_data() {
+    PATIENTS.lock().unwrap().clear();
+    APPOINTMENTS.lock().unwrap().clear();
+    DOCUMENTS.lock().unwrap().clear();
+    DOCUMENT_CONTENT.lock().unwrap().clear();
+    DOCUMENT_BLOBS.lock().unwrap().clear();
+    CLINICAL_ITEMS.lock().unwrap().clear();
+    APPOINTMENT_SERIES.lock().unwrap().clear();
+    WAITLIST.lock().unwrap().clear();
+    WAITLIST_OFFERS.lock().unwrap().clear();
+    AUDIT_LOGS.lock().unwrap().clear();
+    PENDING_MFA_LOGINS.lock().unwrap().clear();
+}
+
+async fn switch_to_profile(user_id: &str, email: &str) -> Result<(), String> {
+    if let Some(active) = crate::profiles::active_profile() {
+        if active.user_id == user_id {
+            return Ok(());
         }
+        close_active_profile().await?;
     }
 
-    Ok("Logged out successfully".to_string())
+    crate::profiles::activate_profile(user_id, email)
+        .map_err(|e| format!("Failed to activate profile: {}", e))?;
+    load_local_partition()?;
+    crate::offline_cache::reload_for_active_profile()
+        .await
+        .map_err(|e| format!("Failed to load profile cache: {}", e))
+}
+
+// Persiste os dados do perfil atual e limpa tudo da memória
+async fn close_active_profile() -> Result<(), String> {
+    if let Some(profile) = crate::profiles::active_profile() {
+        crate::drafts::flush_pending(&profile.user_id)
+            .map_err(|e| format!("Failed to save drafts: {}", e))?;
+    }
+    save_local_partition()?;
+    crate::offline_cache::persist_for_active_profile()
+        .await
+        .map_err(|e| format!("Failed to save profile cache: {}", e))?;
+    CURRENT_SESSION.lock().unwrap().take();
+    crate::storage_sync::set_access_token(None);
+    crate::medical_records_sync::set_session(None);
+    clear_in_memory_data();
+    crate::profiles::deactivate_profile();
+    crate::offline_cache::reload_for_active_profile()
+        .await
+        .map_err(|e| format!("Failed to clear profile cache: {}", e))
+}
+
+#[tauri::command]
+pub async fn list_workstation_profiles(_app_handle: AppHandle) -> Result<Vec<crate::profiles::Profile>, String> {
+    crate::profiles::list_profiles()
+        .map_err(|e| format!("Failed to list profiles: {}", e))
+}
+
+// Troca rápida de usuário: salva e fecha o perfil atual sem revogar a sessão no
+// servidor; o próximo usuário entra pelo fluxo normal de login
+#[tauri::command]
+pub async fn switch_user(_app_handle: AppHandle) -> Result<Vec<crate::profiles::Profile>, String> {
+    audit_current_user("SWITCH_USER", "USER", None, "Workstation switched to another user".to_string()).await?;
+    close_active_profile().await?;
+    crate::profiles::list_profiles()
+        .map_err(|e| format!("Failed to list profiles: {}", e))
 }
 
 #[tauri::command]
@@ -844,32 +4291,16 @@ pub async fn refresh_session(_app_handle: AppHandle) -> Result<crate::auth::Logi
 }
 
 // =========================
-// Criptografia de documentos (real)
+// Autenticação em dois fatores (TOTP)
 // =========================
 
-#[tauri::command]
-pub async fn encrypt_document(
-    _app_handle: AppHandle,
-    content: String, // Base64
-    filename: String,
-) -> Result<crate::crypto::EncryptedDocument, String> {
-    use base64::{engine::general_purpose, Engine as _};
+fn current_session_user() -> Result<(String, String), String> {
+    let guard = CURRENT_SESSION.lock().unwrap();
+    let session = guard.as_ref().ok_or("No active session")?;
+    Ok((session.user_id.clone(), session.email.clone()))
+}
 
-    // Executa criptografia sem manter locks durante await (não há await aqui)
-    let encrypted_doc = {
-        let crypto_guard = CRYPTO_SERVICE.lock().unwrap();
-        let crypto_service = crypto_guard
-            .as_ref()
-            .ok_or("Crypto service not initialized")?;
-        let content_bytes = general_purpose::STANDARD
-            .decode(&content)
-            .map_err(|e| format!("Invalid base64 content: {}", e))?;
-        crypto_service
-            .encrypt_document(&content_bytes, &filename)
-            .map_err(|e| format!("Document encryption failed: {}", e))?
-    };
-
-    // Audit log (se houver sessão e serviço de auth)
+async fn audit_current_user(action: &str, entity_type: &str, entity_id: Option<String>, details: String) -> Result<(), String> {
     let (service_opt, sess_info) = {
         let service = AUTH_SERVICE.lock().unwrap().as_ref().cloned();
         let sess = CURRENT_SESSION.lock().unwrap().clone();
@@ -881,10 +4312,10 @@ pub async fn encrypt_document(
             .create_audit_log(
                 session.user_id.clone(),
                 session.email.clone(),
-                "ENCRYPT_DOCUMENT".to_string(),
-                "DOCUMENT".to_string(),
-                Some(filename.clone()),
-                Some(format!("Document encrypted: {}", filename)),
+                action.to_string(),
+                entity_type.to_string(),
+                entity_id,
+                Some(details),
                 None,
                 None,
             )
@@ -892,54 +4323,288 @@ pub async fn encrypt_document(
             .map_err(|e| format!("Failed to create audit log: {}", e))?;
         AUDIT_LOGS.lock().unwrap().push(audit_log);
     }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_totp_status(_app_handle: AppHandle) -> Result<crate::totp::TotpStatus, String> {
+    let (user_id, _) = current_session_user()?;
+    let guard = TOTP_SERVICE.lock().unwrap();
+    let totp = guard.as_ref().ok_or("TOTP service not initialized")?;
+    Ok(totp.status(&user_id))
+}
 
-    Ok(encrypted_doc)
+#[tauri::command]
+pub async fn begin_totp_enrollment(_app_handle: AppHandle) -> Result<crate::totp::TotpSetup, String> {
+    let (user_id, email) = current_session_user()?;
+    let mut guard = TOTP_SERVICE.lock().unwrap();
+    let totp = guard.as_mut().ok_or("TOTP service not initialized")?;
+    totp.begin_enrollment(&user_id, &email)
+        .map_err(|e| format!("Failed to start TOTP enrollment: {}", e))
 }
 
 #[tauri::command]
-pub async fn decrypt_document(
+pub async fn confirm_totp_enrollment(_app_handle: AppHandle, code: String) -> Result<Vec<String>, String> {
+    let (user_id, _) = current_session_user()?;
+    let recovery_codes = {
+        let mut guard = TOTP_SERVICE.lock().unwrap();
+        let totp = guard.as_mut().ok_or("TOTP service not initialized")?;
+        totp.confirm_enrollment(&user_id, &code)
+            .map_err(|e| format!("Failed to confirm TOTP enrollment: {}", e))?
+    };
+
+    audit_current_user("MFA_ENABLED", "USER", Some(user_id), "TOTP two-factor authentication enabled".to_string()).await?;
+    Ok(recovery_codes)
+}
+
+#[tauri::command]
+pub async fn regenerate_recovery_codes(_app_handle: AppHandle, code: String) -> Result<Vec<String>, String> {
+    let (user_id, _) = current_session_user()?;
+    let recovery_codes = {
+        let mut guard = TOTP_SERVICE.lock().unwrap();
+        let totp = guard.as_mut().ok_or("TOTP service not initialized")?;
+        totp.regenerate_recovery_codes(&user_id, &code)
+            .map_err(|e| format!("Failed to regenerate recovery codes: {}", e))?
+    };
+
+    audit_current_user("MFA_RECOVERY_CODES_REGENERATED", "USER", Some(user_id), "Recovery codes regenerated".to_string()).await?;
+    Ok(recovery_codes)
+}
+
+#[tauri::command]
+pub async fn disable_totp(_app_handle: AppHandle, code: String) -> Result<(), String> {
+    let (user_id, _) = current_session_user()?;
+    {
+        let mut guard = TOTP_SERVICE.lock().unwrap();
+        let totp = guard.as_mut().ok_or("TOTP service not initialized")?;
+        totp.disable(&user_id, &code)
+            .map_err(|e| format!("Failed to disable TOTP: {}", e))?;
+    }
+
+    audit_current_user("MFA_DISABLED", "USER", Some(user_id), "TOTP two-factor authentication disabled".to_string()).await
+}
+
+// =========================
+// Bloqueio de contas (admin)
+// =========================
+
+fn ensure_admin() -> Result<(), String> {
+    let guard = CURRENT_SESSION.lock().unwrap();
+    let session = guard.as_ref().ok_or("No active session")?;
+    match crate::auth::UserRole::from_str(&session.role) {
+        Some(crate::auth::UserRole::Admin) => Ok(()),
+        None => Err("Permission denied".into()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_login_lockouts(_app_handle: AppHandle) -> Result<Vec<crate::login_throttle::LockoutInfo>, String> {
+    ensure_admin()?;
+    crate::login_throttle::list_lockouts()
+        .map_err(|e| format!("Failed to list lockouts: {}", e))
+}
+
+#[tauri::command]
+pub async fn unlock_account(_app_handle: AppHandle, email: String) -> Result<bool, String> {
+    ensure_admin()?;
+    let unlocked = crate::login_throttle::unlock_account(&email)
+        .map_err(|e| format!("Failed to unlock account: {}", e))?;
+
+    audit_current_user("ACCOUNT_UNLOCKED", "USER", Some(email.clone()), format!("Account unlocked: {}", email)).await?;
+    Ok(unlocked)
+}
+
+#[tauri::command]
+pub async fn unlock_workstation(_app_handle: AppHandle, workstation_id: String) -> Result<bool, String> {
+    ensure_admin()?;
+    let unlocked = crate::login_throttle::unlock_workstation(&workstation_id)
+        .map_err(|e| format!("Failed to unlock workstation: {}", e))?;
+
+    audit_current_user("WORKSTATION_UNLOCKED", "WORKSTATION", Some(workstation_id.clone()), format!("Workstation unlocked: {}", workstation_id)).await?;
+    Ok(unlocked)
+}
+
+// =========================
+// Rotação da chave mestra (admin)
+// =========================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotationStatus {
+    pub active_key_id: String,
+    pub key_versions: Vec<crate::keyvault::KeyVersion>,
+    pub running: bool,
+    pub rotation: Option<crate::key_rotation::RotationState>,
+}
+
+#[tauri::command]
+pub async fn rotate_master_key(app_handle: AppHandle) -> Result<crate::key_rotation::RotationState, String> {
+    ensure_admin()?;
+    let (user_id, _) = current_session_user()?;
+
+    let state = crate::key_rotation::start_rotation(&user_id)
+        .map_err(|e| format!("Failed to start key rotation: {}", e))?;
+
+    audit_current_user(
+        "MASTER_KEY_ROTATION_STARTED",
+        "KEYVAULT",
+        Some(state.new_key_id.clone()),
+        format!("Master key rotation started: {} -> {}", state.old_key_id, state.new_key_id),
+    ).await?;
+
+    // A recifragem roda em segundo plano; o progresso chega pelo evento key-rotation-progress
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = crate::key_rotation::run_rotation(app).await {
+            eprintln!("Key rotation failed: {}", e);
+        }
+    });
+
+    Ok(state)
+}
+
+#[tauri::command]
+pub async fn get_key_rotation_status(_app_handle: AppHandle) -> Result<KeyRotationStatus, String> {
+    ensure_admin()?;
+    let (active_key_id, key_versions) = crate::keyvault::with_shared_vault(|vault| {
+        Ok((vault.master_key_id().to_string(), vault.key_versions()))
+    })
+    .map_err(|e| format!("Failed to read key versions: {}", e))?;
+
+    let rotation = crate::key_rotation::load_state()
+        .map_err(|e| format!("Failed to read rotation state: {}", e))?;
+
+    Ok(KeyRotationStatus {
+        active_key_id,
+        key_versions,
+        running: crate::key_rotation::is_running(),
+        rotation,
+    })
+}
+
+// =========================
+// Kit de recuperação da chave mestra (admin)
+// =========================
+
+#[tauri::command]
+pub async fn export_recovery_kit(_app_handle: AppHandle, passphrase: String) -> Result<crate::recovery_kit::RecoveryKit, String> {
+    ensure_admin()?;
+    let material = crate::keyvault::with_shared_vault(|vault| vault.export_key_material())
+        .map_err(|e| format!("Failed to export key material: {}", e))?;
+    let kit = crate::recovery_kit::create_recovery_kit(&material, &passphrase)
+        .map_err(|e| format!("Failed to create recovery kit: {}", e))?;
+
+    audit_current_user(
+        "RECOVERY_KIT_EXPORTED",
+        "KEYVAULT",
+        Some(kit.kit_id.clone()),
+        format!("Passphrase recovery kit exported for keys: {}", kit.key_ids.join(", ")),
+    ).await?;
+    Ok(kit)
+}
+
+#[tauri::command]
+pub async fn export_recovery_shares(
     _app_handle: AppHandle,
-    encrypted_doc: crate::crypto::EncryptedDocument,
-) -> Result<String, String> {
-    use base64::{engine::general_purpose, Engine as _};
+    threshold: u8,
+    share_count: u8,
+) -> Result<crate::recovery_kit::RecoveryShares, String> {
+    ensure_admin()?;
+    let material = crate::keyvault::with_shared_vault(|vault| vault.export_key_material())
+        .map_err(|e| format!("Failed to export key material: {}", e))?;
+    let shares = crate::recovery_kit::create_recovery_shares(&material, threshold, share_count)
+        .map_err(|e| format!("Failed to create recovery shares: {}", e))?;
+
+    audit_current_user(
+        "RECOVERY_SHARES_EXPORTED",
+        "KEYVAULT",
+        Some(shares.kit_id.clone()),
+        format!("{}-of-{} recovery shares exported for keys: {}", threshold, share_count, shares.key_ids.join(", ")),
+    ).await?;
+    Ok(shares)
+}
 
-    // Decripta sem manter locks durante awaits
-    let decrypted_content_b64 = {
-        let crypto_guard = CRYPTO_SERVICE.lock().unwrap();
-        let crypto_service = crypto_guard
-            .as_ref()
-            .ok_or("Crypto service not initialized")?;
-        let decrypted_bytes = crypto_service
-            .decrypt_document(&encrypted_doc)
-            .map_err(|e| format!("Document decryption failed: {}", e))?;
-        general_purpose::STANDARD.encode(&decrypted_bytes)
+#[tauri::command]
+pub async fn import_recovery_kit(app_handle: AppHandle, code: String, passphrase: String) -> Result<KeyRotationStatus, String> {
+    ensure_admin()?;
+    let material = match crate::recovery_kit::open_recovery_kit(&code, &passphrase) {
+        Ok(material) => material,
+        Err(e) => {
+            audit_current_user("RECOVERY_KIT_IMPORT_FAILED", "KEYVAULT", None, e.to_string()).await?;
+            return Err(format!("Failed to open recovery kit: {}", e));
+        }
     };
+    restore_key_material(app_handle, material, "passphrase kit").await
+}
 
-    // Audit log (se houver sessão e serviço de auth)
-    let (service_opt, sess_info) = {
-        let service = AUTH_SERVICE.lock().unwrap().as_ref().cloned();
-        let sess = CURRENT_SESSION.lock().unwrap().clone();
-        (service, sess)
+#[tauri::command]
+pub async fn import_recovery_shares(app_handle: AppHandle, shares: Vec<String>) -> Result<KeyRotationStatus, String> {
+    ensure_admin()?;
+    let material = match crate::recovery_kit::combine_recovery_shares(&shares) {
+        Ok(material) => material,
+        Err(e) => {
+            audit_current_user("RECOVERY_KIT_IMPORT_FAILED", "KEYVAULT", None, e.to_string()).await?;
+            return Err(format!("Failed to combine recovery shares: {}", e));
+        }
     };
+    restore_key_material(app_handle, material, "recovery shares").await
+}
 
-    if let (Some(service), Some(session)) = (service_opt, sess_info) {
-        let audit_log = service
-            .create_audit_log(
-                session.user_id.clone(),
-                session.email.clone(),
-                "DECRYPT_DOCUMENT".to_string(),
-                "DOCUMENT".to_string(),
-                Some(encrypted_doc.file_hash.clone()),
-                Some("Document decrypted".to_string()),
-                None,
-                None,
-            )
-            .await
-            .map_err(|e| format!("Failed to create audit log: {}", e))?;
-        AUDIT_LOGS.lock().unwrap().push(audit_log);
+async fn restore_key_material(
+    app_handle: AppHandle,
+    material: crate::keyvault::KeyMaterial,
+    source: &str,
+) -> Result<KeyRotationStatus, String> {
+    let (user_id, _) = current_session_user()?;
+    let replaced = crate::keyvault::with_shared_vault(|vault| vault.import_key_material(&material))
+        .map_err(|e| format!("Failed to import recovery kit: {}", e))?;
+
+    // A chave gerada nesta máquina antes da importação é migrada e descartada
+    if let Some(local_key_id) = &replaced {
+        crate::key_rotation::start_migration(local_key_id, &material.active_key_id, &user_id)
+            .map_err(|e| format!("Failed to schedule key migration: {}", e))?;
+        let app = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = crate::key_rotation::run_rotation(app).await {
+                eprintln!("Key migration failed: {}", e);
+            }
+        });
     }
 
-    Ok(decrypted_content_b64)
+    audit_current_user(
+        "RECOVERY_KIT_IMPORTED",
+        "KEYVAULT",
+        Some(material.active_key_id.clone()),
+        format!(
+            "Master key restored from {}{}",
+            source,
+            replaced.map(|id| format!("; local key {} scheduled for migration", id)).unwrap_or_default()
+        ),
+    ).await?;
+
+    get_key_rotation_status(app_handle).await
+}
+
+// =========================
+// Criptografia de documentos (obsoleto)
+// =========================
+
+// Dependiam de crate::crypto, que não existe neste crate. Continuam registrados
+// para o frontend receber um erro claro em vez de "command not found".
+#[tauri::command]
+pub async fn encrypt_document(
+    _app_handle: AppHandle,
+    _content: String, // Base64
+    filename: String,
+) -> Result<serde_json::Value, String> {
+    Err(format!("encrypt_document is deprecated and no longer encrypts {}; use create_document", filename))
+}
+
+#[tauri::command]
+pub async fn decrypt_document(
+    _app_handle: AppHandle,
+    _encrypted_doc: serde_json::Value,
+) -> Result<String, String> {
+    Err("decrypt_document is deprecated; use get_document_content".to_string())
 }
 
 // =========================
@@ -995,3 +4660,287 @@ pub async fn check_permission(
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clinic_user(id: &str, email: &str) -> crate::auth::User {
+        crate::auth::User {
+            id: id.to_string(),
+            email: email.to_string(),
+            name: "Recepção".to_string(),
+            role: crate::auth::UserRole::Admin,
+            active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn base32_decode(text: &str) -> Vec<u8> {
+        let (mut buffer, mut bits, mut out) = (0u64, 0, Vec::new());
+        for c in text.bytes() {
+            let value = crate::totp::BASE32_ALPHABET.iter().position(|&a| a == c).expect("base32") as u64;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                out.push((buffer >> bits) as u8);
+            }
+        }
+        out
+    }
+
+    #[test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    fn offline_login_still_requires_the_second_factor() {
+        crate::keyvault::test_support::isolate_data_dir();
+        crate::keyvault::test_support::install_memory_vault();
+        let _vault = crate::keyvault::test_support::shared_vault();
+
+        let enrolled = clinic_user("offline-mfa-user", "mfa@clinica.com");
+        let plain = clinic_user("offline-plain-user", "plain@clinica.com");
+        crate::offline_credentials::remember(&enrolled, "senha-correta").unwrap();
+        crate::offline_credentials::remember(&plain, "senha-correta").unwrap();
+        {
+            let mut guard = TOTP_SERVICE.lock().unwrap();
+            let totp = guard.get_or_insert_with(|| crate::totp::TotpService::new().unwrap());
+            let setup = totp.begin_enrollment(&enrolled.id, &enrolled.email).unwrap();
+            let code = crate::totp::totp_at(&base32_decode(&setup.secret), Utc::now().timestamp() as u64);
+            totp.confirm_enrollment(&enrolled.id, &code).unwrap();
+        }
+
+        assert!(offline_login("mfa@clinica.com", "senha-errada").unwrap().is_none());
+        assert!(offline_login("nunca-entrou@clinica.com", "senha-correta").is_err());
+
+        // A senha guardada confere, mas a sessão só sai depois do TOTP
+        let response = offline_login("mfa@clinica.com", "senha-correta").unwrap().unwrap();
+        let challenge = second_factor_challenge(&response).unwrap().expect("second factor required offline");
+        assert!(challenge.access_token.is_empty() && challenge.refresh_token.is_empty());
+        let challenge_id = challenge.mfa_challenge_id.unwrap();
+        assert!(PENDING_MFA_LOGINS.lock().unwrap().remove(&challenge_id).is_some());
+
+        let response = offline_login("plain@clinica.com", "senha-correta").unwrap().unwrap();
+        assert!(second_factor_challenge(&response).unwrap().is_none());
+    }
+
+    fn days_ago(now: chrono::DateTime<chrono::Utc>, days: i64) -> String {
+        (now - chrono::Duration::days(days)).to_rfc3339()
+    }
+
+    fn document_with_versions(uploaded_at: &[String]) -> Document {
+        let encryption = crate::document_crypto::DocumentEncryption {
+            algorithm: "AES-256-GCM".to_string(),
+            wrapped_key: String::new(),
+            wrapping_key_id: String::new(),
+            iv: String::new(),
+            salt: String::new(),
+            sha256: String::new(),
+            encrypted_at: chrono::Utc::now(),
+            segment_size: None,
+        };
+        let versions: Vec<DocumentVersion> = uploaded_at
+            .iter()
+            .enumerate()
+            .map(|(i, at)| DocumentVersion {
+                version: i as u32 + 1,
+                content_id: format!("blob-{}", i + 1),
+                content_hash: String::new(),
+                file_size: None,
+                filename: "laudo.pdf".to_string(),
+                file_type: None,
+                uploaded_by: None,
+                uploaded_at: at.clone(),
+                note: None,
+                rolled_back_from: None,
+                encryption: encryption.clone(),
+                preview: None,
+                deduplicated: false,
+            })
+            .collect();
+        Document {
+            id: "doc-1".to_string(),
+            patient_id: "p1".to_string(),
+            appointment_id: None,
+            filename: "laudo.pdf".to_string(),
+            file_type: None,
+            file_size: None,
+            encrypted: true,
+            created_at: uploaded_at[0].clone(),
+            updated_at: uploaded_at[uploaded_at.len() - 1].clone(),
+            rev: 0,
+            deleted_at: None,
+            last_editor: None,
+            last_pulled_rev: None,
+            encryption: Some(encryption),
+            content_id: Some(format!("blob-{}", versions.len())),
+            current_version: versions.len() as u32,
+            versions,
+        }
+    }
+
+    fn kept(document: &Document) -> Vec<u32> {
+        document.versions.iter().map(|v| v.version).collect()
+    }
+
+    #[test]
+    fn retention_prunes_when_either_limit_is_exceeded() {
+        let now = chrono::Utc::now();
+        let policy = DocumentRetentionPolicy { max_versions: 3, max_age_days: 365 };
+
+        // Só o limite de quantidade: as mais antigas saem mesmo sendo recentes
+        let mut document = document_with_versions(&(0..5).map(|i| days_ago(now, 10 - i)).collect::<Vec<_>>());
+        assert_eq!(apply_retention(&mut document, &policy, now), vec!["blob-1", "blob-2"]);
+        assert_eq!(kept(&document), vec![3, 4, 5]);
+
+        // Só o limite de idade: poucas versões, mas uma passou de um ano
+        let mut document = document_with_versions(&[days_ago(now, 400), days_ago(now, 30), days_ago(now, 1)]);
+        assert_eq!(apply_retention(&mut document, &policy, now), vec!["blob-1"]);
+        assert_eq!(kept(&document), vec![2, 3]);
+
+        // Dentro dos dois limites nada sai
+        let mut document = document_with_versions(&[days_ago(now, 20), days_ago(now, 10)]);
+        assert!(apply_retention(&mut document, &policy, now).is_empty());
+        assert_eq!(kept(&document), vec![1, 2]);
+    }
+
+    #[test]
+    fn retention_keeps_the_current_version() {
+        let now = chrono::Utc::now();
+        let policy = DocumentRetentionPolicy { max_versions: 1, max_age_days: 30 };
+
+        let mut document = document_with_versions(&[days_ago(now, 900), days_ago(now, 800), days_ago(now, 700)]);
+        assert_eq!(apply_retention(&mut document, &policy, now), vec!["blob-1", "blob-2"]);
+        assert_eq!(kept(&document), vec![3]);
+
+        // A versão atual pode não ser a última da lista; ela fica além das
+        // max_versions mais recentes
+        let mut document = document_with_versions(&[days_ago(now, 900), days_ago(now, 800), days_ago(now, 1)]);
+        document.current_version = 1;
+        assert_eq!(apply_retention(&mut document, &policy, now), vec!["blob-2"]);
+        assert_eq!(kept(&document), vec![1, 3]);
+    }
+
+    #[test]
+    fn retention_policy_reads_the_old_field_name() {
+        let policy: DocumentRetentionPolicy = serde_json::from_str(r#"{"max_versions":5,"min_retention_days":90}"#).unwrap();
+        assert_eq!(policy.max_age_days, 90);
+    }
+
+    fn booked(id: &str, time: &str, professional: &str) -> Appointment {
+        let mut appointment = new_appointment(id.to_string());
+        appointment.patient_id = format!("patient-{}", id);
+        appointment.date = "2026-03-02".to_string();
+        appointment.time = time.to_string();
+        appointment.professional_id = Some(professional.to_string());
+        appointment
+    }
+
+    #[test]
+    fn edits_a_stored_appointment_in_place_or_not_at_all() {
+        let rules = SchedulingRules::default();
+        let mut appointments = vec![booked("a", "09:00", "dra"), booked("b", "11:00", "dra")];
+        appointments[1].status = AppointmentStatus::Confirmed;
+
+        // Conflito bloqueante: nada é gravado
+        let (_, conflicts) = edit_stored_appointment(&mut appointments, "b", &rules, |a| {
+            a.time = "09:15".to_string();
+            Ok(())
+        })
+        .unwrap();
+        assert!(conflicts.iter().any(|c| c.blocking && c.appointment.id == "a"));
+        assert_eq!(appointments[1].time, "11:00");
+
+        // A edição parte do que está gravado, então o status mudado por outro
+        // comando não volta atrás
+        let (edited, conflicts) = edit_stored_appointment(&mut appointments, "b", &rules, |a| {
+            a.notes = Some("retorno".to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert!(conflicts.is_empty());
+        assert_eq!(edited.status, AppointmentStatus::Confirmed);
+        assert_eq!(appointments[1].notes.as_deref(), Some("retorno"));
+
+        let err = edit_stored_appointment(&mut appointments, "b", &rules, |_| Err("Invalid time".to_string())).unwrap_err();
+        assert_eq!(err.message, "Invalid time");
+        assert!(edit_stored_appointment(&mut appointments, "missing", &rules, |_| Ok(())).is_err());
+    }
+
+    fn occurrence(id: &str, series_id: &str, date: chrono::NaiveDate, professional: &str) -> Appointment {
+        let mut appointment = booked(id, "09:00", professional);
+        appointment.date = date.format("%Y-%m-%d").to_string();
+        appointment.series_id = Some(series_id.to_string());
+        appointment.occurrence_date = Some(appointment.date.clone());
+        appointment
+    }
+
+    #[test]
+    fn regeneration_frees_the_slots_it_no_longer_occupies() {
+        let day = |d| chrono::NaiveDate::from_ymd_opt(2026, 3, d).unwrap();
+        let replaced = vec![occurrence("a", "s", day(2), "dra"), occurrence("b", "s", day(9), "dra"), occurrence("c", "s", day(16), "dra")];
+
+        let mut moved = replaced[1].clone();
+        moved.time = "10:00".to_string();
+        let mut notes_only = replaced[0].clone();
+        notes_only.notes = Some("trazer exames".to_string());
+        // "a" fica no mesmo horário, "b" muda de horário e "c" sai da série
+        let freed = freed_by_regeneration(&replaced, &[notes_only, moved]);
+
+        let ids: Vec<&str> = freed.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c"]);
+        assert_eq!(freed[0].time, "09:00"); // O horário antigo é o que vagou
+    }
+
+    #[test]
+    fn deleted_series_occurrences_are_offered_to_the_waitlist() {
+        let professional = "dra-series-offer-test";
+        let series_id = "series-offer-test";
+        let today = today();
+        APPOINTMENTS.lock().unwrap().extend([
+            occurrence("past", series_id, today - chrono::Duration::days(7), professional),
+            occurrence("next", series_id, today + chrono::Duration::days(7), professional),
+            occurrence("later", series_id, today + chrono::Duration::days(14), professional),
+            occurrence("other", "another-series", today + chrono::Duration::days(7), "dra-other"),
+        ]);
+        let now = chrono::Utc::now().to_rfc3339();
+        let entry = WaitlistEntry {
+            id: "entry-series-offer-test".to_string(),
+            patient_id: "patient-waiting".to_string(),
+            preferred_weekdays: Vec::new(),
+            preferred_times: Vec::new(),
+            professional_ids: vec![professional.to_string()],
+            duration_minutes: None,
+            earliest_date: None,
+            latest_date: None,
+            priority: Default::default(),
+            notes: None,
+            created_at: now.clone(),
+            updated_at: now,
+            rev: 0,
+            deleted_at: None,
+            last_editor: None,
+            last_pulled_rev: None,
+        };
+        WAITLIST.lock().unwrap().push(entry);
+
+        let removed = take_appointments(|a| {
+            a.series_id.as_deref() == Some(series_id) && crate::scheduling::parse_date(&a.date).is_ok_and(|d| d >= today)
+        });
+        let offers: Vec<SlotOffer> = removed.iter().filter_map(record_slot_offer).collect();
+
+        let mut removed_ids: Vec<&str> = removed.iter().map(|a| a.id.as_str()).collect();
+        removed_ids.sort();
+        assert_eq!(removed_ids, ["later", "next"]);
+        assert!(APPOINTMENTS.lock().unwrap().iter().any(|a| a.id == "past"));
+        assert_eq!(offers.len(), 2);
+        for offer in &offers {
+            assert_eq!(offer.candidates[0].entry_id, "entry-series-offer-test");
+            assert!(WAITLIST_OFFERS.lock().unwrap().iter().any(|o| o.id == offer.id));
+        }
+
+        APPOINTMENTS.lock().unwrap().retain(|a| !matches!(a.id.as_str(), "past" | "other"));
+        WAITLIST.lock().unwrap().retain(|e| e.id != "entry-series-offer-test");
+        WAITLIST_OFFERS.lock().unwrap().retain(|o| o.slot.professional_id.as_deref() != Some(professional));
+    }
+}
@@ -14,6 +14,9 @@ pub struct AppConfig {
     pub supabase: SupabaseConfig,
     pub master_password: String,
     pub database_encrypted: bool,
+    pub keyvault_backend: Option<String>, // dpapi | file | keyring
+    pub keyvault_passphrase: Option<String>,
+    pub storage_bucket: String, // Bucket do Supabase Storage para documentos cifrados
 }
 
 impl AppConfig {
@@ -39,6 +42,12 @@ impl AppConfig {
             .parse::<bool>()
             .unwrap_or(false);
 
+        let keyvault_backend = env::var("KEYVAULT_BACKEND").ok();
+        let keyvault_passphrase = env::var("KEYVAULT_PASSPHRASE").ok();
+
+        let storage_bucket = env::var("SUPABASE_STORAGE_BUCKET")
+            .unwrap_or_else(|_| "documents".to_string());
+
         // Debug logging
         eprintln!("Supabase URL: {}", supabase_url);
         eprintln!("Supabase Anon Key: {}...", &supabase_anon_key[..20]);
@@ -52,6 +61,9 @@ impl AppConfig {
             },
             master_password,
             database_encrypted,
+            keyvault_backend,
+            keyvault_passphrase,
+            storage_bucket,
         })
     }
 }
@@ -0,0 +1,576 @@
+use anyhow::Result;
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::keyvault::{entity_aad, with_shared_vault, KeyVault};
+
+// =====================================================
+// DOCUMENT ENCRYPTION (per-document data keys)
+// =====================================================
+
+// Cada documento recebe uma chave de dados (DEK) aleatória. A DEK é guardada
+// cifrada pelo KeyVault (envelope versionado), então a rotação da chave mestra
+// só precisa recifrar a DEK, não o arquivo inteiro. O conteúdo é cifrado com
+// AES-256-GCM usando HKDF(DEK, salt) e o hash SHA-256 do original é conferido a
+// cada leitura.
+
+pub const DOCUMENT_ALGORITHM: &str = "AES-256-GCM";
+const DEK_PURPOSE: &str = "document_dek";
+const CONTENT_KEY_INFO: &[u8] = b"dra_bruna_document_content_v1";
+const DATA_PURPOSE: &str = "app_data";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentEncryption {
+    pub algorithm: String,
+    pub wrapped_key: String,    // DEK cifrada pelo KeyVault
+    pub wrapping_key_id: String, // Versão da chave mestra que cifrou a DEK
+    pub iv: String,             // Base64, 12 bytes (prefixo de 7 bytes no formato segmentado)
+    pub salt: String,           // Base64, 16 bytes
+    pub sha256: String,         // Hex do conteúdo original
+    pub encrypted_at: DateTime<Utc>,
+    // Presente apenas em documentos gravados no formato segmentado (arquivos grandes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment_size: Option<u32>,
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hex::encode(hasher.finalize())
+}
+
+// SHA-256 de um arquivo lido em blocos, sem carregá-lo inteiro
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn content_key(dek: &[u8], salt: &[u8]) -> Result<[u8; 32]> {
+    let hkdf = Hkdf::<Sha256>::new(Some(salt), dek);
+    let mut key = [0u8; 32];
+    hkdf.expand(CONTENT_KEY_INFO, &mut key)
+        .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
+    Ok(key)
+}
+
+// Devolve o conteúdo cifrado (Base64) e os metadados a guardar no Document
+pub fn encrypt_document(document_id: &str, plaintext: &[u8]) -> Result<(String, DocumentEncryption)> {
+    let (dek, salt, wrapped_key, wrapping_key_id) = new_data_key(document_id)?;
+    let mut iv = [0u8; 12];
+    rand::thread_rng().fill(&mut iv);
+
+    let key = content_key(&dek, &salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&iv), Payload { msg: plaintext, aad: &entity_aad("document", document_id) })
+        .map_err(|e| anyhow::anyhow!("Document encryption failed: {}", e))?;
+
+    let metadata = DocumentEncryption {
+        algorithm: DOCUMENT_ALGORITHM.to_string(),
+        wrapped_key,
+        wrapping_key_id,
+        iv: general_purpose::STANDARD.encode(iv),
+        salt: general_purpose::STANDARD.encode(salt),
+        sha256: sha256_hex(plaintext),
+        encrypted_at: Utc::now(),
+        segment_size: None,
+    };
+    Ok((general_purpose::STANDARD.encode(ciphertext), metadata))
+}
+
+// Decifra e confere o SHA-256; qualquer divergência é tratada como adulteração
+pub fn decrypt_document(document_id: &str, ciphertext: &str, metadata: &DocumentEncryption) -> Result<Vec<u8>> {
+    if metadata.segment_size.is_some() {
+        return Err(anyhow::anyhow!("Document is stored in segmented format"));
+    }
+    let key = unwrap_content_key(document_id, metadata)?;
+    let iv = general_purpose::STANDARD.decode(&metadata.iv)?;
+    if iv.len() != 12 {
+        return Err(anyhow::anyhow!("Invalid document IV"));
+    }
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let plaintext = cipher
+        .decrypt(
+            Nonce::from_slice(&iv),
+            Payload { msg: &general_purpose::STANDARD.decode(ciphertext)?, aad: &entity_aad("document", document_id) },
+        )
+        .map_err(|_| anyhow::anyhow!("Document integrity check failed: ciphertext was modified"))?;
+
+    if sha256_hex(&plaintext) != metadata.sha256 {
+        return Err(anyhow::anyhow!("Document integrity check failed: SHA-256 mismatch"));
+    }
+    Ok(plaintext)
+}
+
+fn unwrap_content_key(document_id: &str, metadata: &DocumentEncryption) -> Result<[u8; 32]> {
+    if metadata.algorithm != DOCUMENT_ALGORITHM {
+        return Err(anyhow::anyhow!("Unsupported document algorithm: {}", metadata.algorithm));
+    }
+
+    let dek = with_shared_vault(|vault| {
+        vault.decrypt_with_aad(&metadata.wrapped_key, DEK_PURPOSE, &entity_aad("document", document_id))
+    })?;
+    let salt = general_purpose::STANDARD.decode(&metadata.salt)?;
+    content_key(&dek, &salt)
+}
+
+fn new_data_key(document_id: &str) -> Result<([u8; 32], [u8; 16], String, String)> {
+    let mut dek = [0u8; 32];
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill(&mut dek);
+    rand::thread_rng().fill(&mut salt);
+
+    let (wrapped_key, wrapping_key_id) = with_shared_vault(|vault| {
+        let wrapped = vault.encrypt_with_aad(&dek, DEK_PURPOSE, &entity_aad("document", document_id))?;
+        Ok((wrapped, vault.master_key_id().to_string()))
+    })?;
+    Ok((dek, salt, wrapped_key, wrapping_key_id))
+}
+
+// Recifra apenas a DEK com a chave mestra ativa. Devolve true se mudou algo.
+pub fn rewrap_document_key(vault: &mut KeyVault, document_id: &str, metadata: &mut DocumentEncryption) -> Result<bool> {
+    let (_, upgraded) = vault.decrypt_and_upgrade(&metadata.wrapped_key, DEK_PURPOSE, &entity_aad("document", document_id))?;
+    match upgraded {
+        Some(wrapped_key) => {
+            metadata.wrapped_key = wrapped_key;
+            metadata.wrapping_key_id = vault.master_key_id().to_string();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+// =====================================================
+// STREAMING ENCRYPTION (large files)
+// =====================================================
+
+// Radiografias panorâmicas e laudos escaneados podem ter centenas de MB. O
+// formato segmentado (construção STREAM) cifra o arquivo em segmentos de
+// tamanho fixo, cada um com seu próprio tag GCM, mantendo a memória limitada a
+// dois segmentos. O nonce de cada segmento é prefixo aleatório || contador ||
+// flag de último segmento, então segmentos trocados de ordem, repetidos ou
+// cortados no final não passam na verificação.
+//
+// Cabeçalho: "DBSE" | versão (1) | tamanho do segmento (u32 BE) | prefixo do nonce (7)
+
+const STREAM_MAGIC: &[u8; 4] = b"DBSE";
+const STREAM_VERSION: u8 = 1;
+pub const STREAM_SEGMENT_SIZE: usize = 1024 * 1024;
+const MAX_STREAM_SEGMENT_SIZE: usize = 16 * 1024 * 1024;
+const STREAM_NONCE_PREFIX_LEN: usize = 7;
+const STREAM_HEADER_LEN: usize = 4 + 1 + 4 + STREAM_NONCE_PREFIX_LEN;
+const GCM_TAG_LEN: usize = 16;
+
+fn segment_nonce(prefix: &[u8], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..STREAM_NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[7..11].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn segment_aad(header: &[u8], document_id: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(&entity_aad("document", document_id));
+    aad
+}
+
+// Lê até encher o buffer ou chegar ao fim; devolve quantos bytes foram lidos
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+// Cifra o conteúdo de `reader` em `writer`. Devolve os metadados e o tamanho
+// original em bytes.
+pub fn encrypt_stream<R: Read, W: Write>(document_id: &str, mut reader: R, mut writer: W) -> Result<(DocumentEncryption, u64)> {
+    let (dek, salt, wrapped_key, wrapping_key_id) = new_data_key(document_id)?;
+    let mut prefix = [0u8; STREAM_NONCE_PREFIX_LEN];
+    rand::thread_rng().fill(&mut prefix);
+
+    let mut header = STREAM_MAGIC.to_vec();
+    header.push(STREAM_VERSION);
+    header.extend_from_slice(&(STREAM_SEGMENT_SIZE as u32).to_be_bytes());
+    header.extend_from_slice(&prefix);
+    writer.write_all(&header)?;
+
+    let key = content_key(&dek, &salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let aad = segment_aad(&header, document_id);
+    let mut hasher = Sha256::new();
+    let mut total: u64 = 0;
+
+    // Um segmento de antecedência para saber qual é o último
+    let mut current = vec![0u8; STREAM_SEGMENT_SIZE];
+    let mut next = vec![0u8; STREAM_SEGMENT_SIZE];
+    let mut current_len = read_full(&mut reader, &mut current)?;
+    let mut counter: u32 = 0;
+
+    loop {
+        let next_len = if current_len == STREAM_SEGMENT_SIZE { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+
+        let segment = &current[..current_len];
+        hasher.update(segment);
+        total += current_len as u64;
+
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&segment_nonce(&prefix, counter, last)), Payload { msg: segment, aad: &aad })
+            .map_err(|e| anyhow::anyhow!("Document encryption failed: {}", e))?;
+        writer.write_all(&ciphertext)?;
+
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or_else(|| anyhow::anyhow!("Document too large"))?;
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+    writer.flush()?;
+
+    let metadata = DocumentEncryption {
+        algorithm: DOCUMENT_ALGORITHM.to_string(),
+        wrapped_key,
+        wrapping_key_id,
+        iv: general_purpose::STANDARD.encode(prefix),
+        salt: general_purpose::STANDARD.encode(salt),
+        sha256: hex::encode(hasher.finalize()),
+        encrypted_at: Utc::now(),
+        segment_size: Some(STREAM_SEGMENT_SIZE as u32),
+    };
+    Ok((metadata, total))
+}
+
+// Decifra segmento a segmento em `writer`. Cada segmento já é autenticado antes
+// de ser escrito; o SHA-256 do arquivo inteiro é conferido no final.
+pub fn decrypt_stream<R: Read, W: Write>(
+    document_id: &str,
+    mut reader: R,
+    mut writer: W,
+    metadata: &DocumentEncryption,
+) -> Result<u64> {
+    let mut header = [0u8; STREAM_HEADER_LEN];
+    if read_full(&mut reader, &mut header)? != STREAM_HEADER_LEN || &header[..4] != STREAM_MAGIC {
+        return Err(anyhow::anyhow!("Not a segmented document"));
+    }
+    if header[4] != STREAM_VERSION {
+        return Err(anyhow::anyhow!("Unsupported segmented document version: {}", header[4]));
+    }
+
+    let segment_size = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if segment_size == 0 || segment_size > MAX_STREAM_SEGMENT_SIZE || Some(segment_size as u32) != metadata.segment_size {
+        return Err(anyhow::anyhow!("Invalid segment size"));
+    }
+    let prefix = &header[9..];
+    if general_purpose::STANDARD.encode(prefix) != metadata.iv {
+        return Err(anyhow::anyhow!("Document header does not match its metadata"));
+    }
+
+    let key = unwrap_content_key(document_id, metadata)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let aad = segment_aad(&header, document_id);
+    let mut hasher = Sha256::new();
+    let mut total: u64 = 0;
+
+    let sealed_size = segment_size + GCM_TAG_LEN;
+    let mut current = vec![0u8; sealed_size];
+    let mut next = vec![0u8; sealed_size];
+    let mut current_len = read_full(&mut reader, &mut current)?;
+    let mut counter: u32 = 0;
+
+    loop {
+        if current_len < GCM_TAG_LEN {
+            return Err(anyhow::anyhow!("Document integrity check failed: file is truncated"));
+        }
+        let next_len = if current_len == sealed_size { read_full(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&segment_nonce(prefix, counter, last)),
+                Payload { msg: &current[..current_len], aad: &aad },
+            )
+            .map_err(|_| anyhow::anyhow!("Document integrity check failed: segment {} was modified, reordered or truncated", counter))?;
+        hasher.update(&plaintext);
+        total += plaintext.len() as u64;
+        writer.write_all(&plaintext)?;
+
+        if last {
+            break;
+        }
+        counter = counter.checked_add(1).ok_or_else(|| anyhow::anyhow!("Document too large"))?;
+        std::mem::swap(&mut current, &mut next);
+        current_len = next_len;
+    }
+    writer.flush()?;
+
+    if hex::encode(hasher.finalize()) != metadata.sha256 {
+        return Err(anyhow::anyhow!("Document integrity check failed: SHA-256 mismatch"));
+    }
+    Ok(total)
+}
+
+// Confere pelo cabeçalho se um arquivo está no formato segmentado cifrado
+pub fn is_encrypted_blob(path: &Path) -> Result<bool> {
+    let mut header = [0u8; STREAM_HEADER_LEN];
+    let mut file = std::fs::File::open(path)?;
+    let read = read_full(&mut file, &mut header)?;
+    Ok(read == STREAM_HEADER_LEN && &header[..4] == STREAM_MAGIC && header[4] == STREAM_VERSION)
+}
+
+// Documentos segmentados ficam fora do banco local, no diretório do perfil
+pub fn stream_path(document_id: &str) -> Result<PathBuf> {
+    let dir = crate::profiles::active_profile_dir()?.join("documents");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.dbse", document_id)))
+}
+
+pub fn encrypt_file(document_id: &str, source: &Path, destination: &Path) -> Result<(DocumentEncryption, u64)> {
+    let reader = BufReader::new(std::fs::File::open(source)?);
+    let tmp_path = destination.with_extension("dbse.tmp");
+    let result = (|| {
+        let file = std::fs::File::create(&tmp_path)?;
+        let outcome = encrypt_stream(document_id, reader, BufWriter::new(&file))?;
+        file.sync_all()?;
+        Ok(outcome)
+    })();
+
+    match result {
+        Ok(outcome) => {
+            std::fs::rename(&tmp_path, destination)?;
+            Ok(outcome)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp_path);
+            Err(e)
+        }
+    }
+}
+
+// Decifra para um arquivo temporário visível só para o usuário atual. Em caso
+// de falha de integridade o arquivo parcial é apagado.
+// Cópias decifradas para abrir no visualizador do sistema. Cada exportação tem
+// nome único e só o usuário lê; o frontend libera o arquivo com
+// release_temp_document quando fecha o documento, e o que sobrar é apagado
+// pela varredura periódica (TEMP_FILE_TTL_SECONDS) e na abertura/saída do app.
+pub const TEMP_FILE_TTL_SECONDS: u64 = 60 * 60;
+
+fn temp_dir() -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join("DraBrunaClinic");
+    std::fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+pub fn decrypt_file_to_temp(document_id: &str, source: &Path, metadata: &DocumentEncryption, filename: &str) -> Result<PathBuf> {
+    let dir = temp_dir()?;
+    let safe_name: String = filename
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let path = dir.join(format!("{}_{}", uuid::Uuid::new_v4(), safe_name));
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(&path)?;
+
+    let reader = BufReader::new(std::fs::File::open(source)?);
+    match decrypt_stream(document_id, reader, BufWriter::new(file), metadata) {
+        Ok(_) => Ok(path),
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            Err(e)
+        }
+    }
+}
+
+// Apaga uma cópia exportada. Só aceita arquivos dentro da pasta temporária do
+// app, para o comando não virar um "apagar qualquer arquivo".
+pub fn release_temp_file(path: &Path) -> Result<()> {
+    let dir = temp_dir()?.canonicalize()?;
+    let path = match path.canonicalize() {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if path.parent() != Some(dir.as_path()) {
+        return Err(anyhow::anyhow!("Not a temporary document file"));
+    }
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+// Apaga as cópias mais antigas que max_age (todas, sem max_age). Arquivos ainda
+// abertos em outro programa (Windows) ficam para a próxima varredura.
+pub fn sweep_temp_files(max_age: Option<std::time::Duration>) -> Result<usize> {
+    let mut removed = 0;
+    for entry in std::fs::read_dir(temp_dir()?)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let expired = match max_age {
+            Some(max_age) => entry
+                .metadata()?
+                .modified()?
+                .elapsed()
+                .map_or(true, |age| age >= max_age),
+            None => true,
+        };
+        if expired && std::fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// =====================================================
+// GENERIC DATA ENCRYPTION (encrypt_data / decrypt_data)
+// =====================================================
+
+pub fn encrypt_app_data(data: &[u8]) -> Result<String> {
+    with_shared_vault(|vault| vault.encrypt_with_aad(data, DATA_PURPOSE, b""))
+}
+
+pub fn decrypt_app_data(encrypted: &str) -> Result<Vec<u8>> {
+    with_shared_vault(|vault| vault.decrypt_with_aad(encrypted, DATA_PURPOSE, b""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyvault::test_support::{install_memory_vault, shared_vault};
+
+    // Dois segmentos cheios e um parcial
+    fn sample() -> Vec<u8> {
+        (0..STREAM_SEGMENT_SIZE * 2 + 1000).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn encrypt(document_id: &str, data: &[u8]) -> (Vec<u8>, DocumentEncryption) {
+        install_memory_vault();
+        let mut sealed = Vec::new();
+        let (metadata, total) = encrypt_stream(document_id, data, &mut sealed).unwrap();
+        assert_eq!(total, data.len() as u64);
+        (sealed, metadata)
+    }
+
+    fn decrypt(document_id: &str, sealed: &[u8], metadata: &DocumentEncryption) -> Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        decrypt_stream(document_id, sealed, &mut plaintext, metadata)?;
+        Ok(plaintext)
+    }
+
+    fn segment_range(index: usize) -> std::ops::Range<usize> {
+        let sealed_size = STREAM_SEGMENT_SIZE + GCM_TAG_LEN;
+        let start = STREAM_HEADER_LEN + index * sealed_size;
+        start..start + sealed_size
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let _vault = shared_vault();
+        let data = sample();
+        let (sealed, metadata) = encrypt("doc-1", &data);
+        assert_eq!(decrypt("doc-1", &sealed, &metadata).unwrap(), data);
+
+        let (sealed, metadata) = encrypt("doc-empty", &[]);
+        assert!(decrypt("doc-empty", &sealed, &metadata).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_stream() {
+        let _vault = shared_vault();
+        let (sealed, metadata) = encrypt("doc-2", &sample());
+
+        // Sem o último segmento: o penúltimo não foi selado como último
+        let without_last = &sealed[..segment_range(1).end];
+        assert!(decrypt("doc-2", without_last, &metadata).is_err());
+
+        // Cortado no meio de um segmento
+        assert!(decrypt("doc-2", &sealed[..sealed.len() - 10], &metadata).is_err());
+        assert!(decrypt("doc-2", &sealed[..STREAM_HEADER_LEN + 8], &metadata).is_err());
+    }
+
+    #[test]
+    fn rejects_reordered_segments() {
+        let _vault = shared_vault();
+        let (sealed, metadata) = encrypt("doc-3", &sample());
+        let mut swapped = sealed.clone();
+        let (first, second) = (segment_range(0), segment_range(1));
+        swapped[first.clone()].copy_from_slice(&sealed[second.clone()]);
+        swapped[second].copy_from_slice(&sealed[first]);
+
+        let err = decrypt("doc-3", &swapped, &metadata).unwrap_err();
+        assert!(err.to_string().contains("segment 0"));
+    }
+
+    #[test]
+    fn rejects_segment_from_another_document() {
+        let _vault = shared_vault();
+        let data = sample();
+        let (sealed, metadata) = encrypt("doc-4", &data);
+        assert!(decrypt("doc-5", &sealed, &metadata).is_err());
+    }
+
+    #[test]
+    fn release_only_removes_exported_copies() {
+        let _vault = shared_vault();
+        let data = sample();
+        let (sealed, metadata) = encrypt("doc-6", &data);
+        let source = std::env::temp_dir().join(format!("dbse-test-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&source, &sealed).unwrap();
+
+        let exported = decrypt_file_to_temp("doc-6", &source, &metadata, "raio x.png").unwrap();
+        assert_eq!(std::fs::read(&exported).unwrap(), data);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&exported).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        // Fora da pasta temporária do app
+        assert!(release_temp_file(&source).is_err());
+        assert!(source.exists());
+
+        release_temp_file(&exported).unwrap();
+        assert!(!exported.exists());
+        std::fs::remove_file(&source).unwrap();
+    }
+}
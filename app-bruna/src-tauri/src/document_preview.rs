@@ -0,0 +1,171 @@
+use anyhow::{anyhow, Result};
+use image::io::{Limits, Reader as ImageReader};
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use crate::document_crypto::{decrypt_document, encrypt_document, stream_path, DocumentEncryption};
+use crate::document_validation::{MIME_JPEG, MIME_PDF, MIME_PNG};
+
+// =====================================================
+// DOCUMENT PREVIEWS (THUMBNAILS)
+// =====================================================
+
+pub const PREVIEW_MAX_DIMENSION: u32 = 256;
+const PREVIEW_JPEG_QUALITY: u8 = 75;
+const IMAGE_SOURCE_LIMIT: u64 = 64 * 1024 * 1024; // Imagens maiores não ganham miniatura
+const PDF_SCAN_LIMIT: u64 = 16 * 1024 * 1024; // A primeira página fica no início do arquivo
+const MAX_SOURCE_DIMENSION: u32 = 20_000;
+const MAX_DECODE_ALLOC: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewKind {
+    ImageThumbnail,
+    PdfFirstPage, // Imagem da primeira página (PDFs digitalizados)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPreview {
+    pub kind: PreviewKind,
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub encryption: DocumentEncryption,
+}
+
+// Id usado como AAD da miniatura, distinto do conteúdo principal
+pub fn preview_id(content_id: &str) -> String {
+    format!("{}.preview", content_id)
+}
+
+fn preview_path(content_id: &str) -> Result<PathBuf> {
+    let document_path = stream_path(content_id)?;
+    let dir = document_path
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid document storage path"))?;
+    Ok(dir.join(format!("{}.preview", content_id)))
+}
+
+// =====================================================
+// RENDERING
+// =====================================================
+
+fn decode_image(bytes: &[u8], format: ImageFormat) -> Result<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    Ok(reader.decode()?)
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+// Não há renderizador de PDF embarcado: laudos digitalizados trazem cada página
+// como uma imagem JPEG (DCTDecode), e a primeira delas serve de prévia.
+fn first_pdf_page_image(pdf: &[u8]) -> Option<DynamicImage> {
+    let mut cursor = 0;
+    for _ in 0..8 {
+        let filter = find(pdf, b"/DCTDecode", cursor)?;
+        let stream = find(pdf, b"stream", filter)?;
+        let mut start = stream + b"stream".len();
+        if pdf.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if pdf.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let end = find(pdf, b"endstream", start)?;
+        cursor = end;
+
+        if let Ok(image) = decode_image(&pdf[start..end], ImageFormat::Jpeg) {
+            return Some(image);
+        }
+    }
+    None
+}
+
+fn render(mime_type: &str, bytes: &[u8]) -> Result<Option<(PreviewKind, DynamicImage)>> {
+    let rendered = match mime_type {
+        MIME_JPEG => Some((PreviewKind::ImageThumbnail, decode_image(bytes, ImageFormat::Jpeg)?)),
+        MIME_PNG => Some((PreviewKind::ImageThumbnail, decode_image(bytes, ImageFormat::Png)?)),
+        MIME_PDF => first_pdf_page_image(bytes).map(|image| (PreviewKind::PdfFirstPage, image)),
+        _ => None, // DICOM e DOCX não têm prévia
+    };
+    Ok(rendered)
+}
+
+fn encode_thumbnail(image: &DynamicImage) -> Result<(Vec<u8>, u32, u32)> {
+    let thumbnail = image.thumbnail(PREVIEW_MAX_DIMENSION, PREVIEW_MAX_DIMENSION).to_rgb8();
+    let (width, height) = thumbnail.dimensions();
+
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, PREVIEW_JPEG_QUALITY)
+        .encode_image(&thumbnail)?;
+    Ok((jpeg, width, height))
+}
+
+// =====================================================
+// STORAGE
+// =====================================================
+
+// Gera, cifra e grava a prévia ao lado do documento. None quando o tipo não tem prévia.
+pub fn generate_preview(content_id: &str, mime_type: &str, bytes: &[u8]) -> Result<Option<DocumentPreview>> {
+    let Some((kind, image)) = render(mime_type, bytes)? else {
+        return Ok(None);
+    };
+    let (jpeg, width, height) = encode_thumbnail(&image)?;
+    let (ciphertext, encryption) = encrypt_document(&preview_id(content_id), &jpeg)?;
+
+    let path = preview_path(content_id)?;
+    let tmp = path.with_extension("preview.tmp");
+    std::fs::write(&tmp, ciphertext)?;
+    std::fs::rename(&tmp, &path)?;
+
+    Ok(Some(DocumentPreview {
+        kind,
+        mime_type: MIME_JPEG.to_string(),
+        width,
+        height,
+        encryption,
+    }))
+}
+
+// Versão para arquivos importados do disco: lê só o necessário para a prévia
+pub fn generate_preview_from_file(content_id: &str, mime_type: &str, source: &Path) -> Result<Option<DocumentPreview>> {
+    let limit = match mime_type {
+        MIME_JPEG | MIME_PNG => {
+            if std::fs::metadata(source)?.len() > IMAGE_SOURCE_LIMIT {
+                return Ok(None);
+            }
+            IMAGE_SOURCE_LIMIT
+        }
+        MIME_PDF => PDF_SCAN_LIMIT,
+        _ => return Ok(None),
+    };
+
+    let mut bytes = Vec::new();
+    std::fs::File::open(source)?.take(limit).read_to_end(&mut bytes)?;
+    generate_preview(content_id, mime_type, &bytes)
+}
+
+pub fn load_preview(content_id: &str, preview: &DocumentPreview) -> Result<Vec<u8>> {
+    let ciphertext = std::fs::read_to_string(preview_path(content_id)?)?;
+    decrypt_document(&preview_id(content_id), &ciphertext, &preview.encryption)
+}
+
+pub fn remove_preview(content_id: &str) {
+    if let Ok(path) = preview_path(content_id) {
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+// =====================================================
+// DOCUMENT TYPE DETECTION AND VALIDATION
+// =====================================================
+
+const POLICY_FILE: &str = "document_policy.json";
+const HEAD_BYTES: usize = 8 * 1024;
+const TAIL_BYTES: u64 = 64 * 1024; // Diretório central do ZIP fica no fim do arquivo
+
+pub const MIME_PDF: &str = "application/pdf";
+pub const MIME_JPEG: &str = "image/jpeg";
+pub const MIME_PNG: &str = "image/png";
+pub const MIME_DICOM: &str = "application/dicom";
+pub const MIME_DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+// Tipos que o sistema sabe reconhecer: (MIME, extensões aceitas)
+const KNOWN_TYPES: &[(&str, &[&str])] = &[
+    (MIME_PDF, &["pdf"]),
+    (MIME_JPEG, &["jpg", "jpeg"]),
+    (MIME_PNG, &["png"]),
+    (MIME_DICOM, &["dcm", "dicom"]),
+    (MIME_DOCX, &["docx"]),
+];
+
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "com", "bat", "cmd", "msi", "scr", "ps1", "vbs", "js", "jar", "sh", "app", "bin",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPolicy {
+    pub allowed_types: Vec<String>, // MIME types aceitos pela clínica
+    pub max_size_bytes: u64,
+}
+
+impl Default for DocumentPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_types: KNOWN_TYPES.iter().map(|(mime, _)| mime.to_string()).collect(),
+            max_size_bytes: 512 * 1024 * 1024, // Panorâmicas e laudos digitalizados podem passar de 100MB
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidatedDocument {
+    pub mime_type: String,
+    pub size: u64,
+}
+
+fn policy_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join(POLICY_FILE))
+}
+
+pub fn load_policy() -> DocumentPolicy {
+    policy_path()
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_policy(policy: &DocumentPolicy) -> Result<()> {
+    if policy.max_size_bytes == 0 {
+        bail!("Maximum document size must be greater than zero");
+    }
+    if policy.allowed_types.is_empty() {
+        bail!("At least one document type must be allowed");
+    }
+    for mime in &policy.allowed_types {
+        if !KNOWN_TYPES.iter().any(|(known, _)| known == mime) {
+            bail!("Unsupported document type in allow-list: {}", mime);
+        }
+    }
+
+    let path = policy_path()?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(policy)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// =====================================================
+// MAGIC BYTES
+// =====================================================
+
+fn is_executable(head: &[u8]) -> bool {
+    const MAGICS: &[&[u8]] = &[
+        b"MZ",                     // PE (Windows)
+        b"\x7fELF",                // ELF
+        &[0xFE, 0xED, 0xFA, 0xCE], // Mach-O 32
+        &[0xFE, 0xED, 0xFA, 0xCF], // Mach-O 64
+        &[0xCE, 0xFA, 0xED, 0xFE],
+        &[0xCF, 0xFA, 0xED, 0xFE],
+        &[0xCA, 0xFE, 0xBA, 0xBE], // Mach-O universal / Java class
+        b"#!",                     // Scripts
+    ];
+    MAGICS.iter().any(|magic| head.starts_with(magic))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+// Detecta o tipo real a partir do início (e, para ZIP, do fim) do arquivo
+pub fn sniff(head: &[u8], tail: &[u8]) -> Result<&'static str> {
+    if head.is_empty() {
+        bail!("Document is empty");
+    }
+    if is_executable(head) {
+        bail!("Executable files are not accepted as documents");
+    }
+
+    if head.starts_with(b"%PDF-") {
+        return Ok(MIME_PDF);
+    }
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Ok(MIME_JPEG);
+    }
+    if head.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Ok(MIME_PNG);
+    }
+    // DICOM Part 10: preâmbulo de 128 bytes seguido de "DICM"
+    if head.len() >= 132 && &head[128..132] == b"DICM" {
+        return Ok(MIME_DICOM);
+    }
+    if head.starts_with(b"PK\x03\x04") {
+        let marker = b"word/document.xml";
+        if contains(head, marker) || contains(tail, marker) {
+            return Ok(MIME_DOCX);
+        }
+        bail!("ZIP archives are not accepted as documents");
+    }
+
+    bail!("Unsupported document type; accepted types are PDF, JPEG, PNG, DICOM and DOCX")
+}
+
+// Aceita tanto MIME ("application/pdf") quanto extensão ("pdf", ".pdf")
+fn normalize_declared_type(declared: &str) -> Option<&'static str> {
+    let declared = declared.trim().trim_start_matches('.').to_lowercase();
+    let declared = match declared.as_str() {
+        "image/jpg" | "image/pjpeg" => MIME_JPEG.to_string(),
+        "application/dicom+json" | "application/x-dicom" => MIME_DICOM.to_string(),
+        _ => declared,
+    };
+    KNOWN_TYPES
+        .iter()
+        .find(|(mime, extensions)| *mime == declared || extensions.contains(&declared.as_str()))
+        .map(|(mime, _)| *mime)
+}
+
+fn check_declarations(detected: &str, filename: &str, declared_type: Option<&str>) -> Result<()> {
+    let extension = Path::new(filename)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    if let Some(extension) = &extension {
+        if EXECUTABLE_EXTENSIONS.contains(&extension.as_str()) {
+            bail!("Executable files are not accepted as documents (.{})", extension);
+        }
+        if let Some(by_extension) = normalize_declared_type(extension) {
+            if by_extension != detected {
+                bail!("File extension .{} does not match the detected type {}", extension, detected);
+            }
+        }
+    }
+
+    if let Some(declared) = declared_type.filter(|d| !d.trim().is_empty()) {
+        match normalize_declared_type(declared) {
+            Some(mime) if mime == detected => {}
+            _ => bail!("Declared type {} does not match the detected type {}", declared, detected),
+        }
+    }
+    Ok(())
+}
+
+fn check_size(policy: &DocumentPolicy, size: u64) -> Result<()> {
+    if size > policy.max_size_bytes {
+        bail!(
+            "Document is too large ({} bytes); the maximum allowed is {} bytes",
+            size,
+            policy.max_size_bytes
+        );
+    }
+    Ok(())
+}
+
+fn check_allowed(policy: &DocumentPolicy, mime_type: &str) -> Result<()> {
+    if !policy.allowed_types.iter().any(|t| t == mime_type) {
+        bail!("Document type {} is not allowed by the clinic policy", mime_type);
+    }
+    Ok(())
+}
+
+// =====================================================
+// VALIDATION
+// =====================================================
+
+pub fn validate_bytes(bytes: &[u8], filename: &str, declared_type: Option<&str>) -> Result<ValidatedDocument> {
+    let size = bytes.len() as u64;
+    let policy = load_policy();
+    check_size(&policy, size)?;
+
+    let head = &bytes[..bytes.len().min(HEAD_BYTES)];
+    let tail = &bytes[bytes.len().saturating_sub(TAIL_BYTES as usize)..];
+    let mime_type = sniff(head, tail)?;
+    check_declarations(mime_type, filename, declared_type)?;
+    check_allowed(&policy, mime_type)?;
+
+    Ok(ValidatedDocument { mime_type: mime_type.to_string(), size })
+}
+
+// Lê só o início e o fim do arquivo, sem carregá-lo inteiro
+pub fn validate_file(path: &Path, filename: &str, declared_type: Option<&str>) -> Result<ValidatedDocument> {
+    let size = std::fs::metadata(path)?.len();
+    let policy = load_policy();
+    check_size(&policy, size)?;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut head = Vec::with_capacity(HEAD_BYTES);
+    (&mut file).take(HEAD_BYTES as u64).read_to_end(&mut head)?;
+
+    let mut tail = Vec::new();
+    if size > HEAD_BYTES as u64 {
+        file.seek(SeekFrom::Start(size.saturating_sub(TAIL_BYTES)))?;
+        file.read_to_end(&mut tail)?;
+    }
+
+    let mime_type = sniff(&head, &tail)?;
+    check_declarations(mime_type, filename, declared_type)?;
+    check_allowed(&policy, mime_type)?;
+
+    Ok(ValidatedDocument { mime_type: mime_type.to_string(), size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PDF: &[u8] = b"%PDF-1.7\n%\xe2\xe3\xcf\xd3\n1 0 obj";
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 13];
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0, 16, b'J', b'F', b'I', b'F'];
+
+    fn dicom() -> Vec<u8> {
+        let mut data = vec![0u8; 128];
+        data.extend_from_slice(b"DICM\x02\x00\x00\x00");
+        data
+    }
+
+    #[test]
+    fn detects_types_by_magic_bytes() {
+        assert_eq!(sniff(PDF, &[]).unwrap(), MIME_PDF);
+        assert_eq!(sniff(PNG, &[]).unwrap(), MIME_PNG);
+        assert_eq!(sniff(JPEG, &[]).unwrap(), MIME_JPEG);
+        assert_eq!(sniff(&dicom(), &[]).unwrap(), MIME_DICOM);
+
+        // O marcador do DOCX pode estar só no diretório central, no fim do arquivo
+        let zip = b"PK\x03\x04\x14\x00\x06\x00[Content_Types].xml";
+        assert_eq!(sniff(zip, b"PK\x01\x02word/document.xml").unwrap(), MIME_DOCX);
+        assert!(sniff(zip, b"PK\x01\x02payload.bin").is_err());
+    }
+
+    #[test]
+    fn rejects_executables_and_unknown_content() {
+        for head in [&b"MZ\x90\x00\x03"[..], b"\x7fELF\x02\x01", b"#!/bin/sh\nrm -rf /"] {
+            let err = sniff(head, &[]).unwrap_err();
+            assert!(err.to_string().contains("Executable"), "{:?}", head);
+        }
+        assert!(sniff(b"", &[]).is_err());
+        assert!(sniff(b"just some text", &[]).is_err());
+        // Um DICOM precisa do preâmbulo inteiro
+        assert!(sniff(&dicom()[..130], &[]).is_err());
+    }
+
+    #[test]
+    fn extension_must_match_magic_bytes() {
+        assert!(check_declarations(MIME_PDF, "laudo.pdf", None).is_ok());
+        assert!(check_declarations(MIME_JPEG, "RAIO-X.JPEG", None).is_ok());
+        // Extensão desconhecida não contradiz o conteúdo
+        assert!(check_declarations(MIME_PDF, "laudo.scan", None).is_ok());
+
+        let err = check_declarations(MIME_PNG, "laudo.pdf", None).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+        let err = check_declarations(MIME_PDF, "laudo.pdf.exe", None).unwrap_err();
+        assert!(err.to_string().contains("Executable"));
+    }
+
+    #[test]
+    fn declared_type_must_match_the_detected_type() {
+        assert!(check_declarations(MIME_PDF, "laudo", Some("application/pdf")).is_ok());
+        assert!(check_declarations(MIME_JPEG, "foto", Some("image/jpg")).is_ok());
+        assert!(check_declarations(MIME_PNG, "foto", Some(".png")).is_ok());
+        assert!(check_declarations(MIME_PDF, "laudo", Some(" ")).is_ok());
+
+        let err = check_declarations(MIME_PDF, "laudo", Some("image/png")).unwrap_err();
+        assert!(err.to_string().contains("Declared type image/png"));
+        assert!(check_declarations(MIME_PDF, "laudo", Some("application/zip")).is_err());
+    }
+}
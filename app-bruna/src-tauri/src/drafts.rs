@@ -0,0 +1,322 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+
+// =====================================================
+// AUTOSAVE DRAFTS
+// =====================================================
+
+// Rascunhos do que está sendo digitado (prontuário, consulta), por usuário e
+// entidade. Ficam só no perfil local, criptografados, um arquivo por rascunho
+// gravado de forma atômica; nunca entram na partição sincronizada. Só viram
+// dados de verdade quando o formulário é salvo pelo comando normal.
+//
+// A tela chama save_draft a cada alteração; a gravação em disco espera
+// DRAFT_DEBOUNCE sem novas alterações, e só a última revisão é gravada.
+
+const DRAFT_FILE_PREFIX: &str = "draft_";
+const DRAFT_DEBOUNCE: Duration = Duration::from_millis(1500);
+const MAX_DRAFT_BYTES: usize = 1024 * 1024;
+
+pub const DRAFT_ENTITY_TYPES: [&str; 2] = ["medical_record", "appointment"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Draft {
+    pub user_id: String,
+    pub entity_type: String,
+    pub entity_id: Option<String>, // None enquanto o registro ainda não foi criado
+    pub payload: serde_json::Value,
+    pub revision: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub saved_at: Option<DateTime<Utc>>, // Última gravação em disco ("Salvo há X segundos")
+    pub session_id: String, // Execução do app que gravou o rascunho
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DraftStatus {
+    pub entity_type: String,
+    pub entity_id: Option<String>,
+    pub revision: u64,
+    pub updated_at: DateTime<Utc>,
+    pub saved_at: Option<DateTime<Utc>>,
+    pub pending: bool, // Alterações ainda não gravadas em disco
+}
+
+impl Draft {
+    fn status(&self, pending: bool) -> DraftStatus {
+        DraftStatus {
+            entity_type: self.entity_type.clone(),
+            entity_id: self.entity_id.clone(),
+            revision: self.revision,
+            updated_at: self.updated_at,
+            saved_at: self.saved_at,
+            pending,
+        }
+    }
+}
+
+// Identifica esta execução do app: rascunhos de outra execução sobraram de um
+// fechamento inesperado (ou de uma sessão que não salvou o formulário)
+static SESSION_ID: LazyLock<String> = LazyLock::new(|| uuid::Uuid::new_v4().to_string());
+
+// Última revisão de cada rascunho ainda não gravada em disco
+static PENDING: LazyLock<Mutex<HashMap<String, Draft>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Serializa as gravações em disco
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+fn draft_key(user_id: &str, entity_type: &str, entity_id: Option<&str>) -> String {
+    format!("{}:{}:{}", user_id, entity_type, entity_id.unwrap_or("new"))
+}
+
+// O nome do arquivo não expõe usuário nem paciente
+fn draft_file(key: &str) -> String {
+    format!("{}{}", DRAFT_FILE_PREFIX, &hex::encode(Sha256::digest(key.as_bytes()))[..32])
+}
+
+fn read_draft(key: &str) -> Result<Option<Draft>> {
+    crate::profiles::read_profile_file::<Draft>(&draft_file(key))
+}
+
+// Grava só se o rascunho pertence ao perfil ativo e nada mais novo foi gravado
+fn write_draft(key: &str, draft: &mut Draft) -> Result<()> {
+    let active_user = crate::profiles::active_profile().map(|p| p.user_id);
+    if active_user.as_deref() != Some(draft.user_id.as_str()) {
+        bail!("Draft belongs to a profile that is no longer active");
+    }
+    if let Some(existing) = read_draft(key)? {
+        if existing.revision > draft.revision {
+            return Ok(());
+        }
+    }
+    draft.saved_at = Some(Utc::now());
+    crate::profiles::write_profile_file(&draft_file(key), draft)
+}
+
+// Grava a revisão pendente, se ela ainda for a mais recente. O lock de escrita
+// cobre retirar e gravar, para um discard no meio não ser desfeito.
+fn flush_key(key: &str, revision: Option<u64>) -> Result<()> {
+    let _guard = WRITE_LOCK.lock().unwrap();
+    let draft = {
+        let mut pending = PENDING.lock().unwrap();
+        match pending.get(key) {
+            Some(draft) if revision.is_none_or(|r| r == draft.revision) => pending.remove(key),
+            _ => None,
+        }
+    };
+    match draft {
+        Some(mut draft) => write_draft(key, &mut draft),
+        None => Ok(()),
+    }
+}
+
+// =====================================================
+// PUBLIC API
+// =====================================================
+
+pub fn save_draft(
+    user_id: &str,
+    entity_type: &str,
+    entity_id: Option<&str>,
+    payload: serde_json::Value,
+) -> Result<DraftStatus> {
+    if !DRAFT_ENTITY_TYPES.contains(&entity_type) {
+        bail!("Drafts are not supported for {}", entity_type);
+    }
+    if serde_json::to_vec(&payload)?.len() > MAX_DRAFT_BYTES {
+        bail!("Draft is too large");
+    }
+
+    let key = draft_key(user_id, entity_type, entity_id);
+    // Revisão calculada e inserida sob os mesmos locks (na ordem de flush_key):
+    // dois saves simultâneos não repetem revisão e um flush em andamento não
+    // deixa o arquivo para trás
+    let write_guard = WRITE_LOCK.lock().unwrap();
+    let mut pending = PENDING.lock().unwrap();
+    let previous = match pending.get(&key).cloned() {
+        Some(draft) => Some(draft),
+        None => read_draft(&key)?,
+    };
+    let now = Utc::now();
+    let draft = Draft {
+        user_id: user_id.to_string(),
+        entity_type: entity_type.to_string(),
+        entity_id: entity_id.map(str::to_string),
+        payload,
+        revision: previous.as_ref().map_or(1, |d| d.revision + 1),
+        created_at: previous.as_ref().map_or(now, |d| d.created_at),
+        updated_at: now,
+        saved_at: previous.as_ref().and_then(|d| d.saved_at),
+        session_id: SESSION_ID.clone(),
+    };
+    let status = draft.status(true);
+    pending.insert(key.clone(), draft);
+    drop(pending);
+    drop(write_guard);
+
+    // Debounce: quem chegar depois com revisão maior invalida esta gravação
+    let revision = status.revision;
+    tokio::spawn(async move {
+        tokio::time::sleep(DRAFT_DEBOUNCE).await;
+        if let Err(e) = flush_key(&key, Some(revision)) {
+            eprintln!("Failed to write draft: {}", e);
+        }
+    });
+    Ok(status)
+}
+
+pub fn get_draft(user_id: &str, entity_type: &str, entity_id: Option<&str>) -> Result<Option<Draft>> {
+    let key = draft_key(user_id, entity_type, entity_id);
+    if let Some(draft) = PENDING.lock().unwrap().get(&key) {
+        return Ok(Some(draft.clone()));
+    }
+    read_draft(&key)
+}
+
+pub fn discard_draft(user_id: &str, entity_type: &str, entity_id: Option<&str>) -> Result<()> {
+    let key = draft_key(user_id, entity_type, entity_id);
+    PENDING.lock().unwrap().remove(&key);
+    let _guard = WRITE_LOCK.lock().unwrap();
+    crate::profiles::remove_profile_file(&draft_file(&key))
+}
+
+// Grava tudo o que está pendente do usuário (antes de fechar o perfil)
+pub fn flush_pending(user_id: &str) -> Result<()> {
+    let keys: Vec<String> = PENDING
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, draft)| draft.user_id == user_id)
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in keys {
+        flush_key(&key, None)?;
+    }
+    Ok(())
+}
+
+// Rascunhos deixados por execuções anteriores do app, para oferecer recuperação
+pub fn list_recoverable_drafts(user_id: &str) -> Result<Vec<Draft>> {
+    let mut drafts = Vec::new();
+    for entry in std::fs::read_dir(crate::profiles::active_profile_dir()?)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        let Some(name) = file_name
+            .strip_suffix(".enc")
+            .filter(|name| name.starts_with(DRAFT_FILE_PREFIX))
+        else {
+            continue;
+        };
+        match crate::profiles::read_profile_file::<Draft>(name) {
+            Ok(Some(draft)) if draft.user_id == user_id && draft.session_id != *SESSION_ID => drafts.push(draft),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to read draft {}: {}", name, e),
+        }
+    }
+    drafts.sort_by_key(|d| std::cmp::Reverse(d.updated_at));
+    Ok(drafts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::test_support::activate_test_profile;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    async fn concurrent_saves_get_distinct_increasing_revisions() {
+        let test = activate_test_profile("drafts-concurrent");
+        let user_id = test.profile.user_id.clone();
+
+        let saves: Vec<_> = (0..16)
+            .map(|i| {
+                let user_id = user_id.clone();
+                tokio::task::spawn_blocking(move || {
+                    save_draft(&user_id, "medical_record", Some("rec-1"), serde_json::json!({ "text": i })).unwrap()
+                })
+            })
+            .collect();
+        let mut revisions = Vec::new();
+        for save in saves {
+            revisions.push(save.await.unwrap().revision);
+        }
+        revisions.sort();
+        assert_eq!(revisions, (1..=16).collect::<Vec<u64>>());
+
+        // Só a última revisão chega ao disco
+        flush_pending(&user_id).unwrap();
+        let key = draft_key(&user_id, "medical_record", Some("rec-1"));
+        let saved = read_draft(&key).unwrap().unwrap();
+        assert_eq!(saved.revision, 16);
+        assert!(saved.saved_at.is_some());
+
+        let next = save_draft(&user_id, "medical_record", Some("rec-1"), serde_json::json!({})).unwrap();
+        assert_eq!(next.revision, 17);
+        discard_draft(&user_id, "medical_record", Some("rec-1")).unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    async fn debounce_writes_only_the_latest_revision() {
+        let test = activate_test_profile("drafts-debounce");
+        let user_id = test.profile.user_id.clone();
+        let key = draft_key(&user_id, "appointment", None);
+
+        save_draft(&user_id, "appointment", None, serde_json::json!({ "notes": "a" })).unwrap();
+        let status = save_draft(&user_id, "appointment", None, serde_json::json!({ "notes": "ab" })).unwrap();
+        assert!(status.pending);
+        assert!(read_draft(&key).unwrap().is_none());
+
+        tokio::time::sleep(DRAFT_DEBOUNCE + Duration::from_millis(500)).await;
+        let saved = read_draft(&key).unwrap().unwrap();
+        assert_eq!(saved.revision, 2);
+        assert_eq!(saved.payload["notes"], "ab");
+        discard_draft(&user_id, "appointment", None).unwrap();
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    async fn recovers_drafts_left_by_a_previous_session() {
+        let test = activate_test_profile("drafts-recovery");
+        let user_id = test.profile.user_id.clone();
+
+        // Rascunho gravado por uma execução que fechou sem salvar o formulário
+        let key = draft_key(&user_id, "medical_record", Some("rec-old"));
+        let now = Utc::now();
+        let orphan = Draft {
+            user_id: user_id.clone(),
+            entity_type: "medical_record".to_string(),
+            entity_id: Some("rec-old".to_string()),
+            payload: serde_json::json!({ "text": "anamnese incompleta" }),
+            revision: 3,
+            created_at: now,
+            updated_at: now,
+            saved_at: Some(now),
+            session_id: "previous-session".to_string(),
+        };
+        crate::profiles::write_profile_file(&draft_file(&key), &orphan).unwrap();
+
+        // Rascunho desta execução não é oferecido para recuperação
+        save_draft(&user_id, "medical_record", Some("rec-new"), serde_json::json!({})).unwrap();
+        flush_pending(&user_id).unwrap();
+
+        let recoverable = list_recoverable_drafts(&user_id).unwrap();
+        assert_eq!(recoverable.len(), 1);
+        assert_eq!(recoverable[0].entity_id.as_deref(), Some("rec-old"));
+        assert_eq!(recoverable[0].payload["text"], "anamnese incompleta");
+        assert!(list_recoverable_drafts("someone-else").unwrap().is_empty());
+
+        // Continuar editando o rascunho recuperado segue a numeração dele
+        let status = save_draft(&user_id, "medical_record", Some("rec-old"), serde_json::json!({ "text": "ok" })).unwrap();
+        assert_eq!(status.revision, 4);
+        flush_pending(&user_id).unwrap();
+        assert!(list_recoverable_drafts(&user_id).unwrap().is_empty());
+
+        discard_draft(&user_id, "medical_record", Some("rec-old")).unwrap();
+        discard_draft(&user_id, "medical_record", Some("rec-new")).unwrap();
+    }
+}
@@ -0,0 +1,518 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Utc};
+use tauri::{AppHandle, Emitter};
+
+use crate::document_crypto::{rewrap_document_key, DocumentEncryption};
+use crate::document_preview::preview_id;
+use crate::keyvault::{entity_aad, with_shared_vault};
+use crate::profiles::{profile_purpose, profiles_root, write_atomic};
+
+// =====================================================
+// MASTER KEY ROTATION
+// =====================================================
+
+// A rotação cria uma nova chave mestra e recifra em segundo plano tudo o que
+// ainda usa a chave antiga. O progresso é gravado em disco após cada lote, então
+// se o app fechar no meio a migração continua de onde parou na próxima abertura.
+// A chave antiga só é apagada depois que nenhum dado depende mais dela.
+
+const BATCH_SIZE: usize = 20;
+pub const PROGRESS_EVENT: &str = "key-rotation-progress";
+
+pub const TARGET_DOCUMENT_KEYS: &str = "document_keys";
+pub const TARGET_PROFILE_FILES: &str = "profile_files";
+pub const TARGET_TOTP_SECRETS: &str = "totp_secrets";
+
+const LOCAL_DATABASE_FILE: &str = "local_database";
+
+static ROTATION_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Dados já carregados em memória (perfil ativo) são recifrados por quem os
+// mantém, para que a próxima gravação não volte a usar a chave antiga
+type MemoryHook = fn() -> Result<usize>;
+
+static MEMORY_HOOKS: LazyLock<Mutex<Vec<MemoryHook>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+pub fn register_memory_hook(hook: MemoryHook) {
+    let mut hooks = MEMORY_HOOKS.lock().unwrap();
+    if !hooks.iter().any(|h| *h as usize == hook as usize) {
+        hooks.push(hook);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RotationPhase {
+    Reencrypting,
+    Verifying,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetProgress {
+    pub target: String,
+    pub total: usize,
+    pub processed: usize,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationState {
+    pub rotation_id: String,
+    pub old_key_id: String,
+    pub new_key_id: String,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub phase: RotationPhase,
+    pub targets: Vec<TargetProgress>,
+    pub last_error: Option<String>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+fn state_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join("key_rotation_state.json"))
+}
+
+pub fn load_state() -> Result<Option<RotationState>> {
+    let path = state_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&std::fs::read_to_string(&path)?)?))
+}
+
+fn save_state(state: &RotationState) -> Result<()> {
+    let path = state_path()?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(state)?)?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
+
+pub fn is_running() -> bool {
+    ROTATION_RUNNING.load(Ordering::SeqCst)
+}
+
+// Gera a nova chave e registra a rotação. A recifragem é feita por run_rotation.
+pub fn start_rotation(started_by: &str) -> Result<RotationState> {
+    if let Some(state) = load_state()? {
+        if state.phase != RotationPhase::Completed {
+            return Err(anyhow::anyhow!("A key rotation is already in progress"));
+        }
+    }
+
+    let (old_key_id, new_key_id) = with_shared_vault(|vault| {
+        let old_key_id = vault.master_key_id().to_string();
+        let new_key_id = vault.begin_rotation()?;
+        Ok((old_key_id, new_key_id))
+    })?;
+
+    register_rotation(old_key_id, new_key_id, started_by)
+}
+
+// Usado pela importação do kit de recuperação: a chave local vira a antiga e os
+// dados gravados com ela são migrados para a chave restaurada
+pub fn start_migration(old_key_id: &str, new_key_id: &str, started_by: &str) -> Result<RotationState> {
+    register_rotation(old_key_id.to_string(), new_key_id.to_string(), started_by)
+}
+
+fn register_rotation(old_key_id: String, new_key_id: String, started_by: &str) -> Result<RotationState> {
+    let state = RotationState {
+        rotation_id: uuid::Uuid::new_v4().to_string(),
+        old_key_id,
+        new_key_id,
+        started_by: started_by.to_string(),
+        started_at: Utc::now(),
+        phase: RotationPhase::Reencrypting,
+        // As DEKs vêm antes: a regravação do banco local já usa a chave nova
+        targets: [TARGET_DOCUMENT_KEYS, TARGET_PROFILE_FILES, TARGET_TOTP_SECRETS]
+            .iter()
+            .map(|target| TargetProgress {
+                target: target.to_string(),
+                total: 0,
+                processed: 0,
+                done: false,
+            })
+            .collect(),
+        last_error: None,
+        completed_at: None,
+    };
+    save_state(&state)?;
+    Ok(state)
+}
+
+// Executa (ou retoma) a rotação pendente, emitindo o progresso para o frontend
+pub async fn run_rotation(app: AppHandle) -> Result<Option<RotationState>> {
+    let mut state = match load_state()? {
+        Some(state) if state.phase != RotationPhase::Completed => state,
+        _ => return Ok(None),
+    };
+
+    if ROTATION_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(Some(state));
+    }
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let outcome = process_rotation(&mut state, &|state| {
+            let _ = app.emit(PROGRESS_EVENT, state);
+        });
+        if let Err(e) = &outcome {
+            state.phase = RotationPhase::Failed;
+            state.last_error = Some(e.to_string());
+            let _ = save_state(&state);
+            let _ = app.emit(PROGRESS_EVENT, &state);
+        }
+        outcome.map(|_| state)
+    })
+    .await;
+
+    ROTATION_RUNNING.store(false, Ordering::SeqCst);
+    Ok(Some(result.map_err(|e| anyhow::anyhow!("Key rotation task failed: {}", e))??))
+}
+
+// Chamado após cada gravação do estado em disco
+type ProgressFn<'a> = &'a dyn Fn(&RotationState);
+
+fn process_rotation(state: &mut RotationState, progress: ProgressFn) -> Result<()> {
+    state.phase = RotationPhase::Reencrypting;
+    state.last_error = None;
+    save_state(state)?;
+
+    for index in 0..state.targets.len() {
+        if state.targets[index].done {
+            continue;
+        }
+        let target = state.targets[index].target.clone();
+        match target.as_str() {
+            TARGET_DOCUMENT_KEYS => rewrap_document_keys(state, index, progress)?,
+            TARGET_PROFILE_FILES => reencrypt_profile_files(state, index, progress)?,
+            TARGET_TOTP_SECRETS => reencrypt_totp_secrets(state, index, progress)?,
+            other => return Err(anyhow::anyhow!("Unknown rotation target: {}", other)),
+        }
+        state.targets[index].done = true;
+        save_state(state)?;
+        progress(state);
+    }
+
+    // Confere que nada mais depende da chave antiga antes de apagá-la
+    state.phase = RotationPhase::Verifying;
+    save_state(state)?;
+    let remaining = stale_document_keys()? + stale_profile_files()?.len() + stale_totp_secrets()?;
+    if remaining > 0 {
+        for target in state.targets.iter_mut() {
+            target.done = false;
+            target.processed = 0;
+        }
+        return Err(anyhow::anyhow!("{} items still use the old key; rotation will be retried", remaining));
+    }
+
+    with_shared_vault(|vault| vault.retire_key(&state.old_key_id))?;
+
+    state.phase = RotationPhase::Completed;
+    state.completed_at = Some(Utc::now());
+    save_state(state)?;
+    progress(state);
+    Ok(())
+}
+
+// =====================================================
+// TARGETS
+// =====================================================
+
+// Bancos locais de todos os perfis (o perfil ativo é tratado pelos hooks)
+fn profile_databases() -> Result<Vec<(String, PathBuf)>> {
+    let mut databases = Vec::new();
+    for entry in std::fs::read_dir(profiles_root()?)? {
+        let dir = entry?.path();
+        let path = dir.join(format!("{}.enc", LOCAL_DATABASE_FILE));
+        if let (Some(profile_id), true) = (dir.file_name().and_then(|n| n.to_str()), path.exists()) {
+            databases.push((profile_id.to_string(), path));
+        }
+    }
+    databases.sort();
+    Ok(databases)
+}
+
+fn read_database(profile_id: &str, path: &PathBuf) -> Result<serde_json::Value> {
+    let encrypted = std::fs::read_to_string(path)?;
+    let json = with_shared_vault(|vault| {
+        vault.decrypt_with_aad(&encrypted, &profile_purpose(profile_id), &entity_aad("profile_file", LOCAL_DATABASE_FILE))
+    })?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+// DEKs de todos os documentos, de suas versões e das miniaturas, com o id usado
+// como AAD (o content_id de cada versão; documentos antigos usam o próprio id)
+fn document_keys(database: &mut serde_json::Value) -> Vec<(String, &mut serde_json::Value)> {
+    let mut keys = Vec::new();
+    let Some(documents) = database.get_mut("documents").and_then(|d| d.as_array_mut()) else {
+        return keys;
+    };
+
+    for document in documents.iter_mut() {
+        let Some(fields) = document.as_object_mut() else { continue };
+        let content_id = fields
+            .get("content_id")
+            .and_then(|c| c.as_str())
+            .or_else(|| fields.get("id").and_then(|i| i.as_str()))
+            .map(str::to_string);
+
+        for (field, value) in fields.iter_mut() {
+            match field.as_str() {
+                "encryption" if !value.is_null() => {
+                    if let Some(content_id) = &content_id {
+                        keys.push((content_id.clone(), value));
+                    }
+                }
+                "versions" => {
+                    for version in value.as_array_mut().into_iter().flatten() {
+                        let Some(version) = version.as_object_mut() else { continue };
+                        let Some(version_content) = version.get("content_id").and_then(|c| c.as_str()).map(str::to_string) else {
+                            continue;
+                        };
+                        for (version_field, version_value) in version.iter_mut() {
+                            match version_field.as_str() {
+                                "encryption" if !version_value.is_null() => {
+                                    keys.push((version_content.clone(), version_value));
+                                }
+                                "preview" => {
+                                    if let Some(encryption) = version_value.get_mut("encryption").filter(|e| !e.is_null()) {
+                                        keys.push((preview_id(&version_content), encryption));
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    keys
+}
+
+fn stale_document_keys() -> Result<usize> {
+    let mut stale = 0;
+    for (profile_id, path) in profile_databases()? {
+        let mut database = read_database(&profile_id, &path)?;
+        for (_, encryption) in document_keys(&mut database) {
+            let metadata: DocumentEncryption = serde_json::from_value(encryption.clone())?;
+            if with_shared_vault(|vault| Ok(vault.needs_upgrade(&metadata.wrapped_key)))? {
+                stale += 1;
+            }
+        }
+    }
+    Ok(stale)
+}
+
+fn rewrap_document_keys(state: &mut RotationState, index: usize, progress: ProgressFn) -> Result<()> {
+    let hooks = MEMORY_HOOKS.lock().unwrap().clone();
+    for hook in hooks {
+        state.targets[index].processed += hook()?;
+    }
+
+    let databases = profile_databases()?;
+    state.targets[index].total = state.targets[index].total.max(databases.len());
+    for (profile_id, path) in databases {
+        let mut database = read_database(&profile_id, &path)?;
+        let mut rewrapped = 0;
+        for (content_id, encryption) in document_keys(&mut database) {
+            let mut metadata: DocumentEncryption = serde_json::from_value(encryption.clone())?;
+            if with_shared_vault(|vault| rewrap_document_key(vault, &content_id, &mut metadata))? {
+                *encryption = serde_json::to_value(&metadata)?;
+                rewrapped += 1;
+            }
+        }
+
+        if rewrapped > 0 {
+            let encrypted = with_shared_vault(|vault| {
+                vault.encrypt_with_aad(
+                    &serde_json::to_vec(&database)?,
+                    &profile_purpose(&profile_id),
+                    &entity_aad("profile_file", LOCAL_DATABASE_FILE),
+                )
+            })?;
+            write_atomic(&path, &encrypted)?;
+        }
+
+        state.targets[index].processed += rewrapped;
+        save_state(state)?;
+        progress(state);
+    }
+    Ok(())
+}
+
+// Arquivos .enc de todos os perfis da estação (sessão, cache offline e banco
+// local, que inclui os documentos), não só do perfil ativo
+fn stale_profile_files() -> Result<Vec<(String, PathBuf)>> {
+    let mut stale = Vec::new();
+    for entry in std::fs::read_dir(profiles_root()?)? {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let profile_id = match dir.file_name().and_then(|n| n.to_str()) {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        for file in std::fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("enc") {
+                continue;
+            }
+            let encrypted = std::fs::read_to_string(&path)?;
+            if with_shared_vault(|vault| Ok(vault.needs_upgrade(&encrypted)))? {
+                stale.push((profile_id.clone(), path));
+            }
+        }
+    }
+    stale.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(stale)
+}
+
+fn reencrypt_profile_files(state: &mut RotationState, index: usize, progress: ProgressFn) -> Result<()> {
+    // A lista é recalculada a cada lote: arquivos já recifrados deixam de aparecer
+    loop {
+        let stale = stale_profile_files()?;
+        if state.targets[index].total == 0 {
+            state.targets[index].total = stale.len();
+        }
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        for (profile_id, path) in stale.iter().take(BATCH_SIZE) {
+            let name = path.file_stem().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+            let encrypted = std::fs::read_to_string(path)?;
+            let (_, upgraded) = with_shared_vault(|vault| {
+                vault.decrypt_and_upgrade(&encrypted, &profile_purpose(profile_id), &entity_aad("profile_file", &name))
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to re-encrypt {}: {}", path.display(), e))?;
+
+            if let Some(upgraded) = upgraded {
+                write_atomic(path, &upgraded)?;
+            }
+            state.targets[index].processed += 1;
+        }
+
+        save_state(state)?;
+        progress(state);
+    }
+}
+
+fn with_totp_service<T>(f: impl FnOnce(&mut crate::totp::TotpService) -> Result<T>) -> Result<T> {
+    let mut guard = crate::totp::TOTP_SERVICE.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(crate::totp::TotpService::new()?);
+    }
+    f(guard.as_mut().unwrap())
+}
+
+fn stale_totp_secrets() -> Result<usize> {
+    with_totp_service(|service| service.stale_secret_count())
+}
+
+fn reencrypt_totp_secrets(state: &mut RotationState, index: usize, progress: ProgressFn) -> Result<()> {
+    let count = with_totp_service(|service| service.reencrypt_secrets())?;
+    state.targets[index].total = state.targets[index].total.max(count);
+    state.targets[index].processed += count;
+    save_state(state)?;
+    progress(state);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyvault::test_support::{exclusive_vault, install_memory_vault, isolate_data_dir};
+    use crate::keyvault::KeyStatus;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    const PROFILE_ID: &str = "rotation-test";
+
+    fn profile_files() -> Vec<(String, PathBuf)> {
+        let dir = profiles_root().unwrap().join(PROFILE_ID);
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .map(|path| (path.file_stem().unwrap().to_str().unwrap().to_string(), path))
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn decrypt(name: &str, path: &PathBuf) -> Result<Vec<u8>> {
+        let encrypted = std::fs::read_to_string(path)?;
+        with_shared_vault(|vault| {
+            vault.decrypt_with_aad(&encrypted, &profile_purpose(PROFILE_ID), &entity_aad("profile_file", name))
+        })
+    }
+
+    #[test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    fn resumes_an_interrupted_rotation() {
+        isolate_data_dir();
+        install_memory_vault();
+        let _vault = exclusive_vault();
+
+        // Mais arquivos do que cabe em um lote
+        let dir = profiles_root().unwrap().join(PROFILE_ID);
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in 0..BATCH_SIZE + 5 {
+            let name = format!("file_{:02}", i);
+            let encrypted = with_shared_vault(|vault| {
+                vault.encrypt_with_aad(name.as_bytes(), &profile_purpose(PROFILE_ID), &entity_aad("profile_file", &name))
+            })
+            .unwrap();
+            write_atomic(&dir.join(format!("{}.enc", name)), &encrypted).unwrap();
+        }
+
+        let mut state = start_rotation("tester").unwrap();
+        let old_key_id = state.old_key_id.clone();
+
+        // O app "fecha" logo depois de gravar o primeiro lote
+        let interrupted = catch_unwind(AssertUnwindSafe(|| {
+            process_rotation(&mut state, &|state| {
+                if state.targets[1].processed >= BATCH_SIZE {
+                    panic!("app closed");
+                }
+            })
+        }));
+        assert!(interrupted.is_err());
+
+        let mut state = load_state().unwrap().unwrap();
+        assert_eq!(state.phase, RotationPhase::Reencrypting);
+        assert!(state.targets[0].done);
+        assert!(!state.targets[1].done);
+        assert_eq!(state.targets[1].processed, BATCH_SIZE);
+        let stale = profile_files()
+            .iter()
+            .filter(|(_, path)| {
+                let encrypted = std::fs::read_to_string(path).unwrap();
+                with_shared_vault(|vault| Ok(vault.needs_upgrade(&encrypted))).unwrap()
+            })
+            .count();
+        assert_eq!(stale, 5);
+
+        // Na próxima abertura a rotação continua de onde parou
+        process_rotation(&mut state, &|_| {}).unwrap();
+        assert_eq!(state.phase, RotationPhase::Completed);
+        assert_eq!(load_state().unwrap().unwrap().phase, RotationPhase::Completed);
+
+        for (name, path) in profile_files() {
+            assert_eq!(decrypt(&name, &path).unwrap(), name.as_bytes());
+        }
+        let versions = with_shared_vault(|vault| Ok(vault.key_versions())).unwrap();
+        let old = versions.iter().find(|v| v.id == old_key_id).unwrap();
+        assert_eq!(old.status, KeyStatus::Retired);
+    }
+}
@@ -1,37 +1,122 @@
 use anyhow::Result;
 use std::collections::HashMap;
 use aes_gcm::{Aes256Gcm, Key, Nonce, KeyInit};
-use aes_gcm::aead::Aead;
+use aes_gcm::aead::{Aead, Payload};
 use base64::{engine::general_purpose, Engine as _};
 use rand::Rng;
 use sha2::{Sha256, Digest};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+use chrono::{DateTime, Utc};
+
+use crate::secret_store::SecretStore;
+
+const MASTER_KEY_NAME: &str = "dra_bruna_master_key";
+const KEY_MANIFEST_NAME: &str = "dra_bruna_key_manifest";
+const CONTENT_ADDRESS_KEY_NAME: &str = "dra_bruna_content_address_key";
+const SIGNING_KEY_PREFIX: &str = "dra_bruna_signing_key";
+const DERIVED_KEY_TTL_SECONDS: u64 = 600;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyStatus {
+    Active,
+    Retiring, // Substituída, mas ainda há dados cifrados com ela
+    Retired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyVersion {
+    pub id: String,
+    pub secret_name: String,
+    pub status: KeyStatus,
+    pub created_at: DateTime<Utc>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyManifest {
+    pub active_key_id: String,
+    pub legacy_key_id: Option<String>, // Chave usada pelos blobs anteriores ao envelope
+    pub versions: Vec<KeyVersion>,
+}
 
-#[cfg(target_os = "windows")]
-use windows::{
-    core::PCSTR,
-    Win32::Security::Cryptography::{
-        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN,
-        DATA_BLOB, CRYPT_INTEGER_BLOB,
-    },
-};
+#[derive(Debug, Clone)]
+pub struct KeyMaterial {
+    pub active_key_id: String,
+    pub legacy_key_id: Option<String>,
+    pub keys: Vec<(String, Vec<u8>)>,
+}
 
 pub struct KeyVault {
-    master_key: Vec<u8>,
-    key_cache: HashMap<String, Vec<u8>>,
+    master_keys: HashMap<String, Vec<u8>>,
+    manifest: KeyManifest,
+    key_cache: HashMap<(String, String), (Vec<u8>, Instant)>,
+    content_address_key: Option<Vec<u8>>,
+    store: Box<dyn SecretStore>,
 }
 
 impl KeyVault {
     pub fn new() -> Result<Self> {
-        let master_key = Self::get_or_create_master_key()?;
+        Self::with_store(crate::secret_store::from_config()?)
+    }
+
+    pub fn with_store(store: Box<dyn SecretStore>) -> Result<Self> {
+        let manifest = Self::get_or_create_manifest(store.as_ref())?;
+
+        let mut master_keys = HashMap::new();
+        for version in manifest.versions.iter().filter(|v| v.status != KeyStatus::Retired) {
+            let key = store.load_secret(&version.secret_name)?
+                .ok_or_else(|| anyhow::anyhow!("Master key {} is missing from the secret store", version.id))?;
+            master_keys.insert(version.id.clone(), key);
+        }
+
+        if !master_keys.contains_key(&manifest.active_key_id) {
+            return Err(anyhow::anyhow!("Active master key not available"));
+        }
+
         Ok(Self {
-            master_key,
+            master_keys,
+            manifest,
             key_cache: HashMap::new(),
+            content_address_key: None,
+            store,
         })
     }
 
-    fn get_or_create_master_key() -> Result<Vec<u8>> {
-        // Try to load existing key from DPAPI
-        if let Ok(key) = Self::load_key_from_dpapi("dra_bruna_master_key") {
+    pub fn backend_name(&self) -> &'static str {
+        self.store.name()
+    }
+
+    fn get_or_create_manifest(store: &dyn SecretStore) -> Result<KeyManifest> {
+        if let Some(json) = store.load_secret(KEY_MANIFEST_NAME)? {
+            return Ok(serde_json::from_slice(&json)?);
+        }
+
+        // Instalações anteriores ao versionamento têm apenas a chave original
+        let master_key = Self::get_or_create_master_key(store)?;
+        let key_id = master_key_fingerprint(&master_key);
+        let manifest = KeyManifest {
+            active_key_id: key_id.clone(),
+            legacy_key_id: Some(key_id.clone()),
+            versions: vec![KeyVersion {
+                id: key_id,
+                secret_name: MASTER_KEY_NAME.to_string(),
+                status: KeyStatus::Active,
+                created_at: Utc::now(),
+                retired_at: None,
+            }],
+        };
+        store.store_secret(KEY_MANIFEST_NAME, &serde_json::to_vec(&manifest)?)?;
+        Ok(manifest)
+    }
+
+    fn get_or_create_master_key(store: &dyn SecretStore) -> Result<Vec<u8>> {
+        // Try to load existing key from the configured secret store. A load
+        // error is propagated: regenerating here would orphan all existing data.
+        if let Some(key) = store.load_secret(MASTER_KEY_NAME)? {
             return Ok(key);
         }
 
@@ -39,155 +124,345 @@ impl KeyVault {
         let mut key = [0u8; 32];
         rand::thread_rng().fill(&mut key);
         
-        // Store in DPAPI
-        Self::store_key_in_dpapi("dra_bruna_master_key", &key)?;
+        store.store_secret(MASTER_KEY_NAME, &key)?;
         
         Ok(key.to_vec())
     }
 
-    #[cfg(target_os = "windows")]
-    fn store_key_in_dpapi(key_name: &str, data: &[u8]) -> Result<()> {
-        let data_blob = DATA_BLOB {
-            cbData: data.len() as u32,
-            pbData: data.as_ptr() as *mut u8,
-        };
+    fn save_manifest(&self) -> Result<()> {
+        self.store.store_secret(KEY_MANIFEST_NAME, &serde_json::to_vec(&self.manifest)?)
+    }
 
-        let mut encrypted_blob = DATA_BLOB::default();
-        let result = unsafe {
-            CryptProtectData(
-                &data_blob,
-                PCSTR::from_raw(format!("Dra Bruna Clinic - {}", key_name).as_ptr() as *const u8),
-                None,
-                None,
-                None,
-                CRYPTPROTECT_UI_FORBIDDEN,
-                &mut encrypted_blob,
-            )
-        };
+    pub fn master_key_id(&self) -> &str {
+        &self.manifest.active_key_id
+    }
+
+    pub fn key_versions(&self) -> Vec<KeyVersion> {
+        self.manifest.versions.clone()
+    }
+
+    pub fn rotation_in_progress(&self) -> bool {
+        self.manifest.versions.iter().any(|v| v.status == KeyStatus::Retiring)
+    }
 
-        if result.is_err() {
-            return Err(anyhow::anyhow!("Failed to encrypt data with DPAPI"));
+    // =====================================================
+    // KEY ROTATION
+    // =====================================================
+
+    // Cria uma nova versão da chave mestra e passa a cifrar tudo com ela.
+    // A versão anterior continua disponível (Retiring) até a migração terminar.
+    pub fn begin_rotation(&mut self) -> Result<String> {
+        if self.rotation_in_progress() {
+            return Err(anyhow::anyhow!("A key rotation is already in progress"));
         }
 
-        // Store encrypted data in registry or file system
-        // For now, we'll use a simple file-based approach
-        let app_data = dirs::data_dir()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
-            .join("DraBrunaClinic");
-        
-        std::fs::create_dir_all(&app_data)?;
-        
-        let key_file = app_data.join(format!("{}.key", key_name));
-        std::fs::write(key_file, unsafe {
-            std::slice::from_raw_parts(encrypted_blob.pbData, encrypted_blob.cbData as usize)
-        })?;
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill(&mut key);
+        let key_id = master_key_fingerprint(&key);
+        let secret_name = format!("{}_{}", MASTER_KEY_NAME, key_id);
+
+        // A chave é gravada antes do manifesto: se o app cair no meio, sobra
+        // apenas um segredo órfão e nada fica ilegível
+        self.store.store_secret(&secret_name, &key)?;
+
+        for version in self.manifest.versions.iter_mut() {
+            if version.status == KeyStatus::Active {
+                version.status = KeyStatus::Retiring;
+            }
+        }
+        self.manifest.versions.push(KeyVersion {
+            id: key_id.clone(),
+            secret_name,
+            status: KeyStatus::Active,
+            created_at: Utc::now(),
+            retired_at: None,
+        });
+        self.manifest.active_key_id = key_id.clone();
+        self.save_manifest()?;
+
+        self.master_keys.insert(key_id.clone(), key.to_vec());
+        self.key_cache.clear();
+        Ok(key_id)
+    }
+
+    // Remove definitivamente uma chave substituída. Só deve ser chamado depois
+    // que todos os dados foram recifrados com a chave ativa.
+    pub fn retire_key(&mut self, key_id: &str) -> Result<()> {
+        let version = self.manifest.versions
+            .iter_mut()
+            .find(|v| v.id == key_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown key: {}", key_id))?;
+
+        if version.status != KeyStatus::Retiring {
+            return Err(anyhow::anyhow!("Only a replaced key can be retired"));
+        }
 
+        version.status = KeyStatus::Retired;
+        version.retired_at = Some(Utc::now());
+        let secret_name = version.secret_name.clone();
+
+        if self.manifest.legacy_key_id.as_deref() == Some(key_id) {
+            self.manifest.legacy_key_id = None;
+        }
+        self.save_manifest()?;
+        self.store.delete_secret(&secret_name)?;
+
+        self.master_keys.remove(key_id);
+        self.key_cache.clear();
         Ok(())
     }
 
-    #[cfg(target_os = "windows")]
-    fn load_key_from_dpapi(key_name: &str) -> Result<Vec<u8>> {
-        let app_data = dirs::data_dir()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
-            .join("DraBrunaClinic");
-        
-        let key_file = app_data.join(format!("{}.key", key_name));
-        
-        if !key_file.exists() {
-            return Err(anyhow::anyhow!("Key file not found"));
+    // =====================================================
+    // CONTENT ADDRESSING
+    // =====================================================
+
+    // Id de blob = HMAC-SHA256(chave da clínica, SHA-256 do conteúdo), para que o
+    // mesmo arquivo não gere o mesmo id em clínicas diferentes. A chave fica fora
+    // da rotação: perdê-la só impede deduplicar contra blobs antigos.
+    pub fn content_address(&mut self, content_hash: &str) -> Result<String> {
+        if self.content_address_key.is_none() {
+            let key = match self.store.load_secret(CONTENT_ADDRESS_KEY_NAME)? {
+                Some(key) => key,
+                None => {
+                    let mut key = [0u8; 32];
+                    rand::thread_rng().fill(&mut key);
+                    self.store.store_secret(CONTENT_ADDRESS_KEY_NAME, &key)?;
+                    key.to_vec()
+                }
+            };
+            self.content_address_key = Some(key);
         }
 
-        let encrypted_data = std::fs::read(key_file)?;
-        
-        let encrypted_blob = DATA_BLOB {
-            cbData: encrypted_data.len() as u32,
-            pbData: encrypted_data.as_ptr() as *mut u8,
-        };
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.content_address_key.as_deref().unwrap_or_default())
+            .map_err(|e| anyhow::anyhow!("Invalid content address key: {}", e))?;
+        mac.update(content_hash.as_bytes());
+        Ok(format!("blob-{}", hex::encode(mac.finalize().into_bytes())))
+    }
 
-        let mut decrypted_blob = DATA_BLOB::default();
-        let result = unsafe {
-            CryptUnprotectData(
-                &encrypted_blob,
-                None,
-                None,
-                None,
-                None,
-                CRYPTPROTECT_UI_FORBIDDEN,
-                &mut decrypted_blob,
-            )
+    // =====================================================
+    // SIGNING KEYS
+    // =====================================================
+
+    // Chave Ed25519 do usuário para assinar prontuários. Fica direto no armazenamento
+    // seguro e não é derivada da chave mestra: rotacionar a chave mestra não pode
+    // mudar a identidade de quem assinou.
+    pub fn signing_key(&mut self, user_id: &str) -> Result<ed25519_dalek::SigningKey> {
+        // Nome do segredo vira nome de arquivo em alguns backends: usa o hash do id
+        let name = format!("{}_{}", SIGNING_KEY_PREFIX, hex::encode(Sha256::digest(user_id.as_bytes())));
+        let seed = match self.store.load_secret(&name)? {
+            Some(seed) => seed,
+            None => {
+                let mut seed = [0u8; 32];
+                rand::thread_rng().fill(&mut seed);
+                self.store.store_secret(&name, &seed)?;
+                seed.to_vec()
+            }
         };
+        let seed: [u8; 32] = seed
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Signing key for user {} is corrupted", user_id))?;
+        Ok(ed25519_dalek::SigningKey::from_bytes(&seed))
+    }
+
+    // =====================================================
+    // KEY ESCROW (recovery kit)
+    // =====================================================
 
-        if result.is_err() {
-            return Err(anyhow::anyhow!("Failed to decrypt data with DPAPI"));
+    // Material bruto das chaves ainda em uso, para o kit de recuperação
+    pub fn export_key_material(&self) -> Result<KeyMaterial> {
+        if self.rotation_in_progress() {
+            return Err(anyhow::anyhow!("Finish the pending key rotation before exporting a recovery kit"));
         }
 
-        let decrypted_data = unsafe {
-            std::slice::from_raw_parts(decrypted_blob.pbData, decrypted_blob.cbData as usize)
-        };
+        let keys = self.manifest.versions
+            .iter()
+            .filter(|v| v.status != KeyStatus::Retired)
+            .filter_map(|v| self.master_keys.get(&v.id).map(|k| (v.id.clone(), k.clone())))
+            .collect();
 
-        Ok(decrypted_data.to_vec())
+        Ok(KeyMaterial {
+            active_key_id: self.manifest.active_key_id.clone(),
+            legacy_key_id: self.manifest.legacy_key_id.clone(),
+            keys,
+        })
     }
 
-    #[cfg(not(target_os = "windows"))]
-    fn store_key_in_dpapi(_key_name: &str, _data: &[u8]) -> Result<()> {
-        // Fallback for non-Windows systems
-        // In production, you'd want to use platform-specific key storage
-        Err(anyhow::anyhow!("DPAPI not available on this platform"))
+    // Restaura as chaves de um kit. Se a máquina já tinha outra chave ativa (ex.:
+    // instalação nova), ela passa a Retiring e o id dela é devolvido para que os
+    // dados locais sejam migrados pela rotação.
+    pub fn import_key_material(&mut self, material: &KeyMaterial) -> Result<Option<String>> {
+        if self.rotation_in_progress() {
+            return Err(anyhow::anyhow!("Finish the pending key rotation before importing a recovery kit"));
+        }
+        if !material.keys.iter().any(|(id, _)| *id == material.active_key_id) {
+            return Err(anyhow::anyhow!("Recovery kit does not contain its active key"));
+        }
+        for (id, key) in &material.keys {
+            if key.len() != 32 || master_key_fingerprint(key) != *id {
+                return Err(anyhow::anyhow!("Recovery kit key {} failed integrity check", id));
+            }
+        }
+
+        for (id, key) in &material.keys {
+            if self.master_keys.contains_key(id) {
+                continue;
+            }
+            let secret_name = format!("{}_{}", MASTER_KEY_NAME, id);
+            self.store.store_secret(&secret_name, key)?;
+            self.manifest.versions.retain(|v| v.id != *id);
+            self.manifest.versions.push(KeyVersion {
+                id: id.clone(),
+                secret_name,
+                status: KeyStatus::Retiring,
+                created_at: Utc::now(),
+                retired_at: None,
+            });
+            self.master_keys.insert(id.clone(), key.clone());
+        }
+
+        let local_active = self.manifest.active_key_id.clone();
+        let replaced = if local_active == material.active_key_id {
+            None
+        } else {
+            Some(local_active)
+        };
+
+        for version in self.manifest.versions.iter_mut() {
+            if version.id == material.active_key_id {
+                version.status = KeyStatus::Active;
+            } else if version.status == KeyStatus::Active {
+                version.status = KeyStatus::Retiring;
+            }
+        }
+        self.manifest.active_key_id = material.active_key_id.clone();
+        // Os blobs pré-envelope restaurados de backup pertencem à chave do kit;
+        // uma instalação nova não tem dados nesse formato
+        if material.legacy_key_id.is_some() {
+            self.manifest.legacy_key_id = material.legacy_key_id.clone();
+        }
+        self.save_manifest()?;
+        self.key_cache.clear();
+        Ok(replaced)
     }
 
-    #[cfg(not(target_os = "windows"))]
-    fn load_key_from_dpapi(_key_name: &str) -> Result<Vec<u8>> {
-        // Fallback for non-Windows systems
-        Err(anyhow::anyhow!("DPAPI not available on this platform"))
+    pub fn clear_key_cache(&mut self) {
+        self.key_cache.clear();
     }
 
+    // HKDF-SHA256 (RFC 5869) sobre a chave mestra ativa, com salt de domínio e
+    // o propósito como "info"
     pub fn derive_key(&mut self, purpose: &str) -> Result<Vec<u8>> {
-        if let Some(cached_key) = self.key_cache.get(purpose) {
-            return Ok(cached_key.clone());
+        let key_id = self.manifest.active_key_id.clone();
+        self.derive_key_for(&key_id, purpose)
+    }
+
+    fn derive_key_for(&mut self, key_id: &str, purpose: &str) -> Result<Vec<u8>> {
+        let cache_key = (key_id.to_string(), purpose.to_string());
+        if let Some((cached_key, derived_at)) = self.key_cache.get(&cache_key) {
+            if derived_at.elapsed().as_secs() < DERIVED_KEY_TTL_SECONDS {
+                return Ok(cached_key.clone());
+            }
         }
 
-        let mut hasher = Sha256::new();
-        hasher.update(&self.master_key);
-        hasher.update(purpose.as_bytes());
-        let derived_key = hasher.finalize().to_vec();
+        let master_key = self.master_keys
+            .get(key_id)
+            .ok_or_else(|| anyhow::anyhow!("Data encrypted with unknown key: {}", key_id))?;
+
+        let hkdf = Hkdf::<Sha256>::new(Some(KDF_SALT), master_key);
+        let mut derived_key = vec![0u8; 32];
+        hkdf.expand(purpose.as_bytes(), &mut derived_key)
+            .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
 
-        self.key_cache.insert(purpose.to_string(), derived_key.clone());
+        self.key_cache.insert(cache_key, (derived_key.clone(), Instant::now()));
         Ok(derived_key)
     }
 
+    // Derivação antiga (SHA256(master || purpose)), mantida apenas para ler
+    // dados gravados antes do envelope versionado
+    fn derive_legacy_key(&self, purpose: &str) -> Result<Vec<u8>> {
+        let master_key = self.manifest.legacy_key_id
+            .as_ref()
+            .and_then(|id| self.master_keys.get(id))
+            .ok_or_else(|| anyhow::anyhow!("Legacy key no longer available"))?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(master_key);
+        hasher.update(purpose.as_bytes());
+        Ok(hasher.finalize().to_vec())
+    }
+
     pub fn encrypt_data(&mut self, data: &[u8], purpose: &str) -> Result<String> {
+        self.encrypt_with_aad(data, purpose, &[])
+    }
+
+    pub fn decrypt_data(&mut self, encrypted_data: &str, purpose: &str) -> Result<Vec<u8>> {
+        self.decrypt_with_aad(encrypted_data, purpose, &[])
+    }
+
+    // `aad` amarra o ciphertext ao seu contexto (ex.: entity_aad("document", id)),
+    // impedindo que um blob seja copiado para outro registro
+    pub fn encrypt_with_aad(&mut self, data: &[u8], purpose: &str, aad: &[u8]) -> Result<String> {
         let key_bytes = self.derive_key(purpose)?;
-        let key = Key::from_slice(&key_bytes);
+        let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
         let cipher = Aes256Gcm::new(key);
 
         let mut rng = rand::thread_rng();
         let mut nonce_bytes = [0u8; 12];
         rng.fill(&mut nonce_bytes);
 
+        let header = EnvelopeHeader {
+            version: ENVELOPE_VERSION,
+            algorithm: ALG_AES_256_GCM,
+            key_id: self.manifest.active_key_id.clone(),
+            nonce: nonce_bytes.to_vec(),
+        };
+        let header_bytes = header.to_bytes();
+
         let nonce = Nonce::from_slice(&nonce_bytes);
         let ciphertext = cipher
-            .encrypt(nonce, data)
+            .encrypt(nonce, Payload { msg: data, aad: &envelope_aad(&header_bytes, aad) })
             .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
 
-        let mut result = nonce_bytes.to_vec();
+        let mut result = header_bytes;
         result.extend_from_slice(&ciphertext);
 
         Ok(general_purpose::STANDARD.encode(result))
     }
 
-    pub fn decrypt_data(&mut self, encrypted_data: &str, purpose: &str) -> Result<Vec<u8>> {
-        let key_bytes = self.derive_key(purpose)?;
-        let key = Key::from_slice(&key_bytes);
-        let cipher = Aes256Gcm::new(key);
-
+    pub fn decrypt_with_aad(&mut self, encrypted_data: &str, purpose: &str, aad: &[u8]) -> Result<Vec<u8>> {
         let data = general_purpose::STANDARD
             .decode(encrypted_data)
             .map_err(|_| anyhow::anyhow!("Invalid base64 data"))?;
 
+        if let Some((header, header_len)) = EnvelopeHeader::parse(&data) {
+            if header.algorithm != ALG_AES_256_GCM {
+                return Err(anyhow::anyhow!("Unsupported encryption algorithm: {}", header.algorithm));
+            }
+            let key_bytes = self.derive_key_for(&header.key_id, purpose)?;
+            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes));
+            let nonce = Nonce::from_slice(&header.nonce);
+            let header_bytes = &data[..header_len];
+
+            // Cabeçalho v2 válido: falha de autenticação é erro, sem tentar o
+            // formato antigo (senão um envelope adulterado seria tratado como
+            // blob legado)
+            return cipher
+                .decrypt(nonce, Payload { msg: &data[header_len..], aad: &envelope_aad(header_bytes, aad) })
+                .map_err(|_| anyhow::anyhow!("Decryption failed"));
+        }
+
+        // Sem o magic "DBKV" (ou com cabeçalho que não se lê): formato antigo
+        self.decrypt_legacy(&data, purpose)
+    }
+
+    fn decrypt_legacy(&self, data: &[u8], purpose: &str) -> Result<Vec<u8>> {
         if data.len() < 12 {
             return Err(anyhow::anyhow!("Invalid encrypted data length"));
         }
 
+        let key_bytes = self.derive_legacy_key(purpose)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes));
+
         let nonce_bytes: [u8; 12] = data[0..12].try_into()
             .map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
         let nonce = Nonce::from_slice(&nonce_bytes);
@@ -199,10 +474,301 @@ impl KeyVault {
 
         Ok(plaintext)
     }
+
+    pub fn inspect_ciphertext(encrypted_data: &str) -> CiphertextFormat {
+        let data = match general_purpose::STANDARD.decode(encrypted_data) {
+            Ok(data) => data,
+            Err(_) => return CiphertextFormat::Invalid,
+        };
+        match EnvelopeHeader::parse(&data) {
+            Some((header, _)) => CiphertextFormat::Envelope {
+                version: header.version,
+                algorithm: header.algorithm,
+                key_id: header.key_id,
+            },
+            None => CiphertextFormat::Legacy,
+        }
+    }
+
+    // Verdadeiro para blobs no formato antigo ou cifrados com outra chave mestra
+    pub fn needs_upgrade(&self, encrypted_data: &str) -> bool {
+        match Self::inspect_ciphertext(encrypted_data) {
+            CiphertextFormat::Envelope { version, algorithm, key_id } => {
+                version != ENVELOPE_VERSION || algorithm != ALG_AES_256_GCM || key_id != self.manifest.active_key_id
+            }
+            CiphertextFormat::Legacy => true,
+            CiphertextFormat::Invalid => false,
+        }
+    }
+
+    // Decifra e, se o blob estiver em formato antigo, devolve também a versão
+    // regravada no envelope atual para o chamador persistir
+    pub fn decrypt_and_upgrade(&mut self, encrypted_data: &str, purpose: &str, aad: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+        let plaintext = self.decrypt_with_aad(encrypted_data, purpose, aad)?;
+        let upgraded = if self.needs_upgrade(encrypted_data) {
+            Some(self.encrypt_with_aad(&plaintext, purpose, aad)?)
+        } else {
+            None
+        };
+        Ok((plaintext, upgraded))
+    }
+}
+
+// =====================================================
+// CIPHERTEXT ENVELOPE
+// =====================================================
+
+// Layout (antes do base64):
+//   "DBKV" | version u8 | algorithm u8 | key_id_len u8 | key_id | nonce_len u8 | nonce | ciphertext+tag
+// O cabeçalho inteiro entra no AAD do AES-GCM, junto com o AAD do chamador.
+
+const ENVELOPE_MAGIC: &[u8; 4] = b"DBKV";
+const ENVELOPE_VERSION: u8 = 2;
+const ALG_AES_256_GCM: u8 = 1;
+const KDF_SALT: &[u8] = b"DraBrunaClinic/KeyVault/HKDF-SHA256/v2";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CiphertextFormat {
+    Legacy,
+    Envelope { version: u8, algorithm: u8, key_id: String },
+    Invalid,
+}
+
+struct EnvelopeHeader {
+    version: u8,
+    algorithm: u8,
+    key_id: String,
+    nonce: Vec<u8>,
+}
+
+impl EnvelopeHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(8 + self.key_id.len() + self.nonce.len());
+        out.extend_from_slice(ENVELOPE_MAGIC);
+        out.push(self.version);
+        out.push(self.algorithm);
+        out.push(self.key_id.len() as u8);
+        out.extend_from_slice(self.key_id.as_bytes());
+        out.push(self.nonce.len() as u8);
+        out.extend_from_slice(&self.nonce);
+        out
+    }
+
+    fn parse(data: &[u8]) -> Option<(Self, usize)> {
+        if data.len() < 7 || &data[..4] != ENVELOPE_MAGIC {
+            return None;
+        }
+        let version = data[4];
+        let algorithm = data[5];
+        let key_id_len = data[6] as usize;
+        let mut pos = 7;
+
+        let key_id = std::str::from_utf8(data.get(pos..pos + key_id_len)?).ok()?.to_string();
+        pos += key_id_len;
+
+        let nonce_len = *data.get(pos)? as usize;
+        pos += 1;
+        if nonce_len != 12 {
+            return None;
+        }
+        let nonce = data.get(pos..pos + nonce_len)?.to_vec();
+        pos += nonce_len;
+
+        if version != ENVELOPE_VERSION || data.len() < pos + 16 {
+            return None;
+        }
+
+        Some((Self { version, algorithm, key_id, nonce }, pos))
+    }
+}
+
+fn envelope_aad(header_bytes: &[u8], aad: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(header_bytes.len() + 4 + aad.len());
+    out.extend_from_slice(header_bytes);
+    out.extend_from_slice(&(aad.len() as u32).to_be_bytes());
+    out.extend_from_slice(aad);
+    out
+}
+
+// AAD padrão para dados de uma entidade (tipo + id)
+pub fn entity_aad(entity_type: &str, entity_id: &str) -> Vec<u8> {
+    format!("{}:{}", entity_type, entity_id).into_bytes()
+}
+
+pub fn master_key_fingerprint(master_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"dra_bruna_key_id");
+    hasher.update(master_key);
+    format!("mk-{}", hex::encode(&hasher.finalize()[..6]))
+}
+
+// =====================================================
+// SHARED INSTANCE
+// =====================================================
+
+// Uma única instância por processo: após uma rotação todos os módulos passam a
+// enxergar a nova chave ativa imediatamente
+static SHARED_VAULT: LazyLock<Mutex<Option<KeyVault>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn with_shared_vault<T>(f: impl FnOnce(&mut KeyVault) -> Result<T>) -> Result<T> {
+    let mut guard = SHARED_VAULT.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(KeyVault::new()?);
+    }
+    f(guard.as_mut().unwrap())
+}
+
+// Cofre compartilhado em memória para os testes dos módulos que cifram pelo
+// with_shared_vault (nada é gravado no disco nem no DPAPI)
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+    use std::sync::{Once, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    struct MemoryStore(Mutex<HashMap<String, Vec<u8>>>);
+
+    impl SecretStore for MemoryStore {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        fn load_secret(&self, key_name: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.lock().unwrap().get(key_name).cloned())
+        }
+
+        fn store_secret(&self, key_name: &str, data: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().insert(key_name.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn delete_secret(&self, key_name: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(key_name);
+            Ok(())
+        }
+    }
+
+    pub fn memory_store() -> Box<dyn SecretStore> {
+        Box::new(MemoryStore(Mutex::new(HashMap::new())))
+    }
+
+    pub fn install_memory_vault() {
+        let mut guard = SHARED_VAULT.lock().unwrap_or_else(|e| e.into_inner());
+        if guard.is_none() {
+            *guard = Some(KeyVault::with_store(memory_store()).expect("memory vault"));
+        }
+    }
+
+    // Testes que trocam a chave ativa (rotação) precisam do cofre só para si;
+    // os demais seguram o guard compartilhado enquanto cifram e decifram
+    static VAULT_TESTS: RwLock<()> = RwLock::new(());
+
+    pub fn shared_vault() -> RwLockReadGuard<'static, ()> {
+        VAULT_TESTS.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn exclusive_vault() -> RwLockWriteGuard<'static, ()> {
+        VAULT_TESTS.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Os testes que gravam em disco usam um diretório de dados temporário. Só o
+    // Linux respeita XDG_DATA_HOME; nos outros sistemas esses testes ficam ignorados.
+    pub fn isolate_data_dir() {
+        static ISOLATE: Once = Once::new();
+        ISOLATE.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("dra-bruna-tests-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).expect("test data dir");
+            std::env::set_var("XDG_DATA_HOME", &dir);
+        });
+        let data_dir = dirs::data_dir().expect("data dir");
+        assert!(data_dir.starts_with(std::env::temp_dir()), "refusing to use the real data dir in tests");
+    }
 }
 
-impl Default for KeyVault {
-    fn default() -> Self {
-        Self::new().expect("Failed to initialize KeyVault")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyvault::test_support::memory_store;
+
+    const PURPOSE: &str = "test";
+
+    fn vault() -> KeyVault {
+        KeyVault::with_store(memory_store()).unwrap()
+    }
+
+    fn reencode(data: &[u8]) -> String {
+        general_purpose::STANDARD.encode(data)
+    }
+
+    #[test]
+    fn envelope_round_trip() {
+        let mut vault = vault();
+        let aad = entity_aad("patient", "p-1");
+        let encrypted = vault.encrypt_with_aad(b"prontuario", PURPOSE, &aad).unwrap();
+
+        assert_eq!(
+            KeyVault::inspect_ciphertext(&encrypted),
+            CiphertextFormat::Envelope {
+                version: ENVELOPE_VERSION,
+                algorithm: ALG_AES_256_GCM,
+                key_id: vault.master_key_id().to_string(),
+            }
+        );
+        assert!(!vault.needs_upgrade(&encrypted));
+        assert_eq!(vault.decrypt_with_aad(&encrypted, PURPOSE, &aad).unwrap(), b"prontuario");
+
+        // O AAD e o propósito amarram o blob ao seu contexto
+        assert!(vault.decrypt_with_aad(&encrypted, PURPOSE, &entity_aad("patient", "p-2")).is_err());
+        assert!(vault.decrypt_with_aad(&encrypted, "other", &aad).is_err());
+    }
+
+    #[test]
+    fn tampered_header_fails() {
+        let mut vault = vault();
+        let encrypted = vault.encrypt_with_aad(b"prontuario", PURPOSE, b"").unwrap();
+        let data = general_purpose::STANDARD.decode(&encrypted).unwrap();
+        let (header, header_len) = EnvelopeHeader::parse(&data).unwrap();
+
+        // Nonce do cabeçalho alterado: o cabeçalho ainda é lido, mas não autentica
+        let mut tampered = data.clone();
+        tampered[header_len - 1] ^= 0x01;
+        assert!(vault.decrypt_with_aad(&reencode(&tampered), PURPOSE, b"").is_err());
+
+        // Versão desconhecida não pode cair no formato antigo e decifrar
+        let mut tampered = data.clone();
+        tampered[4] = ENVELOPE_VERSION + 1;
+        assert!(vault.decrypt_with_aad(&reencode(&tampered), PURPOSE, b"").is_err());
+
+        // Algoritmo trocado é recusado antes de decifrar
+        let mut tampered = data.clone();
+        tampered[5] = ALG_AES_256_GCM + 1;
+        let err = vault.decrypt_with_aad(&reencode(&tampered), PURPOSE, b"").unwrap_err();
+        assert!(err.to_string().contains("Unsupported encryption algorithm"));
+
+        // Chave que o cofre não conhece
+        let mut tampered = data.clone();
+        tampered[7] ^= 0x01;
+        assert!(vault.decrypt_with_aad(&reencode(&tampered), PURPOSE, b"").is_err());
+
+        assert_eq!(header.key_id, vault.master_key_id());
+    }
+
+    #[test]
+    fn legacy_blob_decrypts_and_upgrades() {
+        let mut vault = vault();
+        let legacy_key = vault.derive_legacy_key(PURPOSE).unwrap();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&legacy_key));
+        let nonce = [7u8; 12];
+        let mut legacy = nonce.to_vec();
+        legacy.extend(cipher.encrypt(Nonce::from_slice(&nonce), b"dados antigos".as_ref()).unwrap());
+        let legacy = reencode(&legacy);
+
+        assert_eq!(KeyVault::inspect_ciphertext(&legacy), CiphertextFormat::Legacy);
+        assert!(vault.needs_upgrade(&legacy));
+
+        let (plaintext, upgraded) = vault.decrypt_and_upgrade(&legacy, PURPOSE, b"").unwrap();
+        assert_eq!(plaintext, b"dados antigos");
+        let upgraded = upgraded.unwrap();
+        assert!(!vault.needs_upgrade(&upgraded));
+        assert_eq!(vault.decrypt_with_aad(&upgraded, PURPOSE, b"").unwrap(), b"dados antigos");
     }
 }
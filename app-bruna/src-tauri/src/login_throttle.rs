@@ -0,0 +1,366 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Duration, Utc};
+
+// =====================================================
+// LOGIN THROTTLING AND ACCOUNT LOCKOUT
+// =====================================================
+
+pub const MAX_FAILURES_PER_ACCOUNT: u32 = 5;
+pub const MAX_FAILURES_PER_WORKSTATION: u32 = 20;
+pub const LOCKOUT_MINUTES: i64 = 15;
+const FAILURE_WINDOW_MINUTES: i64 = 60; // Falhas mais antigas que isso não contam
+const MAX_DELAY_SECONDS: i64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    pub key: String,
+    pub failures: u32,
+    pub first_failure_at: DateTime<Utc>,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub lockout_count: u32, // Bloqueios consecutivos (dobra a duração a cada novo bloqueio)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ThrottleDecision {
+    Allowed,
+    Delayed { retry_after_seconds: i64 },
+    Locked { until: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockoutInfo {
+    pub key: String,
+    pub failures: u32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub last_failure_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct ThrottleState {
+    workstation_id: String,
+    records: HashMap<String, AttemptRecord>,
+}
+
+static THROTTLE_STATE: LazyLock<Mutex<Option<ThrottleState>>> = LazyLock::new(|| Mutex::new(None));
+
+fn store_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join("login_attempts.json"))
+}
+
+fn load_state() -> Result<ThrottleState> {
+    let path = store_path()?;
+    let mut state: ThrottleState = if path.exists() {
+        serde_json::from_str(&std::fs::read_to_string(&path)?)?
+    } else {
+        ThrottleState::default()
+    };
+
+    if state.workstation_id.is_empty() {
+        state.workstation_id = uuid::Uuid::new_v4().to_string();
+        save_state(&state)?;
+    }
+    Ok(state)
+}
+
+fn save_state(state: &ThrottleState) -> Result<()> {
+    let json = serde_json::to_string_pretty(state)?;
+    crate::profiles::write_atomic(&store_path()?, &json)
+}
+
+fn with_state<T>(f: impl FnOnce(&mut ThrottleState) -> Result<T>) -> Result<T> {
+    let mut guard = THROTTLE_STATE.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(load_state()?);
+    }
+    f(guard.as_mut().unwrap())
+}
+
+fn account_key(email: &str) -> String {
+    format!("account:{}", email.trim().to_lowercase())
+}
+
+fn workstation_key(workstation_id: &str) -> String {
+    format!("workstation:{}", workstation_id)
+}
+
+// Atraso progressivo: 0s nas duas primeiras falhas, depois 2s, 4s, 8s... até 30s
+fn progressive_delay(failures: u32) -> i64 {
+    if failures < 3 {
+        0
+    } else {
+        (1i64 << (failures - 2).min(5)).min(MAX_DELAY_SECONDS)
+    }
+}
+
+fn evaluate(record: Option<&AttemptRecord>, now: DateTime<Utc>) -> ThrottleDecision {
+    let record = match record {
+        Some(r) => r,
+        None => return ThrottleDecision::Allowed,
+    };
+
+    if let Some(until) = record.locked_until {
+        if until > now {
+            return ThrottleDecision::Locked { until };
+        }
+    }
+
+    let ready_at = record.last_failure_at + Duration::seconds(progressive_delay(record.failures));
+    if ready_at > now {
+        return ThrottleDecision::Delayed {
+            retry_after_seconds: (ready_at - now).num_seconds().max(1),
+        };
+    }
+
+    ThrottleDecision::Allowed
+}
+
+fn current_decision(state: &ThrottleState, email: &str, now: DateTime<Utc>) -> ThrottleDecision {
+    for key in [account_key(email), workstation_key(&state.workstation_id)] {
+        match evaluate(state.records.get(&key), now) {
+            ThrottleDecision::Allowed => continue,
+            decision => return decision,
+        }
+    }
+    ThrottleDecision::Allowed
+}
+
+// Só a recusa de e-mail/senha pelo Supabase conta como falha; rede fora do ar
+// ou erro do servidor não devem bloquear a conta
+pub fn is_credential_failure(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<crate::auth::InvalidCredentials>().is_some()
+}
+
+pub fn workstation_id() -> Result<String> {
+    with_state(|state| Ok(state.workstation_id.clone()))
+}
+
+// Deve ser chamado antes de enviar a senha ao Supabase
+pub fn check_login_allowed(email: &str) -> Result<ThrottleDecision> {
+    with_state(|state| Ok(current_decision(state, email, Utc::now())))
+}
+
+// Registra uma falha para a conta e para a estação de trabalho.
+// Retorna a decisão que passa a valer para a próxima tentativa.
+pub fn record_login_failure(email: &str) -> Result<ThrottleDecision> {
+    with_state(|state| {
+        let decision = apply_failure(state, email, Utc::now());
+        save_state(state)?;
+        Ok(decision)
+    })
+}
+
+pub fn record_login_success(email: &str) -> Result<()> {
+    with_state(|state| {
+        apply_success(state, email);
+        save_state(state)
+    })
+}
+
+fn apply_failure(state: &mut ThrottleState, email: &str, now: DateTime<Utc>) -> ThrottleDecision {
+    let limits = [
+        (account_key(email), MAX_FAILURES_PER_ACCOUNT),
+        (workstation_key(&state.workstation_id), MAX_FAILURES_PER_WORKSTATION),
+    ];
+
+    for (key, max_failures) in limits.iter() {
+        let record = state.records.entry(key.clone()).or_insert_with(|| AttemptRecord {
+            key: key.clone(),
+            failures: 0,
+            first_failure_at: now,
+            last_failure_at: now,
+            locked_until: None,
+            lockout_count: 0,
+        });
+
+        // Janela expirada ou bloqueio já cumprido: recomeça a contagem
+        let lock_expired = record.locked_until.is_some_and(|until| until <= now);
+        if lock_expired || now - record.last_failure_at > Duration::minutes(FAILURE_WINDOW_MINUTES) {
+            record.failures = 0;
+            record.first_failure_at = now;
+            record.locked_until = None;
+        }
+
+        record.failures += 1;
+        record.last_failure_at = now;
+
+        if record.failures >= *max_failures {
+            let minutes = LOCKOUT_MINUTES * (1i64 << record.lockout_count.min(4));
+            record.locked_until = Some(now + Duration::minutes(minutes));
+            record.lockout_count += 1;
+        }
+    }
+
+    current_decision(state, email, now)
+}
+
+fn apply_success(state: &mut ThrottleState, email: &str) {
+    state.records.remove(&account_key(email));
+    if let Some(record) = state.records.get_mut(&workstation_key(&state.workstation_id)) {
+        if record.locked_until.is_none() {
+            record.failures = 0;
+        }
+    }
+}
+
+pub fn unlock_account(email: &str) -> Result<bool> {
+    with_state(|state| {
+        let removed = state.records.remove(&account_key(email)).is_some();
+        save_state(state)?;
+        Ok(removed)
+    })
+}
+
+pub fn unlock_workstation(workstation_id: &str) -> Result<bool> {
+    with_state(|state| {
+        let removed = state.records.remove(&workstation_key(workstation_id)).is_some();
+        save_state(state)?;
+        Ok(removed)
+    })
+}
+
+pub fn list_lockouts() -> Result<Vec<LockoutInfo>> {
+    with_state(|state| {
+        let now = Utc::now();
+        let mut out: Vec<LockoutInfo> = state.records
+            .values()
+            .filter(|r| r.locked_until.is_some_and(|until| until > now) || r.failures > 0)
+            .map(|r| LockoutInfo {
+                key: r.key.clone(),
+                failures: r.failures,
+                locked_until: r.locked_until.filter(|until| *until > now),
+                last_failure_at: r.last_failure_at,
+            })
+            .collect();
+        out.sort_by_key(|info| std::cmp::Reverse(info.last_failure_at));
+        Ok(out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::InvalidCredentials;
+
+    const EMAIL: &str = "recepcao@clinica.com";
+
+    fn state() -> ThrottleState {
+        ThrottleState {
+            workstation_id: "ws-1".to_string(),
+            records: HashMap::new(),
+        }
+    }
+
+    fn failures(state: &ThrottleState, email: &str) -> u32 {
+        state.records.get(&account_key(email)).map_or(0, |r| r.failures)
+    }
+
+    #[test]
+    fn delay_grows_after_the_second_failure_and_is_capped() {
+        let delays: Vec<i64> = (1..=8).map(progressive_delay).collect();
+        assert_eq!(delays, vec![0, 0, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[test]
+    fn delays_then_locks_the_account() {
+        let mut state = state();
+        let now = Utc::now();
+
+        assert!(matches!(apply_failure(&mut state, EMAIL, now), ThrottleDecision::Allowed));
+        assert!(matches!(apply_failure(&mut state, EMAIL, now), ThrottleDecision::Allowed));
+        match apply_failure(&mut state, EMAIL, now) {
+            ThrottleDecision::Delayed { retry_after_seconds } => assert_eq!(retry_after_seconds, 2),
+            other => panic!("expected a delay, got {:?}", other),
+        }
+        assert!(matches!(current_decision(&state, EMAIL, now + Duration::seconds(2)), ThrottleDecision::Allowed));
+
+        apply_failure(&mut state, EMAIL, now);
+        match apply_failure(&mut state, EMAIL, now) {
+            ThrottleDecision::Locked { until } => assert_eq!(until, now + Duration::minutes(LOCKOUT_MINUTES)),
+            other => panic!("expected a lockout, got {:?}", other),
+        }
+        // Outra conta na mesma estação só espera o atraso da estação
+        let later = now + Duration::seconds(MAX_DELAY_SECONDS);
+        assert!(matches!(current_decision(&state, "outra@clinica.com", later), ThrottleDecision::Allowed));
+        assert!(matches!(current_decision(&state, EMAIL, later), ThrottleDecision::Locked { .. }));
+    }
+
+    #[test]
+    fn repeated_lockouts_double_the_duration() {
+        let mut state = state();
+        let first = Utc::now();
+        for _ in 0..MAX_FAILURES_PER_ACCOUNT {
+            apply_failure(&mut state, EMAIL, first);
+        }
+
+        // Bloqueio cumprido: a contagem recomeça, mas o próximo bloqueio dura o dobro
+        let second = first + Duration::minutes(LOCKOUT_MINUTES);
+        assert!(matches!(current_decision(&state, EMAIL, second), ThrottleDecision::Allowed));
+        let mut decision = ThrottleDecision::Allowed;
+        for _ in 0..MAX_FAILURES_PER_ACCOUNT {
+            decision = apply_failure(&mut state, EMAIL, second);
+        }
+        match decision {
+            ThrottleDecision::Locked { until } => assert_eq!(until, second + Duration::minutes(2 * LOCKOUT_MINUTES)),
+            other => panic!("expected a lockout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn old_failures_fall_out_of_the_window() {
+        let mut state = state();
+        let now = Utc::now();
+        for _ in 0..MAX_FAILURES_PER_ACCOUNT - 1 {
+            apply_failure(&mut state, EMAIL, now);
+        }
+        let later = now + Duration::minutes(FAILURE_WINDOW_MINUTES + 1);
+        assert!(matches!(apply_failure(&mut state, EMAIL, later), ThrottleDecision::Allowed));
+        assert_eq!(failures(&state, EMAIL), 1);
+    }
+
+    #[test]
+    fn only_rejected_credentials_count() {
+        let rejected: anyhow::Error = InvalidCredentials("invalid_grant".to_string()).into();
+        assert!(is_credential_failure(&rejected));
+
+        let offline = anyhow::anyhow!("Login failed: error sending request");
+        assert!(!is_credential_failure(&offline));
+        let context = rejected.context("while logging in");
+        assert!(is_credential_failure(&context));
+    }
+
+    #[test]
+    fn success_resets_the_counters() {
+        let mut state = state();
+        let now = Utc::now();
+        for _ in 0..MAX_FAILURES_PER_ACCOUNT - 1 {
+            apply_failure(&mut state, EMAIL, now);
+        }
+        apply_success(&mut state, EMAIL);
+        assert_eq!(failures(&state, EMAIL), 0);
+        assert_eq!(state.records[&workstation_key("ws-1")].failures, 0);
+
+        // Depois do sucesso a próxima falha volta a ser a primeira, sem atraso
+        assert!(matches!(apply_failure(&mut state, EMAIL, now), ThrottleDecision::Allowed));
+        assert_eq!(failures(&state, EMAIL), 1);
+    }
+
+    #[test]
+    fn success_does_not_lift_a_workstation_lockout() {
+        let mut state = state();
+        let now = Utc::now();
+        for i in 0..MAX_FAILURES_PER_WORKSTATION {
+            apply_failure(&mut state, &format!("user{}@clinica.com", i), now);
+        }
+        apply_success(&mut state, EMAIL);
+        assert!(matches!(current_decision(&state, EMAIL, now), ThrottleDecision::Locked { .. }));
+    }
+}
@@ -5,10 +5,33 @@ mod supabase;
 mod auth;
 mod config;
 mod commands;
+mod commands_simple;
 mod real_time;
 mod offline_cache;
 mod security;
 mod performance;
+mod secret_store;
+mod keyvault;
+mod totp;
+mod login_throttle;
+mod offline_credentials;
+mod profiles;
+mod key_rotation;
+mod recovery_kit;
+mod document_crypto;
+mod storage_sync;
+mod document_validation;
+mod document_preview;
+mod medical_records_sync;
+mod drafts;
+mod anamnesis;
+mod clinical_alerts;
+mod patients;
+mod scheduling;
+mod availability;
+mod recurrence;
+mod appointment_status;
+mod waitlist;
 
 
 
@@ -21,6 +44,11 @@ fn main() {
                 return Err(e.into());
             }
             
+            // Decrypted copies left behind by a previous run
+            if let Err(e) = document_crypto::sweep_temp_files(None) {
+                eprintln!("Failed to remove temporary documents: {}", e);
+            }
+            
             let app_handle = app.handle().clone();
             
             // Initialize robust system components
@@ -51,9 +79,26 @@ fn main() {
                     eprintln!("Failed to initialize performance monitoring: {}", e);
                 }
                 
+                // Resume an interrupted master key rotation
+                if let Err(e) = key_rotation::run_rotation(app_handle.clone()).await {
+                    eprintln!("Failed to resume key rotation: {}", e);
+                }
+                
                 eprintln!("🚀 Sistema Dra. Bruna inicializado com sucesso!");
             });
             
+            // Expire decrypted copies the frontend did not release
+            tauri::async_runtime::spawn(async {
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(10 * 60));
+                loop {
+                    interval.tick().await;
+                    let max_age = std::time::Duration::from_secs(document_crypto::TEMP_FILE_TTL_SECONDS);
+                    if let Err(e) = document_crypto::sweep_temp_files(Some(max_age)) {
+                        eprintln!("Failed to remove temporary documents: {}", e);
+                    }
+                }
+            });
+            
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -62,13 +107,6 @@ fn main() {
             commands::get_app_info,
             commands::check_connectivity,
             
-            // Authentication commands
-            commands::login,
-            commands::logout,
-            commands::get_current_user,
-            commands::refresh_session,
-            commands::check_permission,
-            
             // Real-time commands
             commands::subscribe_to_changes,
             commands::unsubscribe_from_changes,
@@ -88,7 +126,174 @@ fn main() {
             commands::get_performance_metrics,
             commands::optimize_database,
             commands::cleanup_old_data,
+            
+            // Authentication commands
+            commands_simple::initialize_auth,
+            commands_simple::login,
+            commands_simple::verify_login_second_factor,
+            commands_simple::logout,
+            commands_simple::get_current_user,
+            commands_simple::refresh_session,
+            commands_simple::check_permission,
+            commands_simple::list_audit_logs,
+            
+            // Local profile commands
+            commands_simple::list_workstation_profiles,
+            commands_simple::switch_user,
+            
+            // Two-factor authentication commands
+            commands_simple::get_totp_status,
+            commands_simple::begin_totp_enrollment,
+            commands_simple::confirm_totp_enrollment,
+            commands_simple::regenerate_recovery_codes,
+            commands_simple::disable_totp,
+            
+            // Account lockout commands
+            commands_simple::get_login_lockouts,
+            commands_simple::unlock_account,
+            commands_simple::unlock_workstation,
+            
+            // Master key commands
+            commands_simple::rotate_master_key,
+            commands_simple::get_key_rotation_status,
+            commands_simple::export_recovery_kit,
+            commands_simple::export_recovery_shares,
+            commands_simple::import_recovery_kit,
+            commands_simple::import_recovery_shares,
+            commands_simple::encrypt_data,
+            commands_simple::decrypt_data,
+            
+            // Backup commands
+            commands_simple::backup_database,
+            commands_simple::restore_database,
+            commands_simple::get_backup_info,
+            commands_simple::schedule_automatic_backup,
+            
+            // Patient commands
+            commands_simple::greet,
+            commands_simple::get_patients,
+            commands_simple::validate_patient,
+            commands_simple::create_patient,
+            commands_simple::update_patient,
+            commands_simple::delete_patient,
+            commands_simple::search_patients,
+            commands_simple::get_patient_clinical_items,
+            commands_simple::add_clinical_item,
+            commands_simple::update_clinical_item,
+            commands_simple::delete_clinical_item,
+            commands_simple::get_patient_alerts,
+            
+            // Appointment commands
+            commands_simple::get_appointments,
+            commands_simple::create_appointment,
+            commands_simple::update_appointment,
+            commands_simple::check_appointment_conflicts,
+            commands_simple::get_scheduling_rules,
+            commands_simple::set_scheduling_rules,
+            commands_simple::delete_appointment,
+            commands_simple::change_appointment_status,
+            commands_simple::get_allowed_status_transitions,
+            commands_simple::reschedule_appointment,
+            commands_simple::get_appointment_statistics,
+            
+            // Availability and series commands
+            commands_simple::get_availability_calendar,
+            commands_simple::set_availability_calendar,
+            commands_simple::get_holidays,
+            commands_simple::find_available_slots,
+            commands_simple::preview_appointment_series,
+            commands_simple::create_appointment_series,
+            commands_simple::get_appointment_series,
+            commands_simple::update_appointment_series,
+            commands_simple::delete_appointment_series,
+            
+            // Waitlist commands
+            commands_simple::get_waitlist,
+            commands_simple::add_waitlist_entry,
+            commands_simple::update_waitlist_entry,
+            commands_simple::remove_waitlist_entry,
+            commands_simple::get_waitlist_offers,
+            commands_simple::dismiss_waitlist_offer,
+            commands_simple::accept_waitlist_offer,
+            
+            // Document commands
+            commands_simple::get_documents,
+            commands_simple::create_document,
+            commands_simple::get_document_content,
+            commands_simple::import_document_file,
+            commands_simple::export_document_to_temp,
+            commands_simple::release_temp_document,
+            commands_simple::stream_document,
+            commands_simple::queue_document_for_storage_sync,
+            commands_simple::delete_document,
+            commands_simple::list_document_versions,
+            commands_simple::get_document_version_content,
+            commands_simple::rollback_document,
+            commands_simple::get_document_retention_policy,
+            commands_simple::set_document_retention_policy,
+            commands_simple::apply_document_retention,
+            commands_simple::check_document_duplicate,
+            commands_simple::check_document_file_duplicate,
+            commands_simple::purge_deleted_documents,
+            commands_simple::get_document_preview,
+            commands_simple::get_document_policy,
+            commands_simple::set_document_policy,
+            commands_simple::encrypt_document,
+            commands_simple::decrypt_document,
+            
+            // File storage sync commands
+            commands_simple::add_file_to_sync,
+            commands_simple::sync_all_files,
+            commands_simple::get_file_sync_status,
+            commands_simple::get_files_by_status,
+            commands_simple::download_file,
+            commands_simple::resolve_file_conflict,
+            commands_simple::remove_file_from_sync,
+            
+            // Anamnesis commands
+            commands_simple::get_anamnesis_templates,
+            commands_simple::get_anamnesis_template,
+            commands_simple::save_anamnesis_template,
+            commands_simple::archive_anamnesis_template,
+            commands_simple::validate_anamnesis,
+            commands_simple::fill_anamnesis,
+            commands_simple::get_patient_anamneses,
+            commands_simple::render_anamnesis,
+            commands_simple::find_patients_by_anamnesis_answer,
+            
+            // Medical record commands
+            commands_simple::save_medical_record,
+            commands_simple::get_medical_record,
+            commands_simple::get_patient_medical_records,
+            commands_simple::get_medical_record_history,
+            commands_simple::get_medical_record_field_history,
+            commands_simple::diff_medical_record_versions,
+            commands_simple::reconcile_medical_record,
+            commands_simple::delete_medical_record,
+            commands_simple::sync_medical_records,
+            commands_simple::finalize_medical_record,
+            commands_simple::add_medical_record_addendum,
+            commands_simple::verify_medical_record,
+            commands_simple::get_medical_record_signer_keys,
+            commands_simple::endorse_medical_record_signer_key,
+            commands_simple::save_draft,
+            commands_simple::get_draft,
+            commands_simple::discard_draft,
+            commands_simple::list_recoverable_drafts,
+            
+            // Report commands
+            commands_simple::generate_patients_report,
+            commands_simple::generate_appointments_report,
+            commands_simple::generate_documents_report,
+            commands_simple::generate_daily_appointments_report,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Err(e) = document_crypto::sweep_temp_files(None) {
+                    eprintln!("Failed to remove temporary documents: {}", e);
+                }
+            }
+        });
 }
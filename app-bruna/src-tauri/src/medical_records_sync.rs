@@ -0,0 +1,1720 @@
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, SubsecRound, Utc};
+use ed25519_dalek::{Signature, Signer, VerifyingKey};
+
+use crate::keyvault::with_shared_vault;
+
+// =====================================================
+// MEDICAL RECORDS (PRONTUÁRIO) WITH IMMUTABLE VERSIONS
+// =====================================================
+
+// Cada salvamento cria uma versão imutável que aponta para a versão em que se
+// baseou. Duas edições feitas a partir da mesma versão (em máquinas diferentes ou
+// sem atualizar a tela) viram versões irmãs: o prontuário fica com duas "cabeças"
+// até que um profissional as reconcilie numa versão com os dois pais.
+//
+// A sincronização só insere versões: uma versão que já existe no servidor nunca
+// é sobrescrita, e versões remotas com o mesmo id e conteúdo diferente são
+// recusadas.
+
+const STORE_FILE: &str = "medical_records";
+const VERSIONS_TABLE: &str = "medical_record_versions";
+const SIGNATURES_TABLE: &str = "medical_record_signatures";
+const ADDENDA_TABLE: &str = "medical_record_addenda";
+const SIGNER_KEYS_TABLE: &str = "medical_record_signer_keys";
+const KEY_ENDORSEMENTS_TABLE: &str = "medical_record_key_endorsements";
+const SIGNERS_FILE: &str = "medical_record_signers";
+const LEGACY_SIGNERS_FILE: &str = "medical_record_signers.json";
+const DEVICE_ID_FILE: &str = "device_id";
+const SIGNATURE_ALGORITHM: &str = "Ed25519";
+
+pub const RECORD_FIELDS: [&str; 4] = ["anamnesis", "diagnosis", "treatment_plan", "notes"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MedicalRecordFields {
+    pub anamnesis: Option<String>,
+    pub diagnosis: Option<String>,
+    pub treatment_plan: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl MedicalRecordFields {
+    pub fn get(&self, field: &str) -> Option<&Option<String>> {
+        match field {
+            "anamnesis" => Some(&self.anamnesis),
+            "diagnosis" => Some(&self.diagnosis),
+            "treatment_plan" => Some(&self.treatment_plan),
+            "notes" => Some(&self.notes),
+            _ => None,
+        }
+    }
+}
+
+// Uma versão do prontuário (imutável depois de criada)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicalRecord {
+    pub id: String,        // Id desta versão
+    pub record_id: String, // Prontuário lógico
+    pub patient_id: String,
+    pub appointment_id: Option<String>,
+    pub version: u32, // Irmãs têm o mesmo número
+    #[serde(default)]
+    pub parent_ids: Vec<String>,
+    #[serde(flatten)]
+    pub fields: MedicalRecordFields,
+    #[serde(default)]
+    pub deleted: bool, // Exclusão também é uma versão; o histórico permanece
+    pub content_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+impl MedicalRecord {
+    // Forma canônica (campos em ordem fixa, pais ordenados) usada para o hash
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut parent_ids = self.parent_ids.clone();
+        parent_ids.sort();
+        serde_json::json!([
+            self.id,
+            self.record_id,
+            self.patient_id,
+            self.appointment_id,
+            self.version,
+            parent_ids,
+            self.fields.anamnesis,
+            self.fields.diagnosis,
+            self.fields.treatment_plan,
+            self.fields.notes,
+            self.deleted,
+            self.created_at.to_rfc3339(),
+            self.created_by,
+        ])
+        .to_string()
+        .into_bytes()
+    }
+
+    pub fn compute_hash(&self) -> String {
+        hex::encode(Sha256::digest(self.canonical_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SaveMedicalRecordRequest {
+    pub record_id: Option<String>, // None cria um prontuário novo
+    pub patient_id: String,
+    pub appointment_id: Option<String>,
+    pub base_version_id: Option<String>, // Versão que estava na tela durante a edição
+    #[serde(flatten)]
+    pub fields: MedicalRecordFields,
+}
+
+// Estado atual de um prontuário: uma cabeça, ou várias quando há edições concorrentes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicalRecordView {
+    pub record_id: String,
+    pub patient_id: String,
+    pub heads: Vec<MedicalRecord>,
+    pub has_conflict: bool,
+    pub version_count: usize,
+    pub finalization: Option<MedicalRecordSignature>,
+    pub addenda: Vec<MedicalRecordAddendum>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub kind: ChangeKind,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldHistoryEntry {
+    pub version_id: String,
+    pub version: u32,
+    pub value: Option<String>,
+    pub changed_by: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MedicalRecordSyncReport {
+    pub pushed: usize,
+    pub pulled: usize,
+    pub rejected: Vec<String>, // Itens remotos com conteúdo divergente ou assinatura não reconhecida
+    pub conflicted_records: Vec<String>, // Prontuários que ficaram com versões irmãs
+}
+
+// Assinatura de finalização de uma versão
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicalRecordSignature {
+    pub id: String,
+    pub record_id: String,
+    pub version_id: String,
+    pub patient_id: String,
+    pub content_hash: String, // Hash da versão assinada
+    pub signer_id: String,
+    #[serde(default)]
+    pub device_id: String, // Dispositivo cuja chave assinou
+    pub signed_at: DateTime<Utc>,
+    pub algorithm: String,
+    pub public_key: String, // Hex
+    pub signature: String,  // Hex
+    #[serde(default)]
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+impl MedicalRecordSignature {
+    fn message(&self) -> Vec<u8> {
+        serde_json::json!([
+            "medical_record_signature_v1",
+            self.id,
+            self.record_id,
+            self.version_id,
+            self.patient_id,
+            self.content_hash,
+            self.signer_id,
+            self.signed_at.to_rfc3339(),
+        ])
+        .to_string()
+        .into_bytes()
+    }
+}
+
+// Complemento assinado a um prontuário já finalizado
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicalRecordAddendum {
+    pub id: String,
+    pub record_id: String,
+    pub version_id: String, // Versão finalizada a que o adendo se refere
+    pub patient_id: String,
+    pub text: String,
+    pub created_by: String,
+    #[serde(default)]
+    pub device_id: String,
+    pub created_at: DateTime<Utc>,
+    pub public_key: String,
+    pub signature: String,
+    #[serde(default)]
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+impl MedicalRecordAddendum {
+    fn message(&self) -> Vec<u8> {
+        serde_json::json!([
+            "medical_record_addendum_v1",
+            self.id,
+            self.record_id,
+            self.version_id,
+            self.patient_id,
+            self.text,
+            self.created_by,
+            self.created_at.to_rfc3339(),
+        ])
+        .to_string()
+        .into_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MedicalRecordVerification {
+    pub record_id: String,
+    pub finalized: bool,
+    pub valid: bool,
+    pub signer_verified: bool, // Chave de quem finalizou é confiável nesta estação
+    pub version_id: Option<String>,
+    pub signer_id: Option<String>,
+    pub signed_at: Option<DateTime<Utc>>,
+    pub addenda_checked: usize,
+    pub issues: Vec<String>, // Conteúdo ou assinatura não conferem
+    pub unverified: Vec<String>, // Assinaturas íntegras, mas de chaves não confiáveis
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MedicalRecordStore {
+    versions: Vec<MedicalRecord>,
+    #[serde(default)]
+    signatures: Vec<MedicalRecordSignature>,
+    #[serde(default)]
+    addenda: Vec<MedicalRecordAddendum>,
+}
+
+// Leitura-modificação-gravação do arquivo do perfil é serializada
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+fn load_store() -> Result<MedicalRecordStore> {
+    Ok(crate::profiles::read_profile_file::<MedicalRecordStore>(STORE_FILE)?.unwrap_or_default())
+}
+
+fn save_store(store: &MedicalRecordStore) -> Result<()> {
+    crate::profiles::write_profile_file(STORE_FILE, store)
+}
+
+// =====================================================
+// LOCAL VERSION GRAPH
+// =====================================================
+
+fn record_versions<'a>(store: &'a MedicalRecordStore, record_id: &str) -> Vec<&'a MedicalRecord> {
+    let mut versions: Vec<&MedicalRecord> = store.versions.iter().filter(|v| v.record_id == record_id).collect();
+    versions.sort_by(|a, b| a.version.cmp(&b.version).then(a.created_at.cmp(&b.created_at)));
+    versions
+}
+
+// Versões sem filhas
+fn heads_of<'a>(store: &'a MedicalRecordStore, record_id: &str) -> Vec<&'a MedicalRecord> {
+    let versions = record_versions(store, record_id);
+    let parents: HashSet<&str> = versions.iter().flat_map(|v| v.parent_ids.iter().map(String::as_str)).collect();
+    versions.into_iter().filter(|v| !parents.contains(v.id.as_str())).collect()
+}
+
+fn view_of(store: &MedicalRecordStore, record_id: &str) -> Option<MedicalRecordView> {
+    let heads: Vec<MedicalRecord> = heads_of(store, record_id).into_iter().cloned().collect();
+    let first = heads.first()?;
+    Some(MedicalRecordView {
+        record_id: record_id.to_string(),
+        patient_id: first.patient_id.clone(),
+        has_conflict: heads.len() > 1,
+        version_count: record_versions(store, record_id).len(),
+        finalization: finalization_of(store, record_id).cloned(),
+        addenda: store.addenda.iter().filter(|a| a.record_id == record_id).cloned().collect(),
+        heads,
+    })
+}
+
+// O banco guarda timestamps com precisão de microssegundos; datas que entram em
+// hashes ou assinaturas são truncadas antes, para que o item volte do servidor
+// com a mesma forma canônica
+fn stored_now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+// Número da versão segue o maior pai; sem pais é a primeira versão
+fn new_version(
+    record_id: String,
+    patient_id: String,
+    appointment_id: Option<String>,
+    parents: &[&MedicalRecord],
+    fields: MedicalRecordFields,
+    deleted: bool,
+    created_by: Option<String>,
+) -> MedicalRecord {
+    let mut record = MedicalRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        record_id,
+        patient_id,
+        appointment_id,
+        version: parents.iter().map(|p| p.version).max().unwrap_or(0) + 1,
+        parent_ids: parents.iter().map(|p| p.id.clone()).collect(),
+        fields,
+        deleted,
+        content_hash: String::new(),
+        created_at: stored_now(),
+        created_by,
+        synced_at: None,
+    };
+    record.content_hash = record.compute_hash();
+    record
+}
+
+// Versão base de uma edição: a informada pela tela ou, na falta dela, a única cabeça
+fn resolve_base<'a>(store: &'a MedicalRecordStore, record_id: &str, base_version_id: Option<&str>) -> Result<&'a MedicalRecord> {
+    match base_version_id {
+        Some(base_id) => store
+            .versions
+            .iter()
+            .find(|v| v.id == base_id && v.record_id == record_id)
+            .ok_or_else(|| anyhow!("Base version {} not found in record {}", base_id, record_id)),
+        None => {
+            let heads = heads_of(store, record_id);
+            match heads.as_slice() {
+                [] => bail!("Medical record not found: {}", record_id),
+                [head] => Ok(*head),
+                _ => bail!("Medical record has concurrent versions; reconcile them first"),
+            }
+        }
+    }
+}
+
+// Cria um prontuário ou acrescenta uma versão. Editar uma versão que já tem filha
+// gera uma versão irmã em vez de sobrescrever o trabalho de outra pessoa.
+pub fn save_record(request: SaveMedicalRecordRequest, created_by: Option<String>) -> Result<MedicalRecord> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+
+    let record = match &request.record_id {
+        None => new_version(
+            uuid::Uuid::new_v4().to_string(),
+            request.patient_id,
+            request.appointment_id,
+            &[],
+            request.fields,
+            false,
+            created_by,
+        ),
+        Some(record_id) => {
+            ensure_not_finalized(&store, record_id)?;
+            let base = resolve_base(&store, record_id, request.base_version_id.as_deref())?;
+            if base.patient_id != request.patient_id {
+                bail!("Medical record belongs to another patient");
+            }
+            if base.deleted {
+                bail!("Medical record was deleted");
+            }
+            if base.fields == request.fields && base.appointment_id == request.appointment_id {
+                return Ok(base.clone()); // Nada mudou: não cria versão vazia
+            }
+            new_version(
+                record_id.clone(),
+                base.patient_id.clone(),
+                request.appointment_id,
+                &[base],
+                request.fields,
+                false,
+                created_by,
+            )
+        }
+    };
+
+    store.versions.push(record.clone());
+    save_store(&store)?;
+    Ok(record)
+}
+
+// Junta versões irmãs numa nova versão com todas elas como pais
+pub fn reconcile_record(
+    record_id: &str,
+    parent_version_ids: &[String],
+    fields: MedicalRecordFields,
+    created_by: Option<String>,
+) -> Result<MedicalRecord> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    ensure_not_finalized(&store, record_id)?;
+
+    let heads = heads_of(&store, record_id);
+    if heads.len() < 2 {
+        bail!("Medical record has no concurrent versions to reconcile");
+    }
+    let head_ids: HashSet<&str> = heads.iter().map(|h| h.id.as_str()).collect();
+    let requested: HashSet<&str> = parent_version_ids.iter().map(String::as_str).collect();
+    if requested != head_ids {
+        bail!("Reconciliation must include every current version of the record");
+    }
+
+    let first = heads[0];
+    let record = new_version(
+        record_id.to_string(),
+        first.patient_id.clone(),
+        first.appointment_id.clone(),
+        &heads,
+        fields,
+        false,
+        created_by,
+    );
+
+    store.versions.push(record.clone());
+    save_store(&store)?;
+    Ok(record)
+}
+
+// Exclusão lógica: nova versão marcada como excluída, sem apagar o histórico
+pub fn delete_record(record_id: &str, base_version_id: Option<&str>, created_by: Option<String>) -> Result<MedicalRecord> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    ensure_not_finalized(&store, record_id)?;
+
+    let base = resolve_base(&store, record_id, base_version_id)?;
+    if base.deleted {
+        return Ok(base.clone());
+    }
+    let record = new_version(
+        record_id.to_string(),
+        base.patient_id.clone(),
+        base.appointment_id.clone(),
+        &[base],
+        base.fields.clone(),
+        true,
+        created_by,
+    );
+
+    store.versions.push(record.clone());
+    save_store(&store)?;
+    Ok(record)
+}
+
+pub fn get_record(record_id: &str) -> Result<Option<MedicalRecordView>> {
+    let store = load_store()?;
+    Ok(view_of(&store, record_id))
+}
+
+pub fn get_version(version_id: &str) -> Result<Option<MedicalRecord>> {
+    Ok(load_store()?.versions.into_iter().find(|v| v.id == version_id))
+}
+
+// Prontuários do paciente (excluídos não aparecem)
+pub fn list_patient_records(patient_id: &str) -> Result<Vec<MedicalRecordView>> {
+    let store = load_store()?;
+    let mut record_ids: Vec<&str> = store
+        .versions
+        .iter()
+        .filter(|v| v.patient_id == patient_id)
+        .map(|v| v.record_id.as_str())
+        .collect();
+    record_ids.sort();
+    record_ids.dedup();
+
+    let mut views: Vec<MedicalRecordView> = record_ids
+        .into_iter()
+        .filter_map(|id| view_of(&store, id))
+        .filter(|view| !view.heads.iter().all(|h| h.deleted))
+        .collect();
+    views.sort_by(|a, b| {
+        let latest = |v: &MedicalRecordView| v.heads.iter().map(|h| h.created_at).max();
+        latest(b).cmp(&latest(a))
+    });
+    Ok(views)
+}
+
+pub fn record_history(record_id: &str) -> Result<Vec<MedicalRecord>> {
+    let store = load_store()?;
+    Ok(record_versions(&store, record_id).into_iter().cloned().collect())
+}
+
+pub fn diff_versions(from_version_id: &str, to_version_id: &str) -> Result<Vec<FieldChange>> {
+    let store = load_store()?;
+    let find = |id: &str| {
+        store
+            .versions
+            .iter()
+            .find(|v| v.id == id)
+            .ok_or_else(|| anyhow!("Medical record version not found: {}", id))
+    };
+    let from = find(from_version_id)?;
+    let to = find(to_version_id)?;
+    if from.record_id != to.record_id {
+        bail!("Versions belong to different medical records");
+    }
+
+    let changes = RECORD_FIELDS
+        .iter()
+        .filter_map(|field| {
+            let before = from.fields.get(field).cloned().flatten();
+            let after = to.fields.get(field).cloned().flatten();
+            let kind = match (&before, &after) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(b), Some(a)) if b != a => ChangeKind::Modified,
+                _ => return None,
+            };
+            Some(FieldChange {
+                field: field.to_string(),
+                kind,
+                before,
+                after,
+            })
+        })
+        .collect();
+    Ok(changes)
+}
+
+// Versões em que o campo mudou em relação à versão anterior (o primeiro pai)
+pub fn field_history(record_id: &str, field: &str) -> Result<Vec<FieldHistoryEntry>> {
+    if !RECORD_FIELDS.contains(&field) {
+        bail!("Unknown medical record field: {}", field);
+    }
+    let store = load_store()?;
+    let versions = record_versions(&store, record_id);
+    let by_id: HashMap<&str, &MedicalRecord> = versions.iter().map(|v| (v.id.as_str(), *v)).collect();
+
+    let history = versions
+        .iter()
+        .filter(|v| {
+            let previous = v.parent_ids.first().and_then(|p| by_id.get(p.as_str()));
+            match previous {
+                Some(parent) => parent.fields.get(field) != v.fields.get(field),
+                None => v.fields.get(field).is_some_and(|value| value.is_some()),
+            }
+        })
+        .map(|v| FieldHistoryEntry {
+            version_id: v.id.clone(),
+            version: v.version,
+            value: v.fields.get(field).cloned().flatten(),
+            changed_by: v.created_by.clone(),
+            changed_at: v.created_at,
+        })
+        .collect();
+    Ok(history)
+}
+
+// =====================================================
+// FINALIZATION AND SIGNATURES
+// =====================================================
+
+// Ao finalizar o atendimento a versão atual é assinada com a chave Ed25519 do
+// profissional e o prontuário fica travado: dali em diante só entram adendos,
+// também assinados. A assinatura cobre o hash canônico da versão, então qualquer
+// alteração posterior aparece na verificação.
+
+// Cada dispositivo tem a sua chave de assinatura (ela nunca sai do armazenamento
+// seguro), então a chave pública é registrada por (signatário, dispositivo). O
+// registro local fica cifrado no perfil; chaves novas deste dispositivo sobem
+// para a tabela do servidor. Quem escreve nessa tabela não decide em quem esta
+// estação confia: uma chave só é confiável se foi fixada aqui (a do próprio
+// dispositivo) ou endossada por uma chave já confiável. Assinaturas de chaves
+// não confiáveis ficam como não verificadas. Quem chama estas funções segura
+// STORE_LOCK.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerKey {
+    pub signer_id: String,
+    pub device_id: String,
+    pub public_key: String, // Hex
+    pub registered_at: DateTime<Utc>,
+    #[serde(default)]
+    pub pinned: bool, // Só local: chave deste dispositivo
+    #[serde(default)]
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+// Chave de (signatário, dispositivo) conferida e assinada por outra já confiável
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEndorsement {
+    pub id: String,
+    pub signer_id: String,
+    pub device_id: String,
+    pub public_key: String,
+    pub endorsed_by: String,
+    pub endorser_device_id: String,
+    pub created_at: DateTime<Utc>,
+    pub signature: String, // Hex, pela chave de quem endossou
+    #[serde(default)]
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+impl KeyEndorsement {
+    fn message(&self) -> Vec<u8> {
+        serde_json::json!([
+            "medical_record_key_endorsement_v1",
+            self.id,
+            self.signer_id,
+            self.device_id,
+            self.public_key,
+            self.endorsed_by,
+            self.endorser_device_id,
+            self.created_at.to_rfc3339(),
+        ])
+        .to_string()
+        .into_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerKeyView {
+    pub signer_id: String,
+    pub device_id: String,
+    pub fingerprint: String, // Para conferir com o dono da chave antes de endossar
+    pub registered_at: DateTime<Utc>,
+    pub trusted: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SignerRegistry {
+    keys: Vec<SignerKey>,
+    #[serde(default)]
+    endorsements: Vec<KeyEndorsement>,
+}
+
+impl SignerRegistry {
+    fn key_for(&self, signer_id: &str, device_id: &str) -> Option<&SignerKey> {
+        self.keys.iter().find(|k| k.signer_id == signer_id && k.device_id == device_id)
+    }
+
+    // Chaves fixadas e, a partir delas, as endossadas (em cadeia)
+    fn trusted_keys(&self) -> HashSet<(&str, &str)> {
+        let mut trusted: HashSet<(&str, &str)> = self
+            .keys
+            .iter()
+            .filter(|k| k.pinned)
+            .map(|k| (k.signer_id.as_str(), k.device_id.as_str()))
+            .collect();
+        loop {
+            let before = trusted.len();
+            for endorsement in &self.endorsements {
+                let endorsed = (endorsement.signer_id.as_str(), endorsement.device_id.as_str());
+                if trusted.contains(&endorsed)
+                    || !trusted.contains(&(endorsement.endorsed_by.as_str(), endorsement.endorser_device_id.as_str()))
+                {
+                    continue;
+                }
+                let same_key = self.key_for(endorsed.0, endorsed.1).is_some_and(|k| k.public_key == endorsement.public_key);
+                let endorser_signed = self
+                    .key_for(&endorsement.endorsed_by, &endorsement.endorser_device_id)
+                    .is_some_and(|k| verify_with(&k.public_key, &endorsement.signature, &endorsement.message()).is_ok());
+                if same_key && endorser_signed {
+                    trusted.insert(endorsed);
+                }
+            }
+            if trusted.len() == before {
+                return trusted;
+            }
+        }
+    }
+
+}
+
+pub fn key_fingerprint(public_key: &str) -> String {
+    let digest = hex::encode_upper(&Sha256::digest(public_key.as_bytes())[..8]);
+    digest.as_bytes().chunks(4).map(|c| std::str::from_utf8(c).unwrap_or_default()).collect::<Vec<_>>().join("-")
+}
+
+fn app_data_dir() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data)
+}
+
+// Identificador desta instalação (não é segredo)
+pub fn device_id() -> Result<String> {
+    let path = app_data_dir()?.join(DEVICE_ID_FILE);
+    if let Ok(id) = std::fs::read_to_string(&path) {
+        if !id.trim().is_empty() {
+            return Ok(id.trim().to_string());
+        }
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    crate::profiles::write_atomic(&path, &id)?;
+    Ok(id)
+}
+
+fn load_signers() -> Result<SignerRegistry> {
+    if let Some(registry) = crate::profiles::read_profile_file::<SignerRegistry>(SIGNERS_FILE)? {
+        return Ok(registry);
+    }
+
+    // Versões antigas guardavam signatário -> chave em JSON aberto; as entradas
+    // viram chaves sem dispositivo, como as assinaturas da mesma época
+    let legacy_path = app_data_dir()?.join(LEGACY_SIGNERS_FILE);
+    let Ok(json) = std::fs::read_to_string(&legacy_path) else {
+        return Ok(SignerRegistry::default());
+    };
+    #[derive(Deserialize)]
+    struct LegacyRegistry {
+        keys: HashMap<String, String>,
+    }
+    let legacy: LegacyRegistry = serde_json::from_str(&json)?;
+    let registry = SignerRegistry {
+        keys: legacy
+            .keys
+            .into_iter()
+            .map(|(signer_id, public_key)| SignerKey {
+                signer_id,
+                device_id: String::new(),
+                public_key,
+                registered_at: stored_now(),
+                pinned: true, // Registradas nesta estação
+                synced_at: None,
+            })
+            .collect(),
+        endorsements: Vec::new(),
+    };
+    save_signers(&registry)?;
+    std::fs::remove_file(&legacy_path)?;
+    Ok(registry)
+}
+
+fn save_signers(registry: &SignerRegistry) -> Result<()> {
+    crate::profiles::write_profile_file(SIGNERS_FILE, registry)
+}
+
+// Chave deste dispositivo: registrada (e fixada) na primeira assinatura, depois
+// precisa conferir. Registros anteriores à fixação são fixados aqui.
+fn register_own_key(signer_id: &str, device_id: &str, public_key: &str) -> Result<()> {
+    let mut registry = load_signers()?;
+    match registry.keys.iter_mut().find(|k| k.signer_id == signer_id && k.device_id == device_id) {
+        Some(known) if known.public_key != public_key => {
+            bail!("Signing key differs from the key registered for this user on this device")
+        }
+        Some(known) if known.pinned => Ok(()),
+        Some(known) => {
+            known.pinned = true;
+            save_signers(&registry)
+        }
+        None => {
+            registry.keys.push(SignerKey {
+                signer_id: signer_id.to_string(),
+                device_id: device_id.to_string(),
+                public_key: public_key.to_string(),
+                registered_at: stored_now(),
+                pinned: true,
+                synced_at: None,
+            });
+            save_signers(&registry)
+        }
+    }
+}
+
+// Chaves e endossos vindos do servidor. Entram sem confiança própria: a chave
+// só passa a valer com um endosso de chave confiável. Um par (signatário,
+// dispositivo) já conhecido com outra chave não é substituído. Devolve os itens
+// recusados.
+fn merge_signer_keys(remote: Vec<SignerKey>, endorsements: Vec<KeyEndorsement>) -> Result<Vec<String>> {
+    let mut registry = load_signers()?;
+    let mut rejected = Vec::new();
+    let mut changed = false;
+    for mut key in remote {
+        match registry.key_for(&key.signer_id, &key.device_id) {
+            Some(known) if known.public_key != key.public_key => {
+                rejected.push(format!("{}@{}", key.signer_id, key.device_id))
+            }
+            Some(_) => {}
+            None => {
+                key.pinned = false;
+                key.synced_at = Some(Utc::now());
+                registry.keys.push(key);
+                changed = true;
+            }
+        }
+    }
+    for mut endorsement in endorsements {
+        if !registry.endorsements.iter().any(|e| e.id == endorsement.id) {
+            endorsement.synced_at = Some(Utc::now());
+            registry.endorsements.push(endorsement);
+            changed = true;
+        }
+    }
+    if changed {
+        save_signers(&registry)?;
+    }
+    Ok(rejected)
+}
+
+fn verify_with(public_key: &str, signature: &str, message: &[u8]) -> Result<()> {
+    let public_key: [u8; 32] = hex::decode(public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("invalid public key"))?;
+    let signature: [u8; 64] = hex::decode(signature)?
+        .try_into()
+        .map_err(|_| anyhow!("invalid signature"))?;
+    VerifyingKey::from_bytes(&public_key)?
+        .verify_strict(message, &Signature::from_bytes(&signature))
+        .map_err(|_| anyhow!("signature does not match the content"))
+}
+
+// Confere a assinatura com a chave registrada para (signatário, dispositivo).
+// Se a chave é confiável fica a cargo de quem chama (SignerRegistry::trusted_keys).
+fn check_signature(
+    registry: &SignerRegistry,
+    signer_id: &str,
+    device_id: &str,
+    public_key: &str,
+    signature: &str,
+    message: &[u8],
+) -> Result<()> {
+    match registry.key_for(signer_id, device_id) {
+        Some(known) if known.public_key != public_key => {
+            bail!("public key does not match the key registered for {} on device {}", signer_id, device_id)
+        }
+        Some(_) => {}
+        None => bail!("no public key registered for {} on device {}", signer_id, device_id),
+    }
+    verify_with(public_key, signature, message)
+}
+
+// A finalização aceita primeiro é a que vale: uma segunda finalização do mesmo
+// prontuário é recusada no merge, nunca escolhida pela data
+fn finalization_of<'a>(store: &'a MedicalRecordStore, record_id: &str) -> Option<&'a MedicalRecordSignature> {
+    store.signatures.iter().find(|s| s.record_id == record_id)
+}
+
+fn ensure_not_finalized(store: &MedicalRecordStore, record_id: &str) -> Result<()> {
+    if let Some(signature) = finalization_of(store, record_id) {
+        bail!(
+            "Medical record was finalized by {} at {}; only addenda can be added",
+            signature.signer_id,
+            signature.signed_at.to_rfc3339()
+        );
+    }
+    Ok(())
+}
+
+pub fn finalize_record(record_id: &str, version_id: Option<&str>, signer_id: &str) -> Result<MedicalRecordSignature> {
+    let signing_key = with_shared_vault(|vault| vault.signing_key(signer_id))?;
+    let public_key = hex::encode(signing_key.verifying_key().to_bytes());
+    let device_id = device_id()?;
+
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    ensure_not_finalized(&store, record_id)?;
+
+    if heads_of(&store, record_id).len() > 1 {
+        bail!("Medical record has concurrent versions; reconcile them first");
+    }
+    let version = resolve_base(&store, record_id, version_id)?;
+    if !heads_of(&store, record_id).iter().any(|h| h.id == version.id) {
+        bail!("Only the current version of a medical record can be finalized");
+    }
+    if version.deleted {
+        bail!("Medical record was deleted");
+    }
+    if version.compute_hash() != version.content_hash {
+        bail!("Medical record version failed its integrity check");
+    }
+    register_own_key(signer_id, &device_id, &public_key)?;
+
+    let mut signature = MedicalRecordSignature {
+        id: uuid::Uuid::new_v4().to_string(),
+        record_id: record_id.to_string(),
+        version_id: version.id.clone(),
+        patient_id: version.patient_id.clone(),
+        content_hash: version.content_hash.clone(),
+        signer_id: signer_id.to_string(),
+        device_id,
+        signed_at: stored_now(),
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        public_key,
+        signature: String::new(),
+        synced_at: None,
+    };
+    signature.signature = hex::encode(signing_key.sign(&signature.message()).to_bytes());
+
+    store.signatures.push(signature.clone());
+    save_store(&store)?;
+    Ok(signature)
+}
+
+pub fn add_addendum(record_id: &str, text: &str, author_id: &str) -> Result<MedicalRecordAddendum> {
+    if text.trim().is_empty() {
+        bail!("Addendum text cannot be empty");
+    }
+    let signing_key = with_shared_vault(|vault| vault.signing_key(author_id))?;
+    let public_key = hex::encode(signing_key.verifying_key().to_bytes());
+    let device_id = device_id()?;
+
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    let finalization = finalization_of(&store, record_id)
+        .ok_or_else(|| anyhow!("Addenda can only be added to finalized medical records"))?;
+    register_own_key(author_id, &device_id, &public_key)?;
+
+    let mut addendum = MedicalRecordAddendum {
+        id: uuid::Uuid::new_v4().to_string(),
+        record_id: record_id.to_string(),
+        version_id: finalization.version_id.clone(),
+        patient_id: finalization.patient_id.clone(),
+        text: text.to_string(),
+        created_by: author_id.to_string(),
+        device_id,
+        created_at: stored_now(),
+        public_key,
+        signature: String::new(),
+        synced_at: None,
+    };
+    addendum.signature = hex::encode(signing_key.sign(&addendum.message()).to_bytes());
+
+    store.addenda.push(addendum.clone());
+    save_store(&store)?;
+    Ok(addendum)
+}
+
+pub fn list_signer_keys() -> Result<Vec<SignerKeyView>> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let registry = load_signers()?;
+    let trusted = registry.trusted_keys();
+    let mut keys: Vec<SignerKeyView> = registry
+        .keys
+        .iter()
+        .map(|k| SignerKeyView {
+            signer_id: k.signer_id.clone(),
+            device_id: k.device_id.clone(),
+            fingerprint: key_fingerprint(&k.public_key),
+            registered_at: k.registered_at,
+            trusted: trusted.contains(&(k.signer_id.as_str(), k.device_id.as_str())),
+        })
+        .collect();
+    keys.sort_by(|a, b| a.signer_id.cmp(&b.signer_id).then_with(|| a.device_id.cmp(&b.device_id)));
+    Ok(keys)
+}
+
+// Endossa a chave de outro (signatário, dispositivo) com a chave deste
+// dispositivo, depois que o usuário conferiu a impressão digital com o dono
+pub fn endorse_signer_key(signer_id: &str, device_id: &str, fingerprint: &str, endorser_id: &str) -> Result<KeyEndorsement> {
+    let signing_key = with_shared_vault(|vault| vault.signing_key(endorser_id))?;
+    let endorser_device_id = self::device_id()?;
+
+    let _guard = STORE_LOCK.lock().unwrap();
+    register_own_key(endorser_id, &endorser_device_id, &hex::encode(signing_key.verifying_key().to_bytes()))?;
+    let mut registry = load_signers()?;
+    let key = registry
+        .key_for(signer_id, device_id)
+        .ok_or_else(|| anyhow!("No public key registered for {} on device {}", signer_id, device_id))?;
+    let normalize = |f: &str| f.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase();
+    if normalize(&key_fingerprint(&key.public_key)) != normalize(fingerprint) {
+        bail!("Fingerprint does not match the key registered for {} on device {}", signer_id, device_id);
+    }
+
+    let mut endorsement = KeyEndorsement {
+        id: uuid::Uuid::new_v4().to_string(),
+        signer_id: signer_id.to_string(),
+        device_id: device_id.to_string(),
+        public_key: key.public_key.clone(),
+        endorsed_by: endorser_id.to_string(),
+        endorser_device_id,
+        created_at: stored_now(),
+        signature: String::new(),
+        synced_at: None,
+    };
+    endorsement.signature = hex::encode(signing_key.sign(&endorsement.message()).to_bytes());
+    registry.endorsements.push(endorsement.clone());
+    save_signers(&registry)?;
+    Ok(endorsement)
+}
+
+// Confere assinatura, conteúdo da versão assinada, adendos e se algo foi
+// acrescentado ao prontuário depois da finalização
+pub fn verify_record(record_id: &str) -> Result<MedicalRecordVerification> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let store = load_store()?;
+    let signers = load_signers()?;
+    let mut report = MedicalRecordVerification {
+        record_id: record_id.to_string(),
+        finalized: false,
+        valid: false,
+        signer_verified: false,
+        version_id: None,
+        signer_id: None,
+        signed_at: None,
+        addenda_checked: 0,
+        issues: Vec::new(),
+        unverified: Vec::new(),
+    };
+
+    let Some(signature) = finalization_of(&store, record_id) else {
+        report.issues.push("Medical record is not finalized".to_string());
+        return Ok(report);
+    };
+    report.finalized = true;
+    report.version_id = Some(signature.version_id.clone());
+    report.signer_id = Some(signature.signer_id.clone());
+    report.signed_at = Some(signature.signed_at);
+
+    match store.versions.iter().find(|v| v.id == signature.version_id) {
+        None => report.issues.push("Signed version is missing".to_string()),
+        Some(version) => {
+            if version.compute_hash() != version.content_hash || version.content_hash != signature.content_hash {
+                report.issues.push("Signed version content was altered".to_string());
+            }
+            if version.record_id != record_id || version.patient_id != signature.patient_id {
+                report.issues.push("Signed version belongs to another record or patient".to_string());
+            }
+        }
+    }
+    if signature.algorithm != SIGNATURE_ALGORITHM {
+        report.issues.push(format!("Unsupported signature algorithm: {}", signature.algorithm));
+    }
+    let trusted = signers.trusted_keys();
+    let untrusted = |signer_id: &str, device_id: &str| {
+        (!trusted.contains(&(signer_id, device_id)))
+            .then(|| format!("key of {} on device {} is not trusted on this workstation", signer_id, device_id))
+    };
+    match check_signature(
+        &signers,
+        &signature.signer_id,
+        &signature.device_id,
+        &signature.public_key,
+        &signature.signature,
+        &signature.message(),
+    ) {
+        Err(e) => report.issues.push(format!("Finalization signature: {}", e)),
+        Ok(()) => match untrusted(&signature.signer_id, &signature.device_id) {
+            Some(issue) => report.unverified.push(format!("Finalization signature: {}", issue)),
+            None => report.signer_verified = true,
+        },
+    }
+
+    let later = heads_of(&store, record_id)
+        .iter()
+        .filter(|h| h.id != signature.version_id)
+        .count();
+    if later > 0 {
+        report.issues.push(format!("{} version(s) were added after finalization", later));
+    }
+
+    for addendum in store.addenda.iter().filter(|a| a.record_id == record_id) {
+        report.addenda_checked += 1;
+        if addendum.version_id != signature.version_id {
+            report.issues.push(format!("Addendum {} refers to an unsigned version", addendum.id));
+        }
+        let checked = check_signature(
+            &signers,
+            &addendum.created_by,
+            &addendum.device_id,
+            &addendum.public_key,
+            &addendum.signature,
+            &addendum.message(),
+        )
+        .map(|_| untrusted(&addendum.created_by, &addendum.device_id));
+        match checked {
+            Err(e) => report.issues.push(format!("Addendum {}: {}", addendum.id, e)),
+            Ok(Some(issue)) => report.unverified.push(format!("Addendum {}: {}", addendum.id, issue)),
+            Ok(None) => {}
+        }
+    }
+
+    report.valid = report.issues.is_empty() && report.unverified.is_empty();
+    Ok(report)
+}
+
+// =====================================================
+// MERGING REMOTE DATA
+// =====================================================
+
+#[derive(Default)]
+struct RemoteRecords {
+    versions: Vec<MedicalRecord>,
+    signatures: Vec<MedicalRecordSignature>,
+    addenda: Vec<MedicalRecordAddendum>,
+    signer_keys: Vec<SignerKey>,
+    endorsements: Vec<KeyEndorsement>,
+}
+
+// Incorpora o que veio do servidor sem nunca substituir itens locais;
+// devolve (inseridos, recusados)
+fn merge_remote(remote: RemoteRecords) -> Result<(usize, Vec<String>)> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    let now = Some(Utc::now());
+    let mut inserted = 0;
+    let mut rejected = Vec::new();
+
+    for mut version in remote.versions {
+        if version.compute_hash() != version.content_hash {
+            rejected.push(version.id);
+            continue;
+        }
+        match store.versions.iter().find(|v| v.id == version.id) {
+            Some(local) if local.content_hash != version.content_hash => rejected.push(version.id),
+            Some(_) => {}
+            None => {
+                version.synced_at = now;
+                store.versions.push(version);
+                inserted += 1;
+            }
+        }
+    }
+
+    // Assinaturas e adendos de outros dispositivos só entram se a chave estiver
+    // registrada para aquele (signatário, dispositivo) e a assinatura conferir;
+    // se a chave é confiável, verify_record informa
+    rejected.extend(merge_signer_keys(remote.signer_keys, remote.endorsements)?);
+    let signers = load_signers()?;
+    let (accepted, refused) = merge_signatures(&mut store, &signers, remote.signatures);
+    inserted += accepted;
+    rejected.extend(refused);
+    for mut addendum in remote.addenda {
+        match store.addenda.iter().find(|a| a.id == addendum.id) {
+            Some(local) if local.message() != addendum.message() || local.signature != addendum.signature => {
+                rejected.push(addendum.id)
+            }
+            Some(_) => {}
+            None if check_signature(
+                &signers,
+                &addendum.created_by,
+                &addendum.device_id,
+                &addendum.public_key,
+                &addendum.signature,
+                &addendum.message(),
+            )
+            .is_err() =>
+            {
+                rejected.push(addendum.id)
+            }
+            None => {
+                addendum.synced_at = now;
+                store.addenda.push(addendum);
+                inserted += 1;
+            }
+        }
+    }
+
+    if inserted > 0 {
+        save_store(&store)?;
+    }
+    Ok((inserted, rejected))
+}
+
+fn merge_signatures(
+    store: &mut MedicalRecordStore,
+    signers: &SignerRegistry,
+    signatures: Vec<MedicalRecordSignature>,
+) -> (usize, Vec<String>) {
+    let now = Some(Utc::now());
+    let mut inserted = 0;
+    let mut rejected = Vec::new();
+    for mut signature in signatures {
+        match store.signatures.iter().find(|s| s.id == signature.id) {
+            Some(local) if local.message() != signature.message() || local.signature != signature.signature => {
+                rejected.push(signature.id)
+            }
+            Some(_) => {}
+            // Prontuário já finalizado (aqui ou por outra assinatura já aceita)
+            None if finalization_of(store, &signature.record_id).is_some() => rejected.push(signature.id),
+            None if signature.algorithm != SIGNATURE_ALGORITHM
+                || check_signature(
+                    signers,
+                    &signature.signer_id,
+                    &signature.device_id,
+                    &signature.public_key,
+                    &signature.signature,
+                    &signature.message(),
+                )
+                .is_err() =>
+            {
+                rejected.push(signature.id)
+            }
+            None => {
+                signature.synced_at = now;
+                store.signatures.push(signature);
+                inserted += 1;
+            }
+        }
+    }
+    (inserted, rejected)
+}
+
+fn mark_synced(ids: &HashSet<String>) -> Result<()> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut store = load_store()?;
+    let now = Some(Utc::now());
+    for version in store.versions.iter_mut().filter(|v| ids.contains(&v.id)) {
+        version.synced_at = now;
+    }
+    for signature in store.signatures.iter_mut().filter(|s| ids.contains(&s.id)) {
+        signature.synced_at = now;
+    }
+    for addendum in store.addenda.iter_mut().filter(|a| ids.contains(&a.id)) {
+        addendum.synced_at = now;
+    }
+    save_store(&store)
+}
+
+// Chaves e endossos deste dispositivo que ainda não estão no servidor
+fn pending_signer_keys() -> Result<(Vec<SignerKey>, Vec<KeyEndorsement>)> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let registry = load_signers()?;
+    Ok((
+        registry.keys.into_iter().filter(|k| k.synced_at.is_none()).collect(),
+        registry.endorsements.into_iter().filter(|e| e.synced_at.is_none()).collect(),
+    ))
+}
+
+fn mark_signer_keys_synced(pushed: &[SignerKey], endorsements: &[KeyEndorsement]) -> Result<()> {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut registry = load_signers()?;
+    let now = Some(Utc::now());
+    for key in registry.keys.iter_mut() {
+        if pushed.iter().any(|p| p.signer_id == key.signer_id && p.device_id == key.device_id) {
+            key.synced_at = now;
+        }
+    }
+    for endorsement in registry.endorsements.iter_mut() {
+        if endorsements.iter().any(|e| e.id == endorsement.id) {
+            endorsement.synced_at = now;
+        }
+    }
+    save_signers(&registry)
+}
+
+// Linha da tabela remota (estado de sincronização e fixação são só locais)
+fn remote_row<T: Serialize>(item: &T) -> Result<serde_json::Value> {
+    let mut row = serde_json::to_value(item)?;
+    if let Some(object) = row.as_object_mut() {
+        object.remove("synced_at");
+        object.remove("pinned");
+    }
+    Ok(row)
+}
+
+// Ids usados nos filtros do PostgREST: só UUIDs, que não têm como alterar a consulta
+fn uuid_filter_value(id: &str) -> Result<String> {
+    Ok(uuid::Uuid::parse_str(id.trim())
+        .map_err(|_| anyhow!("Invalid id: {:?}", id))?
+        .hyphenated()
+        .to_string())
+}
+
+// =====================================================
+// SUPABASE SYNC
+// =====================================================
+
+#[derive(Debug, Clone)]
+pub struct SyncSession {
+    pub user_id: String,
+    pub access_token: String,
+}
+
+static SESSION: LazyLock<Mutex<Option<SyncSession>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn set_session(session: Option<SyncSession>) {
+    *SESSION.lock().unwrap() = session;
+}
+
+fn session_user() -> Option<String> {
+    SESSION.lock().unwrap().as_ref().map(|s| s.user_id.clone())
+}
+
+pub struct MedicalRecordsSync {
+    url: String,
+    anon_key: String,
+    client: reqwest::Client,
+}
+
+impl MedicalRecordsSync {
+    pub fn new() -> Self {
+        let (url, anon_key) = match crate::config::get_config() {
+            Ok(c) => (c.supabase.url.clone(), c.supabase.anon_key.clone()),
+            Err(_) => (
+                std::env::var("SUPABASE_URL").unwrap_or_default(),
+                std::env::var("SUPABASE_ANON_KEY").unwrap_or_default(),
+            ),
+        };
+        Self {
+            url,
+            anon_key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn bearer_token(&self) -> String {
+        SESSION
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|s| s.access_token.clone())
+            .unwrap_or_else(|| self.anon_key.clone())
+    }
+
+    fn table_url(&self, table: &str) -> String {
+        format!("{}/rest/v1/{}", self.url, table)
+    }
+
+    // Inserção pura: linhas que já existem no servidor são ignoradas, nunca sobrescritas
+    async fn push_rows(&self, table: &str, rows: Vec<serde_json::Value>) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let response = self
+            .client
+            .post(self.table_url(table))
+            .header("apikey", &self.anon_key)
+            .bearer_auth(self.bearer_token())
+            .header("Prefer", "resolution=ignore-duplicates,return=minimal")
+            .json(&rows)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("Failed to push to {}: HTTP {}", table, response.status());
+        }
+        Ok(())
+    }
+
+    async fn fetch_rows<T: DeserializeOwned>(&self, table: &str, patient_id: &str) -> Result<Vec<T>> {
+        self.fetch_where(table, "patient_id", format!("eq.{}", patient_id)).await
+    }
+
+    async fn fetch_where<T: DeserializeOwned>(&self, table: &str, column: &str, filter: String) -> Result<Vec<T>> {
+        let response = self
+            .client
+            .get(self.table_url(table))
+            .header("apikey", &self.anon_key)
+            .bearer_auth(self.bearer_token())
+            .query(&[(column, filter), ("select", "*".to_string())])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            bail!("Failed to fetch {}: HTTP {}", table, response.status());
+        }
+        Ok(response.json().await?)
+    }
+
+    // Salva localmente os dados vindos da tela e sincroniza o paciente
+    pub async fn sync_medical_record(&mut self, patient_id: &str, record_data: &serde_json::Value) -> Result<MedicalRecord> {
+        let mut data = record_data.clone();
+        if let Some(object) = data.as_object_mut() {
+            object.insert("patient_id".to_string(), serde_json::Value::String(patient_id.to_string()));
+        }
+        let request: SaveMedicalRecordRequest = serde_json::from_value(data)?;
+        let record = save_record(request, session_user())?;
+
+        if let Err(e) = self.sync_patient(patient_id).await {
+            // Sem conexão a versão fica pendente e sobe na próxima sincronização
+            eprintln!("Medical record saved locally; sync failed: {}", e);
+        }
+        Ok(record)
+    }
+
+    pub async fn get_record_versions(&self, patient_id: &str) -> Result<Vec<MedicalRecord>> {
+        let mut versions: Vec<MedicalRecord> = load_store()?
+            .versions
+            .into_iter()
+            .filter(|v| v.patient_id == patient_id)
+            .collect();
+        versions.sort_by(|a, b| a.record_id.cmp(&b.record_id).then(a.version.cmp(&b.version)).then(a.created_at.cmp(&b.created_at)));
+        Ok(versions)
+    }
+
+    pub async fn sync_patient(&self, patient_id: &str) -> Result<MedicalRecordSyncReport> {
+        let mut report = MedicalRecordSyncReport::default();
+        let patient_id = uuid_filter_value(patient_id)?;
+        let patient_id = patient_id.as_str();
+
+        // 1. Sobe o que está pendente; versões antes das assinaturas que as citam
+        let store = load_store()?;
+        let versions: Vec<&MedicalRecord> = store
+            .versions
+            .iter()
+            .filter(|v| v.patient_id == patient_id && v.synced_at.is_none())
+            .collect();
+        let signatures: Vec<&MedicalRecordSignature> = store
+            .signatures
+            .iter()
+            .filter(|s| s.patient_id == patient_id && s.synced_at.is_none())
+            .collect();
+        let addenda: Vec<&MedicalRecordAddendum> = store
+            .addenda
+            .iter()
+            .filter(|a| a.patient_id == patient_id && a.synced_at.is_none())
+            .collect();
+
+        // A chave de quem assinou sobe antes da assinatura
+        let (signer_keys, endorsements) = pending_signer_keys()?;
+        self.push_rows(SIGNER_KEYS_TABLE, signer_keys.iter().map(remote_row).collect::<Result<_>>()?).await?;
+        self.push_rows(KEY_ENDORSEMENTS_TABLE, endorsements.iter().map(remote_row).collect::<Result<_>>()?).await?;
+        if !signer_keys.is_empty() || !endorsements.is_empty() {
+            mark_signer_keys_synced(&signer_keys, &endorsements)?;
+        }
+        self.push_rows(VERSIONS_TABLE, versions.iter().map(remote_row).collect::<Result<_>>()?).await?;
+        self.push_rows(SIGNATURES_TABLE, signatures.iter().map(remote_row).collect::<Result<_>>()?).await?;
+        self.push_rows(ADDENDA_TABLE, addenda.iter().map(remote_row).collect::<Result<_>>()?).await?;
+
+        let pushed: HashSet<String> = versions
+            .iter()
+            .map(|v| v.id.clone())
+            .chain(signatures.iter().map(|s| s.id.clone()))
+            .chain(addenda.iter().map(|a| a.id.clone()))
+            .collect();
+        if !pushed.is_empty() {
+            mark_synced(&pushed)?;
+        }
+        report.pushed = pushed.len();
+
+        // 2. Baixa o que ainda não existe aqui
+        let signatures: Vec<MedicalRecordSignature> = self.fetch_rows(SIGNATURES_TABLE, patient_id).await?;
+        let addenda: Vec<MedicalRecordAddendum> = self.fetch_rows(ADDENDA_TABLE, patient_id).await?;
+        // Id que não é UUID não entra no filtro; o item dele fica sem chave e é recusado
+        let signer_ids: HashSet<String> = signatures
+            .iter()
+            .map(|s| s.signer_id.as_str())
+            .chain(addenda.iter().map(|a| a.created_by.as_str()))
+            .filter_map(|id| uuid_filter_value(id).ok())
+            .collect();
+        let (signer_keys, endorsements) = if signer_ids.is_empty() {
+            (Vec::new(), Vec::new())
+        } else {
+            let list = format!("in.({})", signer_ids.into_iter().collect::<Vec<_>>().join(","));
+            (
+                self.fetch_where(SIGNER_KEYS_TABLE, "signer_id", list.clone()).await?,
+                self.fetch_where(KEY_ENDORSEMENTS_TABLE, "signer_id", list).await?,
+            )
+        };
+        let remote = RemoteRecords {
+            versions: self.fetch_rows(VERSIONS_TABLE, patient_id).await?,
+            signatures,
+            addenda,
+            signer_keys,
+            endorsements,
+        };
+        let (pulled, rejected) = merge_remote(remote)?;
+        report.pulled = pulled;
+        report.rejected = rejected;
+
+        report.conflicted_records = list_patient_records(patient_id)?
+            .into_iter()
+            .filter(|view| view.has_conflict)
+            .map(|view| view.record_id)
+            .collect();
+        Ok(report)
+    }
+}
+
+impl Default for MedicalRecordsSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Simula a ida e volta pelo Postgres: o timestamptz volta com 6 casas decimais
+    fn through_database(record: &MedicalRecord) -> MedicalRecord {
+        let mut row = remote_row(record).unwrap();
+        let created_at: DateTime<Utc> = serde_json::from_value(row["created_at"].clone()).unwrap();
+        row["created_at"] = serde_json::json!(created_at.format("%Y-%m-%dT%H:%M:%S%.6f+00:00").to_string());
+        serde_json::from_value(row).unwrap()
+    }
+
+    fn sample_version() -> MedicalRecord {
+        let fields = MedicalRecordFields {
+            anamnesis: Some("Dor lombar há 3 semanas".to_string()),
+            notes: Some("Retorno em 15 dias".to_string()),
+            ..Default::default()
+        };
+        new_version(
+            "record-1".to_string(),
+            "patient-1".to_string(),
+            None,
+            &[],
+            fields,
+            false,
+            Some("user-1".to_string()),
+        )
+    }
+
+    #[test]
+    fn new_versions_use_database_precision() {
+        let version = sample_version();
+        assert_eq!(version.created_at.timestamp_subsec_nanos() % 1_000, 0);
+    }
+
+    #[test]
+    fn hash_survives_database_round_trip() {
+        let parent = sample_version();
+        let child = new_version(
+            parent.record_id.clone(),
+            parent.patient_id.clone(),
+            None,
+            &[&parent],
+            MedicalRecordFields::default(),
+            true,
+            None,
+        );
+        for version in [parent, child] {
+            let pulled = through_database(&version);
+            assert_eq!(pulled.created_at, version.created_at);
+            assert_eq!(pulled.compute_hash(), version.content_hash);
+        }
+    }
+
+    fn signed(signing_key: &ed25519_dalek::SigningKey, device_id: &str) -> MedicalRecordSignature {
+        let version = sample_version();
+        let mut signature = MedicalRecordSignature {
+            id: "signature-1".to_string(),
+            record_id: version.record_id.clone(),
+            version_id: version.id.clone(),
+            patient_id: version.patient_id.clone(),
+            content_hash: version.content_hash.clone(),
+            signer_id: "user-1".to_string(),
+            device_id: device_id.to_string(),
+            signed_at: stored_now(),
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            public_key: hex::encode(signing_key.verifying_key().to_bytes()),
+            signature: String::new(),
+            synced_at: None,
+        };
+        signature.signature = hex::encode(signing_key.sign(&signature.message()).to_bytes());
+        signature
+    }
+
+    fn registry_with(signer_id: &str, device_id: &str, signing_key: &ed25519_dalek::SigningKey) -> SignerRegistry {
+        SignerRegistry {
+            keys: vec![SignerKey {
+                signer_id: signer_id.to_string(),
+                device_id: device_id.to_string(),
+                public_key: hex::encode(signing_key.verifying_key().to_bytes()),
+                registered_at: stored_now(),
+                pinned: true,
+                synced_at: None,
+            }],
+            endorsements: Vec::new(),
+        }
+    }
+
+    fn endorse(
+        registry: &SignerRegistry,
+        endorser: (&str, &str, &ed25519_dalek::SigningKey),
+        signer_id: &str,
+        device_id: &str,
+    ) -> KeyEndorsement {
+        let (endorsed_by, endorser_device_id, endorser_key) = endorser;
+        let mut endorsement = KeyEndorsement {
+            id: format!("endorsement-{}-{}", signer_id, device_id),
+            signer_id: signer_id.to_string(),
+            device_id: device_id.to_string(),
+            public_key: registry.key_for(signer_id, device_id).unwrap().public_key.clone(),
+            endorsed_by: endorsed_by.to_string(),
+            endorser_device_id: endorser_device_id.to_string(),
+            created_at: stored_now(),
+            signature: String::new(),
+            synced_at: None,
+        };
+        endorsement.signature = hex::encode(endorser_key.sign(&endorsement.message()).to_bytes());
+        endorsement
+    }
+
+    fn fetched_key(signer_id: &str, device_id: &str, signing_key: &ed25519_dalek::SigningKey) -> SignerKey {
+        let mut key = registry_with(signer_id, device_id, signing_key).keys.remove(0);
+        key.pinned = false;
+        key
+    }
+
+    fn check(registry: &SignerRegistry, signature: &MedicalRecordSignature) -> Result<()> {
+        check_signature(
+            registry,
+            &signature.signer_id,
+            &signature.device_id,
+            &signature.public_key,
+            &signature.signature,
+            &signature.message(),
+        )
+    }
+
+    #[test]
+    fn signature_survives_database_round_trip() {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let signature = signed(&signing_key, "device-a");
+        let mut row = remote_row(&signature).unwrap();
+        let signed_at: DateTime<Utc> = serde_json::from_value(row["signed_at"].clone()).unwrap();
+        row["signed_at"] = serde_json::json!(signed_at.format("%Y-%m-%dT%H:%M:%S%.6f+00:00").to_string());
+        let pulled: MedicalRecordSignature = serde_json::from_value(row).unwrap();
+
+        assert_eq!(pulled.message(), signature.message());
+        assert!(check(&registry_with("user-1", "device-a", &signing_key), &pulled).is_ok());
+    }
+
+    #[test]
+    fn keys_are_registered_per_device() {
+        let laptop = ed25519_dalek::SigningKey::from_bytes(&[1u8; 32]);
+        let desktop = ed25519_dalek::SigningKey::from_bytes(&[2u8; 32]);
+        let mut registry = registry_with("user-1", "laptop", &laptop);
+
+        // Outro dispositivo do mesmo profissional só vale depois de registrado
+        let from_desktop = signed(&desktop, "desktop");
+        assert!(check(&registry, &from_desktop).is_err());
+        registry.keys.extend(registry_with("user-1", "desktop", &desktop).keys);
+        assert!(check(&registry, &from_desktop).is_ok());
+        assert!(check(&registry, &signed(&laptop, "laptop")).is_ok());
+
+        // A chave de um dispositivo não vale em nome de outro
+        assert!(check(&registry, &signed(&desktop, "laptop")).is_err());
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[3u8; 32]);
+        let registry = registry_with("user-1", "device-a", &signing_key);
+        let mut signature = signed(&signing_key, "device-a");
+        signature.content_hash = "0".repeat(64);
+        assert!(check(&registry, &signature).is_err());
+    }
+
+    #[test]
+    fn fetched_keys_are_trusted_only_through_endorsements() {
+        let own = ed25519_dalek::SigningKey::from_bytes(&[4u8; 32]);
+        let colleague = ed25519_dalek::SigningKey::from_bytes(&[5u8; 32]);
+        let second_hop = ed25519_dalek::SigningKey::from_bytes(&[6u8; 32]);
+        let mut registry = registry_with("user-1", "laptop", &own);
+        registry.keys.push(fetched_key("user-2", "desktop", &colleague));
+        registry.keys.push(fetched_key("user-3", "tablet", &second_hop));
+
+        // A assinatura confere com a chave vinda do servidor, mas ela não é confiável
+        let mut from_colleague = signed(&colleague, "desktop");
+        from_colleague.signer_id = "user-2".to_string();
+        from_colleague.signature = hex::encode(colleague.sign(&from_colleague.message()).to_bytes());
+        assert!(check(&registry, &from_colleague).is_ok());
+        assert!(!registry.trusted_keys().contains(&("user-2", "desktop")));
+
+        // Endossos só valem em cadeia a partir de uma chave fixada
+        let chained = endorse(&registry, ("user-2", "desktop", &colleague), "user-3", "tablet");
+        registry.endorsements.push(chained);
+        assert!(!registry.trusted_keys().contains(&("user-3", "tablet")));
+        let direct = endorse(&registry, ("user-1", "laptop", &own), "user-2", "desktop");
+        registry.endorsements.push(direct);
+        let trusted = registry.trusted_keys();
+        assert!(trusted.contains(&("user-2", "desktop")));
+        assert!(trusted.contains(&("user-3", "tablet")));
+    }
+
+    #[test]
+    fn forged_endorsements_are_not_trusted() {
+        let own = ed25519_dalek::SigningKey::from_bytes(&[4u8; 32]);
+        let attacker = ed25519_dalek::SigningKey::from_bytes(&[9u8; 32]);
+        let mut registry = registry_with("user-1", "laptop", &own);
+        registry.keys.push(fetched_key("user-2", "desktop", &attacker));
+
+        // Quem escreve na tabela de chaves não consegue assinar em nome da chave fixada
+        let forged = endorse(&registry, ("user-1", "laptop", &attacker), "user-2", "desktop");
+        registry.endorsements.push(forged);
+        assert!(!registry.trusted_keys().contains(&("user-2", "desktop")));
+
+        // Nem reaproveitar um endosso verdadeiro para outra chave
+        let mut swapped = endorse(&registry, ("user-1", "laptop", &own), "user-2", "desktop");
+        swapped.public_key = hex::encode(ed25519_dalek::SigningKey::from_bytes(&[8u8; 32]).verifying_key().to_bytes());
+        registry.endorsements = vec![swapped];
+        assert!(!registry.trusted_keys().contains(&("user-2", "desktop")));
+    }
+
+    #[test]
+    fn second_finalization_is_rejected() {
+        let laptop = ed25519_dalek::SigningKey::from_bytes(&[1u8; 32]);
+        let desktop = ed25519_dalek::SigningKey::from_bytes(&[2u8; 32]);
+        let mut registry = registry_with("user-1", "laptop", &laptop);
+        registry.keys.extend(registry_with("user-1", "desktop", &desktop).keys);
+        let mut store = MedicalRecordStore::default();
+        let first = signed(&laptop, "laptop");
+        store.signatures.push(first.clone());
+
+        // Mesmo com data anterior, outra finalização do prontuário não substitui a local
+        let mut earlier = signed(&desktop, "desktop");
+        earlier.id = "signature-2".to_string();
+        earlier.signed_at = first.signed_at - chrono::Duration::hours(1);
+        earlier.signature = hex::encode(desktop.sign(&earlier.message()).to_bytes());
+        let (inserted, rejected) = merge_signatures(&mut store, &registry, vec![first.clone(), earlier]);
+
+        assert_eq!(inserted, 0);
+        assert_eq!(rejected, vec!["signature-2".to_string()]);
+        assert_eq!(finalization_of(&store, &first.record_id).unwrap().id, first.id);
+    }
+
+    #[test]
+    fn filters_only_take_uuids() {
+        let id = uuid::Uuid::new_v4().to_string();
+        assert_eq!(uuid_filter_value(&format!(" {} ", id.to_uppercase())).unwrap(), id);
+        assert!(uuid_filter_value("user-1").is_err());
+        assert!(uuid_filter_value("\"),signer_id.neq.(\"").is_err());
+        assert!(uuid_filter_value(&format!("{}\",x", id)).is_err());
+    }
+
+    #[test]
+    fn hash_detects_changed_content() {
+        let mut version = through_database(&sample_version());
+        version.fields.diagnosis = Some("Lombalgia".to_string());
+        assert_ne!(version.compute_hash(), version.content_hash);
+    }
+}
@@ -3,7 +3,6 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use chrono::{DateTime, Utc};
-use tokio::fs;
 use tokio::sync::RwLock;
 
 // =====================================================
@@ -58,6 +57,9 @@ static mut CACHE: Option<CacheStore> = None;
 static mut PENDING_OPERATIONS: Option<PendingOperations> = None;
 static mut CACHE_METRICS: Option<CacheMetrics> = None;
 
+const CACHE_FILE_NAME: &str = "offline_cache";
+const PENDING_OPERATIONS_FILE_NAME: &str = "pending_operations";
+
 // =====================================================
 // INITIALIZATION
 // =====================================================
@@ -178,16 +180,42 @@ pub async fn clear_cache() -> Result<()> {
         }
     }
     
-    // Clear disk cache
-    if let Err(e) = fs::remove_file("cache.json").await {
-        if e.kind() != std::io::ErrorKind::NotFound {
-            return Err(e.into());
-        }
+    // Clear disk cache of the active profile
+    if crate::profiles::active_profile().is_some() {
+        crate::profiles::remove_profile_file(CACHE_FILE_NAME)?;
+        crate::profiles::remove_profile_file(PENDING_OPERATIONS_FILE_NAME)?;
     }
     
     Ok(())
 }
 
+// Called before the app user changes: writes the cache and the operations not
+// yet synced to the profile that is still active
+pub async fn persist_for_active_profile() -> Result<()> {
+    save_cache_to_disk().await?;
+    save_pending_operations_to_disk()
+}
+
+// Called when the app user changes: drops everything held in memory for the
+// previous profile and loads the cache and pending operations of the new one
+// (if any)
+pub async fn reload_for_active_profile() -> Result<()> {
+    unsafe {
+        if let Some(cache) = &CACHE {
+            let mut cache_guard = cache.write().await;
+            cache_guard.clear();
+        }
+        
+        if let Some(operations) = &PENDING_OPERATIONS {
+            let mut ops_guard = operations.lock().unwrap();
+            ops_guard.clear();
+        }
+    }
+    
+    load_cache_from_disk().await?;
+    load_pending_operations_from_disk()
+}
+
 // =====================================================
 // OFFLINE OPERATIONS
 // =====================================================
@@ -438,13 +466,18 @@ async fn update_failed_sync_count() {
 // =====================================================
 
 async fn save_cache_to_disk() -> Result<()> {
+    // Without an active profile the cache lives only in memory, so nothing
+    // from one user ends up on disk where another user could load it
+    if crate::profiles::active_profile().is_none() {
+        return Ok(());
+    }
+    
     unsafe {
         if let Some(cache) = &CACHE {
             let cache_guard = cache.read().await;
             let entries: Vec<CacheEntry> = cache_guard.values().cloned().collect();
             
-            let json = serde_json::to_string_pretty(&entries)?;
-            fs::write("cache.json", json).await?;
+            crate::profiles::write_profile_file(CACHE_FILE_NAME, &entries)?;
         }
     }
     
@@ -452,10 +485,13 @@ async fn save_cache_to_disk() -> Result<()> {
 }
 
 async fn load_cache_from_disk() -> Result<()> {
-    match fs::read_to_string("cache.json").await {
-        Ok(json) => {
-            let entries: Vec<CacheEntry> = serde_json::from_str(&json)?;
-            
+    if crate::profiles::active_profile().is_none() {
+        println!("📁 Nenhum perfil ativo, cache apenas em memória");
+        return Ok(());
+    }
+    
+    match crate::profiles::read_profile_file::<Vec<CacheEntry>>(CACHE_FILE_NAME)? {
+        Some(entries) => {
             unsafe {
                 if let Some(cache) = &CACHE {
                     let mut cache_guard = cache.write().await;
@@ -468,7 +504,7 @@ async fn load_cache_from_disk() -> Result<()> {
             let entries_count = entries.len();
             println!("📁 Cache carregado do disco: {} entradas", entries_count);
         }
-        Err(_) => {
+        None => {
             println!("📁 Nenhum cache encontrado no disco");
         }
     }
@@ -476,6 +512,38 @@ async fn load_cache_from_disk() -> Result<()> {
     Ok(())
 }
 
+fn save_pending_operations_to_disk() -> Result<()> {
+    if crate::profiles::active_profile().is_none() {
+        return Ok(());
+    }
+    
+    unsafe {
+        if let Some(operations) = &PENDING_OPERATIONS {
+            let ops = operations.lock().unwrap().clone();
+            crate::profiles::write_profile_file(PENDING_OPERATIONS_FILE_NAME, &ops)?;
+        }
+    }
+    
+    Ok(())
+}
+
+fn load_pending_operations_from_disk() -> Result<()> {
+    if crate::profiles::active_profile().is_none() {
+        return Ok(());
+    }
+    
+    if let Some(ops) = crate::profiles::read_profile_file::<Vec<OfflineOperation>>(PENDING_OPERATIONS_FILE_NAME)? {
+        println!("📁 Operações pendentes carregadas do disco: {}", ops.len());
+        unsafe {
+            if let Some(operations) = &PENDING_OPERATIONS {
+                *operations.lock().unwrap() = ops;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
 // =====================================================
 // PUBLIC API
 // =====================================================
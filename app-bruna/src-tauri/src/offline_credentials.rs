@@ -0,0 +1,141 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+
+use crate::auth::User;
+
+// =====================================================
+// OFFLINE LOGIN CREDENTIALS
+// =====================================================
+
+// Cada login online guarda o hash Argon2 da senha e o perfil do usuário, para
+// que ele possa entrar sem conexão com o Supabase. O segundo fator continua
+// valendo: a verificação TOTP é local e o login offline passa por ela.
+
+pub const OFFLINE_VALIDITY_DAYS: i64 = 30; // Depois disso é preciso entrar online de novo
+pub const OFFLINE_SESSION_HOURS: i64 = 8;
+
+// Serializa leitura e gravação do arquivo entre logins simultâneos
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedCredential {
+    user: User,
+    password_hash: String,
+    cached_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum OfflineCheck {
+    Verified(User),
+    Rejected, // Senha diferente da guardada: conta como tentativa falha
+    Unavailable, // Sem credencial guardada (ou vencida) para o e-mail
+}
+
+fn store_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join("offline_credentials.json"))
+}
+
+fn load() -> Result<HashMap<String, CachedCredential>> {
+    let path = store_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(&path)?)?)
+}
+
+fn key_for(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Erro de conexão (ou timeout) ao falar com o Supabase, que libera o login offline
+pub fn is_unreachable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+// Chamado depois que o Supabase aceitou a senha
+pub fn remember(user: &User, password: &str) -> Result<()> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
+        .to_string();
+
+    let _lock = STORE_LOCK.lock().unwrap();
+    let mut credentials = load()?;
+    credentials.insert(key_for(&user.email), CachedCredential {
+        user: user.clone(),
+        password_hash,
+        cached_at: Utc::now(),
+    });
+    let json = serde_json::to_string_pretty(&credentials)?;
+    crate::profiles::write_atomic(&store_path()?, &json)
+}
+
+pub fn verify(email: &str, password: &str, now: DateTime<Utc>) -> Result<OfflineCheck> {
+    let credentials = {
+        let _lock = STORE_LOCK.lock().unwrap();
+        load()?
+    };
+    let Some(cached) = credentials.get(&key_for(email)) else {
+        return Ok(OfflineCheck::Unavailable);
+    };
+    if now - cached.cached_at > Duration::days(OFFLINE_VALIDITY_DAYS) {
+        return Ok(OfflineCheck::Unavailable);
+    }
+
+    let parsed = PasswordHash::new(&cached.password_hash)
+        .map_err(|e| anyhow::anyhow!("Invalid cached credential: {}", e))?;
+    if Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok() {
+        Ok(OfflineCheck::Verified(cached.user.clone()))
+    } else {
+        Ok(OfflineCheck::Rejected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::UserRole;
+
+    fn user(id: &str, email: &str) -> User {
+        User {
+            id: id.to_string(),
+            email: email.to_string(),
+            name: "Recepção".to_string(),
+            role: UserRole::Admin,
+            active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    fn verifies_the_password_of_the_last_online_login() {
+        crate::keyvault::test_support::isolate_data_dir();
+        remember(&user("offline-1", "Offline@Clinica.com"), "senha-correta").unwrap();
+        let now = Utc::now();
+
+        match verify(" offline@clinica.com", "senha-correta", now).unwrap() {
+            OfflineCheck::Verified(user) => assert_eq!(user.id, "offline-1"),
+            other => panic!("expected verified, got {:?}", other),
+        }
+        assert!(matches!(verify("offline@clinica.com", "senha-errada", now).unwrap(), OfflineCheck::Rejected));
+        assert!(matches!(verify("outra@clinica.com", "senha-correta", now).unwrap(), OfflineCheck::Unavailable));
+
+        let expired = now + Duration::days(OFFLINE_VALIDITY_DAYS + 1);
+        assert!(matches!(verify("offline@clinica.com", "senha-correta", expired).unwrap(), OfflineCheck::Unavailable));
+    }
+}
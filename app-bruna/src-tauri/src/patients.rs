@@ -0,0 +1,558 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+// =====================================================
+// PATIENT IDENTIFIERS AND VALIDATION
+// =====================================================
+
+// Dados cadastrais do paciente: CPF, RG, endereço estruturado, responsáveis,
+// contatos de emergência e convênio. Tudo é validado e normalizado antes de
+// gravar: CPF e CEP só com dígitos, telefones em E.164, UF em maiúsculas e
+// data de nascimento como data de verdade. Os erros voltam campo a campo para
+// o formulário marcar cada um.
+
+const ADULT_AGE: i32 = 18;
+
+pub const UFS: [&str; 27] = [
+    "AC", "AL", "AP", "AM", "BA", "CE", "DF", "ES", "GO", "MA", "MT", "MS", "MG", "PA", "PB", "PR",
+    "PE", "PI", "RJ", "RN", "RS", "RO", "RR", "SC", "SP", "SE", "TO",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Address {
+    #[serde(default)]
+    pub cep: String,
+    #[serde(default)]
+    pub street: String,
+    #[serde(default)]
+    pub number: String, // Texto: "S/N", "120A"
+    pub complement: Option<String>,
+    pub neighborhood: Option<String>,
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub uf: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guardian {
+    pub name: String,
+    pub relationship: String, // "mãe", "pai", "tutor legal"...
+    pub cpf: Option<String>,
+    pub phone: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergencyContact {
+    pub name: String,
+    pub relationship: Option<String>,
+    pub phone: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Insurance {
+    pub provider: String, // Operadora
+    pub plan: Option<String>,
+    pub card_number: String, // Número da carteirinha
+    pub valid_until: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatePatientRequest {
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub birth_date: Option<String>, // "AAAA-MM-DD" ou "DD/MM/AAAA"
+    #[serde(default, deserialize_with = "lenient_address")]
+    pub address: Option<Address>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub cpf: Option<String>,
+    #[serde(default)]
+    pub rg: Option<String>,
+    #[serde(default)]
+    pub guardians: Vec<Guardian>,
+    #[serde(default)]
+    pub emergency_contacts: Vec<EmergencyContact>,
+    #[serde(default)]
+    pub insurance: Option<Insurance>,
+}
+
+// Pedido já validado e normalizado, pronto para virar Patient
+#[derive(Debug, Clone)]
+pub struct ValidPatient {
+    pub name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub birth_date: Option<NaiveDate>,
+    pub address: Option<Address>,
+    pub notes: Option<String>,
+    pub cpf: Option<String>,
+    pub rg: Option<String>,
+    pub guardians: Vec<Guardian>,
+    pub emergency_contacts: Vec<EmergencyContact>,
+    pub insurance: Option<Insurance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String, // Caminho do campo: "cpf", "address.cep", "guardians[0].phone"
+    pub code: String,  // "required", "invalid", "duplicate"...
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        FieldError { field: field.into(), code: code.to_string(), message: message.into() }
+    }
+}
+
+// Erro dos comandos de paciente: mensagem geral mais os erros por campo
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientError {
+    pub message: String,
+    pub field_errors: Vec<FieldError>,
+}
+
+impl From<String> for PatientError {
+    fn from(message: String) -> Self {
+        PatientError { message, field_errors: Vec::new() }
+    }
+}
+
+impl From<&str> for PatientError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl From<Vec<FieldError>> for PatientError {
+    fn from(field_errors: Vec<FieldError>) -> Self {
+        PatientError { message: "Invalid patient data".to_string(), field_errors }
+    }
+}
+
+// =====================================================
+// LENIENT DESERIALIZATION (LEGACY DATA)
+// =====================================================
+
+// Cadastros antigos guardavam o endereço como texto livre: vira só a rua
+pub fn lenient_address<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Address>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum AddressValue {
+        Text(String),
+        Structured(Address),
+    }
+    Ok(match Option::<AddressValue>::deserialize(deserializer)? {
+        Some(AddressValue::Text(text)) if text.trim().is_empty() => None,
+        Some(AddressValue::Text(text)) => Some(Address { street: text.trim().to_string(), ..Default::default() }),
+        Some(AddressValue::Structured(address)) => Some(address),
+        None => None,
+    })
+}
+
+// Datas antigas em formato livre que não dão para interpretar são descartadas
+// em vez de impedir a carga da partição local
+pub fn lenient_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.and_then(|s| parse_date(&s)))
+}
+
+// =====================================================
+// NORMALIZATION
+// =====================================================
+
+fn digits(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%d/%m/%Y"))
+        .ok()
+        // Timestamps completos ("2001-04-03T00:00:00Z")
+        .or_else(|| value.get(..10).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok()))
+}
+
+// CPF com os dois dígitos verificadores; devolve só os 11 dígitos
+pub fn normalize_cpf(value: &str) -> Result<String, &'static str> {
+    let cpf = digits(value);
+    if cpf.len() != 11 {
+        return Err("CPF must have 11 digits");
+    }
+    let d: Vec<u32> = cpf.chars().map(|c| c.to_digit(10).unwrap()).collect();
+    if d.iter().all(|&x| x == d[0]) {
+        return Err("Invalid CPF");
+    }
+    let check = |len: usize| {
+        let sum: u32 = d[..len].iter().enumerate().map(|(i, x)| x * (len as u32 + 1 - i as u32)).sum();
+        (sum * 10 % 11) % 10
+    };
+    if check(9) != d[9] || check(10) != d[10] {
+        return Err("Invalid CPF check digits");
+    }
+    Ok(cpf)
+}
+
+// Telefone em E.164. Sem código de país assume Brasil: DDD + 8 dígitos (fixo)
+// ou 9 dígitos começando com 9 (celular)
+pub fn normalize_phone(value: &str) -> Result<String, &'static str> {
+    let trimmed = value.trim();
+    let international = trimmed.starts_with('+') || trimmed.starts_with("00");
+    let mut number = digits(trimmed);
+    if trimmed.starts_with("00") {
+        number = number[2..].to_string();
+    }
+
+    let national = if international {
+        match number.strip_prefix("55") {
+            Some(rest) => rest.to_string(),
+            None => {
+                if !(8..=15).contains(&number.len()) || number.starts_with('0') {
+                    return Err("Invalid international phone number");
+                }
+                return Ok(format!("+{}", number));
+            }
+        }
+    } else {
+        // Prefixo de longa distância ("0 11 ...") ou com operadora ("0 15 11 ...")
+        match number.len() {
+            11 | 12 if number.starts_with('0') => number[1..].to_string(),
+            13 | 14 if number.starts_with('0') => number[3..].to_string(),
+            12 | 13 if number.starts_with("55") => number[2..].to_string(),
+            _ => number,
+        }
+    };
+
+    let (ddd, subscriber) = match national.len() {
+        10 | 11 => national.split_at(2),
+        8 | 9 => return Err("Phone number must include the area code (DDD)"),
+        _ => return Err("Invalid phone number"),
+    };
+    if ddd.contains('0') {
+        return Err("Invalid area code (DDD)");
+    }
+    let valid = match subscriber.len() {
+        9 => subscriber.starts_with('9'),
+        _ => matches!(subscriber.as_bytes()[0], b'2'..=b'5'),
+    };
+    if !valid {
+        return Err("Invalid phone number");
+    }
+    Ok(format!("+55{}", national))
+}
+
+pub fn normalize_cep(value: &str) -> Result<String, &'static str> {
+    let cep = digits(value);
+    if cep.len() != 8 || value.chars().any(|c| !c.is_ascii_digit() && c != '-' && c != '.' && c != ' ') {
+        return Err("CEP must have 8 digits");
+    }
+    Ok(cep)
+}
+
+fn age_on(birth_date: NaiveDate, today: NaiveDate) -> i32 {
+    let mut age = today.year() - birth_date.year();
+    if (today.month(), today.day()) < (birth_date.month(), birth_date.day()) {
+        age -= 1;
+    }
+    age
+}
+
+pub fn is_minor(birth_date: NaiveDate) -> bool {
+    age_on(birth_date, Utc::now().date_naive()) < ADULT_AGE
+}
+
+// =====================================================
+// VALIDATION
+// =====================================================
+
+fn required(errors: &mut Vec<FieldError>, field: &str, value: &str) -> String {
+    let value = value.trim();
+    if value.is_empty() {
+        errors.push(FieldError::new(field, "required", "This field is required"));
+    }
+    value.to_string()
+}
+
+fn phone_field(errors: &mut Vec<FieldError>, field: &str, value: Option<String>) -> Option<String> {
+    let value = non_empty(value)?;
+    match normalize_phone(&value) {
+        Ok(phone) => Some(phone),
+        Err(message) => {
+            errors.push(FieldError::new(field, "invalid", message));
+            None
+        }
+    }
+}
+
+fn cpf_field(errors: &mut Vec<FieldError>, field: &str, value: Option<String>) -> Option<String> {
+    let value = non_empty(value)?;
+    match normalize_cpf(&value) {
+        Ok(cpf) => Some(cpf),
+        Err(message) => {
+            errors.push(FieldError::new(field, "invalid", message));
+            None
+        }
+    }
+}
+
+fn validate_address(errors: &mut Vec<FieldError>, address: Address) -> Address {
+    let cep = required(errors, "address.cep", &address.cep);
+    let cep = if cep.is_empty() {
+        cep
+    } else {
+        normalize_cep(&cep).unwrap_or_else(|message| {
+            errors.push(FieldError::new("address.cep", "invalid", message));
+            cep
+        })
+    };
+    let uf = required(errors, "address.uf", &address.uf).to_uppercase();
+    if !uf.is_empty() && !UFS.contains(&uf.as_str()) {
+        errors.push(FieldError::new("address.uf", "invalid", "Unknown state (UF)"));
+    }
+    Address {
+        cep,
+        street: required(errors, "address.street", &address.street),
+        number: required(errors, "address.number", &address.number),
+        complement: non_empty(address.complement),
+        neighborhood: non_empty(address.neighborhood),
+        city: required(errors, "address.city", &address.city),
+        uf,
+    }
+}
+
+// Valida e normaliza o cadastro. A unicidade do CPF é conferida por quem grava,
+// que conhece os demais pacientes.
+pub fn validate_request(request: CreatePatientRequest) -> Result<ValidPatient, Vec<FieldError>> {
+    let mut errors = Vec::new();
+
+    let name = required(&mut errors, "name", &request.name);
+    if name.chars().count() > 200 {
+        errors.push(FieldError::new("name", "too_long", "Name is too long"));
+    }
+
+    let email = non_empty(request.email).map(|e| e.to_lowercase());
+    if let Some(email) = &email {
+        let valid = email
+            .split_once('@')
+            .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.'));
+        if !valid || email.contains(char::is_whitespace) {
+            errors.push(FieldError::new("email", "invalid", "Invalid email address"));
+        }
+    }
+
+    let phone = phone_field(&mut errors, "phone", request.phone);
+    let cpf = cpf_field(&mut errors, "cpf", request.cpf);
+    let rg = non_empty(request.rg).map(|rg| rg.to_uppercase());
+    if rg.as_ref().is_some_and(|rg| rg.chars().any(|c| !c.is_ascii_alphanumeric() && !".-/ ".contains(c))) {
+        errors.push(FieldError::new("rg", "invalid", "Invalid RG"));
+    }
+
+    let today = Utc::now().date_naive();
+    let birth_date = match non_empty(request.birth_date) {
+        Some(value) => match parse_date(&value) {
+            Some(date) if date > today => {
+                errors.push(FieldError::new("birth_date", "invalid", "Birth date is in the future"));
+                None
+            }
+            Some(date) if date.year() < 1900 => {
+                errors.push(FieldError::new("birth_date", "invalid", "Birth date is too far in the past"));
+                None
+            }
+            Some(date) => Some(date),
+            None => {
+                errors.push(FieldError::new("birth_date", "invalid", "Invalid date (use YYYY-MM-DD or DD/MM/YYYY)"));
+                None
+            }
+        },
+        None => None,
+    };
+
+    let address = request.address.map(|address| validate_address(&mut errors, address));
+
+    let guardians: Vec<Guardian> = request
+        .guardians
+        .into_iter()
+        .enumerate()
+        .map(|(i, g)| Guardian {
+            name: required(&mut errors, &format!("guardians[{}].name", i), &g.name),
+            relationship: required(&mut errors, &format!("guardians[{}].relationship", i), &g.relationship),
+            cpf: cpf_field(&mut errors, &format!("guardians[{}].cpf", i), g.cpf),
+            phone: phone_field(&mut errors, &format!("guardians[{}].phone", i), g.phone),
+        })
+        .collect();
+    if birth_date.is_some_and(is_minor) && guardians.is_empty() {
+        errors.push(FieldError::new("guardians", "required", "Minors must have at least one guardian"));
+    }
+
+    let emergency_contacts = request
+        .emergency_contacts
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let field = format!("emergency_contacts[{}].phone", i);
+            if c.phone.trim().is_empty() {
+                errors.push(FieldError::new(&field, "required", "This field is required"));
+            }
+            EmergencyContact {
+                name: required(&mut errors, &format!("emergency_contacts[{}].name", i), &c.name),
+                relationship: non_empty(c.relationship),
+                phone: phone_field(&mut errors, &field, Some(c.phone)).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let insurance = request.insurance.map(|ins| Insurance {
+        provider: required(&mut errors, "insurance.provider", &ins.provider),
+        plan: non_empty(ins.plan),
+        card_number: required(&mut errors, "insurance.card_number", &ins.card_number),
+        valid_until: ins.valid_until,
+    });
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(ValidPatient {
+        name,
+        email,
+        phone,
+        birth_date,
+        address,
+        notes: non_empty(request.notes),
+        cpf,
+        rg,
+        guardians,
+        emergency_contacts,
+        insurance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: serde_json::Value) -> CreatePatientRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn accepts_valid_cpfs_with_or_without_mask() {
+        assert_eq!(normalize_cpf("529.982.247-25"), Ok("52998224725".to_string()));
+        assert_eq!(normalize_cpf("52998224725"), Ok("52998224725".to_string()));
+        assert_eq!(normalize_cpf(" 111.444.777-35 "), Ok("11144477735".to_string()));
+    }
+
+    #[test]
+    fn remainder_ten_becomes_check_digit_zero() {
+        // 390.533.447: a soma do primeiro dígito deixa resto 10
+        assert_eq!(normalize_cpf("390.533.447-05"), Ok("39053344705".to_string()));
+        assert_eq!(normalize_cpf("390.533.447-15"), Err("Invalid CPF check digits"));
+    }
+
+    #[test]
+    fn rejects_wrong_check_digits() {
+        assert_eq!(normalize_cpf("529.982.247-35"), Err("Invalid CPF check digits"));
+        assert_eq!(normalize_cpf("529.982.247-24"), Err("Invalid CPF check digits"));
+        assert_eq!(normalize_cpf("529.982.247-52"), Err("Invalid CPF check digits"));
+    }
+
+    #[test]
+    fn rejects_repeated_digits_and_wrong_length() {
+        // 111.111.111-11 passa no cálculo, mas não é um CPF emitido
+        assert_eq!(normalize_cpf("111.111.111-11"), Err("Invalid CPF"));
+        assert_eq!(normalize_cpf("000.000.000-00"), Err("Invalid CPF"));
+        assert_eq!(normalize_cpf("529.982.247-2"), Err("CPF must have 11 digits"));
+        assert_eq!(normalize_cpf("529.982.247-255"), Err("CPF must have 11 digits"));
+        assert_eq!(normalize_cpf(""), Err("CPF must have 11 digits"));
+    }
+
+    #[test]
+    fn validation_reports_invalid_cpf_on_its_field() {
+        let errors = validate_request(request(serde_json::json!({
+            "name": "Maria",
+            "cpf": "529.982.247-24",
+            "guardians": [{ "name": "Ana", "relationship": "mãe", "cpf": "123" }],
+        })))
+        .unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert!(fields.contains(&"cpf"));
+        assert!(fields.contains(&"guardians[0].cpf"));
+    }
+
+    #[test]
+    fn validation_stores_cpf_digits_only() {
+        let patient = validate_request(request(serde_json::json!({
+            "name": "Maria",
+            "cpf": "529.982.247-25",
+        })))
+        .unwrap();
+        assert_eq!(patient.cpf.as_deref(), Some("52998224725"));
+    }
+
+    #[test]
+    fn normalizes_brazilian_phones_to_e164() {
+        assert_eq!(normalize_phone("(11) 98765-4321"), Ok("+5511987654321".to_string()));
+        assert_eq!(normalize_phone("11 3456-7890"), Ok("+551134567890".to_string()));
+        assert_eq!(normalize_phone("+55 11 98765-4321"), Ok("+5511987654321".to_string()));
+        assert_eq!(normalize_phone("0055 11 98765 4321"), Ok("+5511987654321".to_string()));
+        // Prefixo de longa distância, com e sem operadora
+        assert_eq!(normalize_phone("0 11 98765-4321"), Ok("+5511987654321".to_string()));
+        assert_eq!(normalize_phone("0 15 11 98765-4321"), Ok("+5511987654321".to_string()));
+        assert_eq!(normalize_phone("+1 212 555 0100"), Ok("+12125550100".to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_phones() {
+        assert_eq!(normalize_phone("98765-4321"), Err("Phone number must include the area code (DDD)"));
+        assert_eq!(normalize_phone("(10) 98765-4321"), Err("Invalid area code (DDD)"));
+        // Celular precisa começar com 9; fixo com 2 a 5
+        assert_eq!(normalize_phone("(11) 88765-4321"), Err("Invalid phone number"));
+        assert_eq!(normalize_phone("(11) 7456-7890"), Err("Invalid phone number"));
+        assert_eq!(normalize_phone("123"), Err("Invalid phone number"));
+        assert_eq!(normalize_phone("+0123456789"), Err("Invalid international phone number"));
+    }
+
+    #[test]
+    fn normalizes_cep() {
+        assert_eq!(normalize_cep("01310-100"), Ok("01310100".to_string()));
+        assert_eq!(normalize_cep("01.310-100"), Ok("01310100".to_string()));
+        assert_eq!(normalize_cep("01310100"), Ok("01310100".to_string()));
+        assert_eq!(normalize_cep("1310-100"), Err("CEP must have 8 digits"));
+        assert_eq!(normalize_cep("01310-1000"), Err("CEP must have 8 digits"));
+        assert_eq!(normalize_cep("01310/100"), Err("CEP must have 8 digits"));
+    }
+
+    #[test]
+    fn minors_require_a_guardian() {
+        let today = Utc::now().date_naive();
+        let child = (today - chrono::Duration::days(365 * 10)).format("%Y-%m-%d").to_string();
+        let adult = (today - chrono::Duration::days(365 * 30)).format("%Y-%m-%d").to_string();
+
+        let errors = validate_request(request(serde_json::json!({ "name": "Joana", "birth_date": child }))).unwrap_err();
+        assert!(errors.iter().any(|e| e.field == "guardians" && e.code == "required"));
+
+        let with_guardian = validate_request(request(serde_json::json!({
+            "name": "Joana",
+            "birth_date": child,
+            "guardians": [{ "name": "Ana", "relationship": "mãe", "phone": "(11) 98765-4321" }],
+        })))
+        .unwrap();
+        assert_eq!(with_guardian.guardians[0].phone.as_deref(), Some("+5511987654321"));
+
+        assert!(validate_request(request(serde_json::json!({ "name": "Carlos", "birth_date": adult }))).is_ok());
+        // Sem data de nascimento não dá para saber a idade
+        assert!(validate_request(request(serde_json::json!({ "name": "Carlos" }))).is_ok());
+    }
+
+    #[test]
+    fn age_counts_only_completed_birthdays() {
+        let birth = NaiveDate::from_ymd_opt(2008, 6, 15).unwrap();
+        assert_eq!(age_on(birth, NaiveDate::from_ymd_opt(2026, 6, 14).unwrap()), 17);
+        assert_eq!(age_on(birth, NaiveDate::from_ymd_opt(2026, 6, 15).unwrap()), 18);
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Utc};
+use sha2::{Sha256, Digest};
+
+use crate::keyvault::{entity_aad, with_shared_vault};
+
+// =====================================================
+// WORKSTATION PROFILES (dados locais isolados por usuário)
+// =====================================================
+
+// Vários funcionários podem compartilhar o mesmo usuário do Windows na recepção.
+// Cada usuário do app ganha um diretório próprio em DraBrunaClinic/profiles/<id>,
+// com cache, sessão e partição do banco local criptografados com uma chave
+// derivada da chave mestra para aquele perfil. A derivação separa os dados dos
+// perfis (um arquivo copiado para outro perfil não abre), mas não é segredo do
+// usuário: qualquer processo com acesso à chave mestra da estação deriva a
+// chave de todos os perfis. O isolamento entre usuários vem do app só ativar o
+// perfil de quem passou pelo login.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub id: String,
+    pub user_id: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+}
+
+struct ProfileState {
+    active: Option<Profile>,
+}
+
+static PROFILE_STATE: LazyLock<Mutex<ProfileState>> = LazyLock::new(|| Mutex::new(ProfileState {
+    active: None,
+}));
+
+pub fn profiles_root() -> Result<PathBuf> {
+    let root = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic")
+        .join("profiles");
+    std::fs::create_dir_all(&root)?;
+    Ok(root)
+}
+
+// O id do perfil não expõe o id do usuário no sistema de arquivos
+fn profile_id_for(user_id: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"dra_bruna_profile:");
+    hasher.update(user_id.as_bytes());
+    hex::encode(&hasher.finalize()[..16])
+}
+
+pub fn profile_purpose(profile_id: &str) -> String {
+    format!("profile:{}", profile_id)
+}
+
+pub fn activate_profile(user_id: &str, email: &str) -> Result<Profile> {
+    let id = profile_id_for(user_id);
+    let dir = profiles_root()?.join(&id);
+    std::fs::create_dir_all(&dir)?;
+
+    let meta_path = dir.join("profile.json");
+    let now = Utc::now();
+    let mut profile = if meta_path.exists() {
+        serde_json::from_str::<Profile>(&std::fs::read_to_string(&meta_path)?)?
+    } else {
+        Profile {
+            id: id.clone(),
+            user_id: user_id.to_string(),
+            email: email.to_string(),
+            created_at: now,
+            last_used_at: now,
+        }
+    };
+    profile.email = email.to_string();
+    profile.last_used_at = now;
+    std::fs::write(&meta_path, serde_json::to_string_pretty(&profile)?)?;
+
+    // Garante que a chave mestra está acessível antes de ativar o perfil
+    with_shared_vault(|_| Ok(()))?;
+    PROFILE_STATE.lock().unwrap().active = Some(profile.clone());
+    Ok(profile)
+}
+
+pub fn deactivate_profile() -> Option<Profile> {
+    PROFILE_STATE.lock().unwrap().active.take()
+}
+
+pub fn active_profile() -> Option<Profile> {
+    PROFILE_STATE.lock().unwrap().active.clone()
+}
+
+pub fn active_profile_dir() -> Result<PathBuf> {
+    let profile = active_profile().ok_or_else(|| anyhow::anyhow!("No active profile"))?;
+    let dir = profiles_root()?.join(&profile.id);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+pub fn list_profiles() -> Result<Vec<Profile>> {
+    let mut profiles = Vec::new();
+    for entry in std::fs::read_dir(profiles_root()?)? {
+        let meta_path = entry?.path().join("profile.json");
+        if let Ok(json) = std::fs::read_to_string(&meta_path) {
+            if let Ok(profile) = serde_json::from_str::<Profile>(&json) {
+                profiles.push(profile);
+            }
+        }
+    }
+    profiles.sort_by_key(|p| std::cmp::Reverse(p.last_used_at));
+    Ok(profiles)
+}
+
+// =====================================================
+// ARMAZENAMENTO CRIPTOGRAFADO DO PERFIL
+// =====================================================
+
+pub fn encrypt_for_active_profile(data: &[u8], aad: &[u8]) -> Result<String> {
+    let profile_id = active_profile()
+        .map(|p| p.id)
+        .ok_or_else(|| anyhow::anyhow!("No active profile"))?;
+    with_shared_vault(|vault| vault.encrypt_with_aad(data, &profile_purpose(&profile_id), aad))
+}
+
+// Devolve também o blob regravado no envelope atual quando o original está em
+// formato antigo
+pub fn decrypt_for_active_profile(encrypted: &str, aad: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+    let profile_id = active_profile()
+        .map(|p| p.id)
+        .ok_or_else(|| anyhow::anyhow!("No active profile"))?;
+    with_shared_vault(|vault| vault.decrypt_and_upgrade(encrypted, &profile_purpose(&profile_id), aad))
+}
+
+pub fn write_atomic(path: &PathBuf, contents: &str) -> Result<()> {
+    // Escrita atômica: evita arquivo truncado se o app fechar no meio
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?; // Conteúdo em disco antes do rename
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+pub fn write_profile_file<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_vec(value)?;
+    let encrypted = encrypt_for_active_profile(&json, &entity_aad("profile_file", name))?;
+    let path = active_profile_dir()?.join(format!("{}.enc", name));
+    write_atomic(&path, &encrypted)
+}
+
+pub fn read_profile_file<T: DeserializeOwned>(name: &str) -> Result<Option<T>> {
+    let path = active_profile_dir()?.join(format!("{}.enc", name));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let encrypted = std::fs::read_to_string(&path)?;
+    let (json, upgraded) = decrypt_for_active_profile(&encrypted, &entity_aad("profile_file", name))?;
+    if let Some(upgraded) = upgraded {
+        write_atomic(&path, &upgraded)?;
+    }
+    Ok(Some(serde_json::from_slice(&json)?))
+}
+
+pub fn remove_profile_file(name: &str) -> Result<()> {
+    let path = active_profile_dir()?.join(format!("{}.enc", name));
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Perfil ativo para os testes que gravam arquivos do perfil. O perfil ativo é
+// global, então um teste por vez segura o guard.
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+    use crate::keyvault::test_support::{install_memory_vault, isolate_data_dir, shared_vault};
+    use std::sync::{MutexGuard, RwLockReadGuard};
+
+    static PROFILE_TESTS: Mutex<()> = Mutex::new(());
+
+    pub struct TestProfile {
+        pub profile: Profile,
+        _profile: MutexGuard<'static, ()>,
+        _vault: RwLockReadGuard<'static, ()>,
+    }
+
+    impl Drop for TestProfile {
+        fn drop(&mut self) {
+            deactivate_profile();
+        }
+    }
+
+    pub fn activate_test_profile(user_id: &str) -> TestProfile {
+        isolate_data_dir();
+        install_memory_vault();
+        let guard = PROFILE_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let vault = shared_vault();
+        let profile = activate_profile(user_id, &format!("{}@clinica.test", user_id)).unwrap();
+        TestProfile { profile, _profile: guard, _vault: vault }
+    }
+}
@@ -0,0 +1,518 @@
+use anyhow::Result;
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+
+use crate::keyvault::KeyMaterial;
+use crate::totp::{base32_encode, BASE32_ALPHABET};
+
+// =====================================================
+// MASTER KEY RECOVERY KIT
+// =====================================================
+
+// Se o perfil do Windows que guarda a chave mestra (DPAPI) for perdido, todos os
+// documentos ficam ilegíveis. O kit de recuperação exporta a chave de duas formas:
+//
+// - Código imprimível protegido por uma frase de recuperação (Argon2id + AES-GCM)
+// - Partes de Shamir (k de n) para dividir a custódia entre pessoas diferentes
+//
+// Os códigos usam Base32 em grupos de 5 caracteres com checksum, para serem
+// digitados de volta a partir de uma folha impressa.
+
+const KIT_MAGIC: &[u8; 4] = b"DBRK";
+const SHARE_MAGIC: &[u8; 4] = b"DBRS";
+const KIT_VERSION: u8 = 1;
+const CHECKSUM_BYTES: usize = 4;
+const GROUP_SIZE: usize = 5;
+
+// Mais forte que o KeyVault local: o kit impresso pode ficar exposto por anos
+const KIT_ARGON2_M_COST: u32 = 128 * 1024;
+const KIT_ARGON2_T_COST: u32 = 4;
+const KIT_ARGON2_P_COST: u32 = 1;
+const MIN_PASSPHRASE_CHARS: usize = 12;
+const LONG_PASSPHRASE_CHARS: usize = 20;
+
+pub const MAX_SHARES: u8 = 16;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryKit {
+    pub kit_id: String,
+    pub code: String,
+    pub key_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryShares {
+    pub kit_id: String,
+    pub threshold: u8,
+    pub shares: Vec<String>,
+    pub key_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// Frases longas são aceitas com qualquer conjunto de caracteres; frases curtas
+// precisam misturar pelo menos três classes
+pub fn validate_recovery_passphrase(passphrase: &str) -> Result<()> {
+    let length = passphrase.chars().count();
+    if length < MIN_PASSPHRASE_CHARS {
+        return Err(anyhow::anyhow!("Recovery passphrase must have at least {} characters", MIN_PASSPHRASE_CHARS));
+    }
+    if length >= LONG_PASSPHRASE_CHARS {
+        return Ok(());
+    }
+
+    let classes = [
+        passphrase.chars().any(|c| c.is_lowercase()),
+        passphrase.chars().any(|c| c.is_uppercase()),
+        passphrase.chars().any(|c| c.is_ascii_digit()),
+        passphrase.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    if classes.iter().filter(|c| **c).count() < 3 {
+        return Err(anyhow::anyhow!(
+            "Recovery passphrase must mix upper/lower case, digits and symbols, or have at least {} characters",
+            LONG_PASSPHRASE_CHARS
+        ));
+    }
+    Ok(())
+}
+
+// =====================================================
+// EXPORT / IMPORT
+// =====================================================
+
+pub fn create_recovery_kit(material: &KeyMaterial, passphrase: &str) -> Result<RecoveryKit> {
+    validate_recovery_passphrase(passphrase)?;
+
+    let mut salt = [0u8; 16];
+    let mut nonce_bytes = [0u8; 12];
+    rand::thread_rng().fill(&mut salt);
+    rand::thread_rng().fill(&mut nonce_bytes);
+
+    let mut header = KIT_MAGIC.to_vec();
+    header.push(KIT_VERSION);
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce_bytes);
+
+    let wrapping_key = derive_kit_key(passphrase, &salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), Payload { msg: &encode_material(material)?, aad: &header })
+        .map_err(|e| anyhow::anyhow!("Recovery kit encryption failed: {}", e))?;
+
+    let mut blob = header;
+    blob.extend_from_slice(&ciphertext);
+
+    Ok(RecoveryKit {
+        kit_id: kit_id_for(&blob),
+        code: to_printable(&blob),
+        key_ids: material.keys.iter().map(|(id, _)| id.clone()).collect(),
+        created_at: Utc::now(),
+    })
+}
+
+pub fn open_recovery_kit(code: &str, passphrase: &str) -> Result<KeyMaterial> {
+    let blob = from_printable(code)?;
+    let header_len = KIT_MAGIC.len() + 1 + 16 + 12;
+    if blob.len() <= header_len || &blob[..4] != KIT_MAGIC {
+        return Err(anyhow::anyhow!("Not a recovery kit code"));
+    }
+    if blob[4] != KIT_VERSION {
+        return Err(anyhow::anyhow!("Unsupported recovery kit version: {}", blob[4]));
+    }
+
+    let (header, ciphertext) = blob.split_at(header_len);
+    let salt = &header[5..21];
+    let nonce_bytes = &header[21..33];
+
+    let wrapping_key = derive_kit_key(passphrase, salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| anyhow::anyhow!("Wrong recovery passphrase"))?;
+
+    decode_material(&plaintext)
+}
+
+pub fn create_recovery_shares(material: &KeyMaterial, threshold: u8, share_count: u8) -> Result<RecoveryShares> {
+    if threshold < 2 || threshold > share_count || share_count > MAX_SHARES {
+        return Err(anyhow::anyhow!(
+            "Invalid share configuration: threshold must be between 2 and the number of shares (max {})",
+            MAX_SHARES
+        ));
+    }
+
+    let secret = encode_material(material)?;
+    let mut kit_id = [0u8; 4];
+    rand::thread_rng().fill(&mut kit_id);
+
+    let shares = shamir_split(&secret, threshold, share_count)
+        .into_iter()
+        .map(|(x, data)| {
+            let mut blob = SHARE_MAGIC.to_vec();
+            blob.push(KIT_VERSION);
+            blob.extend_from_slice(&kit_id);
+            blob.push(threshold);
+            blob.push(x);
+            blob.extend_from_slice(&data);
+            to_printable(&blob)
+        })
+        .collect();
+
+    Ok(RecoveryShares {
+        kit_id: hex::encode(kit_id),
+        threshold,
+        shares,
+        key_ids: material.keys.iter().map(|(id, _)| id.clone()).collect(),
+        created_at: Utc::now(),
+    })
+}
+
+pub fn combine_recovery_shares(codes: &[String]) -> Result<KeyMaterial> {
+    let mut kit_id: Option<Vec<u8>> = None;
+    let mut threshold: Option<u8> = None;
+    let mut points: Vec<(u8, Vec<u8>)> = Vec::new();
+
+    for code in codes {
+        let blob = from_printable(code)?;
+        if blob.len() <= 11 || &blob[..4] != SHARE_MAGIC || blob[4] != KIT_VERSION {
+            return Err(anyhow::anyhow!("Not a recovery share code"));
+        }
+
+        let share_kit = blob[5..9].to_vec();
+        match &kit_id {
+            Some(id) if *id != share_kit => {
+                return Err(anyhow::anyhow!("Shares belong to different recovery kits"));
+            }
+            _ => kit_id = Some(share_kit),
+        }
+
+        // Todas as partes gravam o mesmo k; uma divergente foi adulterada ou
+        // é de outra exportação
+        match threshold {
+            Some(k) if k != blob[9] => {
+                return Err(anyhow::anyhow!("Shares disagree on the number of shares required"));
+            }
+            _ => threshold = Some(blob[9]),
+        }
+        let x = blob[10];
+        if x == 0 || points.iter().any(|(px, _)| *px == x) {
+            return Err(anyhow::anyhow!("Duplicate or invalid share"));
+        }
+        points.push((x, blob[11..].to_vec()));
+    }
+
+    let threshold = threshold.ok_or_else(|| anyhow::anyhow!("No recovery shares given"))?;
+    if threshold < 2 {
+        return Err(anyhow::anyhow!("Invalid recovery share"));
+    }
+    if points.len() < threshold as usize {
+        return Err(anyhow::anyhow!("{} shares are required, got {}", threshold, points.len()));
+    }
+    if points.iter().any(|(_, data)| data.len() != points[0].1.len()) {
+        return Err(anyhow::anyhow!("Shares have inconsistent lengths"));
+    }
+
+    let secret = shamir_combine(&points[..threshold as usize]);
+    decode_material(&secret)
+        .map_err(|_| anyhow::anyhow!("Shares could not be combined into a valid recovery kit"))
+}
+
+fn derive_kit_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let params = Params::new(KIT_ARGON2_M_COST, KIT_ARGON2_T_COST, KIT_ARGON2_P_COST, Some(32))
+        .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+fn kit_id_for(blob: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(blob);
+    hex::encode(&hasher.finalize()[..4])
+}
+
+// =====================================================
+// BINARY ENCODING
+// =====================================================
+
+// count | active index | legacy index (0xFF = nenhum) | (id_len | id | key_len | key)*
+fn encode_material(material: &KeyMaterial) -> Result<Vec<u8>> {
+    let index_of = |id: &str| material.keys.iter().position(|(k, _)| k == id);
+    let active = index_of(&material.active_key_id)
+        .ok_or_else(|| anyhow::anyhow!("Active key missing from key material"))?;
+    let legacy = material.legacy_key_id.as_deref().and_then(index_of);
+
+    let mut out = vec![material.keys.len() as u8, active as u8, legacy.map_or(0xFF, |i| i as u8)];
+    for (id, key) in &material.keys {
+        out.push(id.len() as u8);
+        out.extend_from_slice(id.as_bytes());
+        out.push(key.len() as u8);
+        out.extend_from_slice(key);
+    }
+    Ok(out)
+}
+
+fn decode_material(data: &[u8]) -> Result<KeyMaterial> {
+    let invalid = || anyhow::anyhow!("Invalid recovery kit contents");
+    if data.len() < 3 {
+        return Err(invalid());
+    }
+
+    let (count, active, legacy) = (data[0] as usize, data[1] as usize, data[2]);
+    let mut pos = 3;
+    let mut keys = Vec::with_capacity(count);
+    for _ in 0..count {
+        let id_len = *data.get(pos).ok_or_else(invalid)? as usize;
+        let id = data.get(pos + 1..pos + 1 + id_len).ok_or_else(invalid)?;
+        pos += 1 + id_len;
+        let key_len = *data.get(pos).ok_or_else(invalid)? as usize;
+        let key = data.get(pos + 1..pos + 1 + key_len).ok_or_else(invalid)?;
+        pos += 1 + key_len;
+        keys.push((String::from_utf8(id.to_vec()).map_err(|_| invalid())?, key.to_vec()));
+    }
+    if pos != data.len() || active >= keys.len() {
+        return Err(invalid());
+    }
+
+    Ok(KeyMaterial {
+        active_key_id: keys[active].0.clone(),
+        legacy_key_id: if legacy == 0xFF {
+            None
+        } else {
+            Some(keys.get(legacy as usize).ok_or_else(invalid)?.0.clone())
+        },
+        keys,
+    })
+}
+
+// =====================================================
+// PRINTABLE CODES (Base32 + checksum)
+// =====================================================
+
+fn to_printable(blob: &[u8]) -> String {
+    let mut data = blob.to_vec();
+    let mut hasher = Sha256::new();
+    hasher.update(blob);
+    data.extend_from_slice(&hasher.finalize()[..CHECKSUM_BYTES]);
+
+    let encoded = base32_encode(&data);
+    encoded
+        .as_bytes()
+        .chunks(GROUP_SIZE)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn from_printable(code: &str) -> Result<Vec<u8>> {
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut data = Vec::new();
+
+    for c in code.chars().filter(|c| !c.is_whitespace() && *c != '-') {
+        // Confusões comuns ao digitar a partir do papel
+        let c = match c.to_ascii_uppercase() {
+            '0' => 'O',
+            '1' => 'I',
+            '8' => 'B',
+            other => other,
+        };
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or_else(|| anyhow::anyhow!("Invalid character in recovery code: {}", c))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            data.push((buffer >> (bits - 8)) as u8);
+            bits -= 8;
+        }
+    }
+
+    if data.len() <= CHECKSUM_BYTES {
+        return Err(anyhow::anyhow!("Recovery code is too short"));
+    }
+    let (blob, checksum) = data.split_at(data.len() - CHECKSUM_BYTES);
+    let mut hasher = Sha256::new();
+    hasher.update(blob);
+    if hasher.finalize()[..CHECKSUM_BYTES] != *checksum {
+        return Err(anyhow::anyhow!("Recovery code checksum mismatch (check for typos)"));
+    }
+    Ok(blob.to_vec())
+}
+
+// =====================================================
+// SHAMIR SECRET SHARING OVER GF(256)
+// =====================================================
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    // Multiplicação no corpo do AES (polinômio x^8 + x^4 + x^3 + x + 1)
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+fn gf_inv(a: u8) -> u8 {
+    // a^254 = a^-1 (a != 0)
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+fn shamir_split(secret: &[u8], threshold: u8, share_count: u8) -> Vec<(u8, Vec<u8>)> {
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=share_count)
+        .map(|x| (x, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut rng = rand::thread_rng();
+
+    for &byte in secret {
+        // Polinômio aleatório de grau threshold-1 com termo constante = byte do segredo
+        let mut coefficients = vec![byte];
+        coefficients.extend((1..threshold).map(|_| rng.gen::<u8>()));
+
+        for (x, data) in shares.iter_mut() {
+            let mut y = 0u8;
+            for coefficient in coefficients.iter().rev() {
+                y = gf_mul(y, *x) ^ coefficient;
+            }
+            data.push(y);
+        }
+    }
+    shares
+}
+
+fn shamir_combine(points: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    let len = points[0].1.len();
+    (0..len)
+        .map(|i| {
+            // Interpolação de Lagrange em x = 0
+            let mut value = 0u8;
+            for (j, (xj, yj)) in points.iter().enumerate() {
+                let mut basis = 1u8;
+                for (m, (xm, _)) in points.iter().enumerate() {
+                    if m != j {
+                        basis = gf_mul(basis, gf_mul(*xm, gf_inv(xm ^ xj)));
+                    }
+                }
+                value ^= gf_mul(yj[i], basis);
+            }
+            value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_material() -> KeyMaterial {
+        KeyMaterial {
+            active_key_id: "k2".to_string(),
+            legacy_key_id: Some("k1".to_string()),
+            keys: vec![("k1".to_string(), vec![7u8; 32]), ("k2".to_string(), (0u8..32).collect())],
+        }
+    }
+
+    fn assert_same(a: &KeyMaterial, b: &KeyMaterial) {
+        assert_eq!(a.active_key_id, b.active_key_id);
+        assert_eq!(a.legacy_key_id, b.legacy_key_id);
+        assert_eq!(a.keys, b.keys);
+    }
+
+    #[test]
+    fn gf_inverse_is_multiplicative_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1, "a = {}", a);
+        }
+    }
+
+    #[test]
+    fn any_threshold_subset_recovers_the_secret() {
+        let secret: Vec<u8> = (0u8..=255).collect();
+        let shares = shamir_split(&secret, 3, 5);
+        for (a, b, c) in [(0, 1, 2), (0, 2, 4), (1, 3, 4), (4, 2, 0)] {
+            let subset = vec![shares[a].clone(), shares[b].clone(), shares[c].clone()];
+            assert_eq!(shamir_combine(&subset), secret);
+        }
+    }
+
+    #[test]
+    fn combines_printable_shares() {
+        let material = sample_material();
+        let exported = create_recovery_shares(&material, 2, 3).unwrap();
+        assert_eq!(exported.shares.len(), 3);
+
+        let restored = combine_recovery_shares(&exported.shares[1..]).unwrap();
+        assert_same(&restored, &material);
+
+        // Digitação com minúsculas, espaços e 0/1 no lugar de O/I
+        let typed = exported.shares[0].to_lowercase().replace('-', " ").replace('o', "0");
+        let restored = combine_recovery_shares(&[typed, exported.shares[2].clone()]).unwrap();
+        assert_same(&restored, &material);
+    }
+
+    #[test]
+    fn rejects_too_few_shares() {
+        let exported = create_recovery_shares(&sample_material(), 3, 5).unwrap();
+        let err = combine_recovery_shares(&exported.shares[..2]).unwrap_err();
+        assert!(err.to_string().contains("3 shares are required"));
+    }
+
+    #[test]
+    fn rejects_shares_from_different_kits() {
+        let first = create_recovery_shares(&sample_material(), 2, 3).unwrap();
+        let second = create_recovery_shares(&sample_material(), 2, 3).unwrap();
+        let mixed = vec![first.shares[0].clone(), second.shares[1].clone()];
+        assert!(combine_recovery_shares(&mixed).is_err());
+    }
+
+    #[test]
+    fn threshold_comes_from_every_share() {
+        let exported = create_recovery_shares(&sample_material(), 3, 4).unwrap();
+
+        // Última parte regravada dizendo k = 2: antes bastavam duas partes
+        let mut forged = from_printable(&exported.shares[1]).unwrap();
+        forged[9] = 2;
+        let codes = vec![exported.shares[0].clone(), to_printable(&forged)];
+        let err = combine_recovery_shares(&codes).unwrap_err();
+        assert!(err.to_string().contains("disagree"));
+    }
+
+    #[test]
+    fn rejects_typo_by_checksum() {
+        let exported = create_recovery_shares(&sample_material(), 2, 2).unwrap();
+        let mut code = exported.shares[0].clone().into_bytes();
+        let i = code.iter().position(|c| *c != b'-' && *c != b'A').unwrap();
+        code[i] = b'A';
+        let code = String::from_utf8(code).unwrap();
+        let err = combine_recovery_shares(&[code, exported.shares[1].clone()]).unwrap_err();
+        assert!(err.to_string().contains("checksum"));
+    }
+}
@@ -0,0 +1,526 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use crate::appointment_status::AppointmentStatus;
+
+// =====================================================
+// RECURRING APPOINTMENT SERIES (RRULE SUBSET)
+// =====================================================
+
+// Séries de consultas (manutenção ortodôntica, sessões semanais) descritas por
+// um subconjunto da RRULE do RFC 5545: FREQ (DAILY, WEEKLY, MONTHLY), INTERVAL,
+// BYDAY, COUNT e UNTIL. Cada data vira uma Appointment comum ligada à série;
+// datas excluídas ficam em `exdates` e ocorrências editadas sozinhas ficam
+// marcadas na própria consulta, para edições da série não as sobrescreverem.
+
+const MAX_OCCURRENCES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+// BYDAY: "MO" ou, em séries mensais, com ordinal ("2TU" = segunda terça, "-1FR" = última sexta)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub ordinal: Option<i32>,
+    pub weekday: Weekday,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<ByDay>,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDate>,
+}
+
+fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn parse_weekday(code: &str) -> Option<Weekday> {
+    Some(match code {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+impl RecurrenceRule {
+    // Aceita com ou sem o prefixo "RRULE:"
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let value = value.strip_prefix("RRULE:").unwrap_or(value);
+        let mut freq = None;
+        let mut interval = 1;
+        let mut by_day = Vec::new();
+        let mut count = None;
+        let mut until = None;
+
+        for part in value.split(';').filter(|p| !p.is_empty()) {
+            let (key, val) = part.split_once('=').ok_or_else(|| anyhow!("Invalid RRULE part: {}", part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match val.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        other => bail!("Unsupported FREQ: {}", other),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = val.parse().map_err(|_| anyhow!("Invalid INTERVAL: {}", val))?;
+                    if !(1..=52).contains(&interval) {
+                        bail!("INTERVAL must be between 1 and 52");
+                    }
+                }
+                "BYDAY" => {
+                    for item in val.split(',') {
+                        let item = item.trim().to_ascii_uppercase();
+                        if item.len() < 2 {
+                            bail!("Invalid BYDAY: {}", item);
+                        }
+                        let (ordinal, code) = item.split_at(item.len() - 2);
+                        let weekday = parse_weekday(code).ok_or_else(|| anyhow!("Invalid BYDAY: {}", item))?;
+                        let ordinal = match ordinal {
+                            "" => None,
+                            n => {
+                                let n: i32 = n.trim_start_matches('+').parse().map_err(|_| anyhow!("Invalid BYDAY: {}", item))?;
+                                if n == 0 || !(-5..=5).contains(&n) {
+                                    bail!("Invalid BYDAY ordinal: {}", item);
+                                }
+                                Some(n)
+                            }
+                        };
+                        by_day.push(ByDay { ordinal, weekday });
+                    }
+                }
+                "COUNT" => {
+                    let n: u32 = val.parse().map_err(|_| anyhow!("Invalid COUNT: {}", val))?;
+                    if n == 0 {
+                        bail!("COUNT must be at least 1");
+                    }
+                    count = Some(n);
+                }
+                // "AAAAMMDD" ou "AAAAMMDDTHHMMSSZ": só a data importa
+                "UNTIL" => {
+                    let date = val.get(..8).unwrap_or(val);
+                    until = Some(
+                        NaiveDate::parse_from_str(date, "%Y%m%d").map_err(|_| anyhow!("Invalid UNTIL: {}", val))?,
+                    );
+                }
+                other => bail!("Unsupported RRULE part: {}", other),
+            }
+        }
+
+        let freq = freq.ok_or_else(|| anyhow!("RRULE must have FREQ"))?;
+        if count.is_some() && until.is_some() {
+            bail!("RRULE cannot have both COUNT and UNTIL");
+        }
+        if count.is_none() && until.is_none() {
+            bail!("RRULE must end (COUNT or UNTIL)");
+        }
+        if by_day.iter().any(|d| d.ordinal.is_some()) && freq != Frequency::Monthly {
+            bail!("BYDAY ordinals are only supported with FREQ=MONTHLY");
+        }
+        if freq == Frequency::Daily && !by_day.is_empty() {
+            bail!("BYDAY is not supported with FREQ=DAILY");
+        }
+        Ok(RecurrenceRule { freq, interval, by_day, count, until })
+    }
+
+    pub fn to_rrule(&self) -> String {
+        let mut parts = vec![format!(
+            "FREQ={}",
+            match self.freq {
+                Frequency::Daily => "DAILY",
+                Frequency::Weekly => "WEEKLY",
+                Frequency::Monthly => "MONTHLY",
+            }
+        )];
+        if self.interval != 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<String> = self
+                .by_day
+                .iter()
+                .map(|d| format!("{}{}", d.ordinal.map(|n| n.to_string()).unwrap_or_default(), weekday_code(d.weekday)))
+                .collect();
+            parts.push(format!("BYDAY={}", days.join(",")));
+        }
+        if let Some(count) = self.count {
+            parts.push(format!("COUNT={}", count));
+        }
+        if let Some(until) = self.until {
+            parts.push(format!("UNTIL={}", until.format("%Y%m%d")));
+        }
+        parts.join(";")
+    }
+
+    // Datas candidatas do período (semana ou mês) que começa em `period_start`
+    fn period_dates(&self, dtstart: NaiveDate, period_start: NaiveDate) -> Vec<NaiveDate> {
+        match self.freq {
+            Frequency::Daily => vec![period_start],
+            Frequency::Weekly => {
+                if self.by_day.is_empty() {
+                    return vec![period_start];
+                }
+                // Semana de segunda a domingo, como a WKST padrão
+                let monday = period_start - Duration::days(period_start.weekday().num_days_from_monday() as i64);
+                let mut dates: Vec<NaiveDate> = self
+                    .by_day
+                    .iter()
+                    .map(|d| monday + Duration::days(d.weekday.num_days_from_monday() as i64))
+                    .collect();
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+            Frequency::Monthly => {
+                let (year, month) = (period_start.year(), period_start.month());
+                if self.by_day.is_empty() {
+                    // Meses sem o dia (31, 30, 29/02) são pulados, como no RFC
+                    return NaiveDate::from_ymd_opt(year, month, dtstart.day()).into_iter().collect();
+                }
+                let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+                let days_in_month = (first + Months::new(1) - first).num_days() as u32;
+                let month_days: Vec<NaiveDate> = (1..=days_in_month)
+                    .filter_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+                    .collect();
+                let mut dates = Vec::new();
+                for by_day in &self.by_day {
+                    let matching: Vec<NaiveDate> =
+                        month_days.iter().copied().filter(|d| d.weekday() == by_day.weekday).collect();
+                    match by_day.ordinal {
+                        None => dates.extend(matching),
+                        Some(n) if n > 0 => dates.extend(matching.get(n as usize - 1)),
+                        Some(n) => dates.extend(matching.len().checked_sub(n.unsigned_abs() as usize).map(|i| matching[i])),
+                    }
+                }
+                dates.sort();
+                dates.dedup();
+                dates
+            }
+        }
+    }
+
+    // Todas as ocorrências a partir de DTSTART (que conta como a primeira se casar
+    // com a regra). Séries sem fim não são aceitas, e há um teto de ocorrências.
+    pub fn occurrences(&self, dtstart: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let mut out = Vec::new();
+        let mut period = 0u32;
+        loop {
+            let step = period * self.interval;
+            let period_start = match self.freq {
+                Frequency::Daily => dtstart + Duration::days(step as i64),
+                Frequency::Weekly => dtstart + Duration::weeks(step as i64),
+                Frequency::Monthly => {
+                    let first = NaiveDate::from_ymd_opt(dtstart.year(), dtstart.month(), 1).unwrap();
+                    first + Months::new(step)
+                }
+            };
+            let dates = self.period_dates(dtstart, period_start);
+            if self.until.is_some_and(|until| period_start > until && dates.iter().all(|d| *d > until)) {
+                break;
+            }
+            for date in dates {
+                if date < dtstart || self.until.is_some_and(|until| date > until) {
+                    continue;
+                }
+                if self.count.is_some_and(|count| out.len() >= count as usize) {
+                    return Ok(out);
+                }
+                if out.len() >= MAX_OCCURRENCES {
+                    bail!("Series is too long (max {} occurrences)", MAX_OCCURRENCES);
+                }
+                out.push(date);
+            }
+            if self.count.is_some_and(|count| out.len() >= count as usize) {
+                return Ok(out);
+            }
+            period += 1;
+            // Regras que nunca casam (ex.: dia 31 com INTERVAL=2 em meses curtos)
+            if period > 10_000 {
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    // Mesma regra, encerrada no dia anterior a `date` (divisão da série em
+    // "esta e as seguintes")
+    pub fn ending_before(&self, date: NaiveDate) -> RecurrenceRule {
+        RecurrenceRule { count: None, until: Some(date - Duration::days(1)), ..self.clone() }
+    }
+
+    // Mesma regra recomeçando em `from`, descontando do COUNT o que já passou
+    pub fn continuing_from(&self, dtstart: NaiveDate, from: NaiveDate) -> Result<RecurrenceRule> {
+        let mut rule = self.clone();
+        if let Some(count) = self.count {
+            let before = self.occurrences(dtstart)?.iter().filter(|d| **d < from).count() as u32;
+            rule.count = Some(count.saturating_sub(before).max(1));
+        }
+        Ok(rule)
+    }
+}
+
+// =====================================================
+// SERIES
+// =====================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppointmentSeries {
+    pub id: String,
+    pub patient_id: String,
+    pub rrule: String,
+    pub start_date: NaiveDate, // DTSTART
+    pub time: String,
+    pub duration_minutes: u32,
+    pub professional_id: Option<String>,
+    pub room: Option<String>,
+    pub chair: Option<String>,
+    #[serde(deserialize_with = "crate::appointment_status::lenient_status")]
+    pub status: AppointmentStatus, // Status das consultas geradas (scheduled ou confirmed)
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub exdates: Vec<NaiveDate>, // Ocorrências excluídas
+    pub created_at: String,
+    pub updated_at: String,
+    // Metadados de sincronização híbrida
+    pub rev: i64,
+    pub deleted_at: Option<String>,
+    pub last_editor: Option<String>,
+    pub last_pulled_rev: Option<i64>,
+}
+
+impl AppointmentSeries {
+    pub fn rule(&self) -> Result<RecurrenceRule> {
+        RecurrenceRule::parse(&self.rrule)
+    }
+
+    // Datas da série a partir de `from`, já sem as excluídas
+    pub fn dates_from(&self, from: NaiveDate) -> Result<Vec<NaiveDate>> {
+        Ok(self
+            .rule()?
+            .occurrences(self.start_date)?
+            .into_iter()
+            .filter(|d| *d >= from && !self.exdates.contains(d))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateSeriesRequest {
+    pub patient_id: String,
+    pub rrule: String,
+    pub start_date: String,
+    pub time: String,
+    pub duration_minutes: Option<u32>,
+    pub end_time: Option<String>,
+    pub professional_id: Option<String>,
+    pub room: Option<String>,
+    pub chair: Option<String>,
+    #[serde(default)]
+    pub status: Option<AppointmentStatus>, // Sem status: scheduled
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub skip_conflicts: bool, // Pula (e exclui da série) as datas com feriado ou conflito
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditScope {
+    This,
+    ThisAndFollowing,
+    All,
+}
+
+// Novos dados da série; `rrule` vazio mantém a regra atual e `date` só vale
+// para mover uma ocorrência isolada
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesChanges {
+    pub rrule: Option<String>,
+    pub date: Option<String>,
+    pub time: String,
+    pub duration_minutes: Option<u32>,
+    pub end_time: Option<String>,
+    pub professional_id: Option<String>,
+    pub room: Option<String>,
+    pub chair: Option<String>,
+    #[serde(default)]
+    pub status: Option<AppointmentStatus>, // Sem status: mantém o atual
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub skip_conflicts: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn occurrences(rrule: &str, dtstart: NaiveDate) -> Vec<NaiveDate> {
+        RecurrenceRule::parse(rrule).unwrap().occurrences(dtstart).unwrap()
+    }
+
+    #[test]
+    fn weekly_by_day_with_count() {
+        // 02/03/2026 é segunda-feira
+        assert_eq!(
+            occurrences("RRULE:FREQ=WEEKLY;BYDAY=MO,TH;COUNT=5", date(2026, 3, 2)),
+            vec![date(2026, 3, 2), date(2026, 3, 5), date(2026, 3, 9), date(2026, 3, 12), date(2026, 3, 16)]
+        );
+    }
+
+    #[test]
+    fn dtstart_outside_by_day_is_not_an_occurrence() {
+        // DTSTART numa quarta com BYDAY=MO: a primeira é a segunda seguinte
+        assert_eq!(
+            occurrences("FREQ=WEEKLY;BYDAY=MO;COUNT=2", date(2026, 3, 4)),
+            vec![date(2026, 3, 9), date(2026, 3, 16)]
+        );
+    }
+
+    #[test]
+    fn biweekly_until_is_inclusive() {
+        assert_eq!(
+            occurrences("FREQ=WEEKLY;INTERVAL=2;UNTIL=20260330", date(2026, 3, 2)),
+            vec![date(2026, 3, 2), date(2026, 3, 16), date(2026, 3, 30)]
+        );
+        assert_eq!(occurrences("FREQ=DAILY;UNTIL=20260304T235959Z", date(2026, 3, 2)).len(), 3);
+    }
+
+    #[test]
+    fn monthly_last_friday() {
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYDAY=-1FR;COUNT=4", date(2026, 1, 1)),
+            vec![date(2026, 1, 30), date(2026, 2, 27), date(2026, 3, 27), date(2026, 4, 24)]
+        );
+    }
+
+    #[test]
+    fn monthly_second_tuesday() {
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYDAY=2TU;COUNT=3", date(2026, 1, 1)),
+            vec![date(2026, 1, 13), date(2026, 2, 10), date(2026, 3, 10)]
+        );
+    }
+
+    #[test]
+    fn monthly_fifth_weekday_skips_short_months() {
+        // Só março e junho de 2026 têm cinco segundas até junho
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;BYDAY=5MO;UNTIL=20260630", date(2026, 1, 1)),
+            vec![date(2026, 3, 30), date(2026, 6, 29)]
+        );
+    }
+
+    #[test]
+    fn monthly_day_31_skips_months_without_it() {
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;COUNT=4", date(2026, 1, 31)),
+            vec![date(2026, 1, 31), date(2026, 3, 31), date(2026, 5, 31), date(2026, 7, 31)]
+        );
+        // 29/02 só em anos bissextos, dentro do UNTIL
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;UNTIL=20240531", date(2024, 1, 29)),
+            vec![date(2024, 1, 29), date(2024, 2, 29), date(2024, 3, 29), date(2024, 4, 29), date(2024, 5, 29)]
+        );
+    }
+
+    #[test]
+    fn monthly_day_31_with_interval() {
+        // Maio, julho, (setembro e novembro não têm 31), janeiro, março
+        assert_eq!(
+            occurrences("FREQ=MONTHLY;INTERVAL=2;COUNT=4", date(2026, 5, 31)),
+            vec![date(2026, 5, 31), date(2026, 7, 31), date(2027, 1, 31), date(2027, 3, 31)]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_unsupported_rules() {
+        for rrule in [
+            "FREQ=YEARLY;COUNT=2",
+            "FREQ=WEEKLY",
+            "FREQ=WEEKLY;COUNT=2;UNTIL=20260101",
+            "FREQ=WEEKLY;BYDAY=1MO;COUNT=2",
+            "FREQ=DAILY;BYDAY=MO;COUNT=2",
+            "FREQ=MONTHLY;BYDAY=6MO;COUNT=2",
+            "FREQ=MONTHLY;BYDAY=0MO;COUNT=2",
+            "FREQ=WEEKLY;INTERVAL=0;COUNT=2",
+            "FREQ=WEEKLY;COUNT=0",
+            "FREQ=WEEKLY;BYDAY=XX;COUNT=2",
+            "FREQ=WEEKLY;BYSETPOS=1;COUNT=2",
+        ] {
+            assert!(RecurrenceRule::parse(rrule).is_err(), "{}", rrule);
+        }
+    }
+
+    #[test]
+    fn to_rrule_round_trips() {
+        for rrule in ["FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR;COUNT=10", "FREQ=MONTHLY;BYDAY=-1FR;UNTIL=20261231", "FREQ=DAILY;COUNT=3"] {
+            assert_eq!(RecurrenceRule::parse(rrule).unwrap().to_rrule(), rrule);
+        }
+        assert_eq!(RecurrenceRule::parse("FREQ=MONTHLY;BYDAY=+2TU;COUNT=1").unwrap().to_rrule(), "FREQ=MONTHLY;BYDAY=2TU;COUNT=1");
+    }
+
+    #[test]
+    fn splitting_a_series_keeps_every_date_once() {
+        let rule = RecurrenceRule::parse("FREQ=WEEKLY;BYDAY=TU;COUNT=6").unwrap();
+        let dtstart = date(2026, 3, 3);
+        let all = rule.occurrences(dtstart).unwrap();
+        let split = all[2];
+
+        let before = rule.ending_before(split).occurrences(dtstart).unwrap();
+        let after = rule.continuing_from(dtstart, split).unwrap().occurrences(split).unwrap();
+        assert_eq!(before, all[..2]);
+        assert_eq!(after, all[2..]);
+    }
+
+    #[test]
+    fn series_dates_skip_exdates() {
+        let series = AppointmentSeries {
+            id: "series-1".to_string(),
+            patient_id: "patient-1".to_string(),
+            rrule: "FREQ=WEEKLY;COUNT=4".to_string(),
+            start_date: date(2026, 3, 2),
+            time: "09:00".to_string(),
+            duration_minutes: 30,
+            professional_id: None,
+            room: None,
+            chair: None,
+            status: AppointmentStatus::Scheduled,
+            notes: None,
+            exdates: vec![date(2026, 3, 16)],
+            created_at: String::new(),
+            updated_at: String::new(),
+            rev: 0,
+            deleted_at: None,
+            last_editor: None,
+            last_pulled_rev: None,
+        };
+        assert_eq!(series.dates_from(date(2026, 3, 3)).unwrap(), vec![date(2026, 3, 9), date(2026, 3, 23)]);
+    }
+}
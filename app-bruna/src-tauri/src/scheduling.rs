@@ -0,0 +1,345 @@
+use anyhow::{bail, Result};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// =====================================================
+// APPOINTMENT OVERLAP DETECTION
+// =====================================================
+
+// Duas consultas conflitam quando dividem um recurso (profissional, sala,
+// cadeira ou o próprio paciente) em horários que se sobrepõem. Entre consultas
+// no mesmo profissional, sala ou cadeira é exigido ainda um intervalo
+// (higienização, preparo); o paciente não precisa de intervalo.
+//
+// O que fazer com cada tipo de conflito é configuração da clínica: recusar,
+// só avisar ou ignorar.
+
+const RULES_FILE: &str = "scheduling_rules.json";
+const MAX_DURATION_MINUTES: u32 = 12 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceKind {
+    Professional,
+    Room,
+    Chair,
+    Patient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapPolicy {
+    Reject,
+    Warn,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulingRules {
+    pub default_duration_minutes: u32,
+    pub buffer_minutes: u32, // Intervalo mínimo entre consultas no mesmo recurso
+    pub professional: OverlapPolicy,
+    pub room: OverlapPolicy,
+    pub chair: OverlapPolicy,
+    pub patient: OverlapPolicy,
+}
+
+impl Default for SchedulingRules {
+    fn default() -> Self {
+        Self {
+            default_duration_minutes: 30,
+            buffer_minutes: 10,
+            professional: OverlapPolicy::Reject,
+            room: OverlapPolicy::Reject,
+            chair: OverlapPolicy::Reject,
+            patient: OverlapPolicy::Warn, // Ex.: acompanhante marcado junto com o titular
+        }
+    }
+}
+
+impl SchedulingRules {
+    pub fn policy(&self, resource: ResourceKind) -> OverlapPolicy {
+        match resource {
+            ResourceKind::Professional => self.professional,
+            ResourceKind::Room => self.room,
+            ResourceKind::Chair => self.chair,
+            ResourceKind::Patient => self.patient,
+        }
+    }
+}
+
+// Consultas gravadas antes de existir duração
+pub fn default_duration_minutes() -> u32 {
+    SchedulingRules::default().default_duration_minutes
+}
+
+fn rules_path() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+    Ok(app_data.join(RULES_FILE))
+}
+
+pub fn load_rules() -> SchedulingRules {
+    rules_path()
+        .ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_rules(rules: &SchedulingRules) -> Result<()> {
+    if rules.default_duration_minutes == 0 || rules.default_duration_minutes > MAX_DURATION_MINUTES {
+        bail!("Default duration must be between 1 and {} minutes", MAX_DURATION_MINUTES);
+    }
+    if rules.buffer_minutes > 240 {
+        bail!("Buffer between appointments is too long");
+    }
+
+    let path = rules_path()?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(rules)?)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+// =====================================================
+// TIMES
+// =====================================================
+
+pub fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("Invalid date: {} (use YYYY-MM-DD)", value))
+}
+
+// Aceita "HH:MM" e "HH:MM:SS"
+pub fn parse_time(value: &str) -> Result<NaiveTime> {
+    let value = value.trim();
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .map_err(|_| anyhow::anyhow!("Invalid time: {} (use HH:MM)", value))
+}
+
+pub fn format_time(time: NaiveTime) -> String {
+    time.format("%H:%M").to_string()
+}
+
+// Duração a partir da duração informada, do horário de término ou do padrão da
+// clínica. A consulta tem que terminar no mesmo dia.
+pub fn resolve_duration(
+    start: NaiveTime,
+    duration_minutes: Option<u32>,
+    end_time: Option<&str>,
+    rules: &SchedulingRules,
+) -> Result<u32> {
+    let duration = match (duration_minutes, end_time) {
+        (Some(minutes), _) => minutes,
+        (None, Some(end)) => {
+            let end = parse_time(end)?;
+            if end <= start {
+                bail!("End time must be after the start time");
+            }
+            (end - start).num_minutes() as u32
+        }
+        (None, None) => rules.default_duration_minutes,
+    };
+    if duration == 0 || duration > MAX_DURATION_MINUTES {
+        bail!("Duration must be between 1 and {} minutes", MAX_DURATION_MINUTES);
+    }
+    if end_of(start, duration).is_none() {
+        bail!("Appointment must end on the same day");
+    }
+    Ok(duration)
+}
+
+pub fn end_of(start: NaiveTime, duration_minutes: u32) -> Option<NaiveTime> {
+    let (end, wrapped) = start.overflowing_add_signed(Duration::minutes(duration_minutes as i64));
+    (wrapped == 0 && end > start).then_some(end)
+}
+
+// =====================================================
+// CONFLICTS
+// =====================================================
+
+// O que importa de uma consulta para a agenda
+#[derive(Debug, Clone)]
+pub struct ScheduleEntry {
+    pub id: String,
+    pub patient_id: String,
+    pub date: NaiveDate,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub professional_id: Option<String>,
+    pub room: Option<String>,
+    pub chair: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    pub appointment_id: String,
+    pub resources: Vec<ResourceKind>, // Recursos disputados
+    pub blocking: bool, // Algum dos recursos tem política de recusa
+}
+
+fn shares(a: &Option<String>, b: &Option<String>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a == b)
+}
+
+fn overlaps(a: &ScheduleEntry, b: &ScheduleEntry, buffer_minutes: u32) -> bool {
+    if a.date != b.date {
+        return false;
+    }
+    // Em minutos do dia, para o intervalo não dar a volta na meia-noite
+    let minutes = |t: NaiveTime| (t - NaiveTime::MIN).num_minutes();
+    let buffer = buffer_minutes as i64;
+    minutes(a.start) < minutes(b.end) + buffer && minutes(b.start) < minutes(a.end) + buffer
+}
+
+// Consultas de `existing` que conflitam com `candidate` (ela própria é ignorada,
+// para a edição de uma consulta não conflitar com a versão anterior dela)
+pub fn find_conflicts(candidate: &ScheduleEntry, existing: &[ScheduleEntry], rules: &SchedulingRules) -> Vec<Conflict> {
+    let mut conflicts: Vec<(NaiveTime, Conflict)> = Vec::new();
+    for other in existing.iter().filter(|e| e.id != candidate.id) {
+        let mut resources = Vec::new();
+        let with_buffer = overlaps(candidate, other, rules.buffer_minutes);
+        if with_buffer && shares(&candidate.professional_id, &other.professional_id) {
+            resources.push(ResourceKind::Professional);
+        }
+        if with_buffer && shares(&candidate.room, &other.room) {
+            resources.push(ResourceKind::Room);
+        }
+        if with_buffer && shares(&candidate.chair, &other.chair) {
+            resources.push(ResourceKind::Chair);
+        }
+        if candidate.patient_id == other.patient_id && overlaps(candidate, other, 0) {
+            resources.push(ResourceKind::Patient);
+        }
+        resources.retain(|r| rules.policy(*r) != OverlapPolicy::Ignore);
+        if resources.is_empty() {
+            continue;
+        }
+        let blocking = resources.iter().any(|r| rules.policy(*r) == OverlapPolicy::Reject);
+        conflicts.push((other.start, Conflict { appointment_id: other.id.clone(), resources, blocking }));
+    }
+    conflicts.sort_by_key(|(start, _)| *start);
+    conflicts.into_iter().map(|(_, conflict)| conflict).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        parse_time(value).unwrap()
+    }
+
+    fn entry(id: &str, start: &str, end: &str) -> ScheduleEntry {
+        ScheduleEntry {
+            id: id.to_string(),
+            patient_id: format!("patient-{}", id),
+            date: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+            start: time(start),
+            end: time(end),
+            professional_id: Some("dra-bruna".to_string()),
+            room: None,
+            chair: None,
+        }
+    }
+
+    fn no_buffer() -> SchedulingRules {
+        SchedulingRules { buffer_minutes: 0, ..SchedulingRules::default() }
+    }
+
+    #[test]
+    fn touching_intervals_do_not_overlap_without_buffer() {
+        let first = entry("a", "09:00", "09:30");
+        let next = entry("b", "09:30", "10:00");
+        assert!(!overlaps(&first, &next, 0));
+        assert!(!overlaps(&next, &first, 0));
+        assert!(find_conflicts(&next, std::slice::from_ref(&first), &no_buffer()).is_empty());
+
+        // Um minuto de sobreposição já conflita
+        assert!(overlaps(&first, &entry("c", "09:29", "10:00"), 0));
+        // Outro dia no mesmo horário não conflita
+        let mut tomorrow = first.clone();
+        tomorrow.date = first.date.succ_opt().unwrap();
+        assert!(!overlaps(&first, &tomorrow, 0));
+    }
+
+    #[test]
+    fn buffer_applies_on_both_sides() {
+        let booked = entry("a", "10:00", "10:30");
+        let rules = SchedulingRules::default(); // 10 minutos
+
+        // Antes: termina 5 min antes do início; depois: começa 5 min após o fim
+        for candidate in [entry("b", "09:25", "09:55"), entry("c", "10:35", "11:00")] {
+            let conflicts = find_conflicts(&candidate, std::slice::from_ref(&booked), &rules);
+            assert_eq!(conflicts.len(), 1, "{}", candidate.id);
+            assert_eq!(conflicts[0].resources, vec![ResourceKind::Professional]);
+            assert!(conflicts[0].blocking);
+        }
+
+        // Exatamente o intervalo exigido, dos dois lados
+        for candidate in [entry("d", "09:20", "09:50"), entry("e", "10:40", "11:00")] {
+            assert!(find_conflicts(&candidate, std::slice::from_ref(&booked), &rules).is_empty(), "{}", candidate.id);
+        }
+    }
+
+    #[test]
+    fn conflicts_are_reported_per_resource() {
+        let mut booked = entry("a", "10:00", "10:30");
+        booked.room = Some("sala 1".to_string());
+        booked.chair = Some("cadeira 2".to_string());
+
+        // Outro profissional, mesma sala, outra cadeira
+        let mut candidate = entry("b", "10:15", "10:45");
+        candidate.professional_id = Some("dr-joao".to_string());
+        candidate.room = Some("sala 1".to_string());
+        candidate.chair = Some("cadeira 3".to_string());
+        let conflicts = find_conflicts(&candidate, std::slice::from_ref(&booked), &SchedulingRules::default());
+        assert_eq!(conflicts[0].resources, vec![ResourceKind::Room]);
+
+        // Recursos não informados não conflitam entre si
+        let mut free = entry("c", "10:00", "10:30");
+        free.professional_id = None;
+        let mut other = booked.clone();
+        other.professional_id = None;
+        other.room = None;
+        other.chair = None;
+        assert!(find_conflicts(&free, &[other], &SchedulingRules::default()).is_empty());
+    }
+
+    #[test]
+    fn patient_overlap_ignores_the_buffer_and_follows_its_policy() {
+        let booked = entry("a", "10:00", "10:30");
+
+        // Mesmo paciente, outro profissional, logo em seguida: sem intervalo exigido
+        let mut right_after = entry("b", "10:30", "11:00");
+        right_after.patient_id = booked.patient_id.clone();
+        right_after.professional_id = Some("dr-joao".to_string());
+        assert!(find_conflicts(&right_after, std::slice::from_ref(&booked), &SchedulingRules::default()).is_empty());
+
+        // Ao mesmo tempo: o padrão só avisa
+        let mut same_time = right_after.clone();
+        same_time.start = time("10:15");
+        let conflicts = find_conflicts(&same_time, std::slice::from_ref(&booked), &SchedulingRules::default());
+        assert_eq!(conflicts[0].resources, vec![ResourceKind::Patient]);
+        assert!(!conflicts[0].blocking);
+
+        let ignore = SchedulingRules { patient: OverlapPolicy::Ignore, ..SchedulingRules::default() };
+        assert!(find_conflicts(&same_time, &[booked], &ignore).is_empty());
+    }
+
+    #[test]
+    fn editing_an_appointment_does_not_conflict_with_itself() {
+        let booked = entry("a", "10:00", "10:30");
+        let moved = entry("a", "10:15", "10:45");
+        let later = entry("z", "11:30", "12:00");
+        let earlier = entry("y", "09:00", "10:20");
+        let conflicts = find_conflicts(&moved, &[later, booked, earlier], &SchedulingRules::default());
+        // Só a outra consulta, na ordem de início
+        assert_eq!(conflicts.iter().map(|c| c.appointment_id.as_str()).collect::<Vec<_>>(), vec!["y"]);
+    }
+}
@@ -0,0 +1,449 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use aes_gcm::{Aes256Gcm, Key, Nonce, KeyInit};
+use aes_gcm::aead::{Aead, Payload};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use rand::Rng;
+
+#[cfg(target_os = "windows")]
+use windows::{
+    core::PCSTR,
+    Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN,
+        DATA_BLOB,
+    },
+};
+
+// =====================================================
+// SECRET STORAGE BACKENDS FOR KEYVAULT
+// =====================================================
+
+pub trait SecretStore: Send {
+    fn name(&self) -> &'static str;
+
+    // Ok(None) quando o segredo não existe; erros de leitura/decifragem nunca
+    // devem ser confundidos com ausência, senão a chave mestra seria recriada
+    fn load_secret(&self, key_name: &str) -> Result<Option<Vec<u8>>>;
+
+    fn store_secret(&self, key_name: &str, data: &[u8]) -> Result<()>;
+
+    fn delete_secret(&self, key_name: &str) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecretStoreBackend {
+    Dpapi,
+    PassphraseFile,
+    Keyring,
+}
+
+impl std::str::FromStr for SecretStoreBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "dpapi" => Ok(SecretStoreBackend::Dpapi),
+            "file" | "passphrase_file" => Ok(SecretStoreBackend::PassphraseFile),
+            "keyring" | "secret_service" => Ok(SecretStoreBackend::Keyring),
+            _ => Err(anyhow::anyhow!("Unknown KeyVault backend: {}", s)),
+        }
+    }
+}
+
+impl SecretStoreBackend {
+    pub fn platform_default() -> Self {
+        if cfg!(target_os = "windows") {
+            SecretStoreBackend::Dpapi
+        } else {
+            SecretStoreBackend::PassphraseFile
+        }
+    }
+}
+
+fn key_directory() -> Result<PathBuf> {
+    let app_data = dirs::data_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+        .join("DraBrunaClinic");
+    std::fs::create_dir_all(&app_data)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&app_data, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(app_data)
+}
+
+// Escreve em arquivo temporário com permissão restrita e renomeia por cima
+fn write_key_file(path: &PathBuf, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let tmp_path = path.with_extension("tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Backend escolhido por KEYVAULT_BACKEND (dpapi | file | keyring); sem configuração,
+// usa DPAPI no Windows e o arquivo protegido por senha nas demais plataformas
+pub fn from_config() -> Result<Box<dyn SecretStore>> {
+    let config = crate::config::get_config().ok();
+
+    let backend_name = config
+        .and_then(|c| c.keyvault_backend.clone())
+        .or_else(|| std::env::var("KEYVAULT_BACKEND").ok());
+
+    let backend = match backend_name {
+        Some(name) => name.parse()?,
+        None => SecretStoreBackend::platform_default(),
+    };
+
+    match backend {
+        SecretStoreBackend::Dpapi => dpapi_store(),
+        SecretStoreBackend::PassphraseFile => {
+            // Sem senha própria o cofre não abre: nada de reaproveitar a
+            // MASTER_PASSWORD do .env, que é compartilhada entre instalações
+            let passphrase = config
+                .and_then(|c| c.keyvault_passphrase.clone())
+                .or_else(|| std::env::var("KEYVAULT_PASSPHRASE").ok())
+                .filter(|p| !p.trim().is_empty())
+                .ok_or_else(|| anyhow::anyhow!("KEYVAULT_PASSPHRASE not configured"))?;
+            Ok(Box::new(PassphraseFileStore::new(key_directory()?, passphrase)?))
+        }
+        SecretStoreBackend::Keyring => keyring_store(),
+    }
+}
+
+// =====================================================
+// DPAPI (WINDOWS)
+// =====================================================
+
+#[cfg(target_os = "windows")]
+fn dpapi_store() -> Result<Box<dyn SecretStore>> {
+    Ok(Box::new(DpapiStore { directory: key_directory()? }))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn dpapi_store() -> Result<Box<dyn SecretStore>> {
+    Err(anyhow::anyhow!("DPAPI not available on this platform"))
+}
+
+#[cfg(target_os = "windows")]
+pub struct DpapiStore {
+    directory: PathBuf,
+}
+
+#[cfg(target_os = "windows")]
+impl SecretStore for DpapiStore {
+    fn name(&self) -> &'static str {
+        "dpapi"
+    }
+
+    fn load_secret(&self, key_name: &str) -> Result<Option<Vec<u8>>> {
+        let key_file = self.directory.join(format!("{}.key", key_name));
+
+        if !key_file.exists() {
+            return Ok(None);
+        }
+
+        let encrypted_data = std::fs::read(key_file)?;
+
+        let encrypted_blob = DATA_BLOB {
+            cbData: encrypted_data.len() as u32,
+            pbData: encrypted_data.as_ptr() as *mut u8,
+        };
+
+        let mut decrypted_blob = DATA_BLOB::default();
+        let result = unsafe {
+            CryptUnprotectData(
+                &encrypted_blob,
+                None,
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut decrypted_blob,
+            )
+        };
+
+        if result.is_err() {
+            return Err(anyhow::anyhow!("Failed to decrypt data with DPAPI"));
+        }
+
+        let decrypted_data = unsafe {
+            std::slice::from_raw_parts(decrypted_blob.pbData, decrypted_blob.cbData as usize)
+        };
+
+        Ok(Some(decrypted_data.to_vec()))
+    }
+
+    fn store_secret(&self, key_name: &str, data: &[u8]) -> Result<()> {
+        let data_blob = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+
+        let description = format!("Dra Bruna Clinic - {}\0", key_name);
+        let mut encrypted_blob = DATA_BLOB::default();
+        let result = unsafe {
+            CryptProtectData(
+                &data_blob,
+                PCSTR::from_raw(description.as_ptr()),
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut encrypted_blob,
+            )
+        };
+
+        if result.is_err() {
+            return Err(anyhow::anyhow!("Failed to encrypt data with DPAPI"));
+        }
+
+        let key_file = self.directory.join(format!("{}.key", key_name));
+        write_key_file(&key_file, unsafe {
+            std::slice::from_raw_parts(encrypted_blob.pbData, encrypted_blob.cbData as usize)
+        })
+    }
+
+    fn delete_secret(&self, key_name: &str) -> Result<()> {
+        let key_file = self.directory.join(format!("{}.key", key_name));
+        match std::fs::remove_file(key_file) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// =====================================================
+// PASSPHRASE-WRAPPED KEY FILE (PORTABLE)
+// =====================================================
+
+const ARGON2_M_COST_KIB: u32 = 64 * 1024;
+const ARGON2_T_COST: u32 = 3;
+const ARGON2_P_COST: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WrappedKeyFile {
+    version: u32,
+    kdf: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+pub struct PassphraseFileStore {
+    directory: PathBuf,
+    passphrase: String,
+}
+
+impl PassphraseFileStore {
+    pub fn new(directory: PathBuf, passphrase: String) -> Result<Self> {
+        if passphrase.len() < 8 {
+            return Err(anyhow::anyhow!("KeyVault passphrase must have at least 8 characters"));
+        }
+        Ok(Self { directory, passphrase })
+    }
+
+    fn path_for(&self, key_name: &str) -> PathBuf {
+        self.directory.join(format!("{}.vault", key_name))
+    }
+
+    fn derive_wrapping_key(&self, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<[u8; 32]> {
+        let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+            .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(key)
+    }
+}
+
+impl SecretStore for PassphraseFileStore {
+    fn name(&self) -> &'static str {
+        "passphrase_file"
+    }
+
+    fn load_secret(&self, key_name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path_for(key_name);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let wrapped: WrappedKeyFile = serde_json::from_slice(&std::fs::read(&path)?)?;
+        if wrapped.version != 1 || wrapped.kdf != "argon2id" {
+            return Err(anyhow::anyhow!("Unsupported key file format"));
+        }
+
+        let salt = general_purpose::STANDARD.decode(&wrapped.salt)?;
+        let nonce_bytes = general_purpose::STANDARD.decode(&wrapped.nonce)?;
+        let ciphertext = general_purpose::STANDARD.decode(&wrapped.ciphertext)?;
+        if nonce_bytes.len() != 12 {
+            return Err(anyhow::anyhow!("Invalid nonce"));
+        }
+
+        let key = self.derive_wrapping_key(&salt, wrapped.m_cost, wrapped.t_cost, wrapped.p_cost)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce_bytes), Payload { msg: &ciphertext, aad: key_name.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to unwrap key (wrong passphrase or corrupted file)"))?;
+
+        Ok(Some(plaintext))
+    }
+
+    fn store_secret(&self, key_name: &str, data: &[u8]) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; 16];
+        let mut nonce_bytes = [0u8; 12];
+        rng.fill(&mut salt);
+        rng.fill(&mut nonce_bytes);
+
+        let key = self.derive_wrapping_key(&salt, ARGON2_M_COST_KIB, ARGON2_T_COST, ARGON2_P_COST)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), Payload { msg: data, aad: key_name.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to wrap key"))?;
+
+        let wrapped = WrappedKeyFile {
+            version: 1,
+            kdf: "argon2id".to_string(),
+            m_cost: ARGON2_M_COST_KIB,
+            t_cost: ARGON2_T_COST,
+            p_cost: ARGON2_P_COST,
+            salt: general_purpose::STANDARD.encode(salt),
+            nonce: general_purpose::STANDARD.encode(nonce_bytes),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        };
+
+        write_key_file(&self.path_for(key_name), &serde_json::to_vec_pretty(&wrapped)?)
+    }
+
+    fn delete_secret(&self, key_name: &str) -> Result<()> {
+        match std::fs::remove_file(self.path_for(key_name)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+// =====================================================
+// OS KEYRING / SECRET SERVICE (OPTIONAL)
+// =====================================================
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "DraBrunaClinic";
+
+#[cfg(feature = "keyring")]
+fn keyring_store() -> Result<Box<dyn SecretStore>> {
+    Ok(Box::new(KeyringStore))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_store() -> Result<Box<dyn SecretStore>> {
+    Err(anyhow::anyhow!("Keyring backend not enabled in this build (feature \"keyring\")"))
+}
+
+#[cfg(feature = "keyring")]
+pub struct KeyringStore;
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn load_secret(&self, key_name: &str) -> Result<Option<Vec<u8>>> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, key_name)?;
+        match entry.get_password() {
+            Ok(encoded) => Ok(Some(general_purpose::STANDARD.decode(encoded)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store_secret(&self, key_name: &str, data: &[u8]) -> Result<()> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, key_name)?;
+        entry.set_password(&general_purpose::STANDARD.encode(data))?;
+        Ok(())
+    }
+
+    fn delete_secret(&self, key_name: &str) -> Result<()> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, key_name)?;
+        match entry.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_directory() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dbss-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn passphrase_file_round_trip() {
+        let dir = temp_directory();
+        let store = PassphraseFileStore::new(dir.clone(), "correct horse battery".to_string()).unwrap();
+        assert_eq!(store.name(), "passphrase_file");
+        assert!(store.load_secret("master").unwrap().is_none());
+
+        store.store_secret("master", b"0123456789abcdef").unwrap();
+        let reopened = PassphraseFileStore::new(dir.clone(), "correct horse battery".to_string()).unwrap();
+        assert_eq!(reopened.load_secret("master").unwrap().unwrap(), b"0123456789abcdef");
+
+        // O nome do segredo entra no AAD: um arquivo renomeado não abre
+        std::fs::copy(store.path_for("master"), store.path_for("other")).unwrap();
+        assert!(reopened.load_secret("other").is_err());
+
+        reopened.delete_secret("master").unwrap();
+        assert!(reopened.load_secret("master").unwrap().is_none());
+        reopened.delete_secret("master").unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn wrong_passphrase_is_an_error_not_a_missing_secret() {
+        let dir = temp_directory();
+        let store = PassphraseFileStore::new(dir.clone(), "correct horse battery".to_string()).unwrap();
+        store.store_secret("master", b"0123456789abcdef").unwrap();
+
+        let wrong = PassphraseFileStore::new(dir.clone(), "incorrect horse".to_string()).unwrap();
+        let err = wrong.load_secret("master").unwrap_err();
+        assert!(err.to_string().contains("wrong passphrase"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_short_passphrases() {
+        assert!(PassphraseFileStore::new(temp_directory(), "short".to_string()).is_err());
+    }
+}
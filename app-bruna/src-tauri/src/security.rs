@@ -121,6 +121,9 @@ pub async fn initialize_security_monitoring() -> Result<()> {
 // SECURITY EVENT LOGGING
 // =====================================================
 
+// Value of the "kind" detail on events for a rejected password or code
+pub const CREDENTIAL_FAILURE: &str = "credential_failure";
+
 pub async fn log_security_event(
     level: SecurityLevel,
     category: SecurityCategory,
@@ -471,10 +474,13 @@ async fn detect_threats() -> Result<()> {
 }
 
 async fn detect_brute_force_attack(events: &[&SecurityEvent]) -> Result<bool> {
+    // Only rejected credentials count; the detector's own Critical events and
+    // lockout notices would otherwise keep re-triggering it. Locking the
+    // workstation itself is left to login_throttle.
     let failed_logins = events
         .iter()
-        .filter(|e| matches!(e.category, SecurityCategory::Authentication) && 
-                   matches!(e.level, SecurityLevel::Error | SecurityLevel::Critical))
+        .filter(|e| matches!(e.category, SecurityCategory::Authentication) &&
+                   e.details.get("kind").and_then(|k| k.as_str()) == Some(CREDENTIAL_FAILURE))
         .count();
     
     Ok(failed_logins > 10) // More than 10 failed logins in 1 hour
@@ -0,0 +1,831 @@
+use anyhow::Result;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Utc};
+
+// =====================================================
+// SUPABASE STORAGE SYNC (encrypted document blobs)
+// =====================================================
+
+// Sincroniza com o bucket do Supabase Storage os arquivos de documentos já
+// cifrados localmente (formato segmentado do document_crypto). Nada em texto
+// claro sai da máquina: add_file e download_file recusam qualquer arquivo que
+// não tenha o cabeçalho do formato cifrado.
+//
+// - Upload resumível pelo protocolo TUS do Supabase (offset salvo em disco)
+// - Arquivos cujo hash não mudou desde a última sincronização são pulados
+// - Conflito quando o arquivo local e o remoto mudaram desde a última sincronização
+//
+// Há um único StorageSync, atrás de um lock assíncrono, com o estado gravado no
+// perfil ativo: dois comandos ao mesmo tempo não sobrescrevem o estado um do
+// outro, e a fila de um usuário não aparece para o próximo.
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_CHUNK_SIZE: usize = 6 * 1024 * 1024; // Tamanho exigido pelo Supabase
+const REMOTE_PREFIX: &str = "documents";
+const MAX_ATTEMPTS: u32 = 5;
+const LIST_PAGE_SIZE: usize = 1000; // Máximo por página na listagem do Storage
+const STATE_FILE: &str = "storage_sync";
+
+// Token do usuário logado; as políticas do bucket dependem dele
+static ACCESS_TOKEN: LazyLock<Mutex<Option<String>>> = LazyLock::new(|| Mutex::new(None));
+
+pub fn set_access_token(token: Option<String>) {
+    *ACCESS_TOKEN.lock().unwrap() = token;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FileSyncStatus {
+    Pending,
+    Syncing,
+    Synced,
+    Failed,
+    Conflict,
+    LocalOnly,  // Existe só aqui (ex.: removido do servidor)
+    RemoteOnly, // Existe só no servidor; baixar com download_file
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+    pub id: String,
+    pub local_path: String,
+    pub remote_path: String,
+    pub file_name: String,
+    pub size: u64,
+    pub sha256: Option<String>,           // Hash do arquivo cifrado local
+    pub last_synced_hash: Option<String>, // Hash na última sincronização bem-sucedida
+    pub remote_etag: Option<String>,      // ETag remoto na última sincronização
+    pub status: FileSyncStatus,
+    pub uploaded_by: Option<String>,
+    pub upload_url: Option<String>, // Upload TUS em andamento
+    pub upload_offset: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageSyncStats {
+    pub total_files: usize,
+    pub pending: usize,
+    pub syncing: usize,
+    pub synced: usize,
+    pub failed: usize,
+    pub conflicts: usize,
+    pub local_only: usize,
+    pub remote_only: usize,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub skipped_unchanged: usize,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+    pub last_sync_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StorageSyncState {
+    files: HashMap<String, FileMetadata>,
+    stats: StorageSyncStats,
+}
+
+#[derive(Debug, Clone)]
+pub struct StorageConfig {
+    pub url: String,
+    pub anon_key: String,
+    pub bucket: String,
+}
+
+#[derive(Debug, Clone)]
+struct RemoteObject {
+    etag: Option<String>,
+}
+
+pub struct StorageSync {
+    config: StorageConfig,
+    client: reqwest::Client,
+    state: StorageSyncState,
+    profile_id: Option<String>, // Perfil de onde o estado foi carregado
+}
+
+static STORAGE_SYNC: LazyLock<tokio::sync::Mutex<StorageSync>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(StorageSync::with_default_config()));
+
+// O StorageSync compartilhado, com o estado do perfil ativo. O lock fica com
+// quem chamou até o fim da operação (uploads inclusive).
+pub async fn shared() -> Result<tokio::sync::MutexGuard<'static, StorageSync>> {
+    let mut sync = STORAGE_SYNC.lock().await;
+    sync.load_for_active_profile()?;
+    Ok(sync)
+}
+
+// O que sync_file faz com um arquivo
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SyncAction {
+    Upload,
+    Download,
+    Unchanged,
+    Mark(FileSyncStatus),
+}
+
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Nome de objeto remoto que pode virar nome de arquivo local com segurança
+fn is_safe_object_name(name: &str) -> bool {
+    !name.contains(['/', '\\', ':', '\0']) && name != "." && !name.contains("..")
+}
+
+fn ensure_ciphertext(path: &Path) -> Result<()> {
+    if !crate::document_crypto::is_encrypted_blob(path)? {
+        return Err(anyhow::anyhow!(
+            "Refusing to sync {}: only encrypted document blobs can leave this machine",
+            path.display()
+        ));
+    }
+    Ok(())
+}
+
+// Decide pelo hash local atual (None = não existe aqui) e pelo objeto remoto
+// (None = não existe no servidor), comparando com a última sincronização
+fn decide(file: &FileMetadata, local_hash: Option<&str>, remote: Option<&RemoteObject>) -> Result<SyncAction> {
+    let local_changed = local_hash.is_some() && local_hash != file.last_synced_hash.as_deref();
+    let remote_changed = match (remote, &file.remote_etag) {
+        (Some(r), Some(etag)) => r.etag.as_ref() != Some(etag),
+        (Some(_), None) => file.last_synced_hash.is_some(),
+        (None, _) => false,
+    };
+
+    Ok(match (local_hash, remote) {
+        (None, Some(_)) => SyncAction::Mark(FileSyncStatus::RemoteOnly),
+        (None, None) => return Err(anyhow::anyhow!("File missing locally and remotely")),
+        // Já foi enviado antes e sumiu do servidor
+        (Some(_), None) if file.remote_etag.is_some() && !local_changed => SyncAction::Mark(FileSyncStatus::LocalOnly),
+        (Some(_), None) => SyncAction::Upload,
+        // Objeto remoto que nunca foi reconciliado com o arquivo local
+        (Some(_), Some(_)) if file.last_synced_hash.is_none() => SyncAction::Mark(FileSyncStatus::Conflict),
+        (Some(_), Some(_)) if local_changed && remote_changed => SyncAction::Mark(FileSyncStatus::Conflict),
+        (Some(_), Some(_)) if local_changed => SyncAction::Upload,
+        (Some(_), Some(_)) if remote_changed => SyncAction::Download,
+        // Nada mudou dos dois lados: pula sem transferir
+        (Some(_), Some(_)) => SyncAction::Unchanged,
+    })
+}
+
+impl StorageSync {
+    pub fn new(config: StorageConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            state: StorageSyncState::default(),
+            profile_id: None,
+        }
+    }
+
+    pub fn with_default_config() -> Self {
+        let config = match crate::config::get_config() {
+            Ok(c) => StorageConfig {
+                url: c.supabase.url.clone(),
+                anon_key: c.supabase.anon_key.clone(),
+                bucket: c.storage_bucket.clone(),
+            },
+            Err(_) => StorageConfig {
+                url: std::env::var("SUPABASE_URL").unwrap_or_default(),
+                anon_key: std::env::var("SUPABASE_ANON_KEY").unwrap_or_default(),
+                bucket: std::env::var("SUPABASE_STORAGE_BUCKET").unwrap_or_else(|_| "documents".to_string()),
+            },
+        };
+
+        Self::new(config)
+    }
+
+    // Troca o estado em memória pelo do perfil ativo quando o usuário mudou
+    fn load_for_active_profile(&mut self) -> Result<()> {
+        let profile = crate::profiles::active_profile().ok_or_else(|| anyhow::anyhow!("No active profile"))?;
+        if self.profile_id.as_deref() != Some(profile.id.as_str()) {
+            self.state = crate::profiles::read_profile_file(STATE_FILE)?.unwrap_or_default();
+            self.profile_id = Some(profile.id);
+        }
+        Ok(())
+    }
+
+    // Grava no perfil de onde o estado veio; se o perfil ativo mudou no meio da
+    // operação, nada é gravado no perfil do outro usuário
+    fn save(&self) -> Result<()> {
+        let active = crate::profiles::active_profile().map(|p| p.id);
+        if active.is_none() || active != self.profile_id {
+            return Err(anyhow::anyhow!("Active profile changed during storage sync"));
+        }
+        crate::profiles::write_profile_file(STATE_FILE, &self.state)
+    }
+
+    fn refresh_stats(&mut self) {
+        let stats = &mut self.state.stats;
+        let files = self.state.files.values();
+        stats.total_files = self.state.files.len();
+        stats.pending = 0;
+        stats.syncing = 0;
+        stats.synced = 0;
+        stats.failed = 0;
+        stats.conflicts = 0;
+        stats.local_only = 0;
+        stats.remote_only = 0;
+        for file in files {
+            match file.status {
+                FileSyncStatus::Pending => stats.pending += 1,
+                FileSyncStatus::Syncing => stats.syncing += 1,
+                FileSyncStatus::Synced => stats.synced += 1,
+                FileSyncStatus::Failed => stats.failed += 1,
+                FileSyncStatus::Conflict => stats.conflicts += 1,
+                FileSyncStatus::LocalOnly => stats.local_only += 1,
+                FileSyncStatus::RemoteOnly => stats.remote_only += 1,
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> &StorageSyncStats {
+        &self.state.stats
+    }
+
+    pub fn get_files_by_status(&self, status: FileSyncStatus) -> Vec<&FileMetadata> {
+        let mut files: Vec<&FileMetadata> = self.state.files
+            .values()
+            .filter(|f| f.status == status)
+            .collect();
+        files.sort_by_key(|f| f.created_at);
+        files
+    }
+
+    // =====================================================
+    // PUBLIC OPERATIONS
+    // =====================================================
+
+    pub async fn add_file(&mut self, file_path: &str, remote_path: Option<String>, uploaded_by: Option<String>) -> Result<String> {
+        let path = PathBuf::from(file_path);
+        ensure_ciphertext(&path)?;
+
+        let file_name = path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("Invalid file path: {}", file_path))?;
+        let remote_path = remote_path.unwrap_or_else(|| format!("{}/{}", REMOTE_PREFIX, file_name));
+
+        // O mesmo objeto remoto não é rastreado duas vezes
+        if let Some(existing) = self.state.files.values().find(|f| f.remote_path == remote_path) {
+            return Ok(existing.id.clone());
+        }
+
+        let now = Utc::now();
+        let id = uuid::Uuid::new_v4().to_string();
+        self.state.files.insert(id.clone(), FileMetadata {
+            id: id.clone(),
+            local_path: file_path.to_string(),
+            remote_path,
+            file_name,
+            size: std::fs::metadata(&path)?.len(),
+            sha256: Some(file_sha256(&path)?),
+            last_synced_hash: None,
+            remote_etag: None,
+            status: FileSyncStatus::Pending,
+            uploaded_by,
+            upload_url: None,
+            upload_offset: 0,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            last_synced_at: None,
+        });
+        self.refresh_stats();
+        self.save()?;
+        Ok(id)
+    }
+
+    pub async fn sync_all_files(&mut self) -> Result<StorageSyncStats> {
+        // Descobre objetos que existem só no servidor
+        if let Err(e) = self.discover_remote_files().await {
+            eprintln!("Failed to list remote files: {}", e);
+        }
+
+        let ids: Vec<String> = self.state.files
+            .values()
+            .filter(|f| f.status != FileSyncStatus::RemoteOnly && f.status != FileSyncStatus::Conflict)
+            .filter(|f| f.status != FileSyncStatus::Failed || f.attempts < MAX_ATTEMPTS)
+            .map(|f| f.id.clone())
+            .collect();
+
+        for id in ids {
+            if let Err(e) = self.sync_file(&id).await {
+                if let Some(file) = self.state.files.get_mut(&id) {
+                    file.status = FileSyncStatus::Failed;
+                    file.attempts += 1;
+                    file.last_error = Some(e.to_string());
+                    file.updated_at = Utc::now();
+                }
+            }
+            self.save()?;
+        }
+
+        self.state.stats.last_sync_at = Some(Utc::now());
+        self.refresh_stats();
+        self.save()?;
+        Ok(self.state.stats.clone())
+    }
+
+    pub async fn download_file(&mut self, file_id: &str) -> Result<()> {
+        let file = self.state.files.get(file_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("File not tracked: {}", file_id))?;
+
+        let bytes = self.download_to(&file.remote_path, Path::new(&file.local_path)).await?;
+        let remote = self.remote_object(&file.remote_path).await?;
+        let hash = file_sha256(Path::new(&file.local_path))?;
+
+        if let Some(entry) = self.state.files.get_mut(file_id) {
+            entry.size = bytes;
+            entry.sha256 = Some(hash.clone());
+            entry.last_synced_hash = Some(hash);
+            entry.remote_etag = remote.and_then(|r| r.etag);
+            entry.status = FileSyncStatus::Synced;
+            entry.last_error = None;
+            entry.attempts = 0;
+            entry.updated_at = Utc::now();
+            entry.last_synced_at = Some(Utc::now());
+        }
+        self.state.stats.downloaded += 1;
+        self.state.stats.bytes_downloaded += bytes;
+        self.refresh_stats();
+        self.save()
+    }
+
+    // Para de sincronizar o arquivo (o objeto remoto é mantido)
+    pub async fn remove_file(&mut self, file_id: &str) -> Result<()> {
+        let file = self.state.files.remove(file_id)
+            .ok_or_else(|| anyhow::anyhow!("File not tracked: {}", file_id))?;
+
+        if let Some(upload_url) = file.upload_url {
+            let _ = self.client
+                .delete(&upload_url)
+                .header("Tus-Resumable", TUS_VERSION)
+                .header("apikey", &self.config.anon_key)
+                .bearer_auth(self.bearer_token())
+                .send()
+                .await;
+        }
+
+        self.refresh_stats();
+        self.save()
+    }
+
+    // Resolve um conflito mantendo a versão local (reenvia) ou a remota (baixa)
+    pub async fn resolve_conflict(&mut self, file_id: &str, keep_local: bool) -> Result<()> {
+        let file = self.state.files.get(file_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("File not tracked: {}", file_id))?;
+        if file.status != FileSyncStatus::Conflict {
+            return Err(anyhow::anyhow!("File is not in conflict"));
+        }
+
+        if keep_local {
+            self.upload(file_id).await?;
+        } else {
+            self.download_file(file_id).await?;
+        }
+        self.refresh_stats();
+        self.save()
+    }
+
+    // =====================================================
+    // SYNC DECISION
+    // =====================================================
+
+    async fn sync_file(&mut self, file_id: &str) -> Result<()> {
+        let file = self.state.files.get(file_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("File not tracked: {}", file_id))?;
+        let local_path = PathBuf::from(&file.local_path);
+
+        let local_hash = if local_path.exists() {
+            ensure_ciphertext(&local_path)?;
+            Some(file_sha256(&local_path)?)
+        } else {
+            None
+        };
+        let remote = self.remote_object(&file.remote_path).await?;
+
+        let status = match decide(&file, local_hash.as_deref(), remote.as_ref())? {
+            SyncAction::Upload => return self.upload(file_id).await,
+            SyncAction::Download => return self.download_file(file_id).await,
+            SyncAction::Unchanged => {
+                self.state.stats.skipped_unchanged += 1;
+                FileSyncStatus::Synced
+            }
+            SyncAction::Mark(status) => status,
+        };
+
+        let entry = self.state.files.get_mut(file_id).unwrap();
+        entry.sha256 = local_hash;
+        entry.status = status;
+        entry.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn discover_remote_files(&mut self) -> Result<()> {
+        let objects = self.list_remote_objects().await?;
+        let download_dir = crate::profiles::active_profile_dir()?.join("storage");
+        let now = Utc::now();
+
+        for object in objects {
+            let name = match object.get("name").and_then(|n| n.as_str()) {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => continue,
+            };
+            // Entradas sem id são "pastas"
+            if object.get("id").is_none_or(|id| id.is_null()) {
+                continue;
+            }
+            // O nome vira caminho local: nada de sair da pasta de downloads
+            if !is_safe_object_name(&name) {
+                eprintln!("Skipping remote object with unsafe name: {}", name);
+                continue;
+            }
+            let remote_path = format!("{}/{}", REMOTE_PREFIX, name);
+            if self.state.files.values().any(|f| f.remote_path == remote_path) {
+                continue;
+            }
+
+            let size = object.get("metadata")
+                .and_then(|m| m.get("size"))
+                .and_then(|s| s.as_u64())
+                .unwrap_or(0);
+            let id = uuid::Uuid::new_v4().to_string();
+            self.state.files.insert(id.clone(), FileMetadata {
+                id,
+                local_path: download_dir.join(&name).to_string_lossy().to_string(),
+                remote_path,
+                file_name: name,
+                size,
+                sha256: None,
+                last_synced_hash: None,
+                remote_etag: None,
+                status: FileSyncStatus::RemoteOnly,
+                uploaded_by: None,
+                upload_url: None,
+                upload_offset: 0,
+                attempts: 0,
+                last_error: None,
+                created_at: now,
+                updated_at: now,
+                last_synced_at: None,
+            });
+        }
+        Ok(())
+    }
+
+    // =====================================================
+    // HTTP (Supabase Storage)
+    // =====================================================
+
+    // Lista todos os objetos do prefixo, página a página, até uma página vir
+    // incompleta
+    async fn list_remote_objects(&self) -> Result<Vec<serde_json::Value>> {
+        let url = format!("{}/storage/v1/object/list/{}", self.config.url, self.config.bucket);
+        let mut objects = Vec::new();
+
+        loop {
+            let response = self.client
+                .post(&url)
+                .header("apikey", &self.config.anon_key)
+                .bearer_auth(self.bearer_token())
+                .json(&serde_json::json!({ "prefix": REMOTE_PREFIX, "limit": LIST_PAGE_SIZE, "offset": objects.len() }))
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(anyhow::anyhow!("Storage list failed: HTTP {} - {}", status, error_text));
+            }
+
+            let page: Vec<serde_json::Value> = response.json().await?;
+            let page_len = page.len();
+            objects.extend(page);
+            if page_len < LIST_PAGE_SIZE {
+                return Ok(objects);
+            }
+        }
+    }
+
+    fn bearer_token(&self) -> String {
+        ACCESS_TOKEN.lock().unwrap().clone().unwrap_or_else(|| self.config.anon_key.clone())
+    }
+
+    fn object_url(&self, remote_path: &str) -> String {
+        format!("{}/storage/v1/object/{}/{}", self.config.url, self.config.bucket, remote_path)
+    }
+
+    async fn remote_object(&self, remote_path: &str) -> Result<Option<RemoteObject>> {
+        let response = self.client
+            .head(self.object_url(remote_path))
+            .header("apikey", &self.config.anon_key)
+            .bearer_auth(self.bearer_token())
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND || response.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Storage lookup failed: HTTP {}", response.status()));
+        }
+
+        let headers = response.headers();
+        Ok(Some(RemoteObject {
+            etag: headers.get("etag").and_then(|v| v.to_str().ok()).map(|v| v.trim_matches('"').to_string()),
+        }))
+    }
+
+    // Upload resumível (TUS). O offset é salvo após cada bloco, então uma queda de
+    // conexão ou do app continua do ponto em que parou.
+    async fn upload(&mut self, file_id: &str) -> Result<()> {
+        let file = self.state.files.get(file_id).cloned()
+            .ok_or_else(|| anyhow::anyhow!("File not tracked: {}", file_id))?;
+        let local_path = PathBuf::from(&file.local_path);
+        ensure_ciphertext(&local_path)?;
+
+        let size = std::fs::metadata(&local_path)?.len();
+        let hash = file_sha256(&local_path)?;
+
+        // Upload anterior só é retomado se o arquivo não mudou desde então
+        let mut upload = match (&file.upload_url, file.sha256.as_ref() == Some(&hash)) {
+            (Some(url), true) => self.upload_offset(url).await?.map(|offset| (url.clone(), offset)),
+            _ => None,
+        };
+        if upload.is_none() {
+            let url = self.create_upload(&file.remote_path, size).await?;
+            upload = Some((url, 0));
+        }
+        let (upload_url, mut offset) = upload.unwrap();
+
+        {
+            let entry = self.state.files.get_mut(file_id).unwrap();
+            entry.status = FileSyncStatus::Syncing;
+            entry.sha256 = Some(hash.clone());
+            entry.size = size;
+            entry.upload_url = Some(upload_url.clone());
+            entry.upload_offset = offset;
+        }
+        self.save()?;
+
+        let mut reader = std::fs::File::open(&local_path)?;
+        let mut chunk = vec![0u8; TUS_CHUNK_SIZE];
+        while offset < size {
+            reader.seek(SeekFrom::Start(offset))?;
+            let to_read = ((size - offset) as usize).min(TUS_CHUNK_SIZE);
+            reader.read_exact(&mut chunk[..to_read])?;
+
+            let response = self.client
+                .patch(&upload_url)
+                .header("Tus-Resumable", TUS_VERSION)
+                .header("Upload-Offset", offset.to_string())
+                .header("Content-Type", "application/offset+octet-stream")
+                .header("apikey", &self.config.anon_key)
+                .bearer_auth(self.bearer_token())
+                .body(chunk[..to_read].to_vec())
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(anyhow::anyhow!("Upload failed at offset {}: HTTP {} - {}", offset, status, error_text));
+            }
+
+            offset = response.headers()
+                .get("upload-offset")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(offset + to_read as u64);
+            self.state.stats.bytes_uploaded += to_read as u64;
+            self.state.files.get_mut(file_id).unwrap().upload_offset = offset;
+            self.save()?;
+        }
+
+        let remote = self.remote_object(&file.remote_path).await?;
+        let entry = self.state.files.get_mut(file_id).unwrap();
+        entry.status = FileSyncStatus::Synced;
+        entry.last_synced_hash = Some(hash);
+        entry.remote_etag = remote.and_then(|r| r.etag);
+        entry.upload_url = None;
+        entry.upload_offset = 0;
+        entry.attempts = 0;
+        entry.last_error = None;
+        entry.updated_at = Utc::now();
+        entry.last_synced_at = Some(Utc::now());
+        self.state.stats.uploaded += 1;
+        self.save()
+    }
+
+    async fn create_upload(&self, remote_path: &str, size: u64) -> Result<String> {
+        let metadata = [
+            ("bucketName", self.config.bucket.as_str()),
+            ("objectName", remote_path),
+            ("contentType", "application/octet-stream"),
+            ("cacheControl", "3600"),
+        ]
+        .iter()
+        .map(|(k, v)| format!("{} {}", k, general_purpose::STANDARD.encode(v)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+        let response = self.client
+            .post(format!("{}/storage/v1/upload/resumable", self.config.url))
+            .header("Tus-Resumable", TUS_VERSION)
+            .header("Upload-Length", size.to_string())
+            .header("Upload-Metadata", metadata)
+            .header("x-upsert", "true")
+            .header("apikey", &self.config.anon_key)
+            .bearer_auth(self.bearer_token())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to create upload: HTTP {} - {}", status, error_text));
+        }
+
+        let location = response.headers()
+            .get("location")
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("Upload server did not return a location"))?;
+        Ok(if location.starts_with("http") {
+            location.to_string()
+        } else {
+            format!("{}{}", self.config.url, location)
+        })
+    }
+
+    // Offset confirmado pelo servidor; None se o upload expirou
+    async fn upload_offset(&self, upload_url: &str) -> Result<Option<u64>> {
+        let response = self.client
+            .head(upload_url)
+            .header("Tus-Resumable", TUS_VERSION)
+            .header("apikey", &self.config.anon_key)
+            .bearer_auth(self.bearer_token())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        Ok(response.headers()
+            .get("upload-offset")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()))
+    }
+
+    // Baixa em blocos para um arquivo temporário, confere o formato cifrado e só
+    // então substitui o arquivo local
+    async fn download_to(&self, remote_path: &str, destination: &Path) -> Result<u64> {
+        let url = format!("{}/storage/v1/object/authenticated/{}/{}", self.config.url, self.config.bucket, remote_path);
+        let mut response = self.client
+            .get(&url)
+            .header("apikey", &self.config.anon_key)
+            .bearer_auth(self.bearer_token())
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Download failed: HTTP {} - {}", status, error_text));
+        }
+
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = destination.with_extension("download");
+        let mut file = std::fs::File::create(&tmp_path)?;
+        let mut total: u64 = 0;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk)?;
+            total += chunk.len() as u64;
+        }
+        file.sync_all()?;
+        drop(file);
+
+        if let Err(e) = ensure_ciphertext(&tmp_path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        std::fs::rename(&tmp_path, destination)?;
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::test_support::activate_test_profile;
+
+    fn tracked(last_synced_hash: Option<&str>, remote_etag: Option<&str>) -> FileMetadata {
+        let now = Utc::now();
+        FileMetadata {
+            id: "file-1".to_string(),
+            local_path: "/tmp/doc.dbse".to_string(),
+            remote_path: "documents/doc.dbse".to_string(),
+            file_name: "doc.dbse".to_string(),
+            size: 0,
+            sha256: last_synced_hash.map(str::to_string),
+            last_synced_hash: last_synced_hash.map(str::to_string),
+            remote_etag: remote_etag.map(str::to_string),
+            status: FileSyncStatus::Synced,
+            uploaded_by: None,
+            upload_url: None,
+            upload_offset: 0,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+            last_synced_at: None,
+        }
+    }
+
+    fn remote(etag: &str) -> RemoteObject {
+        RemoteObject { etag: Some(etag.to_string()) }
+    }
+
+    #[test]
+    fn uploads_local_only_files_and_offers_remote_only_ones() {
+        let new_file = tracked(None, None);
+        assert_eq!(decide(&new_file, Some("h1"), None).unwrap(), SyncAction::Upload);
+        assert_eq!(decide(&new_file, None, Some(&remote("e1"))).unwrap(), SyncAction::Mark(FileSyncStatus::RemoteOnly));
+        assert!(decide(&new_file, None, None).is_err());
+    }
+
+    #[test]
+    fn transfers_only_the_side_that_changed() {
+        let synced = tracked(Some("h1"), Some("e1"));
+        assert_eq!(decide(&synced, Some("h1"), Some(&remote("e1"))).unwrap(), SyncAction::Unchanged);
+        assert_eq!(decide(&synced, Some("h2"), Some(&remote("e1"))).unwrap(), SyncAction::Upload);
+        assert_eq!(decide(&synced, Some("h1"), Some(&remote("e2"))).unwrap(), SyncAction::Download);
+    }
+
+    #[test]
+    fn both_sides_changed_is_a_conflict() {
+        let synced = tracked(Some("h1"), Some("e1"));
+        assert_eq!(decide(&synced, Some("h2"), Some(&remote("e2"))).unwrap(), SyncAction::Mark(FileSyncStatus::Conflict));
+        // Objeto remoto que nunca foi reconciliado com o arquivo local
+        let never_synced = tracked(None, None);
+        assert_eq!(decide(&never_synced, Some("h1"), Some(&remote("e1"))).unwrap(), SyncAction::Mark(FileSyncStatus::Conflict));
+    }
+
+    #[test]
+    fn deletions_after_a_sync_are_not_undone() {
+        let synced = tracked(Some("h1"), Some("e1"));
+        // Removido do servidor e intacto aqui: não reenvia
+        assert_eq!(decide(&synced, Some("h1"), None).unwrap(), SyncAction::Mark(FileSyncStatus::LocalOnly));
+        // Removido do servidor mas alterado aqui: a alteração local sobe
+        assert_eq!(decide(&synced, Some("h2"), None).unwrap(), SyncAction::Upload);
+        // Apagado aqui: fica só no servidor, sem baixar sozinho
+        assert_eq!(decide(&synced, None, Some(&remote("e1"))).unwrap(), SyncAction::Mark(FileSyncStatus::RemoteOnly));
+    }
+
+    #[tokio::test]
+    #[cfg_attr(not(target_os = "linux"), ignore = "needs an isolated data dir")]
+    async fn state_belongs_to_the_active_profile() {
+        {
+            let profile = activate_test_profile("storage-sync-a");
+            let mut sync = shared().await.unwrap();
+            assert_eq!(sync.profile_id.as_deref(), Some(profile.profile.id.as_str()));
+            sync.state.files.insert("file-1".to_string(), tracked(Some("h1"), Some("e1")));
+            sync.save().unwrap();
+        }
+
+        {
+            let _profile = activate_test_profile("storage-sync-b");
+            let mut sync = shared().await.unwrap();
+            assert!(sync.state.files.is_empty());
+            // Perfil trocado no meio da operação: não grava no perfil de outro
+            sync.profile_id = Some("another-profile".to_string());
+            assert!(sync.save().is_err());
+        }
+
+        let _profile = activate_test_profile("storage-sync-a");
+        assert!(shared().await.unwrap().state.files.contains_key("file-1"));
+    }
+}
@@ -10,8 +10,13 @@ pub struct Patient {
     pub email: Option<String>,
     pub phone: Option<String>,
     pub birth_date: Option<String>,
-    pub address: Option<String>,
+    #[serde(default, deserialize_with = "crate::patients::lenient_address")]
+    pub address: Option<crate::patients::Address>,
     pub notes: Option<String>,
+    #[serde(default)]
+    pub cpf: Option<String>,
+    #[serde(default)]
+    pub rg: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub user_id: String,
@@ -0,0 +1,433 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use rand::Rng;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+
+use crate::keyvault::{entity_aad, with_shared_vault};
+
+// =====================================================
+// TOTP TWO-FACTOR AUTHENTICATION (RFC 6238)
+// =====================================================
+
+pub const TOTP_ISSUER: &str = "Dra Bruna Clinic";
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_PERIOD_SECONDS: u64 = 30;
+pub const TOTP_SKEW_STEPS: i64 = 1; // Aceita um passo antes/depois (relógio dessincronizado)
+const TOTP_SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const SECRET_PURPOSE: &str = "totp_secret";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpEnrollment {
+    pub user_id: String,
+    pub email: String,
+    pub encrypted_secret: String,
+    pub confirmed: bool,
+    pub recovery_code_hashes: Vec<String>,
+    pub last_used_step: Option<u64>, // Impede reutilização do mesmo código
+    pub created_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpSetup {
+    pub secret: String, // Base32, para digitação manual
+    pub provisioning_uri: String,
+    pub qr_payload: String, // Conteúdo a ser renderizado como QR code no frontend
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotpStatus {
+    pub enabled: bool,
+    pub pending_confirmation: bool,
+    pub recovery_codes_remaining: usize,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SecondFactorMethod {
+    Totp,
+    RecoveryCode,
+}
+
+pub struct TotpService {
+    enrollments: HashMap<String, TotpEnrollment>,
+    store_path: PathBuf,
+}
+
+pub static TOTP_SERVICE: LazyLock<Mutex<Option<TotpService>>> =
+    LazyLock::new(|| Mutex::new(None));
+
+impl TotpService {
+    pub fn new() -> Result<Self> {
+        let app_data = dirs::data_dir()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get app data directory"))?
+            .join("DraBrunaClinic");
+        std::fs::create_dir_all(&app_data)?;
+
+        let store_path = app_data.join("mfa_enrollments.json");
+        let enrollments = if store_path.exists() {
+            let json = std::fs::read_to_string(&store_path)?;
+            serde_json::from_str(&json)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            enrollments,
+            store_path,
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.enrollments)?;
+        crate::profiles::write_atomic(&self.store_path, &json)
+    }
+
+    pub fn is_enabled(&self, user_id: &str) -> bool {
+        self.enrollments
+            .get(user_id)
+            .is_some_and(|e| e.confirmed)
+    }
+
+    pub fn status(&self, user_id: &str) -> TotpStatus {
+        match self.enrollments.get(user_id) {
+            Some(e) => TotpStatus {
+                enabled: e.confirmed,
+                pending_confirmation: !e.confirmed,
+                recovery_codes_remaining: e.recovery_code_hashes.len(),
+                confirmed_at: e.confirmed_at,
+            },
+            None => TotpStatus {
+                enabled: false,
+                pending_confirmation: false,
+                recovery_codes_remaining: 0,
+                confirmed_at: None,
+            },
+        }
+    }
+
+    // Gera um novo segredo. A inscrição só passa a valer após confirm_enrollment.
+    pub fn begin_enrollment(&mut self, user_id: &str, email: &str) -> Result<TotpSetup> {
+        if self.is_enabled(user_id) {
+            return Err(anyhow::anyhow!("TOTP already enabled for this user"));
+        }
+
+        let mut secret = [0u8; TOTP_SECRET_BYTES];
+        rand::thread_rng().fill(&mut secret);
+
+        let encrypted_secret = with_shared_vault(|vault| {
+            vault.encrypt_with_aad(&secret, SECRET_PURPOSE, &entity_aad("user", user_id))
+        })?;
+        self.enrollments.insert(user_id.to_string(), TotpEnrollment {
+            user_id: user_id.to_string(),
+            email: email.to_string(),
+            encrypted_secret,
+            confirmed: false,
+            recovery_code_hashes: Vec::new(),
+            last_used_step: None,
+            created_at: Utc::now(),
+            confirmed_at: None,
+        });
+        self.save()?;
+
+        let secret_b32 = base32_encode(&secret);
+        let uri = provisioning_uri(&secret_b32, email, TOTP_ISSUER);
+        Ok(TotpSetup {
+            secret: secret_b32,
+            qr_payload: uri.clone(),
+            provisioning_uri: uri,
+        })
+    }
+
+    // Confirma a inscrição com um código válido e devolve os códigos de recuperação
+    // em texto claro (exibidos uma única vez).
+    pub fn confirm_enrollment(&mut self, user_id: &str, code: &str) -> Result<Vec<String>> {
+        let secret = self.load_secret(user_id)?;
+        let enrollment = self.enrollments
+            .get_mut(user_id)
+            .ok_or_else(|| anyhow::anyhow!("No pending TOTP enrollment"))?;
+
+        if enrollment.confirmed {
+            return Err(anyhow::anyhow!("TOTP already enabled for this user"));
+        }
+
+        let step = verify_totp(&secret, code, unix_now(), None)
+            .ok_or_else(|| anyhow::anyhow!("Invalid TOTP code"))?;
+
+        let (codes, hashes) = generate_recovery_codes()?;
+        enrollment.confirmed = true;
+        enrollment.confirmed_at = Some(Utc::now());
+        enrollment.last_used_step = Some(step);
+        enrollment.recovery_code_hashes = hashes;
+        self.save()?;
+
+        Ok(codes)
+    }
+
+    // Verifica o segundo fator: primeiro como TOTP, depois como código de recuperação.
+    // Códigos de recuperação são consumidos no primeiro uso.
+    pub fn verify(&mut self, user_id: &str, code: &str) -> Result<Option<SecondFactorMethod>> {
+        let secret = self.load_secret(user_id)?;
+        let enrollment = self.enrollments
+            .get_mut(user_id)
+            .ok_or_else(|| anyhow::anyhow!("TOTP not enabled for this user"))?;
+
+        if !enrollment.confirmed {
+            return Err(anyhow::anyhow!("TOTP not enabled for this user"));
+        }
+
+        if let Some(step) = verify_totp(&secret, code, unix_now(), enrollment.last_used_step) {
+            enrollment.last_used_step = Some(step);
+            self.save()?;
+            return Ok(Some(SecondFactorMethod::Totp));
+        }
+
+        let normalized = normalize_recovery_code(code);
+        let matched = enrollment.recovery_code_hashes.iter().position(|hash| {
+            PasswordHash::new(hash)
+                .map(|parsed| Argon2::default().verify_password(normalized.as_bytes(), &parsed).is_ok())
+                .unwrap_or(false)
+        });
+
+        if let Some(index) = matched {
+            enrollment.recovery_code_hashes.remove(index);
+            self.save()?;
+            return Ok(Some(SecondFactorMethod::RecoveryCode));
+        }
+
+        Ok(None)
+    }
+
+    pub fn regenerate_recovery_codes(&mut self, user_id: &str, code: &str) -> Result<Vec<String>> {
+        match self.verify(user_id, code)? {
+            Some(SecondFactorMethod::Totp) => {}
+            _ => return Err(anyhow::anyhow!("A valid TOTP code is required")),
+        }
+
+        let (codes, hashes) = generate_recovery_codes()?;
+        if let Some(enrollment) = self.enrollments.get_mut(user_id) {
+            enrollment.recovery_code_hashes = hashes;
+        }
+        self.save()?;
+        Ok(codes)
+    }
+
+    pub fn disable(&mut self, user_id: &str, code: &str) -> Result<()> {
+        if self.verify(user_id, code)?.is_none() {
+            return Err(anyhow::anyhow!("Invalid TOTP code"));
+        }
+        self.enrollments.remove(user_id);
+        self.save()
+    }
+
+    // Recifra com a chave ativa todos os segredos ainda presos a uma chave antiga
+    // (usado pela rotação da chave mestra)
+    pub fn reencrypt_secrets(&mut self) -> Result<usize> {
+        let stale: Vec<String> = with_shared_vault(|vault| {
+            Ok(self.enrollments
+                .values()
+                .filter(|e| vault.needs_upgrade(&e.encrypted_secret))
+                .map(|e| e.user_id.clone())
+                .collect())
+        })?;
+
+        for user_id in &stale {
+            self.load_secret(user_id)?;
+        }
+        Ok(stale.len())
+    }
+
+    pub fn stale_secret_count(&self) -> Result<usize> {
+        with_shared_vault(|vault| {
+            Ok(self.enrollments
+                .values()
+                .filter(|e| vault.needs_upgrade(&e.encrypted_secret))
+                .count())
+        })
+    }
+
+    fn load_secret(&mut self, user_id: &str) -> Result<Vec<u8>> {
+        let encrypted = self.enrollments
+            .get(user_id)
+            .map(|e| e.encrypted_secret.clone())
+            .ok_or_else(|| anyhow::anyhow!("TOTP not enrolled for this user"))?;
+        let (secret, upgraded) = with_shared_vault(|vault| {
+            vault.decrypt_and_upgrade(&encrypted, SECRET_PURPOSE, &entity_aad("user", user_id))
+        })?;
+
+        if let Some(upgraded) = upgraded {
+            if let Some(enrollment) = self.enrollments.get_mut(user_id) {
+                enrollment.encrypted_secret = upgraded;
+            }
+            self.save()?;
+        }
+        Ok(secret)
+    }
+}
+
+// =====================================================
+// HOTP/TOTP PRIMITIVES
+// =====================================================
+
+fn unix_now() -> u64 {
+    Utc::now().timestamp().max(0) as u64
+}
+
+pub fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Truncamento dinâmico (RFC 4226, seção 5.3)
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = ((digest[offset] as u32 & 0x7f) << 24)
+        | ((digest[offset + 1] as u32) << 16)
+        | ((digest[offset + 2] as u32) << 8)
+        | (digest[offset + 3] as u32);
+
+    binary % 10u32.pow(TOTP_DIGITS)
+}
+
+pub fn totp_at(secret: &[u8], unix_time: u64) -> String {
+    let step = unix_time / TOTP_PERIOD_SECONDS;
+    format!("{:0width$}", hotp(secret, step), width = TOTP_DIGITS as usize)
+}
+
+// Retorna o passo de tempo aceito, respeitando a tolerância de relógio e
+// rejeitando passos já utilizados.
+pub fn verify_totp(secret: &[u8], code: &str, unix_time: u64, last_used_step: Option<u64>) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let current_step = (unix_time / TOTP_PERIOD_SECONDS) as i64;
+    for offset in -TOTP_SKEW_STEPS..=TOTP_SKEW_STEPS {
+        let step = current_step + offset;
+        if step < 0 {
+            continue;
+        }
+        let step = step as u64;
+        if last_used_step.is_some_and(|last| step <= last) {
+            continue;
+        }
+        let expected = totp_at(secret, step * TOTP_PERIOD_SECONDS);
+        if constant_time_eq(expected.as_bytes(), code.as_bytes()) {
+            return Some(step);
+        }
+    }
+    None
+}
+
+pub fn provisioning_uri(secret_b32: &str, account: &str, issuer: &str) -> String {
+    let encode = |s: &str| url::form_urlencoded::byte_serialize(s.as_bytes()).collect::<String>().replace('+', "%20");
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encode(issuer),
+        encode(account),
+        secret_b32,
+        encode(issuer),
+        TOTP_DIGITS,
+        TOTP_PERIOD_SECONDS
+    )
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(BASE32_ALPHABET[((buffer >> (bits - 5)) & 0x1f) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+// =====================================================
+// RECOVERY CODES
+// =====================================================
+
+fn generate_recovery_codes() -> Result<(Vec<String>, Vec<String>)> {
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789"; // Sem caracteres ambíguos
+    let mut rng = rand::thread_rng();
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    let mut hashes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let raw: String = (0..10)
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+            .collect();
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(raw.as_bytes(), &salt)
+            .map_err(|e| anyhow::anyhow!("Failed to hash recovery code: {}", e))?
+            .to_string();
+
+        codes.push(format!("{}-{}", &raw[..5], &raw[5..]));
+        hashes.push(hash);
+    }
+
+    Ok((codes, hashes))
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Segredo SHA-1 dos vetores de teste da RFC 6238 (apêndice B)
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        // A RFC lista 8 dígitos; com 6 ficam os últimos seis
+        assert_eq!(totp_at(RFC_SECRET, 59), "287082");
+        assert_eq!(totp_at(RFC_SECRET, 1111111109), "081804");
+        assert_eq!(totp_at(RFC_SECRET, 1111111111), "050471");
+        assert_eq!(totp_at(RFC_SECRET, 1234567890), "005924");
+    }
+
+    #[test]
+    fn accepts_one_step_of_clock_skew() {
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, None), Some(1));
+        assert_eq!(verify_totp(RFC_SECRET, "287 082", 89, None), Some(1));
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 150, None), None);
+    }
+
+    #[test]
+    fn rejects_reused_step() {
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, Some(1)), None);
+        assert_eq!(verify_totp(RFC_SECRET, "287082", 59, Some(0)), Some(1));
+    }
+}
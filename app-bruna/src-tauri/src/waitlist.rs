@@ -0,0 +1,291 @@
+use anyhow::{bail, Result};
+use chrono::{Datelike, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+// =====================================================
+// WAITLIST
+// =====================================================
+
+// Pacientes esperando uma vaga, com os dias, horários e profissionais que
+// servem. Quando uma consulta é cancelada ou remarcada, o horário liberado é
+// comparado com a lista e vira uma oferta com os candidatos em ordem:
+// prioridade, quem pediu exatamente aquele dia/horário/profissional e, por
+// fim, quem espera há mais tempo.
+
+pub const OFFERS_EVENT: &str = "waitlist-offers";
+const MAX_DURATION_MINUTES: u32 = 12 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitlistPriority {
+    #[default]
+    Normal,
+    High,
+    Urgent, // Dor, pós-operatório etc.
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitlistEntry {
+    pub id: String,
+    pub patient_id: String,
+    #[serde(default)]
+    pub preferred_weekdays: Vec<Weekday>, // Vazio: qualquer dia
+    #[serde(default)]
+    pub preferred_times: Vec<TimeWindow>, // Vazio: qualquer horário
+    #[serde(default)]
+    pub professional_ids: Vec<String>, // Vazio: qualquer profissional
+    pub duration_minutes: Option<u32>, // Sem duração: a da vaga
+    pub earliest_date: Option<NaiveDate>,
+    pub latest_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub priority: WaitlistPriority,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    // Metadados de sincronização híbrida
+    pub rev: i64,
+    pub deleted_at: Option<String>,
+    pub last_editor: Option<String>,
+    pub last_pulled_rev: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitlistRequest {
+    pub patient_id: String,
+    #[serde(default)]
+    pub preferred_weekdays: Vec<Weekday>,
+    #[serde(default)]
+    pub preferred_times: Vec<TimeWindow>,
+    #[serde(default)]
+    pub professional_ids: Vec<String>,
+    pub duration_minutes: Option<u32>,
+    pub earliest_date: Option<NaiveDate>,
+    pub latest_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub priority: WaitlistPriority,
+    pub notes: Option<String>,
+}
+
+pub fn validate_request(request: &WaitlistRequest) -> Result<()> {
+    if request.patient_id.trim().is_empty() {
+        bail!("Patient is required");
+    }
+    if let Some(window) = request.preferred_times.iter().find(|w| w.start >= w.end) {
+        bail!("Preferred time {} - {} ends before it starts", window.start.format("%H:%M"), window.end.format("%H:%M"));
+    }
+    if request.professional_ids.iter().any(|p| p.trim().is_empty()) {
+        bail!("Professional id cannot be empty");
+    }
+    if let Some(minutes) = request.duration_minutes {
+        if minutes == 0 || minutes > MAX_DURATION_MINUTES {
+            bail!("Duration must be between 1 and {} minutes", MAX_DURATION_MINUTES);
+        }
+    }
+    if let (Some(earliest), Some(latest)) = (request.earliest_date, request.latest_date) {
+        if earliest > latest {
+            bail!("Earliest date must be before the latest date");
+        }
+    }
+    Ok(())
+}
+
+// =====================================================
+// OFFERS
+// =====================================================
+
+// Horário liberado por um cancelamento ou remarcação
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreedSlot {
+    pub appointment_id: String, // Consulta que liberou o horário
+    pub patient_id: String,
+    pub date: NaiveDate,
+    pub start_time: String,
+    pub end_time: String,
+    pub duration_minutes: u32,
+    pub professional_id: Option<String>,
+    pub room: Option<String>,
+    pub chair: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitlistMatch {
+    pub entry_id: String,
+    pub patient_id: String,
+    pub patient_name: Option<String>,
+    pub priority: WaitlistPriority,
+    pub matched_preferences: u32, // Dia, horário e profissional pedidos que a vaga atende
+    pub waiting_days: i64,
+    pub duration_minutes: u32, // Duração da consulta a marcar
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlotOffer {
+    pub id: String,
+    pub slot: FreedSlot,
+    pub candidates: Vec<WaitlistMatch>, // Melhor candidato primeiro
+    pub created_at: String,
+}
+
+fn minutes_of_day(time: NaiveTime) -> i64 {
+    (time - NaiveTime::MIN).num_minutes()
+}
+
+// Preferências atendidas, dias de espera e duração de uma entrada para a vaga,
+// ou None se a vaga não serve
+fn assess(entry: &WaitlistEntry, slot: &FreedSlot, start: NaiveTime, today: NaiveDate) -> Option<(u32, i64, u32)> {
+    if entry.deleted_at.is_some() || entry.patient_id == slot.patient_id {
+        return None;
+    }
+    if entry.earliest_date.is_some_and(|d| slot.date < d) || entry.latest_date.is_some_and(|d| slot.date > d) {
+        return None;
+    }
+    let duration = entry.duration_minutes.unwrap_or(slot.duration_minutes);
+    if duration > slot.duration_minutes {
+        return None;
+    }
+
+    // Preferência informada e atendida conta a favor; "tanto faz" não conta
+    let mut matched = 0;
+    if !entry.preferred_weekdays.is_empty() {
+        if !entry.preferred_weekdays.contains(&slot.date.weekday()) {
+            return None;
+        }
+        matched += 1;
+    }
+    if !entry.preferred_times.is_empty() {
+        let (from, to) = (minutes_of_day(start), minutes_of_day(start) + duration as i64);
+        if !entry
+            .preferred_times
+            .iter()
+            .any(|w| minutes_of_day(w.start) <= from && to <= minutes_of_day(w.end))
+        {
+            return None;
+        }
+        matched += 1;
+    }
+    if !entry.professional_ids.is_empty() {
+        if !slot.professional_id.as_ref().is_some_and(|p| entry.professional_ids.contains(p)) {
+            return None;
+        }
+        matched += 1;
+    }
+
+    let waiting_days = chrono::DateTime::parse_from_rfc3339(&entry.created_at)
+        .map(|created| (today - created.date_naive()).num_days().max(0))
+        .unwrap_or(0);
+    Some((matched, waiting_days, duration))
+}
+
+// Entradas da lista que aceitam a vaga, da melhor para a pior: prioridade,
+// depois preferências atendidas, depois dias de espera (empate: quem entrou
+// antes na lista). Um critério só desempata o anterior, nunca o compensa.
+pub fn rank_entries(entries: &[WaitlistEntry], slot: &FreedSlot, today: NaiveDate) -> Result<Vec<WaitlistMatch>> {
+    let start = crate::scheduling::parse_time(&slot.start_time)?;
+    let mut ranked: Vec<(&WaitlistEntry, WaitlistMatch)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let (matched_preferences, waiting_days, duration_minutes) = assess(entry, slot, start, today)?;
+            Some((
+                entry,
+                WaitlistMatch {
+                    entry_id: entry.id.clone(),
+                    patient_id: entry.patient_id.clone(),
+                    patient_name: None,
+                    priority: entry.priority,
+                    matched_preferences,
+                    waiting_days,
+                    duration_minutes,
+                },
+            ))
+        })
+        .collect();
+    ranked.sort_by(|(a, ma), (b, mb)| {
+        (mb.priority, mb.matched_preferences, mb.waiting_days)
+            .cmp(&(ma.priority, ma.matched_preferences, ma.waiting_days))
+            .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    Ok(ranked.into_iter().map(|(_, m)| m).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, priority: WaitlistPriority, created_at: &str) -> WaitlistEntry {
+        WaitlistEntry {
+            id: id.to_string(),
+            patient_id: format!("patient-{}", id),
+            preferred_weekdays: Vec::new(),
+            preferred_times: Vec::new(),
+            professional_ids: Vec::new(),
+            duration_minutes: None,
+            earliest_date: None,
+            latest_date: None,
+            priority,
+            notes: None,
+            created_at: created_at.to_string(),
+            updated_at: created_at.to_string(),
+            rev: 0,
+            deleted_at: None,
+            last_editor: None,
+            last_pulled_rev: None,
+        }
+    }
+
+    // Terça, 10/03/2026, 09:00-09:30 com a dra-1
+    fn slot() -> FreedSlot {
+        FreedSlot {
+            appointment_id: "freed".to_string(),
+            patient_id: "patient-freed".to_string(),
+            date: NaiveDate::from_ymd_opt(2026, 3, 10).unwrap(),
+            start_time: "09:00".to_string(),
+            end_time: "09:30".to_string(),
+            duration_minutes: 30,
+            professional_id: Some("dra-1".to_string()),
+            room: None,
+            chair: None,
+        }
+    }
+
+    fn ranked_ids(entries: &[WaitlistEntry]) -> Vec<String> {
+        let today = NaiveDate::from_ymd_opt(2026, 3, 9).unwrap();
+        rank_entries(entries, &slot(), today).unwrap().into_iter().map(|m| m.entry_id).collect()
+    }
+
+    #[test]
+    fn priority_beats_preferences_and_waiting_time() {
+        let mut waiting_long = entry("normal", WaitlistPriority::Normal, "2024-01-01T00:00:00Z");
+        waiting_long.preferred_weekdays = vec![Weekday::Tue];
+        waiting_long.professional_ids = vec!["dra-1".to_string()];
+        let urgent = entry("urgent", WaitlistPriority::Urgent, "2026-03-08T00:00:00Z");
+        let high = entry("high", WaitlistPriority::High, "2026-03-08T00:00:00Z");
+
+        assert_eq!(ranked_ids(&[waiting_long, high, urgent]), vec!["urgent", "high", "normal"]);
+    }
+
+    #[test]
+    fn preferences_beat_waiting_time() {
+        let any_day = entry("any", WaitlistPriority::Normal, "2025-01-01T00:00:00Z");
+        let mut tuesday = entry("tuesday", WaitlistPriority::Normal, "2026-03-01T00:00:00Z");
+        tuesday.preferred_weekdays = vec![Weekday::Tue];
+        let mut monday = entry("monday", WaitlistPriority::Urgent, "2026-03-01T00:00:00Z");
+        monday.preferred_weekdays = vec![Weekday::Mon];
+
+        // Quem pediu segunda não serve, mesmo urgente
+        assert_eq!(ranked_ids(&[any_day, tuesday, monday]), vec!["tuesday", "any"]);
+    }
+
+    #[test]
+    fn waiting_time_then_arrival_break_ties() {
+        let older = entry("older", WaitlistPriority::Normal, "2026-01-01T00:00:00Z");
+        let newer = entry("newer", WaitlistPriority::Normal, "2026-02-01T00:00:00Z");
+        let same_day = entry("same-day", WaitlistPriority::Normal, "2026-02-01T12:00:00Z");
+        assert_eq!(ranked_ids(&[same_day, newer, older]), vec!["older", "newer", "same-day"]);
+    }
+}